@@ -0,0 +1,29 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use easy_storage::kv_storage::bench;
+use easy_storage::kv_storage::cached_kv_storage::CachedKvStorage;
+use easy_storage::kv_storage::chunked_kv_storage::ChunkedKvStorage;
+use easy_storage::kv_storage::memory_kv_storage::MemoryKvStorage;
+
+fn backends(c: &mut Criterion) {
+    bench::bench_backend(c, "memory", MemoryKvStorage::new);
+    bench::bench_backend(c, "chunked", || {
+        ChunkedKvStorage::new(MemoryKvStorage::new(), 64 * 1024)
+    });
+    bench::bench_backend(c, "cached", || {
+        CachedKvStorage::new(MemoryKvStorage::new(), 64)
+    });
+
+    #[cfg(all(
+        feature = "file",
+        any(target_family = "unix", target_family = "windows")
+    ))]
+    {
+        use easy_storage::kv_storage::file_based_kv_storage::FileBasedKvStorage;
+
+        let dir = std::env::temp_dir().join("easy_storage_bench").join("app");
+        bench::bench_backend(c, "file", || FileBasedKvStorage::default().with_path(&dir));
+    }
+}
+
+criterion_group!(benches, backends);
+criterion_main!(benches);
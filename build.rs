@@ -0,0 +1,7 @@
+fn main() {
+    // link arguments for node addons, e.g. leaving the napi symbols unresolved on macos
+    #[cfg(feature = "node")]
+    if std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() != Ok("wasm32") {
+        napi_build::setup();
+    }
+}
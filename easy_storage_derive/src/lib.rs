@@ -0,0 +1,152 @@
+// `#[derive(KvModel)]`, re-exported by easy_storage with the `derive` feature. see
+// `easy_storage::kv_storage::kv_model::KvModel` for the attributes
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, LitStr, Path, Result, Type};
+
+#[proc_macro_derive(KvModel, attributes(kv))]
+pub fn derive_kv_model(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+enum FieldDefault {
+    // loading fails without a value
+    Required,
+    Trait,
+    Function(Path),
+}
+
+struct Field {
+    ident: syn::Ident,
+    key: String,
+    default: FieldDefault,
+    skip: bool,
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let mut prefix = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("kv")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("prefix") {
+                prefix = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `prefix`"))
+            }
+        })?;
+    }
+
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "KvModel can only be derived for structs",
+        ));
+    };
+    let Fields::Named(named) = &data.fields else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "KvModel can only be derived for structs with named fields",
+        ));
+    };
+
+    let mut fields = Vec::new();
+    for field in &named.named {
+        let ident = field.ident.clone().expect("named fields have names");
+        let mut name = ident.to_string();
+        let mut key = None;
+        let mut skip = false;
+        // missing options are `None`, as with serde
+        let mut default = if is_option(&field.ty) {
+            FieldDefault::Trait
+        } else {
+            FieldDefault::Required
+        };
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("kv")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                } else if meta.path.is_ident("key") {
+                    key = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("default") {
+                    default = if meta.input.peek(syn::Token![=]) {
+                        FieldDefault::Function(meta.value()?.parse::<LitStr>()?.parse()?)
+                    } else {
+                        FieldDefault::Trait
+                    };
+                } else if meta.path.is_ident("skip") {
+                    skip = true;
+                } else {
+                    return Err(meta.error("expected `rename`, `key`, `default` or `skip`"));
+                }
+                Ok(())
+            })?;
+        }
+        let key = match (key, &prefix) {
+            (Some(key), _) => key,
+            (None, Some(prefix)) => format!("{prefix}/{name}"),
+            (None, None) => name,
+        };
+        fields.push(Field {
+            ident,
+            key,
+            default,
+            skip,
+        });
+    }
+
+    let model = quote!(::easy_storage::kv_storage::kv_model);
+    let loads = fields.iter().map(|field| {
+        let Field { ident, key, .. } = field;
+        if field.skip {
+            return quote!(#ident: ::core::default::Default::default());
+        }
+        let loaded = quote!(#model::load_field(store, #key)?);
+        match &field.default {
+            FieldDefault::Required => quote!(#ident: #model::required(#key, #loaded)?),
+            FieldDefault::Trait => quote!(#ident: #loaded.unwrap_or_default()),
+            FieldDefault::Function(function) => quote!(#ident: #loaded.unwrap_or_else(#function)),
+        }
+    });
+    let saves = fields.iter().filter(|field| !field.skip).map(|field| {
+        let Field { ident, key, .. } = field;
+        quote!(#model::save_field(store, #key, &self.#ident)?;)
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #model::KvModel for #ident #type_generics #where_clause {
+            fn load<S: ::easy_storage::kv_storage::KvStorage + ?Sized>(
+                store: &S,
+            ) -> ::core::result::Result<Self, #model::KvModelReadError<S::ReadErrorType>> {
+                ::core::result::Result::Ok(#ident {
+                    #(#loads,)*
+                })
+            }
+
+            fn save<S: ::easy_storage::kv_storage::KvStorage + ?Sized>(
+                &self,
+                store: &S,
+            ) -> ::core::result::Result<(), #model::KvModelWriteError<S::WriteErrorType>> {
+                #(#saves)*
+                ::core::result::Result::Ok(())
+            }
+        }
+    })
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
@@ -0,0 +1,87 @@
+/* generated by cbindgen from src/ffi.rs, do not edit */
+
+#ifndef EASY_STORAGE_H
+#define EASY_STORAGE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum EsStatus {
+  ES_STATUS_OK = 0,
+  // The key has no value.
+  ES_STATUS_NOT_FOUND = 1,
+  // A pointer was null or a string wasn't valid utf-8.
+  ES_STATUS_INVALID_ARGUMENT = 2,
+  // The backend failed to read, or the value contains a nul byte.
+  ES_STATUS_READ_FAILED = 3,
+  // The backend failed to write or delete.
+  ES_STATUS_WRITE_FAILED = 4,
+  // The library panicked, the store can still be used.
+  ES_STATUS_PANICKED = 5,
+} EsStatus;
+
+// An open store, created by one of the `es_*_store_new` functions and freed with
+// `es_store_free`.
+typedef struct EsStore EsStore;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a store keeping its entries in memory, they're lost when it is freed.
+struct EsStore *es_memory_store_new(void);
+
+// Creates a store keeping every key in a file in the directory `path`, or in the
+// application's default directory if `path` is null. Returns null if `path` isn't valid
+// utf-8, the default directory can't be found or the platform has no file backend.
+//
+// # Safety
+//
+// `path` must be null or point to a nul terminated string.
+struct EsStore *es_file_store_new(const char *path);
+
+// Frees a store, null is ignored.
+//
+// # Safety
+//
+// `store` must be null or a handle that hasn't been freed yet and isn't in use by another
+// thread.
+void es_store_free(struct EsStore *store);
+
+// Reads the value of `key` into `*value`, which is set to null unless `ES_STATUS_OK` is
+// returned.
+//
+// # Safety
+//
+// `store` must be a live handle, `key` a nul terminated string and `value` must point to
+// writable memory for a pointer.
+enum EsStatus es_get(const struct EsStore *store, const char *key, char **value);
+
+// Sets the value of `key`.
+//
+// # Safety
+//
+// `store` must be a live handle, `key` and `value` nul terminated strings.
+enum EsStatus es_set(const struct EsStore *store, const char *key, const char *value);
+
+// Deletes `key`, deleting a key without a value succeeds.
+//
+// # Safety
+//
+// `store` must be a live handle and `key` a nul terminated string.
+enum EsStatus es_delete(const struct EsStore *store, const char *key);
+
+// Frees a string returned by the library, null is ignored.
+//
+// # Safety
+//
+// `string` must be null or a string returned by the library that hasn't been freed yet.
+void es_string_free(char *string);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* EASY_STORAGE_H */
@@ -0,0 +1,301 @@
+use easy_storage::kv_storage::{KvStorage, KvStorageExt};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::error::Error;
+use std::fmt::Display;
+use std::io;
+
+enum Mode {
+    Browse,
+    Filter,
+    // the value being edited and the cursor position in it, in chars
+    Edit { buffer: String, cursor: usize },
+    ConfirmDelete,
+}
+
+struct Browser<'a, S> {
+    store: &'a S,
+    entries: Vec<(String, String)>,
+    filter: String,
+    // indices into `entries` of the keys matching `filter`
+    shown: Vec<usize>,
+    list: ListState,
+    scroll: u16,
+    mode: Mode,
+    status: String,
+}
+
+// browses the entries of `store` in the terminal until the user quits
+pub fn run<S>(store: &S) -> Result<(), Box<dyn Error>>
+where
+    S: KvStorage,
+    S::ReadErrorType: Error + 'static,
+    S::WriteErrorType: Error + 'static,
+{
+    let mut browser = Browser {
+        store,
+        entries: store.scan_all()?,
+        filter: String::new(),
+        shown: Vec::new(),
+        list: ListState::default(),
+        scroll: 0,
+        mode: Mode::Browse,
+        status: String::new(),
+    };
+    browser.refilter();
+    let mut terminal = ratatui::init();
+    let result = browser.run(&mut terminal);
+    ratatui::restore();
+    result.map_err(Into::into)
+}
+
+// json values are shown pretty-printed, anything else as it is
+fn preview(value: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(value) {
+        Ok(json @ (serde_json::Value::Object(_) | serde_json::Value::Array(_))) => {
+            serde_json::to_string_pretty(&json).unwrap_or_else(|_| value.to_string())
+        }
+        _ => value.to_string(),
+    }
+}
+
+fn byte_index(value: &str, chars: usize) -> usize {
+    value
+        .char_indices()
+        .nth(chars)
+        .map_or(value.len(), |(index, _)| index)
+}
+
+impl<S> Browser<'_, S>
+where
+    S: KvStorage,
+    S::ReadErrorType: Display,
+    S::WriteErrorType: Display,
+{
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind == KeyEventKind::Press && !self.handle(key) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn selected(&self) -> Option<usize> {
+        self.list
+            .selected()
+            .and_then(|row| self.shown.get(row).copied())
+    }
+
+    fn refilter(&mut self) {
+        let selected = self.selected().map(|index| self.entries[index].0.clone());
+        self.shown = (0..self.entries.len())
+            .filter(|&index| self.entries[index].0.contains(&self.filter))
+            .collect();
+        let row = selected
+            .and_then(|key| {
+                self.shown
+                    .iter()
+                    .position(|&index| self.entries[index].0 == key)
+            })
+            .or((!self.shown.is_empty()).then_some(0));
+        self.list.select(row);
+        self.scroll = 0;
+    }
+
+    fn reload(&mut self) {
+        match self.store.scan_all() {
+            Ok(entries) => {
+                self.entries = entries;
+                self.status = format!("loaded {} entries", self.entries.len());
+            }
+            Err(e) => self.status = format!("could not read entries: {e}"),
+        }
+        self.refilter();
+    }
+
+    fn select(&mut self, row: usize) {
+        if !self.shown.is_empty() {
+            self.list.select(Some(row.min(self.shown.len() - 1)));
+            self.scroll = 0;
+        }
+    }
+
+    // returns false once the user quits
+    fn handle(&mut self, key: KeyEvent) -> bool {
+        let row = self.list.selected().unwrap_or(0);
+        match &mut self.mode {
+            Mode::Browse => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return false,
+                KeyCode::Down | KeyCode::Char('j') => self.select(row + 1),
+                KeyCode::Up | KeyCode::Char('k') => self.select(row.saturating_sub(1)),
+                KeyCode::PageDown => self.select(row + 20),
+                KeyCode::PageUp => self.select(row.saturating_sub(20)),
+                KeyCode::Home => self.select(0),
+                KeyCode::End => self.select(usize::MAX),
+                KeyCode::Char('J') => self.scroll = self.scroll.saturating_add(1),
+                KeyCode::Char('K') => self.scroll = self.scroll.saturating_sub(1),
+                KeyCode::Char('/') => self.mode = Mode::Filter,
+                KeyCode::Char('r') => self.reload(),
+                KeyCode::Char('e') | KeyCode::Enter => {
+                    if let Some(index) = self.selected() {
+                        let buffer = self.entries[index].1.clone();
+                        let cursor = buffer.chars().count();
+                        self.mode = Mode::Edit { buffer, cursor };
+                    }
+                }
+                KeyCode::Char('d') if self.selected().is_some() => {
+                    self.mode = Mode::ConfirmDelete;
+                }
+                _ => {}
+            },
+            Mode::Filter => match key.code {
+                KeyCode::Enter | KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.refilter();
+                }
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.refilter();
+                }
+                _ => {}
+            },
+            Mode::Edit { buffer, cursor } => {
+                let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+                match key.code {
+                    KeyCode::Esc => {
+                        self.mode = Mode::Browse;
+                        self.status = "edit discarded".to_string();
+                    }
+                    KeyCode::Char('s') if ctrl => {
+                        let value = std::mem::take(buffer);
+                        self.mode = Mode::Browse;
+                        self.save(value);
+                    }
+                    KeyCode::Left => *cursor = cursor.saturating_sub(1),
+                    KeyCode::Right => *cursor = (*cursor + 1).min(buffer.chars().count()),
+                    KeyCode::Home => *cursor = 0,
+                    KeyCode::End => *cursor = buffer.chars().count(),
+                    KeyCode::Backspace if *cursor > 0 => {
+                        *cursor -= 1;
+                        buffer.remove(byte_index(buffer, *cursor));
+                    }
+                    KeyCode::Delete if *cursor < buffer.chars().count() => {
+                        buffer.remove(byte_index(buffer, *cursor));
+                    }
+                    KeyCode::Enter => {
+                        buffer.insert(byte_index(buffer, *cursor), '\n');
+                        *cursor += 1;
+                    }
+                    KeyCode::Char(c) if !ctrl => {
+                        buffer.insert(byte_index(buffer, *cursor), c);
+                        *cursor += 1;
+                    }
+                    _ => {}
+                }
+            }
+            Mode::ConfirmDelete => {
+                self.mode = Mode::Browse;
+                if key.code == KeyCode::Char('y') {
+                    self.delete();
+                }
+            }
+        }
+        true
+    }
+
+    fn save(&mut self, value: String) {
+        let Some(index) = self.selected() else {
+            return;
+        };
+        let key = &self.entries[index].0;
+        match self.store.write(key, &value) {
+            Ok(()) => {
+                self.status = format!("saved '{key}'");
+                self.entries[index].1 = value;
+            }
+            Err(e) => self.status = format!("could not save '{key}': {e}"),
+        }
+    }
+
+    fn delete(&mut self) {
+        let Some(index) = self.selected() else {
+            return;
+        };
+        let key = self.entries[index].0.clone();
+        match self.store.delete(&key) {
+            Ok(()) => {
+                self.status = format!("deleted '{key}'");
+                self.entries.remove(index);
+                self.refilter();
+            }
+            Err(e) => self.status = format!("could not delete '{key}': {e}"),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [keys, value] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .areas(main);
+
+        let title = match self.filter.as_str() {
+            "" => format!("Keys ({})", self.shown.len()),
+            filter => format!("Keys ({}) matching '{filter}'", self.shown.len()),
+        };
+        let items = self
+            .shown
+            .iter()
+            .map(|&index| self.entries[index].0.as_str());
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(list, keys, &mut self.list);
+
+        let selected = self.selected().map(|index| &self.entries[index]);
+        let (title, text) = match (&self.mode, selected) {
+            (Mode::Edit { buffer, .. }, Some((key, _))) => {
+                (format!("Editing {key}"), buffer.clone())
+            }
+            (_, Some((key, value))) => (key.clone(), preview(value)),
+            (_, None) => (String::new(), String::new()),
+        };
+        let paragraph = Paragraph::new(text.as_str())
+            .block(Block::bordered().title(title))
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0));
+        frame.render_widget(paragraph, value);
+
+        if let Mode::Edit { buffer, cursor } = &self.mode {
+            // without wrapping taken into account, good enough for short lines
+            let before = &buffer[..byte_index(buffer, *cursor)];
+            let line = before.matches('\n').count() as u16;
+            let column = before.rsplit('\n').next().unwrap_or("").chars().count() as u16;
+            frame.set_cursor_position(Position::new(value.x + 1 + column, value.y + 1 + line));
+        }
+
+        let help = match &self.mode {
+            Mode::Browse => "q quit  j/k move  J/K scroll  / filter  e edit  d delete  r reload",
+            Mode::Filter => "type to filter keys  enter/esc done",
+            Mode::Edit { .. } => "ctrl-s save  esc discard",
+            Mode::ConfirmDelete => "delete this key? y/n",
+        };
+        let line = match (&self.mode, self.status.as_str()) {
+            (Mode::Browse, status) if !status.is_empty() => {
+                Line::from(format!("{status}  |  {help}"))
+            }
+            (Mode::Filter, _) => Line::from(format!("/{}  |  {help}", self.filter)),
+            _ => Line::from(help),
+        };
+        frame.render_widget(Paragraph::new(line.dim()), status);
+    }
+}
@@ -0,0 +1,168 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use easy_storage::kv_storage::{archive, KvStorage, KvStorageExt};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[cfg(feature = "tui")]
+mod browser;
+
+// lets support look at and fix a user's store without writing code
+#[derive(Parser)]
+#[command(
+    name = "easy-storage",
+    version,
+    about = "Inspect and edit the entries of a store"
+)]
+struct Cli {
+    #[arg(long, value_enum, default_value_t = Backend::File)]
+    backend: Backend,
+
+    #[arg(
+        long,
+        help = "Directory of the file backend, defaults to the one the application uses"
+    )]
+    path: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Backend {
+    File,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Print the value of a key")]
+    Get { key: String },
+    #[command(about = "Set the value of a key, read from stdin if the value is '-'")]
+    Set { key: String, value: String },
+    #[command(about = "Delete a key, or every key starting with it")]
+    Del {
+        key: String,
+        #[arg(long, help = "Delete every key starting with the given one")]
+        prefix: bool,
+    },
+    #[command(about = "List keys, optionally only those starting with a prefix")]
+    List {
+        prefix: Option<String>,
+        #[arg(long, help = "Print the values next to the keys, separated by a tab")]
+        values: bool,
+    },
+    #[command(about = "Write every entry as a json archive to a file, or stdout")]
+    Export { file: Option<PathBuf> },
+    #[command(about = "Write every entry of a json archive into the store")]
+    Import { file: PathBuf },
+    #[cfg(feature = "tui")]
+    #[command(about = "Browse and edit the entries in the terminal")]
+    Browse,
+}
+
+type CliResult = Result<ExitCode, Box<dyn Error>>;
+
+// unused until the platform has a backend
+#[cfg_attr(
+    not(all(
+        feature = "file",
+        any(target_family = "unix", target_family = "windows")
+    )),
+    allow(dead_code)
+)]
+fn run<S>(store: &S, command: Command) -> CliResult
+where
+    S: KvStorage,
+    S::ReadErrorType: Error + 'static,
+    S::WriteErrorType: Error + 'static,
+{
+    match command {
+        Command::Get { key } => match store.read_opt(&key)? {
+            Some(value) => println!("{value}"),
+            None => {
+                eprintln!("key '{key}' not found");
+                return Ok(ExitCode::FAILURE);
+            }
+        },
+        Command::Set { key, value } => {
+            let value = match value.as_str() {
+                "-" => {
+                    let mut value = String::new();
+                    io::stdin().read_to_string(&mut value)?;
+                    value
+                }
+                _ => value,
+            };
+            store.write(&key, &value)?;
+        }
+        Command::Del { key, prefix: true } => store.delete_prefix(&key)?,
+        Command::Del { key, prefix: false } => store.delete(&key)?,
+        Command::List { prefix, values } => {
+            let prefix = prefix.unwrap_or_default();
+            let mut stdout = io::stdout().lock();
+            for (key, value) in store.scan_all()? {
+                match (key.starts_with(&prefix), values) {
+                    (false, _) => {}
+                    (true, true) => writeln!(stdout, "{key}\t{value}")?,
+                    (true, false) => writeln!(stdout, "{key}")?,
+                }
+            }
+        }
+        Command::Export { file } => {
+            let count = match file {
+                Some(file) => archive::export(store, File::create(file)?)?,
+                None => archive::export(store, io::stdout().lock())?,
+            };
+            eprintln!("exported {count} entries");
+        }
+        Command::Import { file } => {
+            let count = archive::import(store, File::open(file)?)?;
+            eprintln!("imported {count} entries");
+        }
+        #[cfg(feature = "tui")]
+        Command::Browse => browser::run(store)?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(all(
+    feature = "file",
+    any(target_family = "unix", target_family = "windows")
+))]
+fn open(cli: Cli) -> CliResult {
+    use easy_storage::kv_storage::file_based_kv_storage::FileBasedKvStorage;
+
+    let Backend::File = cli.backend;
+    let store = match cli.path {
+        Some(path) => FileBasedKvStorage::at(path),
+        None => FileBasedKvStorage::default(),
+    };
+    run(&store, cli.command)
+}
+
+#[cfg(not(all(
+    feature = "file",
+    any(target_family = "unix", target_family = "windows")
+)))]
+fn open(cli: Cli) -> CliResult {
+    let Backend::File = cli.backend;
+    let _ = (cli.path, cli.command);
+    Err("the file backend is not available on this platform".into())
+}
+
+fn main() -> ExitCode {
+    match open(Cli::parse()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {e}");
+            let mut source = e.source();
+            while let Some(e) = source {
+                eprintln!("  caused by: {e}");
+                source = e.source();
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,203 @@
+// c interface to the storage backends, include/easy_storage.h is generated from this file with
+// `cbindgen --config cbindgen.toml --output include/easy_storage.h`. build the library for
+// linking with e.g. `cargo rustc --lib --release --features ffi --crate-type staticlib`
+//
+// strings are nul terminated utf-8. a store handle may be shared between threads, strings
+// returned by the library are owned by the caller and freed with `es_string_free`
+
+use crate::kv_storage::boxed_kv_storage::BoxedKvStorage;
+use crate::kv_storage::memory_kv_storage::MemoryKvStorage;
+use crate::kv_storage::{IsNotFound, KvStorage};
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// An open store, created by one of the `es_*_store_new` functions and freed with
+/// `es_store_free`.
+pub struct EsStore(BoxedKvStorage);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EsStatus {
+    Ok = 0,
+    /// The key has no value.
+    NotFound = 1,
+    /// A pointer was null or a string wasn't valid utf-8.
+    InvalidArgument = 2,
+    /// The backend failed to read, or the value contains a nul byte.
+    ReadFailed = 3,
+    /// The backend failed to write or delete.
+    WriteFailed = 4,
+    /// The library panicked, the store can still be used.
+    Panicked = 5,
+}
+
+fn into_handle(store: BoxedKvStorage) -> *mut EsStore {
+    Box::into_raw(Box::new(EsStore(store)))
+}
+
+// null for a null pointer or a string that isn't utf-8
+unsafe fn as_str<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        return None;
+    }
+    CStr::from_ptr(string).to_str().ok()
+}
+
+fn guarded(f: impl FnOnce() -> EsStatus) -> EsStatus {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(EsStatus::Panicked)
+}
+
+// null if creating the store panicked
+fn guarded_new(f: impl FnOnce() -> BoxedKvStorage) -> *mut EsStore {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_or(ptr::null_mut(), into_handle)
+}
+
+/// Creates a store keeping its entries in memory, they're lost when it is freed.
+#[no_mangle]
+pub extern "C" fn es_memory_store_new() -> *mut EsStore {
+    guarded_new(|| BoxedKvStorage::new(MemoryKvStorage::new()))
+}
+
+/// Creates a store keeping every key in a file in the directory `path`, or in the
+/// application's default directory if `path` is null. Returns null if `path` isn't valid
+/// utf-8, the default directory can't be found or the platform has no file backend.
+///
+/// # Safety
+///
+/// `path` must be null or point to a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn es_file_store_new(path: *const c_char) -> *mut EsStore {
+    #[cfg(all(
+        feature = "file",
+        any(target_family = "unix", target_family = "windows", target_os = "wasi")
+    ))]
+    {
+        use crate::kv_storage::file_based_kv_storage::FileBasedKvStorage;
+
+        let path = match path.is_null() {
+            true => None,
+            false => match as_str(path) {
+                Some(path) => Some(path),
+                None => return ptr::null_mut(),
+            },
+        };
+        // looking up the default directory panics without a home directory
+        guarded_new(|| match path {
+            Some(path) => BoxedKvStorage::new(FileBasedKvStorage::at(path)),
+            None => BoxedKvStorage::new(FileBasedKvStorage::default()),
+        })
+    }
+
+    #[cfg(not(all(
+        feature = "file",
+        any(target_family = "unix", target_family = "windows", target_os = "wasi")
+    )))]
+    {
+        let _ = path;
+        ptr::null_mut()
+    }
+}
+
+/// Frees a store, null is ignored.
+///
+/// # Safety
+///
+/// `store` must be null or a handle that hasn't been freed yet and isn't in use by another
+/// thread.
+#[no_mangle]
+pub unsafe extern "C" fn es_store_free(store: *mut EsStore) {
+    if !store.is_null() {
+        drop(Box::from_raw(store));
+    }
+}
+
+/// Reads the value of `key` into `*value`, which is set to null unless `ES_STATUS_OK` is
+/// returned.
+///
+/// # Safety
+///
+/// `store` must be a live handle, `key` a nul terminated string and `value` must point to
+/// writable memory for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn es_get(
+    store: *const EsStore,
+    key: *const c_char,
+    value: *mut *mut c_char,
+) -> EsStatus {
+    if value.is_null() {
+        return EsStatus::InvalidArgument;
+    }
+    *value = ptr::null_mut();
+    let (Some(store), Some(key)) = (store.as_ref(), as_str(key)) else {
+        return EsStatus::InvalidArgument;
+    };
+    guarded(|| match store.0.read(key) {
+        Ok(read) => match CString::new(read) {
+            Ok(read) => {
+                *value = read.into_raw();
+                EsStatus::Ok
+            }
+            Err(_) => EsStatus::ReadFailed,
+        },
+        Err(e) if e.is_not_found() => EsStatus::NotFound,
+        Err(e) => {
+            log::warn!("could not read '{key}': {e}");
+            EsStatus::ReadFailed
+        }
+    })
+}
+
+/// Sets the value of `key`.
+///
+/// # Safety
+///
+/// `store` must be a live handle, `key` and `value` nul terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn es_set(
+    store: *const EsStore,
+    key: *const c_char,
+    value: *const c_char,
+) -> EsStatus {
+    let (Some(store), Some(key), Some(value)) = (store.as_ref(), as_str(key), as_str(value)) else {
+        return EsStatus::InvalidArgument;
+    };
+    guarded(|| match store.0.write(key, value) {
+        Ok(()) => EsStatus::Ok,
+        Err(e) => {
+            log::warn!("could not write '{key}': {e}");
+            EsStatus::WriteFailed
+        }
+    })
+}
+
+/// Deletes `key`, deleting a key without a value succeeds.
+///
+/// # Safety
+///
+/// `store` must be a live handle and `key` a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn es_delete(store: *const EsStore, key: *const c_char) -> EsStatus {
+    let (Some(store), Some(key)) = (store.as_ref(), as_str(key)) else {
+        return EsStatus::InvalidArgument;
+    };
+    guarded(|| match store.0.delete(key) {
+        Ok(()) => EsStatus::Ok,
+        Err(e) => {
+            log::warn!("could not delete '{key}': {e}");
+            EsStatus::WriteFailed
+        }
+    })
+}
+
+/// Frees a string returned by the library, null is ignored.
+///
+/// # Safety
+///
+/// `string` must be null or a string returned by the library that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn es_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
@@ -0,0 +1,76 @@
+// javascript class wrapping a store, so scripts on the page read and write the same keys
+// through the same wrappers (encryption, key encoding, ...) as the rust side. export a
+// configured store from rust:
+//
+//     #[wasm_bindgen]
+//     pub fn settings() -> JsStore {
+//         JsStore::new(EncryptedKvStorage::new(WasmCookiesKvStorage::default(), &KEY))
+//     }
+//
+// and use it from javascript:
+//
+//     const store = settings();
+//     store.write("volume", "3");
+//     store.read("volume"); // "3", undefined for a missing key
+
+use crate::kv_storage::boxed_kv_storage::BoxedKvStorage;
+use crate::kv_storage::memory_kv_storage::MemoryKvStorage;
+use crate::kv_storage::wasm_cookies_kv_storage::WasmCookiesKvStorage;
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use std::error::Error;
+use std::fmt::Display;
+use wasm_bindgen::prelude::*;
+
+fn js_error(e: impl Display) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+#[wasm_bindgen(js_name = Store)]
+pub struct JsStore(BoxedKvStorage);
+
+impl JsStore {
+    pub fn new<S>(store: S) -> Self
+    where
+        S: KvStorage + Send + Sync + 'static,
+        S::ReadErrorType: Error + Send + Sync + 'static,
+        S::WriteErrorType: Error + Send + Sync + 'static,
+    {
+        JsStore(BoxedKvStorage::new(store))
+    }
+}
+
+#[wasm_bindgen(js_class = Store)]
+impl JsStore {
+    // the page's cookies, as `WasmCookiesKvStorage::default()` stores them
+    pub fn cookies() -> JsStore {
+        JsStore::new(WasmCookiesKvStorage::default())
+    }
+
+    // keeps everything in memory until the page is closed
+    pub fn memory() -> JsStore {
+        JsStore::new(MemoryKvStorage::new())
+    }
+
+    pub fn read(&self, key: &str) -> Result<Option<String>, JsError> {
+        self.0.read_opt(key).map_err(js_error)
+    }
+
+    pub fn write(&self, key: &str, value: &str) -> Result<(), JsError> {
+        self.0.write(key, value).map_err(js_error)
+    }
+
+    pub fn delete(&self, key: &str) -> Result<(), JsError> {
+        self.0.delete(key).map_err(js_error)
+    }
+
+    #[wasm_bindgen(js_name = deletePrefix)]
+    pub fn delete_prefix(&self, prefix: &str) -> Result<(), JsError> {
+        self.0.delete_prefix(prefix).map_err(js_error)
+    }
+
+    // every key in order
+    pub fn keys(&self) -> Result<Vec<String>, JsError> {
+        let entries = self.0.scan_all().map_err(js_error)?;
+        Ok(entries.into_iter().map(|(key, _)| key).collect())
+    }
+}
@@ -0,0 +1,209 @@
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Deny,
+    ReadOnly,
+    ReadWrite,
+}
+
+impl Access {
+    fn can_read(self) -> bool {
+        self != Access::Deny
+    }
+
+    fn can_write(self) -> bool {
+        self == Access::ReadWrite
+    }
+}
+
+// '*' matches any sequence of characters, including '/'
+pub(crate) fn matches(pattern: &str, key: &str) -> bool {
+    let (pattern, key) = (pattern.as_bytes(), key.as_bytes());
+    let (mut p, mut k) = (0, 0);
+    // position of the last '*' and the key position it was tried at
+    let mut backtrack = None;
+    while k < key.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, k));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == key[k] {
+            p += 1;
+            k += 1;
+        } else if let Some((star, tried)) = backtrack {
+            p = star + 1;
+            k = tried + 1;
+            backtrack = Some((star, tried + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+// restricts what can be done with keys matching glob patterns, e.g. to hand plugins a view of
+// the application's store where "plugins/<name>/*" is writable, "settings/*" read only and
+// everything else hidden. the first rule matching a key applies, keys no rule matches get the
+// default access. denied keys are left out of scans
+pub struct AccessControlledKvStorage<S> {
+    inner: S,
+    rules: Vec<(String, Access)>,
+    default: Access,
+}
+
+impl<S> AccessControlledKvStorage<S> {
+    pub fn new(inner: S, default: Access) -> Self {
+        AccessControlledKvStorage {
+            inner,
+            rules: Vec::new(),
+            default,
+        }
+    }
+
+    pub fn with_rule(mut self, pattern: &str, access: Access) -> Self {
+        self.rules.push((pattern.to_string(), access));
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    pub fn access(&self, key: &str) -> Access {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| matches(pattern, key))
+            .map_or(self.default, |(_, access)| *access)
+    }
+
+    fn check_write<R, W>(&self, key: &str) -> Result<(), AccessWriteError<R, W>> {
+        match self.access(key).can_write() {
+            true => Ok(()),
+            false => Err(AccessWriteError::Denied(key.to_string())),
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for AccessControlledKvStorage<S> {
+    type WriteErrorType = AccessWriteError<S::ReadErrorType, S::WriteErrorType>;
+    type ReadErrorType = AccessReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        if !self.access(key).can_read() {
+            return Err(AccessReadError::Denied(key.to_string()));
+        }
+        self.inner.read(key).map_err(AccessReadError::Inner)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.check_write(key)?;
+        self.inner
+            .write(key, value)
+            .map_err(AccessWriteError::Inner)
+    }
+
+    // denied keys are left out, so pages can be shorter than `limit`
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let mut page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(AccessReadError::Inner)?;
+        page.entries.retain(|(key, _)| self.access(key).can_read());
+        Ok(page)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.check_write(key)?;
+        self.inner.delete(key).map_err(AccessWriteError::Inner)
+    }
+
+    // only allowed if every key currently under the prefix is writable. the checked keys are
+    // deleted one by one, so keys added after the check are left alone
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        let keys: Vec<_> = self
+            .inner
+            .scan_all()
+            .map_err(AccessWriteError::Read)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .filter(|key| key.starts_with(prefix))
+            .collect();
+        for key in &keys {
+            self.check_write(key)?;
+        }
+        for key in &keys {
+            self.inner.delete(key).map_err(AccessWriteError::Inner)?;
+        }
+        Ok(())
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for AccessControlledKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.check_write(key)?;
+        self.inner
+            .write_with_ttl(key, value, ttl)
+            .map_err(AccessWriteError::Inner)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.check_write(key)?;
+        self.inner
+            .expire_at(key, at)
+            .map_err(AccessWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.check_write(key)?;
+        self.inner.touch(key).map_err(AccessWriteError::Inner)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AccessReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Access to key '{0}' is denied")]
+    Denied(String),
+}
+
+impl<E: IsNotFound> IsNotFound for AccessReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            AccessReadError::Inner(e) => e.is_not_found(),
+            AccessReadError::Denied(_) => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AccessWriteError<R, W> {
+    #[error("Could not read keys to delete")]
+    Read(#[source] R),
+
+    #[error("Writing key '{0}' is denied")]
+    Denied(String),
+
+    #[error(transparent)]
+    Inner(W),
+}
@@ -0,0 +1,172 @@
+use crate::kv_storage::{KvStorageExt, KvStorageTtl};
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use rand::distr::{Alphanumeric, SampleString};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::{Duration, SystemTime};
+
+type SessionState = HashMap<String, String>;
+
+// actix-session's `SessionStore` on top of a store with ttls:
+//
+//     let store = KvSessionStore::new(store).with_prefix("sessions/");
+//     App::new().wrap(SessionMiddleware::new(store, key))
+//
+// sessions are stored as json and expire with their ttl. the store is called right on the
+// request's task, backends that block for long should be wrapped in something that doesn't
+pub struct KvSessionStore<S> {
+    inner: S,
+    prefix: String,
+}
+
+impl<S> KvSessionStore<S> {
+    pub fn new(inner: S) -> Self {
+        KvSessionStore {
+            inner,
+            prefix: "session/".to_string(),
+        }
+    }
+
+    // prepended to every session key, "session/" by default
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn key(&self, session_key: &SessionKey) -> String {
+        format!("{}{}", self.prefix, session_key.as_ref())
+    }
+}
+
+fn other_error(e: impl Display) -> anyhow::Error {
+    anyhow::anyhow!("{e}")
+}
+
+// like actix-session's own stores
+fn generate_session_key() -> SessionKey {
+    Alphanumeric
+        .sample_string(&mut rand::rng(), 64)
+        .try_into()
+        .expect("64 characters fit a session key")
+}
+
+// negative ttls have already run out
+fn std_ttl(ttl: &time::Duration) -> Duration {
+    Duration::try_from(*ttl).unwrap_or_default()
+}
+
+impl<S> KvSessionStore<S>
+where
+    S: KvStorageTtl,
+    S::ReadErrorType: Display,
+    S::WriteErrorType: Display,
+{
+    fn write_state(
+        &self,
+        session_key: &SessionKey,
+        state: &SessionState,
+        ttl: &time::Duration,
+    ) -> Result<(), anyhow::Error> {
+        let key = self.key(session_key);
+        let ttl = std_ttl(ttl);
+        if ttl.is_zero() {
+            return self.inner.delete(&key).map_err(other_error);
+        }
+        let value = serde_json::to_string(state)?;
+        self.inner
+            .write_with_ttl(&key, &value, ttl)
+            .map_err(other_error)
+    }
+
+    fn exists(&self, session_key: &SessionKey) -> Result<bool, anyhow::Error> {
+        let value = self
+            .inner
+            .read_opt(self.key(session_key))
+            .map_err(other_error)?;
+        Ok(value.is_some())
+    }
+
+    // picks another key while the generated one is taken. this isn't atomic, two requests
+    // generating the same random key at the same time is left to the odds
+    fn save_state(
+        &self,
+        state: SessionState,
+        ttl: &time::Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let mut session_key = generate_session_key();
+        while self.exists(&session_key).map_err(SaveError::Other)? {
+            session_key = generate_session_key();
+        }
+        self.write_state(&session_key, &state, ttl)
+            .map_err(SaveError::Other)?;
+        Ok(session_key)
+    }
+}
+
+impl<S> SessionStore for KvSessionStore<S>
+where
+    S: KvStorageTtl,
+    S::ReadErrorType: Display,
+    S::WriteErrorType: Display,
+{
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let value = self
+            .inner
+            .read_opt(self.key(session_key))
+            .map_err(|e| LoadError::Other(other_error(e)))?;
+        value
+            .map(|value| serde_json::from_str(&value))
+            .transpose()
+            .map_err(|e| LoadError::Deserialization(e.into()))
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &time::Duration,
+    ) -> Result<SessionKey, SaveError> {
+        self.save_state(session_state, ttl)
+    }
+
+    // a session that expired in the meantime is saved under a new key
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &time::Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        if !self.exists(&session_key).map_err(UpdateError::Other)? {
+            return self.save_state(session_state, ttl).map_err(|e| match e {
+                SaveError::Serialization(e) => UpdateError::Serialization(e),
+                SaveError::Other(e) => UpdateError::Other(e),
+            });
+        }
+        self.write_state(&session_key, &session_state, ttl)
+            .map_err(UpdateError::Other)?;
+        Ok(session_key)
+    }
+
+    async fn update_ttl(
+        &self,
+        session_key: &SessionKey,
+        ttl: &time::Duration,
+    ) -> Result<(), anyhow::Error> {
+        self.inner
+            .expire_at(&self.key(session_key), SystemTime::now() + std_ttl(ttl))
+            .map_err(other_error)
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        self.inner
+            .delete(&self.key(session_key))
+            .map_err(other_error)
+    }
+}
@@ -0,0 +1,220 @@
+use crate::kv_storage::secret::{self, SecretString};
+use crate::kv_storage::{self, IsNotFound};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::io::{self, Read, Write};
+use std::str::Utf8Error;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// values are encrypted to every recipient; reading needs a matching identity, so a store
+// constructed without identities is write-only
+pub struct AgeKvStorage<S> {
+    inner: S,
+    recipients: Vec<Box<dyn age::Recipient + Send + Sync>>,
+    identities: Vec<Box<dyn age::Identity + Send + Sync>>,
+}
+
+impl<S> AgeKvStorage<S> {
+    pub fn new(inner: S, recipient: impl age::Recipient + Send + Sync + 'static) -> Self {
+        AgeKvStorage {
+            inner,
+            recipients: vec![Box::new(recipient)],
+            identities: Vec::new(),
+        }
+    }
+
+    pub fn with_recipient(
+        mut self,
+        recipient: impl age::Recipient + Send + Sync + 'static,
+    ) -> Self {
+        self.recipients.push(Box::new(recipient));
+        self
+    }
+
+    pub fn with_identity(mut self, identity: impl age::Identity + Send + Sync + 'static) -> Self {
+        self.identities.push(Box::new(identity));
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn encrypt<E>(&self, value: &str) -> Result<String, AgeWriteError<E>> {
+        let recipients = self
+            .recipients
+            .iter()
+            .map(|recipient| recipient.as_ref() as &dyn age::Recipient);
+        let encryptor = age::Encryptor::with_recipients(recipients)?;
+
+        let mut sealed = Vec::new();
+        let mut writer = encryptor.wrap_output(&mut sealed)?;
+        writer.write_all(value.as_bytes())?;
+        writer.finish()?;
+        Ok(BASE64.encode(sealed))
+    }
+
+    fn decrypt<E>(&self, value: &str) -> Result<String, AgeReadError<E>> {
+        if self.identities.is_empty() {
+            return Err(AgeReadError::NoIdentity);
+        }
+        let sealed = BASE64.decode(value)?;
+        let identities = self
+            .identities
+            .iter()
+            .map(|identity| identity.as_ref() as &dyn age::Identity);
+        let mut reader = age::Decryptor::new_buffered(&sealed[..])?.decrypt(identities)?;
+
+        // the plaintext is shorter than what it's sealed in, so the buffer isn't reallocated,
+        // which would leave copies behind
+        let mut plaintext = Vec::with_capacity(sealed.len());
+        if let Err(e) = reader.read_to_end(&mut plaintext) {
+            secret::wipe(plaintext);
+            return Err(e.into());
+        }
+        Ok(secret::into_string(plaintext)?)
+    }
+}
+
+impl<S: kv_storage::KvStorage> AgeKvStorage<S> {
+    // `read` with the value wiped from memory once it's dropped
+    pub fn read_secret(&self, key: &str) -> Result<SecretString, AgeReadError<S::ReadErrorType>> {
+        let sealed = self.inner.read(key).map_err(AgeReadError::Inner)?;
+        self.decrypt(&sealed).map(SecretString::new)
+    }
+
+    pub fn write_secret(
+        &self,
+        key: &str,
+        value: &SecretString,
+    ) -> Result<(), AgeWriteError<S::WriteErrorType>> {
+        kv_storage::KvStorage::write(self, key, value.expose())
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for AgeKvStorage<S> {
+    type WriteErrorType = AgeWriteError<S::WriteErrorType>;
+    type ReadErrorType = AgeReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let sealed = self.inner.read(key).map_err(AgeReadError::Inner)?;
+        self.decrypt(&sealed)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        let sealed = self.encrypt(value)?;
+        self.inner.write(key, &sealed).map_err(AgeWriteError::Inner)
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(AgeReadError::Inner)?;
+        let entries = page
+            .entries
+            .into_iter()
+            .map(|(key, sealed)| {
+                let value = self.decrypt(&sealed)?;
+                Ok((key, value))
+            })
+            .collect::<Result<_, Self::ReadErrorType>>()?;
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete(key).map_err(AgeWriteError::Inner)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .delete_prefix(prefix)
+            .map_err(AgeWriteError::Inner)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for AgeKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge().map_err(AgeWriteError::Inner)
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for AgeKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        let sealed = self.encrypt(value)?;
+        self.inner
+            .write_with_ttl(key, &sealed, ttl)
+            .map_err(AgeWriteError::Inner)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner.expire_at(key, at).map_err(AgeWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key).map_err(AgeWriteError::Inner)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AgeReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Store has no identity to decrypt values with")]
+    NoIdentity,
+
+    #[error("Stored value is not valid base64")]
+    Decode(#[from] base64::DecodeError),
+
+    #[error("Could not decrypt value")]
+    Decrypt(#[from] age::DecryptError),
+
+    #[error("Error reading decrypted value")]
+    Io(#[from] io::Error),
+
+    #[error("Decrypted value is not valid utf-8")]
+    Utf8(#[from] Utf8Error),
+}
+
+impl<E: IsNotFound> IsNotFound for AgeReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            AgeReadError::Inner(e) => e.is_not_found(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AgeWriteError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Could not encrypt value")]
+    Encrypt(#[from] age::EncryptError),
+
+    #[error("Error writing encrypted value")]
+    Io(#[from] io::Error),
+}
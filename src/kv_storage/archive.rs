@@ -0,0 +1,85 @@
+use crate::kv_storage::{self, KvStorageExt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use thiserror::Error;
+
+pub const FORMAT: &str = "easy_storage";
+pub const VERSION: u32 = 1;
+
+// {"format": "easy_storage", "version": 1, "entries": {"<key>": "<value>", ...}}
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    format: String,
+    version: u32,
+    entries: BTreeMap<String, String>,
+}
+
+// writes every entry of `store` as a json archive, returns the number of entries
+pub fn export<S, W>(store: &S, writer: W) -> Result<usize, ExportError<S::ReadErrorType>>
+where
+    S: kv_storage::KvStorage + ?Sized,
+    W: Write,
+{
+    let entries: BTreeMap<_, _> = store
+        .scan_all()
+        .map_err(ExportError::Read)?
+        .into_iter()
+        .collect();
+    let count = entries.len();
+    let archive = Archive {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        entries,
+    };
+    serde_json::to_writer(writer, &archive)?;
+    Ok(count)
+}
+
+// writes every entry of the archive into `store`, replacing existing values. the archive is
+// parsed completely before the first write, so a malformed one leaves the store untouched
+pub fn import<S, R>(store: &S, reader: R) -> Result<usize, ImportError<S::WriteErrorType>>
+where
+    S: kv_storage::KvStorage + ?Sized,
+    R: Read,
+{
+    let archive: Archive = serde_json::from_reader(reader)?;
+    if archive.format != FORMAT {
+        return Err(ImportError::UnknownFormat(archive.format));
+    }
+    if archive.version > VERSION {
+        return Err(ImportError::UnsupportedVersion(archive.version));
+    }
+
+    let count = archive.entries.len();
+    for (key, value) in archive.entries {
+        store
+            .write(&key, &value)
+            .map_err(|e| ImportError::Write(key, e))?;
+    }
+    Ok(count)
+}
+
+#[derive(Error, Debug)]
+pub enum ExportError<E> {
+    #[error("Could not read from storage")]
+    Read(#[source] E),
+
+    #[error("Could not write archive")]
+    Serialize(#[from] serde_json::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum ImportError<E> {
+    #[error("Archive is not valid")]
+    Parse(#[from] serde_json::Error),
+
+    #[error("Archive has unknown format '{0}'")]
+    UnknownFormat(String),
+
+    #[error("Archive version {0} is newer than supported")]
+    UnsupportedVersion(u32),
+
+    #[error("Could not write key '{0}'")]
+    Write(String, #[source] E),
+}
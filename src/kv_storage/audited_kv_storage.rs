@@ -0,0 +1,276 @@
+use crate::kv_storage::{self, KvStorageExt};
+use crate::time;
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::fmt;
+use std::io;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+// hex encoded sha-256 of a value, so the audit log proves what changed without containing it
+pub fn hash(value: &str) -> String {
+    Sha256::digest(value.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub key: String,
+    // `None` if the key didn't exist before
+    pub old_hash: Option<String>,
+    // `None` if the key was deleted
+    pub new_hash: Option<String>,
+    pub timestamp: SystemTime,
+    pub actor: Option<String>,
+}
+
+// one line per record: "<unix millis> <actor or -> <quoted key> <old hash or -> <new hash or ->"
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        write!(
+            f,
+            "{millis} {} {:?} {} {}",
+            self.actor.as_deref().unwrap_or("-"),
+            self.key,
+            self.old_hash.as_deref().unwrap_or("-"),
+            self.new_hash.as_deref().unwrap_or("-"),
+        )
+    }
+}
+
+pub trait AuditSink {
+    type Error;
+
+    fn append(&self, record: &AuditRecord) -> Result<(), Self::Error>;
+}
+
+impl<F: Fn(&AuditRecord)> AuditSink for F {
+    type Error = Infallible;
+
+    fn append(&self, record: &AuditRecord) -> Result<(), Self::Error> {
+        self(record);
+        Ok(())
+    }
+}
+
+// appends every record as a line to e.g. a file opened in append mode, flushing after each
+pub struct WriterAuditSink<W>(Mutex<W>);
+
+impl<W: io::Write> WriterAuditSink<W> {
+    pub fn new(writer: W) -> Self {
+        WriterAuditSink(Mutex::new(writer))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<W: io::Write> AuditSink for WriterAuditSink<W> {
+    type Error = io::Error;
+
+    fn append(&self, record: &AuditRecord) -> Result<(), Self::Error> {
+        let mut writer = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        writeln!(writer, "{record}")?;
+        writer.flush()
+    }
+}
+
+// appends a record to the sink after every successful write or delete. the old value is read
+// first, so each mutation costs an extra read. if the sink fails the mutation already happened
+// and the error says so. changing only the expiry of an entry is not recorded
+pub struct AuditedKvStorage<S, A> {
+    inner: S,
+    sink: A,
+    actor: Mutex<Option<String>>,
+}
+
+impl<S, A> AuditedKvStorage<S, A> {
+    pub fn new(inner: S, sink: A) -> Self {
+        AuditedKvStorage {
+            inner,
+            sink,
+            actor: Mutex::new(None),
+        }
+    }
+
+    pub fn with_actor(self, actor: &str) -> Self {
+        self.set_actor(Some(actor));
+        self
+    }
+
+    // e.g. the logged in user, recorded with every following mutation
+    pub fn set_actor(&self, actor: Option<&str>) {
+        *self.actor.lock().unwrap_or_else(PoisonError::into_inner) = actor.map(str::to_string);
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn sink(&self) -> &A {
+        &self.sink
+    }
+
+    pub fn into_inner(self) -> (S, A) {
+        (self.inner, self.sink)
+    }
+}
+
+type AuditedWriteError<S, A> = AuditWriteError<
+    <S as kv_storage::KvStorage>::ReadErrorType,
+    <S as kv_storage::KvStorage>::WriteErrorType,
+    <A as AuditSink>::Error,
+>;
+
+impl<S: kv_storage::KvStorage, A: AuditSink> AuditedKvStorage<S, A> {
+    fn audited(
+        &self,
+        key: &str,
+        new: Option<&str>,
+        mutate: impl FnOnce() -> Result<(), S::WriteErrorType>,
+    ) -> Result<(), AuditedWriteError<S, A>> {
+        let old = self.inner.read_opt(key).map_err(AuditWriteError::Read)?;
+        mutate().map_err(AuditWriteError::Inner)?;
+        self.record(key, old.as_deref(), new)
+    }
+
+    fn record(
+        &self,
+        key: &str,
+        old: Option<&str>,
+        new: Option<&str>,
+    ) -> Result<(), AuditedWriteError<S, A>> {
+        let record = AuditRecord {
+            key: key.to_string(),
+            old_hash: old.map(hash),
+            new_hash: new.map(hash),
+            timestamp: time::now(),
+            actor: self
+                .actor
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        };
+        self.sink.append(&record).map_err(AuditWriteError::Sink)
+    }
+}
+
+impl<S: kv_storage::KvStorage, A: AuditSink> kv_storage::KvStorage for AuditedKvStorage<S, A> {
+    type WriteErrorType = AuditedWriteError<S, A>;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.inner.read(key)
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.inner.read_with(key, f)
+    }
+
+    fn read_into(&self, key: &str, buf: &mut String) -> Result<usize, Self::ReadErrorType> {
+        self.inner.read_into(key, buf)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.audited(key, Some(value), || self.inner.write(key, value))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.inner.scan(cursor, limit)
+    }
+
+    // deleting a missing key is not recorded
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        let Some(old) = self.inner.read_opt(key).map_err(AuditWriteError::Read)? else {
+            return self.inner.delete(key).map_err(AuditWriteError::Inner);
+        };
+        self.inner.delete(key).map_err(AuditWriteError::Inner)?;
+        self.record(key, Some(&old), None)
+    }
+
+    // records one deletion per removed key
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        let removed: Vec<_> = self
+            .inner
+            .scan_all()
+            .map_err(AuditWriteError::Read)?
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .collect();
+        self.inner
+            .delete_prefix(prefix)
+            .map_err(AuditWriteError::Inner)?;
+        for (key, old) in removed {
+            self.record(&key, Some(&old), None)?;
+        }
+        Ok(())
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+// records one deletion per key that was stored
+impl<S: kv_storage::KvStoragePurge, A: AuditSink> kv_storage::KvStoragePurge
+    for AuditedKvStorage<S, A>
+{
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        let removed = self.inner.scan_all().map_err(AuditWriteError::Read)?;
+        self.inner.purge().map_err(AuditWriteError::Inner)?;
+        for (key, old) in removed {
+            self.record(&key, Some(&old), None)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl, A: AuditSink> kv_storage::KvStorageTtl
+    for AuditedKvStorage<S, A>
+{
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.audited(key, Some(value), || {
+            self.inner.write_with_ttl(key, value, ttl)
+        })
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .expire_at(key, at)
+            .map_err(AuditWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key).map_err(AuditWriteError::Inner)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AuditWriteError<R, W, A> {
+    #[error("Could not read previous value")]
+    Read(#[source] R),
+
+    #[error(transparent)]
+    Inner(W),
+
+    #[error("Change was made but could not be recorded in the audit log")]
+    Sink(#[source] A),
+}
@@ -0,0 +1,129 @@
+use crate::kv_storage::{self, IsNotFound};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::string::FromUtf8Error;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// stores every value base64 encoded, so string only backends like cookies can hold bytes.
+// string values go through the same encoding, which keeps `read` and `read_bytes` of one key
+// consistent
+pub struct Base64KvStorage<S>(S);
+
+impl<S> Base64KvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        Base64KvStorage(inner)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+
+    fn decode<E>(stored: &str) -> Result<String, Base64ReadError<E>> {
+        Ok(String::from_utf8(BASE64.decode(stored)?)?)
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for Base64KvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = Base64ReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let stored = self.0.read(key).map_err(Base64ReadError::Inner)?;
+        Self::decode(&stored)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.write(key, &BASE64.encode(value))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self.0.scan(cursor, limit).map_err(Base64ReadError::Inner)?;
+        let entries = page
+            .entries
+            .into_iter()
+            .map(|(key, stored)| Ok((key, Self::decode(&stored)?)))
+            .collect::<Result<_, Self::ReadErrorType>>()?;
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.delete(key)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.delete_prefix(prefix)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.0.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorageBytes for Base64KvStorage<S> {
+    fn read_bytes(&self, key: &str) -> Result<Vec<u8>, Self::ReadErrorType> {
+        let stored = self.0.read(key).map_err(Base64ReadError::Inner)?;
+        Ok(BASE64.decode(stored)?)
+    }
+
+    fn write_bytes(&self, key: &str, value: &[u8]) -> Result<(), Self::WriteErrorType> {
+        self.0.write(key, &BASE64.encode(value))
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for Base64KvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.0.purge()
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for Base64KvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.0.write_with_ttl(key, &BASE64.encode(value), ttl)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.0.expire_at(key, at)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.touch(key)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum Base64ReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Stored value is not valid base64")]
+    Decode(#[from] base64::DecodeError),
+
+    #[error("Stored value is not valid utf-8")]
+    Utf8(#[from] FromUtf8Error),
+}
+
+impl<E: IsNotFound> IsNotFound for Base64ReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            Base64ReadError::Inner(e) => e.is_not_found(),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use criterion::{BenchmarkId, Criterion, Throughput};
+use std::cell::Cell;
+use std::fmt::Debug;
+use std::hint::black_box;
+
+// the workloads work on KEYS keys under "bench/" with VALUE_SIZE byte values, the store is
+// filled before measuring. the large value workload uses fewer keys to bound the memory used
+const KEYS: usize = 256;
+const VALUE_SIZE: usize = 128;
+const LARGE_KEYS: usize = 8;
+const LARGE_VALUE_SIZE: usize = 1 << 20;
+
+fn key(index: usize) -> String {
+    format!("bench/{index:04}")
+}
+
+fn value(size: usize) -> String {
+    "x".repeat(size)
+}
+
+struct Workload<'a, S> {
+    store: &'a S,
+    keys: usize,
+    value: String,
+    next: Cell<usize>,
+}
+
+impl<S: KvStorage> Workload<'_, S>
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    fn next_key(&self) -> String {
+        let next = self.next.get();
+        self.next.set(next.wrapping_add(7));
+        key(next % self.keys)
+    }
+
+    fn step(&self, step: char) {
+        match step {
+            'r' => self.read(),
+            'w' => self.write(),
+            'd' => self.delete(),
+            's' => self.scan(),
+            _ => unreachable!("unknown workload step '{step}'"),
+        }
+    }
+
+    fn read(&self) {
+        let key = self.next_key();
+        black_box(self.store.read(&key).expect("read failed"));
+    }
+
+    fn write(&self) {
+        let key = self.next_key();
+        self.store.write(&key, &self.value).expect("write failed");
+    }
+
+    fn delete(&self) {
+        let key = self.next_key();
+        self.store.delete(&key).expect("delete failed");
+        self.store.write(&key, &self.value).expect("write failed");
+    }
+
+    fn scan(&self) {
+        let cursor = self.next_key();
+        black_box(self.store.scan(Some(&cursor), 16).expect("scan failed"));
+    }
+}
+
+// runs the standard workloads against stores from `new_store` and reports them under
+// `backend`, so the numbers of several backends end up side by side in each workload's group:
+//
+//     fn backends(c: &mut Criterion) {
+//         bench::bench_backend(c, "memory", MemoryKvStorage::new);
+//         bench::bench_backend(c, "mine", MyBackend::default);
+//     }
+pub fn bench_backend<S, F>(c: &mut Criterion, backend: &str, new_store: F)
+where
+    S: KvStorage,
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+    F: Fn() -> S,
+{
+    // r read, w write, d delete and rewrite, s scan a page
+    let workloads = [
+        ("write_heavy", "wwwwwwwwwr"),
+        ("read_heavy", "rrrrrrrrrw"),
+        ("mixed", "rwrwsrwdrw"),
+    ];
+    for (name, steps) in workloads {
+        let store = new_store();
+        let workload = fill(&store, KEYS, VALUE_SIZE);
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Elements(steps.len() as u64));
+        group.bench_function(BenchmarkId::from_parameter(backend), |b| {
+            b.iter(|| steps.chars().for_each(|step| workload.step(step)))
+        });
+        group.finish();
+    }
+
+    let store = new_store();
+    let workload = fill(&store, LARGE_KEYS, LARGE_VALUE_SIZE);
+    let mut group = c.benchmark_group("large_values");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(2 * LARGE_VALUE_SIZE as u64));
+    group.bench_function(BenchmarkId::from_parameter(backend), |b| {
+        b.iter(|| {
+            workload.write();
+            workload.read();
+        })
+    });
+    group.finish();
+}
+
+fn fill<S: KvStorage>(store: &S, keys: usize, size: usize) -> Workload<'_, S>
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    store.delete_prefix("bench/").expect("clearing failed");
+    let value = value(size);
+    for index in 0..keys {
+        store.write(&key(index), &value).expect("filling failed");
+    }
+    // a store that drops writes would make reads look fast
+    assert_eq!(
+        store.read_opt(key(0)).expect("read failed").as_deref(),
+        Some(value.as_str())
+    );
+    Workload {
+        store,
+        keys,
+        value,
+        next: Cell::new(0),
+    }
+}
@@ -0,0 +1,171 @@
+use crate::kv_storage::boxed_kv_storage::{BoxedError, BoxedKvStorage};
+use crate::kv_storage::codec::{CodecReadError, CodecWriteError, JsonCodec, KvStorageCodecExt};
+use crate::kv_storage::KvStorage;
+use crate::time::Instant;
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+
+// persists resources through any backend:
+//
+//     App::new()
+//         .add_plugins(KvStoragePlugin::new(store))
+//         .persist_resource::<Settings>("settings", SaveMode::OnChange)
+//         .persist_resource::<Progress>("progress", SaveMode::Every(Duration::from_secs(30)))
+//
+// resources are stored as json
+pub struct KvStoragePlugin {
+    store: Arc<BoxedKvStorage>,
+}
+
+impl KvStoragePlugin {
+    pub fn new<S>(store: S) -> Self
+    where
+        S: KvStorage + Send + Sync + 'static,
+        S::ReadErrorType: Error + Send + Sync + 'static,
+        S::WriteErrorType: Error + Send + Sync + 'static,
+    {
+        KvStoragePlugin {
+            store: Arc::new(BoxedKvStorage::new(store)),
+        }
+    }
+}
+
+impl Plugin for KvStoragePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PersistentStore(self.store.clone()));
+    }
+}
+
+// the plugin's store, e.g. for save slots the game loads and saves itself
+#[derive(Resource, Clone)]
+pub struct PersistentStore(Arc<BoxedKvStorage>);
+
+impl PersistentStore {
+    pub fn save<T: Serialize + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+    ) -> Result<(), CodecWriteError<BoxedError, serde_json::Error>> {
+        self.0.write_encoded(&JsonCodec::default(), key, value)
+    }
+
+    pub fn load<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Result<Option<T>, CodecReadError<BoxedError, serde_json::Error>> {
+        self.0.read_decoded_opt(&JsonCodec::default(), key)
+    }
+
+    pub fn delete(&self, key: &str) -> Result<(), BoxedError> {
+        self.0.delete(key)
+    }
+
+    pub fn store(&self) -> &BoxedKvStorage {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveMode {
+    // at the end of every frame the resource changed in
+    OnChange,
+    // at most once per interval, and when the app exits
+    Every(Duration),
+    // when the app exits
+    OnExit,
+}
+
+pub trait PersistResourceExt {
+    // loads the resource from `key` right away, falling back to its `FromWorld` value, and
+    // saves it whenever `mode` says it's due. `KvStoragePlugin` has to be added first
+    fn persist_resource<R>(&mut self, key: &str, mode: SaveMode) -> &mut Self
+    where
+        R: Resource + FromWorld + Serialize + DeserializeOwned;
+}
+
+impl PersistResourceExt for App {
+    fn persist_resource<R>(&mut self, key: &str, mode: SaveMode) -> &mut Self
+    where
+        R: Resource + FromWorld + Serialize + DeserializeOwned,
+    {
+        let store = self
+            .world()
+            .get_resource::<PersistentStore>()
+            .expect("KvStoragePlugin has to be added before persisting resources")
+            .clone();
+        match store.load::<R>(key) {
+            Ok(Some(resource)) => {
+                self.insert_resource(resource);
+            }
+            Ok(None) => {
+                self.init_resource::<R>();
+            }
+            Err(e) => {
+                log::warn!("could not load '{key}', using the default: {e}");
+                self.init_resource::<R>();
+            }
+        }
+        self.insert_resource(Persisted::<R> {
+            key: key.to_string(),
+            mode,
+            resource: PhantomData,
+        });
+        self.add_systems(Last, save_resource::<R>)
+    }
+}
+
+#[derive(Resource)]
+struct Persisted<R> {
+    key: String,
+    mode: SaveMode,
+    resource: PhantomData<fn() -> R>,
+}
+
+#[derive(Default)]
+struct SaveState {
+    // the first run sees the resource as changed, it was just loaded
+    started: bool,
+    dirty: bool,
+    saved_at: Option<Instant>,
+}
+
+fn save_resource<R: Resource + Serialize>(
+    store: Res<PersistentStore>,
+    persisted: Res<Persisted<R>>,
+    resource: Option<Res<R>>,
+    mut exits: MessageReader<AppExit>,
+    mut state: Local<SaveState>,
+) {
+    let exiting = exits.read().count() > 0;
+    let Some(resource) = resource else {
+        return;
+    };
+    if state.started && resource.is_changed() {
+        state.dirty = true;
+    }
+    state.started = true;
+
+    let due = match persisted.mode {
+        SaveMode::OnChange => true,
+        SaveMode::Every(interval) => {
+            exiting || state.saved_at.is_none_or(|at| at.elapsed() >= interval)
+        }
+        SaveMode::OnExit => exiting,
+    };
+    if !state.dirty || !due {
+        return;
+    }
+    match store.save(&persisted.key, &*resource) {
+        Ok(()) => {
+            state.dirty = false;
+            state.saved_at = Some(Instant::now());
+        }
+        // stays dirty, the next due save retries
+        Err(e) => log::warn!("could not save '{}': {e}", persisted.key),
+    }
+}
@@ -0,0 +1,156 @@
+use crate::kv_storage::{self, IsNotFound};
+use std::error::Error;
+use std::fmt;
+
+type DynKvStorage = dyn kv_storage::KvStorage<ReadErrorType = BoxedError, WriteErrorType = BoxedError>
+    + Send
+    + Sync;
+
+// type erased backend, lets stores with different error types live in one collection
+pub struct BoxedKvStorage(Box<DynKvStorage>);
+
+impl BoxedKvStorage {
+    pub fn new<S>(inner: S) -> Self
+    where
+        S: kv_storage::KvStorage + Send + Sync + 'static,
+        S::ReadErrorType: Error + Send + Sync + 'static,
+        S::WriteErrorType: Error + Send + Sync + 'static,
+    {
+        BoxedKvStorage(Box::new(ErasedKvStorage(inner)))
+    }
+}
+
+impl kv_storage::KvStorage for BoxedKvStorage {
+    type WriteErrorType = BoxedError;
+    type ReadErrorType = BoxedError;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.0.read(key)
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.0.read_with(key, f)
+    }
+
+    fn read_into(&self, key: &str, buf: &mut String) -> Result<usize, Self::ReadErrorType> {
+        self.0.read_into(key, buf)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.write(key, value)
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.0.scan(cursor, limit)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.delete(key)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.delete_prefix(prefix)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.0.health_check()
+    }
+}
+
+struct ErasedKvStorage<S>(S);
+
+impl<S> kv_storage::KvStorage for ErasedKvStorage<S>
+where
+    S: kv_storage::KvStorage,
+    S::ReadErrorType: Error + Send + Sync + 'static,
+    S::WriteErrorType: Error + Send + Sync + 'static,
+{
+    type WriteErrorType = BoxedError;
+    type ReadErrorType = BoxedError;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.0.read(key).map_err(BoxedError::from_read)
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.0.read_with(key, f).map_err(BoxedError::from_read)
+    }
+
+    fn read_into(&self, key: &str, buf: &mut String) -> Result<usize, Self::ReadErrorType> {
+        self.0.read_into(key, buf).map_err(BoxedError::from_read)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.write(key, value).map_err(BoxedError::new)
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.0.scan(cursor, limit).map_err(BoxedError::from_read)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.delete(key).map_err(BoxedError::new)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.delete_prefix(prefix).map_err(BoxedError::new)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.0.health_check()
+    }
+}
+
+// keeps the not found classification of the erased error
+#[derive(Debug)]
+pub struct BoxedError {
+    source: Box<dyn Error + Send + Sync>,
+    not_found: bool,
+}
+
+impl BoxedError {
+    pub fn new(source: impl Error + Send + Sync + 'static) -> Self {
+        BoxedError {
+            source: Box::new(source),
+            not_found: false,
+        }
+    }
+
+    fn from_read<E: Error + IsNotFound + Send + Sync + 'static>(source: E) -> Self {
+        let not_found = source.is_not_found();
+        BoxedError {
+            source: Box::new(source),
+            not_found,
+        }
+    }
+
+    pub fn into_inner(self) -> Box<dyn Error + Send + Sync> {
+        self.source
+    }
+}
+
+impl fmt::Display for BoxedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl Error for BoxedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.source()
+    }
+}
+
+impl IsNotFound for BoxedError {
+    fn is_not_found(&self) -> bool {
+        self.not_found
+    }
+}
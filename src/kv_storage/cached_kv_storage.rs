@@ -0,0 +1,607 @@
+use crate::kv_storage::{self, IsNotFound};
+use crate::time::Instant;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+#[cfg(not(target_family = "wasm"))]
+use std::sync::Arc;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+#[cfg(not(target_family = "wasm"))]
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+struct CacheEntry {
+    value: String,
+    cached_at: Instant,
+    last_used: u64,
+    // written to the cache but not yet to the backend
+    dirty: bool,
+}
+
+// answers "definitely not in the store" or "maybe", deleted keys stay in as false positives
+struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+    capacity: usize,
+    len: usize,
+}
+
+impl BloomFilter {
+    fn new(capacity: usize, false_positive_rate: f64) -> Self {
+        let capacity = capacity.max(1);
+        let rate = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let bits = (-(capacity as f64) * rate.ln() / (2f64.ln() * 2f64.ln())).ceil() as usize;
+        let words = bits.div_ceil(64).max(1);
+        let hashes = ((words * 64) as f64 / capacity as f64 * 2f64.ln()).round() as u32;
+        BloomFilter {
+            bits: vec![0; words],
+            hashes: hashes.clamp(1, 16),
+            capacity,
+            len: 0,
+        }
+    }
+
+    // double hashing, the positions are `h1 + i * h2`
+    fn positions(&self, key: &str) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        let (h1, h2) = (hash, hash.rotate_left(32) | 1);
+        let bits = self.bits.len() as u64 * 64;
+        (0..u64::from(self.hashes))
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+
+    fn insert(&mut self, key: &str) {
+        let positions: Vec<_> = self.positions(key).collect();
+        for position in positions {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+        self.len += 1;
+    }
+
+    fn may_contain(&self, key: &str) -> bool {
+        self.positions(key)
+            .all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+
+    // past its capacity the false positive rate climbs, it gets rebuilt from the keys instead
+    fn is_full(&self) -> bool {
+        self.len > self.capacity
+    }
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<String, CacheEntry>,
+    clock: u64,
+    dirty_since: Option<Instant>,
+    // `None` until the first read that could use it builds it
+    bloom: Option<BloomFilter>,
+    // the backend's generation the cached values were read at, and when it was last checked
+    generation: Option<u64>,
+    generation_checked_at: Option<Instant>,
+}
+
+impl Cache {
+    fn get(&mut self, key: &str, ttl: Option<Duration>) -> Option<String> {
+        let expired = {
+            let entry = self.entries.get(key)?;
+            !entry.dirty && ttl.is_some_and(|ttl| entry.cached_at.elapsed() >= ttl)
+        };
+        if expired {
+            self.entries.remove(key);
+            return None;
+        }
+
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.clock;
+        Some(entry.value.clone())
+    }
+
+    fn insert(&mut self, key: &str, value: &str, capacity: usize, dirty: bool) {
+        if capacity == 0 && !dirty {
+            return;
+        }
+        if !self.entries.contains_key(key) && self.entries.len() >= capacity {
+            self.evict_least_recently_used();
+        }
+        if dirty && self.dirty_since.is_none() {
+            self.dirty_since = Some(Instant::now());
+        }
+
+        self.clock += 1;
+        self.entries.insert(
+            key.to_string(),
+            CacheEntry {
+                value: value.to_string(),
+                cached_at: Instant::now(),
+                last_used: self.clock,
+                dirty,
+            },
+        );
+    }
+
+    // dirty entries are never evicted, the cache grows past capacity until they're flushed
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| !entry.dirty)
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            self.entries.remove(&key);
+        }
+    }
+
+    fn note_key(&mut self, key: &str) {
+        let full = self.bloom.as_mut().is_some_and(|bloom| {
+            bloom.insert(key);
+            bloom.is_full()
+        });
+        if full {
+            self.bloom = None;
+        }
+    }
+
+    fn dirty_count(&self) -> usize {
+        self.entries.values().filter(|entry| entry.dirty).count()
+    }
+
+    fn flush<S: kv_storage::KvStorage>(&mut self, inner: &S) -> Result<(), S::WriteErrorType> {
+        let mut dirty: Vec<_> = self
+            .entries
+            .iter_mut()
+            .filter(|(_, entry)| entry.dirty)
+            .collect();
+        dirty.sort_unstable_by_key(|(_, entry)| entry.last_used);
+
+        for (key, entry) in dirty {
+            inner.write(key, &entry.value)?;
+            entry.dirty = false;
+        }
+        self.dirty_since = None;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WriteBehind {
+    // flush once this many writes are pending
+    pub max_pending: usize,
+    // flush on the next operation once the oldest pending write is this old
+    pub max_delay: Duration,
+}
+
+impl Default for WriteBehind {
+    fn default() -> Self {
+        WriteBehind {
+            max_pending: 64,
+            max_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+// write-through cache in front of any backend; values written behind the cache's back stay
+// stale until they're evicted, expire or get invalidated
+//
+// in write-behind mode writes only land in the cache and are flushed in batches. there is no
+// background thread (wasm has none), pending writes go out on `flush`, on drop, or during a
+// later operation once `WriteBehind::max_pending` or `WriteBehind::max_delay` is exceeded
+//
+// with a bloom filter, reads of keys the store definitely doesn't have are answered without
+// asking the backend, e.g. for `exists` checks against a slow one. it's built from a scan of the
+// keys on the first read that misses the cache, so keys written behind the cache's back read as
+// missing until `clear` drops it
+//
+// over a store other processes change too, see `with_generation_check`
+pub struct CachedKvStorage<S: kv_storage::KvStorage> {
+    // only `None` after `into_inner` moved it out
+    inner: Option<S>,
+    capacity: usize,
+    ttl: Option<Duration>,
+    write_behind: Option<WriteBehind>,
+    // expected number of keys and false positive rate
+    bloom_filter: Option<(usize, f64)>,
+    // how often to check the backend's generation, and how
+    generation_check: Option<Duration>,
+    generation: fn(&S) -> Option<u64>,
+    cache: Mutex<Cache>,
+}
+
+impl<S: kv_storage::KvStorage> CachedKvStorage<S> {
+    pub fn new(inner: S, capacity: usize) -> Self {
+        CachedKvStorage {
+            inner: Some(inner),
+            capacity,
+            ttl: None,
+            write_behind: None,
+            bloom_filter: None,
+            generation_check: None,
+            generation: |_| None,
+            cache: Mutex::default(),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_write_behind(mut self, write_behind: WriteBehind) -> Self {
+        self.write_behind = Some(write_behind);
+        self
+    }
+
+    // grows past `expected_keys` by rebuilding, with room for twice the keys the store has
+    pub fn with_bloom_filter(mut self, expected_keys: usize, false_positive_rate: f64) -> Self {
+        self.bloom_filter = Some((expected_keys, false_positive_rate));
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        self.inner
+            .as_ref()
+            .expect("inner storage is only taken on into_inner")
+    }
+
+    // flushes pending writes first, returning the error if that fails
+    pub fn into_inner(mut self) -> Result<S, S::WriteErrorType> {
+        self.flush()?;
+        Ok(self
+            .inner
+            .take()
+            .expect("inner storage is only taken on into_inner"))
+    }
+
+    pub fn pending_writes(&self) -> usize {
+        self.cache().dirty_count()
+    }
+
+    pub fn flush(&self) -> Result<(), S::WriteErrorType> {
+        if self.pending_writes() == 0 {
+            return Ok(());
+        }
+        let before = self.generation_before_write();
+        self.cache().flush(self.inner())?;
+        self.note_own_write(before);
+        Ok(())
+    }
+
+    fn flush_if_due(&self) -> Result<(), S::WriteErrorType> {
+        let Some(write_behind) = self.write_behind else {
+            return Ok(());
+        };
+        let mut cache = self.cache();
+        let overdue = cache
+            .dirty_since
+            .is_some_and(|since| since.elapsed() >= write_behind.max_delay);
+        if overdue || cache.dirty_count() >= write_behind.max_pending {
+            let before = self.generation_before_write();
+            cache.flush(self.inner())?;
+            drop(cache);
+            self.note_own_write(before);
+        }
+        Ok(())
+    }
+
+    // invalidation only drops cached copies, pending writes are kept
+    pub fn invalidate(&self, key: &str) {
+        let mut cache = self.cache();
+        if cache.entries.get(key).is_some_and(|entry| !entry.dirty) {
+            cache.entries.remove(key);
+        }
+    }
+
+    pub fn invalidate_prefix(&self, prefix: &str) {
+        self.cache()
+            .entries
+            .retain(|key, entry| entry.dirty || !key.starts_with(prefix));
+    }
+
+    // the bloom filter too, it's rebuilt from the backend on the next read
+    pub fn clear(&self) {
+        let mut cache = self.cache();
+        cache.entries.retain(|_, entry| entry.dirty);
+        cache.bloom = None;
+    }
+
+    // clears the cache once the backend's generation moved on since it was last seen
+    fn revalidate(&self) {
+        let Some(interval) = self.generation_check else {
+            return;
+        };
+        let due = self
+            .cache()
+            .generation_checked_at
+            .is_none_or(|checked_at| checked_at.elapsed() >= interval);
+        if !due {
+            return;
+        }
+        let current = (self.generation)(self.inner());
+        let mut cache = self.cache();
+        cache.generation_checked_at = Some(Instant::now());
+        // a failed check clears the cache every time
+        if current.is_none() || current != cache.generation {
+            cache.entries.retain(|_, entry| entry.dirty);
+            cache.bloom = None;
+            cache.generation = current;
+        }
+    }
+
+    fn generation_before_write(&self) -> Option<u64> {
+        self.generation_check
+            .and_then(|_| (self.generation)(self.inner()))
+    }
+
+    // our own writes move the generation too, they're not a reason to clear the cache. if it
+    // had already moved on before the write, a change from elsewhere came first and the cache
+    // is cleared
+    fn note_own_write(&self, before: Option<u64>) {
+        if self.generation_check.is_none() {
+            return;
+        }
+        let current = (self.generation)(self.inner());
+        let mut cache = self.cache();
+        if before.is_none() || before != cache.generation {
+            cache.entries.retain(|_, entry| entry.dirty);
+            cache.bloom = None;
+        }
+        cache.generation = current;
+    }
+
+    // `Ok(true)` only if the bloom filter rules the key out. the lock is held while the filter
+    // is built, so writes going on at the same time can't be missed
+    fn is_absent(&self, key: &str) -> Result<bool, S::ReadErrorType> {
+        let Some((expected_keys, false_positive_rate)) = self.bloom_filter else {
+            return Ok(false);
+        };
+        let mut cache = self.cache();
+        if cache.bloom.is_none() {
+            let mut keys: Vec<String> = cache.entries.keys().cloned().collect();
+            let mut cursor = None;
+            loop {
+                let page = self.inner().scan(cursor.as_deref(), 128)?;
+                keys.extend(page.entries.into_iter().map(|(key, _)| key));
+                match page.cursor {
+                    Some(next) => cursor = Some(next),
+                    None => break,
+                }
+            }
+            let mut bloom =
+                BloomFilter::new(expected_keys.max(keys.len() * 2), false_positive_rate);
+            for key in &keys {
+                bloom.insert(key);
+            }
+            cache.bloom = Some(bloom);
+        }
+        Ok(cache
+            .bloom
+            .as_ref()
+            .is_some_and(|bloom| !bloom.may_contain(key)))
+    }
+
+    fn discard(&self, key: &str) {
+        self.cache().entries.remove(key);
+    }
+
+    fn discard_prefix(&self, prefix: &str) {
+        self.cache()
+            .entries
+            .retain(|key, _| !key.starts_with(prefix));
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<S: kv_storage::KvStorageGeneration> CachedKvStorage<S> {
+    // values changed by other processes are noticed within `interval`, by comparing the
+    // backend's generation before serving a cached value. any change drops every cached value.
+    // `Duration::ZERO` checks on every read
+    pub fn with_generation_check(mut self, interval: Duration) -> Self {
+        self.generation_check = Some(interval);
+        self.generation = |inner| inner.generation().ok();
+        self
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl<S> CachedKvStorage<S>
+where
+    S: kv_storage::KvStorage + Send + Sync + 'static,
+{
+    // reads the keys into the cache on a background thread, e.g. for the screen the user is
+    // about to open, so reading them later doesn't wait on the backend. the cache needs the
+    // capacity to hold them, prefetched keys are evicted like any other
+    pub fn prefetch<K: AsRef<str>>(
+        self: &Arc<Self>,
+        keys: impl IntoIterator<Item = K>,
+    ) -> JoinHandle<()> {
+        let keys: Vec<String> = keys
+            .into_iter()
+            .map(|key| key.as_ref().to_string())
+            .collect();
+        let cached = Arc::clone(self);
+        thread::spawn(move || {
+            for key in &keys {
+                cached.warm(key);
+            }
+        })
+    }
+
+    // reads the key into the cache unless it's already there, failures are left for the read
+    // that needs the value to report
+    fn warm(&self, key: &str) {
+        self.revalidate();
+        if self.cache().get(key, self.ttl).is_some() || self.is_absent(key).unwrap_or(false) {
+            return;
+        }
+        if let Ok(value) = self.inner().read(key) {
+            let mut cache = self.cache();
+            // a write since the read is newer
+            if !cache.entries.contains_key(key) {
+                cache.insert(key, &value, self.capacity, false);
+            }
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorage> Drop for CachedKvStorage<S> {
+    fn drop(&mut self) {
+        if self.inner.is_none() {
+            return;
+        }
+        let pending = self.pending_writes();
+        if pending > 0 && self.flush().is_err() {
+            log::warn!("Could not flush {pending} pending writes, they are lost");
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for CachedKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = CachedReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.revalidate();
+        if let Some(value) = self.cache().get(key, self.ttl) {
+            return Ok(value);
+        }
+        if self.is_absent(key).map_err(CachedReadError::Inner)? {
+            return Err(CachedReadError::NotFound(key.to_string()));
+        }
+        let value = self.inner().read(key).map_err(CachedReadError::Inner)?;
+        self.cache().insert(key, &value, self.capacity, false);
+        Ok(value)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        // before the write, so a read racing it can't be told the key is missing
+        self.cache().note_key(key);
+        if self.write_behind.is_some() {
+            self.cache().insert(key, value, self.capacity, true);
+            return self.flush_if_due();
+        }
+
+        let before = self.generation_before_write();
+        let result = self.inner().write(key, value);
+        match result {
+            Ok(()) => {
+                self.note_own_write(before);
+                self.cache().insert(key, value, self.capacity, false);
+            }
+            Err(_) => self.discard(key),
+        }
+        result
+    }
+
+    // pending writes replace the values in the page, keys that only exist as pending writes
+    // show up once they're flushed
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let mut page = self
+            .inner()
+            .scan(cursor, limit)
+            .map_err(CachedReadError::Inner)?;
+        let cache = self.cache();
+        for (key, value) in &mut page.entries {
+            if let Some(entry) = cache.entries.get(key).filter(|entry| entry.dirty) {
+                value.clone_from(&entry.value);
+            }
+        }
+        Ok(page)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.discard(key);
+        let before = self.generation_before_write();
+        self.inner().delete(key)?;
+        self.note_own_write(before);
+        Ok(())
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.discard_prefix(prefix);
+        let before = self.generation_before_write();
+        self.inner().delete_prefix(prefix)?;
+        self.note_own_write(before);
+        Ok(())
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner().health_check()
+    }
+}
+
+// pending writes are dropped too
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for CachedKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.discard_prefix("");
+        self.cache().bloom = None;
+        let before = self.generation_before_write();
+        self.inner().purge()?;
+        self.note_own_write(before);
+        Ok(())
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for CachedKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        // the backend owns expiry, caching the value could serve it past its ttl
+        self.discard(key);
+        self.cache().note_key(key);
+        let before = self.generation_before_write();
+        self.inner().write_with_ttl(key, value, ttl)?;
+        self.note_own_write(before);
+        Ok(())
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.flush()?;
+        self.discard(key);
+        let before = self.generation_before_write();
+        self.inner().expire_at(key, at)?;
+        self.note_own_write(before);
+        Ok(())
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.flush()?;
+        let before = self.generation_before_write();
+        self.inner().touch(key)?;
+        self.note_own_write(before);
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CachedReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    // the bloom filter ruled the key out without asking the backend
+    #[error("Key '{0}' not found")]
+    NotFound(String),
+}
+
+impl<E: IsNotFound> IsNotFound for CachedReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            CachedReadError::Inner(e) => e.is_not_found(),
+            CachedReadError::NotFound(_) => true,
+        }
+    }
+}
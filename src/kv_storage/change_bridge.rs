@@ -0,0 +1,420 @@
+use crate::kv_storage::observed_kv_storage::{Change, ListenerId, ObservedKvStorage};
+use crate::kv_storage::retrying_kv_storage::RetryPolicy;
+use crate::time::Instant;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+#[cfg(feature = "mqtt")]
+use thiserror::Error;
+
+// where a `ChangeBridge` delivers its batches, e.g. a `WebhookSink`, an `MqttSink`, or a
+// closure for other transports
+pub trait ChangeSink: Send + 'static {
+    type Error: Display;
+
+    // only returns once the batch was accepted, a batch that failed is sent again
+    fn send(&mut self, changes: &[Change]) -> Result<(), Self::Error>;
+}
+
+impl<F, E> ChangeSink for F
+where
+    F: FnMut(&[Change]) -> Result<(), E> + Send + 'static,
+    E: Display,
+{
+    type Error = E;
+
+    fn send(&mut self, changes: &[Change]) -> Result<(), E> {
+        self(changes)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatchPolicy {
+    // a batch is sent once it holds this many changes
+    pub max_changes: usize,
+    // or once its oldest change waited this long
+    pub max_delay: Duration,
+}
+
+impl Default for BatchPolicy {
+    fn default() -> Self {
+        BatchPolicy {
+            max_changes: 100,
+            max_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Backlog {
+    // changes stay queued until the sink accepted them, with when they were made
+    changes: VecDeque<(Instant, Change)>,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct Queue {
+    backlog: Mutex<Backlog>,
+    wake: Condvar,
+}
+
+impl Queue {
+    fn backlog(&self) -> MutexGuard<'_, Backlog> {
+        self.backlog.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// forwards the changes made through an `ObservedKvStorage` to a backend from a background
+// thread, e.g. settings changed on a device to a webhook that pushes them to the fleet's
+// dashboard. changes are sent in batches of `BatchPolicy`, in the order they were made. a batch
+// the sink fails to take is retried with the backoff of `RetryPolicy`, and dropped after
+// `max_attempts` so one the backend rejects doesn't hold up the ones after it. a batch can be
+// delivered twice when the sink's answer got lost, so backends should expect repeats
+pub struct ChangeBridge<T> {
+    queue: Arc<Queue>,
+    listener: ListenerId,
+    worker: Option<JoinHandle<T>>,
+}
+
+impl<T: ChangeSink> ChangeBridge<T> {
+    pub fn start<S>(
+        source: &ObservedKvStorage<S>,
+        sink: T,
+        batch: BatchPolicy,
+        retry: RetryPolicy,
+    ) -> Self {
+        let queue = Arc::new(Queue::default());
+        // the listener stays subscribed until `source.unsubscribe(bridge.listener())`, but does
+        // nothing once the bridge is gone
+        let listener = {
+            let queue = Arc::downgrade(&queue);
+            source.subscribe(move |change| {
+                if let Some(queue) = queue.upgrade() {
+                    queue
+                        .backlog()
+                        .changes
+                        .push_back((Instant::now(), change.clone()));
+                    queue.wake.notify_all();
+                }
+            })
+        };
+        let worker = {
+            let queue = queue.clone();
+            thread::spawn(move || forward(&queue, sink, batch, retry))
+        };
+        ChangeBridge {
+            queue,
+            listener,
+            worker: Some(worker),
+        }
+    }
+}
+
+impl<T> ChangeBridge<T> {
+    pub fn listener(&self) -> ListenerId {
+        self.listener
+    }
+
+    // changes not yet accepted by the sink
+    pub fn pending(&self) -> usize {
+        self.queue.backlog().changes.len()
+    }
+
+    // waits until every change so far was sent or dropped, returns false on timeout. changes
+    // still wait for their batch to fill up or for `max_delay`
+    pub fn wait_idle(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut backlog = self.queue.backlog();
+        while !backlog.changes.is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            backlog = self
+                .queue
+                .wake
+                .wait_timeout(backlog, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        true
+    }
+
+    // stops forwarding after the batch in flight and returns the sink together with the changes
+    // it didn't get yet
+    pub fn stop(mut self) -> (T, Vec<Change>) {
+        let sink = self.shutdown().expect("the worker is only joined once");
+        let changes = self
+            .queue
+            .backlog()
+            .changes
+            .drain(..)
+            .map(|(_, change)| change)
+            .collect();
+        (sink, changes)
+    }
+
+    fn shutdown(&mut self) -> Option<T> {
+        let worker = self.worker.take()?;
+        self.queue.backlog().shutdown = true;
+        self.queue.wake.notify_all();
+        match worker.join() {
+            Ok(sink) => Some(sink),
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl<T> Drop for ChangeBridge<T> {
+    fn drop(&mut self) {
+        if self.shutdown().is_some() {
+            let pending = self.queue.backlog().changes.len();
+            if pending > 0 {
+                log::warn!("Dropping change bridge with {pending} changes not sent");
+            }
+        }
+    }
+}
+
+fn forward<T: ChangeSink>(queue: &Queue, mut sink: T, batch: BatchPolicy, retry: RetryPolicy) -> T {
+    let max_changes = batch.max_changes.max(1);
+    let mut attempt = 0;
+    let mut backlog = queue.backlog();
+    loop {
+        if backlog.shutdown {
+            return sink;
+        }
+        let Some((oldest, _)) = backlog.changes.front() else {
+            backlog = queue
+                .wake
+                .wait(backlog)
+                .unwrap_or_else(PoisonError::into_inner);
+            continue;
+        };
+        // retries don't wait for the batch again
+        let due = *oldest + batch.max_delay;
+        let now = Instant::now();
+        if attempt == 0 && backlog.changes.len() < max_changes && now < due {
+            backlog = queue
+                .wake
+                .wait_timeout(backlog, due - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+            continue;
+        }
+        let changes: Vec<_> = backlog
+            .changes
+            .iter()
+            .take(max_changes)
+            .map(|(_, change)| change.clone())
+            .collect();
+        drop(backlog);
+
+        let result = sink.send(&changes);
+        backlog = queue.backlog();
+        attempt += 1;
+        match result {
+            Ok(()) => {}
+            Err(e) if attempt >= retry.max_attempts => {
+                log::error!(
+                    "Dropping {} changes the sink didn't take: {e}",
+                    changes.len()
+                );
+            }
+            Err(e) => {
+                let backoff = retry.backoff(attempt - 1);
+                log::warn!("Could not send changes, retrying in {backoff:?}: {e}");
+                // a shutdown ends the backoff early
+                backlog = queue
+                    .wake
+                    .wait_timeout_while(backlog, backoff, |backlog| !backlog.shutdown)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0;
+                continue;
+            }
+        }
+        backlog.changes.drain(..changes.len());
+        attempt = 0;
+        // wakes `wait_idle`
+        queue.wake.notify_all();
+    }
+}
+
+// a batch as a json array, e.g. `[{"op":"write","key":"theme","value":"dark"}]`, with "delete"
+// entries holding a "key" and "delete_prefix" entries a "prefix"
+#[cfg(feature = "json")]
+pub fn to_json(changes: &[Change]) -> String {
+    let changes: Vec<_> = changes
+        .iter()
+        .map(|change| match change {
+            Change::Written { key, value } => {
+                serde_json::json!({ "op": "write", "key": key, "value": value })
+            }
+            Change::Deleted { key } => serde_json::json!({ "op": "delete", "key": key }),
+            Change::DeletedPrefix { prefix } => {
+                serde_json::json!({ "op": "delete_prefix", "prefix": prefix })
+            }
+        })
+        .collect();
+    serde_json::Value::Array(changes).to_string()
+}
+
+// posts every batch as `to_json` to a url. answers other than 2xx count as failures
+#[cfg(feature = "webhook")]
+pub struct WebhookSink {
+    agent: ureq::Agent,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+#[cfg(feature = "webhook")]
+impl WebhookSink {
+    pub fn new(url: &str) -> Self {
+        WebhookSink {
+            agent: ureq::Agent::new_with_defaults(),
+            url: url.to_string(),
+            headers: Vec::new(),
+        }
+    }
+
+    // e.g. an "Authorization" header
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = ureq::Agent::config_builder()
+            .timeout_global(Some(timeout))
+            .build()
+            .new_agent();
+        self
+    }
+}
+
+#[cfg(feature = "webhook")]
+impl ChangeSink for WebhookSink {
+    type Error = ureq::Error;
+
+    fn send(&mut self, changes: &[Change]) -> Result<(), ureq::Error> {
+        let mut request = self.agent.post(&self.url);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        request
+            .content_type("application/json")
+            .send(to_json(changes))?;
+        Ok(())
+    }
+}
+
+// publishes every batch as `to_json` to a topic and waits until the broker acknowledged it, as
+// far as the qos asks for. the connection is made with the options given, e.g. with their
+// credentials and, with rumqttc's tls features, their transport. batches have to fit the
+// options' packet size
+#[cfg(feature = "mqtt")]
+pub struct MqttSink {
+    client: rumqttc::Client,
+    connection: rumqttc::Connection,
+    topic: String,
+    qos: rumqttc::QoS,
+    retain: bool,
+    timeout: Duration,
+}
+
+#[cfg(feature = "mqtt")]
+impl MqttSink {
+    pub fn new(options: rumqttc::MqttOptions, topic: &str) -> Self {
+        let (client, connection) = rumqttc::Client::new(options, 16);
+        MqttSink {
+            client,
+            connection,
+            topic: topic.to_string(),
+            qos: rumqttc::QoS::AtLeastOnce,
+            retain: false,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    // `AtLeastOnce` by default
+    pub fn with_qos(mut self, qos: rumqttc::QoS) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    // so devices subscribing later get the last batch
+    pub fn with_retain(mut self, retain: bool) -> Self {
+        self.retain = retain;
+        self
+    }
+
+    // how long a batch may take to be acknowledged, including reconnecting
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[cfg(feature = "mqtt")]
+impl ChangeSink for MqttSink {
+    type Error = MqttSinkError;
+
+    // the connection is only driven while a batch is sent, a broker that closed it in between
+    // is connected to again
+    fn send(&mut self, changes: &[Change]) -> Result<(), MqttSinkError> {
+        use rumqttc::{Event, Incoming, Outgoing, QoS};
+
+        self.client
+            .publish(&self.topic, self.qos, self.retain, to_json(changes))?;
+        let deadline = Instant::now() + self.timeout;
+        // the packet id of the publish once it's sent
+        let mut sent = None;
+        let mut error = None;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(error.map_or(MqttSinkError::Timeout, MqttSinkError::Connection));
+            }
+            let event = match self.connection.recv_timeout(deadline - now) {
+                Ok(Ok(event)) => event,
+                Ok(Err(e)) => {
+                    log::debug!("MQTT connection failed, reconnecting: {e}");
+                    error = Some(e);
+                    thread::sleep(Duration::from_millis(100).min(deadline - now));
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            match (event, sent, self.qos) {
+                (Event::Outgoing(Outgoing::Publish(_)), None, QoS::AtMostOnce) => return Ok(()),
+                (Event::Outgoing(Outgoing::Publish(id)), None, _) => sent = Some(id),
+                (Event::Incoming(Incoming::PubAck(ack)), Some(id), QoS::AtLeastOnce)
+                    if ack.pkid == id =>
+                {
+                    return Ok(())
+                }
+                (Event::Incoming(Incoming::PubComp(comp)), Some(id), QoS::ExactlyOnce)
+                    if comp.pkid == id =>
+                {
+                    return Ok(())
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(feature = "mqtt")]
+#[derive(Error, Debug)]
+pub enum MqttSinkError {
+    #[error("Could not queue the publish")]
+    Client(#[from] rumqttc::ClientError),
+
+    #[error("Could not connect to the broker")]
+    Connection(#[source] rumqttc::ConnectionError),
+
+    #[error("The broker did not acknowledge the publish in time")]
+    Timeout,
+}
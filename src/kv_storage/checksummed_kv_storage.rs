@@ -0,0 +1,134 @@
+use crate::kv_storage::{self, IsNotFound};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// values are stored as "<crc32 as 8 hex digits>:<value>"
+const HEADER_LEN: usize = 9;
+
+pub struct ChecksummedKvStorage<S>(S);
+
+impl<S> ChecksummedKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        ChecksummedKvStorage(inner)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+
+    fn seal(value: &str) -> String {
+        format!("{:08x}:{value}", crc32fast::hash(value.as_bytes()))
+    }
+
+    fn verify<E>(key: &str, mut stored: String) -> Result<String, ChecksummedReadError<E>> {
+        let corrupted = || ChecksummedReadError::Corrupted(key.to_string());
+
+        let header = stored.get(..HEADER_LEN).ok_or_else(corrupted)?;
+        let checksum = header
+            .strip_suffix(':')
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(corrupted)?;
+        if crc32fast::hash(&stored.as_bytes()[HEADER_LEN..]) != checksum {
+            return Err(corrupted());
+        }
+
+        stored.drain(..HEADER_LEN);
+        Ok(stored)
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for ChecksummedKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = ChecksummedReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let stored = self.0.read(key).map_err(ChecksummedReadError::Inner)?;
+        Self::verify(key, stored)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.write(key, &Self::seal(value))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .0
+            .scan(cursor, limit)
+            .map_err(ChecksummedReadError::Inner)?;
+        let entries = page
+            .entries
+            .into_iter()
+            .map(|(key, stored)| {
+                let value = Self::verify(&key, stored)?;
+                Ok((key, value))
+            })
+            .collect::<Result<_, Self::ReadErrorType>>()?;
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.delete(key)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.delete_prefix(prefix)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.0.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for ChecksummedKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.0.purge()
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for ChecksummedKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.0.write_with_ttl(key, &Self::seal(value), ttl)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.0.expire_at(key, at)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.touch(key)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ChecksummedReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Value of key '{0}' is corrupted")]
+    Corrupted(String),
+}
+
+impl<E: IsNotFound> IsNotFound for ChecksummedReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            ChecksummedReadError::Inner(e) => e.is_not_found(),
+            ChecksummedReadError::Corrupted(_) => false,
+        }
+    }
+}
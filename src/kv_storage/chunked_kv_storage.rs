@@ -0,0 +1,357 @@
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// values longer than the chunk size are stored as "<key>.part<generation>.0" up to
+// "<key>.part<generation>.<n-1>" with the index record MARKER + "<n>@<generation>:<length>"
+// under the key itself. shorter values are stored as is. every write of a chunked value uses a
+// new generation and only deletes the old one's chunks once the index points at the new ones,
+// so an interrupted write leaves the old value readable. indexes written before generations,
+// MARKER + n with chunks "<key>.part0".."<key>.part<n-1>", are still read
+const MARKER: &str = "\u{1}chunks:";
+
+#[derive(Clone, Copy, Default)]
+struct Index {
+    parts: usize,
+    // `None` for an index without generations
+    generation: Option<u64>,
+    // bytes of the whole value, `None` for an index without generations
+    len: Option<usize>,
+}
+
+impl Index {
+    fn encode(&self) -> String {
+        let generation = self.generation.unwrap_or_default();
+        let len = self.len.unwrap_or_default();
+        format!("{MARKER}{}@{generation}:{len}", self.parts)
+    }
+}
+
+pub struct ChunkedKvStorage<S> {
+    inner: S,
+    chunk_size: usize,
+}
+
+impl<S> ChunkedKvStorage<S> {
+    // `chunk_size` is in bytes, chunks are only split at char boundaries
+    pub fn new(inner: S, chunk_size: usize) -> Self {
+        assert!(chunk_size >= 4, "a chunk has to fit any utf-8 char");
+        ChunkedKvStorage { inner, chunk_size }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn part_key(key: &str, generation: Option<u64>, part: usize) -> String {
+        match generation {
+            Some(generation) => format!("{key}.part{generation}.{part}"),
+            None => format!("{key}.part{part}"),
+        }
+    }
+
+    // the base key, generation and part number of a chunk's key
+    fn parse_part_key(key: &str) -> Option<(&str, Option<u64>, usize)> {
+        let (base, part) = key.rsplit_once(".part")?;
+        fn number<N: std::str::FromStr>(number: &str) -> Option<N> {
+            let digits = !number.is_empty() && number.bytes().all(|c| c.is_ascii_digit());
+            digits.then(|| number.parse().ok()).flatten()
+        }
+        match part.split_once('.') {
+            Some((generation, part)) => Some((base, Some(number(generation)?), number(part)?)),
+            None => Some((base, None, number(part)?)),
+        }
+    }
+
+    // `Some(None)` for a value that isn't chunked, `None` for an invalid index
+    fn parse_index(stored: &str) -> Option<Option<Index>> {
+        let Some(index) = stored.strip_prefix(MARKER) else {
+            return Some(None);
+        };
+        let Some((parts, rest)) = index.split_once('@') else {
+            let parts = index.parse().ok()?;
+            return Some(Some(Index {
+                parts,
+                ..Index::default()
+            }));
+        };
+        let (generation, len) = rest.split_once(':')?;
+        Some(Some(Index {
+            parts: parts.parse().ok()?,
+            generation: Some(generation.parse().ok()?),
+            len: Some(len.parse().ok()?),
+        }))
+    }
+
+    fn split<'a>(&self, value: &'a str) -> Vec<&'a str> {
+        let mut chunks = Vec::new();
+        let mut rest = value;
+        while !rest.is_empty() {
+            let mut end = rest.len().min(self.chunk_size);
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            let (chunk, tail) = rest.split_at(end);
+            chunks.push(chunk);
+            rest = tail;
+        }
+        chunks
+    }
+
+    // values starting with the marker are chunked too, so they can't be mistaken for an index
+    fn needs_chunks(&self, value: &str) -> bool {
+        value.len() > self.chunk_size || value.starts_with(MARKER)
+    }
+}
+
+impl<S: kv_storage::KvStorage> ChunkedKvStorage<S> {
+    // the index of the current value of `key`, without parts if it isn't chunked
+    fn index(
+        &self,
+        key: &str,
+    ) -> Result<Index, ChunkedWriteError<S::ReadErrorType, S::WriteErrorType>> {
+        match self.inner.read_opt(key).map_err(ChunkedWriteError::Read)? {
+            Some(stored) => Ok(Self::parse_index(&stored).flatten().unwrap_or_default()),
+            None => Ok(Index::default()),
+        }
+    }
+
+    fn assemble(
+        &self,
+        key: &str,
+        stored: String,
+    ) -> Result<String, ChunkedReadError<S::ReadErrorType>> {
+        let index = match Self::parse_index(&stored) {
+            Some(Some(index)) => index,
+            Some(None) => return Ok(stored),
+            None => return Err(ChunkedReadError::InvalidIndex(key.to_string())),
+        };
+
+        let mut value = String::new();
+        for part in 0..index.parts {
+            let part_key = Self::part_key(key, index.generation, part);
+            match self.inner.read_opt(&part_key) {
+                Ok(Some(chunk)) => value.push_str(&chunk),
+                Ok(None) => return Err(ChunkedReadError::MissingChunk(part_key)),
+                Err(e) => return Err(ChunkedReadError::Inner(e)),
+            }
+        }
+        if index.len.is_some_and(|len| len != value.len()) {
+            return Err(ChunkedReadError::LengthMismatch(key.to_string()));
+        }
+        Ok(value)
+    }
+
+    fn remove_parts(&self, key: &str, index: Index) -> Result<(), S::WriteErrorType> {
+        for part in 0..index.parts {
+            self.inner
+                .delete(&Self::part_key(key, index.generation, part))?;
+        }
+        Ok(())
+    }
+
+    fn write_chunked(
+        &self,
+        key: &str,
+        value: &str,
+        write: impl Fn(&str, &str) -> Result<(), S::WriteErrorType>,
+    ) -> Result<(), ChunkedWriteError<S::ReadErrorType, S::WriteErrorType>> {
+        let old = self.index(key)?;
+        if self.needs_chunks(value) {
+            let generation = old
+                .generation
+                .map_or(1, |generation| generation.wrapping_add(1));
+            let chunks = self.split(value);
+            for (part, chunk) in chunks.iter().enumerate() {
+                write(&Self::part_key(key, Some(generation), part), chunk)
+                    .map_err(ChunkedWriteError::Inner)?;
+            }
+            // the index goes last, so a failed write leaves it pointing at the old chunks
+            let index = Index {
+                parts: chunks.len(),
+                generation: Some(generation),
+                len: Some(value.len()),
+            };
+            write(key, &index.encode()).map_err(ChunkedWriteError::Inner)?;
+        } else {
+            write(key, value).map_err(ChunkedWriteError::Inner)?;
+        }
+
+        self.remove_parts(key, old)
+            .map_err(ChunkedWriteError::Inner)
+    }
+
+    // deletes chunks their index doesn't point at, left behind by interrupted writes. part keys
+    // without any index are kept, they can't be told apart from plain values with such a key.
+    // returns the number of chunks removed
+    pub fn remove_orphans(
+        &self,
+    ) -> Result<usize, ChunkedWriteError<S::ReadErrorType, S::WriteErrorType>> {
+        let entries = self.inner.scan_all().map_err(ChunkedWriteError::Read)?;
+        let indexes: HashMap<&str, Index> = entries
+            .iter()
+            .filter_map(|(key, stored)| Some((key.as_str(), Self::parse_index(stored)??)))
+            .collect();
+
+        let mut removed = 0;
+        for (key, _) in &entries {
+            let orphaned = Self::parse_part_key(key).is_some_and(|(base, generation, part)| {
+                indexes
+                    .get(base)
+                    .is_some_and(|index| generation != index.generation || part >= index.parts)
+            });
+            if orphaned {
+                self.inner.delete(key).map_err(ChunkedWriteError::Inner)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for ChunkedKvStorage<S> {
+    type WriteErrorType = ChunkedWriteError<S::ReadErrorType, S::WriteErrorType>;
+    type ReadErrorType = ChunkedReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let stored = self.inner.read(key).map_err(ChunkedReadError::Inner)?;
+        self.assemble(key, stored)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.write_chunked(key, value, |key, value| self.inner.write(key, value))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(ChunkedReadError::Inner)?;
+
+        let mut is_chunked = HashMap::new();
+        let mut entries = Vec::with_capacity(page.entries.len());
+        for (key, stored) in page.entries {
+            if let Some((base, _, _)) = Self::parse_part_key(&key) {
+                if !is_chunked.contains_key(base) {
+                    let chunked = self
+                        .inner
+                        .read_opt(base)
+                        .map_err(ChunkedReadError::Inner)?
+                        .is_some_and(|stored| stored.starts_with(MARKER));
+                    is_chunked.insert(base.to_string(), chunked);
+                }
+                if is_chunked[base] {
+                    continue;
+                }
+            }
+            let value = self.assemble(&key, stored)?;
+            entries.push((key, value));
+        }
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        let index = self.index(key)?;
+        self.inner.delete(key).map_err(ChunkedWriteError::Inner)?;
+        self.remove_parts(key, index)
+            .map_err(ChunkedWriteError::Inner)
+    }
+
+    // chunks share the prefix of their key, so they go with it
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .delete_prefix(prefix)
+            .map_err(ChunkedWriteError::Inner)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> ChunkedKvStorage<S> {
+    fn for_key_and_parts(
+        &self,
+        key: &str,
+        f: impl Fn(&str) -> Result<(), S::WriteErrorType>,
+    ) -> Result<(), ChunkedWriteError<S::ReadErrorType, S::WriteErrorType>> {
+        let index = self.index(key)?;
+        f(key).map_err(ChunkedWriteError::Inner)?;
+        for part in 0..index.parts {
+            f(&Self::part_key(key, index.generation, part)).map_err(ChunkedWriteError::Inner)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for ChunkedKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge().map_err(ChunkedWriteError::Inner)
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for ChunkedKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.write_chunked(key, value, |key, value| {
+            self.inner.write_with_ttl(key, value, ttl)
+        })
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.for_key_and_parts(key, |key| self.inner.expire_at(key, at))
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.for_key_and_parts(key, |key| self.inner.touch(key))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ChunkedReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Chunk '{0}' is missing")]
+    MissingChunk(String),
+
+    #[error("Chunk index of key '{0}' is not valid")]
+    InvalidIndex(String),
+
+    #[error("Chunks of key '{0}' don't add up to the length in its index")]
+    LengthMismatch(String),
+}
+
+impl<E: IsNotFound> IsNotFound for ChunkedReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            ChunkedReadError::Inner(e) => e.is_not_found(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ChunkedWriteError<R, W> {
+    #[error("Could not read chunk index")]
+    Read(#[source] R),
+
+    #[error(transparent)]
+    Inner(W),
+}
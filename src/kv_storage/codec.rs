@@ -0,0 +1,225 @@
+use crate::kv_storage::{self, KvStorageExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+// turns typed values into the strings backends store. binary formats are base64 encoded
+pub trait Codec {
+    type Error;
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<String, Self::Error>;
+    fn decode<T: DeserializeOwned>(&self, encoded: &str) -> Result<T, Self::Error>;
+}
+
+pub trait KvStorageCodecExt: kv_storage::KvStorage {
+    fn read_decoded<T: DeserializeOwned, C: Codec>(
+        &self,
+        codec: &C,
+        key: impl AsRef<str>,
+    ) -> Result<T, CodecReadError<Self::ReadErrorType, C::Error>> {
+        let encoded = self.read(key.as_ref()).map_err(CodecReadError::Inner)?;
+        codec.decode(&encoded).map_err(CodecReadError::Decode)
+    }
+
+    fn read_decoded_opt<T: DeserializeOwned, C: Codec>(
+        &self,
+        codec: &C,
+        key: impl AsRef<str>,
+    ) -> Result<Option<T>, CodecReadError<Self::ReadErrorType, C::Error>> {
+        match self.read_opt(key).map_err(CodecReadError::Inner)? {
+            Some(encoded) => codec
+                .decode(&encoded)
+                .map(Some)
+                .map_err(CodecReadError::Decode),
+            None => Ok(None),
+        }
+    }
+
+    fn write_encoded<T: Serialize + ?Sized, C: Codec>(
+        &self,
+        codec: &C,
+        key: impl AsRef<str>,
+        value: &T,
+    ) -> Result<(), CodecWriteError<Self::WriteErrorType, C::Error>> {
+        let encoded = codec.encode(value).map_err(CodecWriteError::Encode)?;
+        self.write(key.as_ref(), &encoded)
+            .map_err(CodecWriteError::Inner)
+    }
+}
+
+impl<S: kv_storage::KvStorage + ?Sized> KvStorageCodecExt for S {}
+
+#[derive(Error, Debug)]
+pub enum CodecReadError<E, C> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Could not decode stored value")]
+    Decode(#[source] C),
+}
+
+impl<E: kv_storage::IsNotFound, C> kv_storage::IsNotFound for CodecReadError<E, C> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            CodecReadError::Inner(e) => e.is_not_found(),
+            CodecReadError::Decode(_) => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CodecWriteError<E, C> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Could not encode value")]
+    Encode(#[source] C),
+}
+
+#[cfg(feature = "json")]
+pub use json::JsonCodec;
+
+#[cfg(feature = "json")]
+mod json {
+    use super::*;
+
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct JsonCodec {
+        pretty: bool,
+    }
+
+    impl JsonCodec {
+        pub fn pretty() -> Self {
+            JsonCodec { pretty: true }
+        }
+    }
+
+    impl Codec for JsonCodec {
+        type Error = serde_json::Error;
+
+        fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<String, Self::Error> {
+            if self.pretty {
+                serde_json::to_string_pretty(value)
+            } else {
+                serde_json::to_string(value)
+            }
+        }
+
+        fn decode<T: DeserializeOwned>(&self, encoded: &str) -> Result<T, Self::Error> {
+            serde_json::from_str(encoded)
+        }
+    }
+}
+
+#[cfg(feature = "msgpack")]
+pub use msgpack::{MsgPackCodec, MsgPackError};
+
+#[cfg(feature = "msgpack")]
+mod msgpack {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+
+    // struct fields are encoded by name, so values survive reordering fields
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct MsgPackCodec;
+
+    impl Codec for MsgPackCodec {
+        type Error = MsgPackError;
+
+        fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<String, Self::Error> {
+            Ok(BASE64.encode(rmp_serde::to_vec_named(value)?))
+        }
+
+        fn decode<T: DeserializeOwned>(&self, encoded: &str) -> Result<T, Self::Error> {
+            Ok(rmp_serde::from_slice(&BASE64.decode(encoded)?)?)
+        }
+    }
+
+    #[derive(Error, Debug)]
+    pub enum MsgPackError {
+        #[error("Could not encode value as MessagePack")]
+        Encode(#[from] rmp_serde::encode::Error),
+
+        #[error("Could not decode MessagePack value")]
+        Decode(#[from] rmp_serde::decode::Error),
+
+        #[error("Stored value is not valid base64")]
+        Base64(#[from] base64::DecodeError),
+    }
+}
+
+#[cfg(feature = "bincode")]
+pub use bincode_codec::{BincodeCodec, BincodeError};
+
+#[cfg(feature = "bincode")]
+mod bincode_codec {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+
+    // the most compact option, but values don't survive adding or reordering struct fields
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct BincodeCodec;
+
+    impl Codec for BincodeCodec {
+        type Error = BincodeError;
+
+        fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<String, Self::Error> {
+            Ok(BASE64.encode(bincode::serialize(value)?))
+        }
+
+        fn decode<T: DeserializeOwned>(&self, encoded: &str) -> Result<T, Self::Error> {
+            Ok(bincode::deserialize(&BASE64.decode(encoded)?)?)
+        }
+    }
+
+    #[derive(Error, Debug)]
+    pub enum BincodeError {
+        #[error("Could not encode or decode bincode value")]
+        Bincode(#[from] bincode::Error),
+
+        #[error("Stored value is not valid base64")]
+        Base64(#[from] base64::DecodeError),
+    }
+}
+
+#[cfg(feature = "cbor")]
+pub use cbor::{CborCodec, CborError};
+
+#[cfg(feature = "cbor")]
+mod cbor {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use std::io;
+
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct CborCodec;
+
+    impl Codec for CborCodec {
+        type Error = CborError;
+
+        fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<String, Self::Error> {
+            let mut bytes = Vec::new();
+            ciborium::into_writer(value, &mut bytes)?;
+            Ok(BASE64.encode(bytes))
+        }
+
+        fn decode<T: DeserializeOwned>(&self, encoded: &str) -> Result<T, Self::Error> {
+            Ok(ciborium::from_reader(BASE64.decode(encoded)?.as_slice())?)
+        }
+    }
+
+    #[derive(Error, Debug)]
+    pub enum CborError {
+        #[error("Could not encode value as CBOR")]
+        Encode(#[from] ciborium::ser::Error<io::Error>),
+
+        #[error("Could not decode CBOR value")]
+        Decode(#[from] ciborium::de::Error<io::Error>),
+
+        #[error("Stored value is not valid base64")]
+        Base64(#[from] base64::DecodeError),
+    }
+}
@@ -0,0 +1,225 @@
+use crate::kv_storage::enveloped_kv_storage::{self as envelope, Metadata};
+use crate::kv_storage::{self, IsNotFound};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{self, Read, Write};
+use std::string::FromUtf8Error;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// compressed values are stored as base64(gzip(value)) in an envelope flagged as compressed;
+// anything without an envelope is returned as is, so stores written before compression was
+// enabled keep working. values stored uncompressed that look like an envelope get one without
+// flags around them. values from before the envelope start with LEGACY_MARKER, which is still
+// read, then base64(gzip(value)), or ':' and the uncompressed value
+const LEGACY_MARKER: &str = "\u{1}gz:";
+const DEFAULT_THRESHOLD: usize = 256;
+// stored values can come from users, e.g. edited cookies, so a small one can't decompress to
+// anything that fills the memory
+const DEFAULT_MAX_SIZE: usize = 64 * 1024 * 1024;
+
+pub struct CompressedKvStorage<S> {
+    inner: S,
+    threshold: usize,
+    level: Compression,
+    max_size: usize,
+}
+
+impl<S> CompressedKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        CompressedKvStorage {
+            inner,
+            threshold: DEFAULT_THRESHOLD,
+            level: Compression::default(),
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    // values shorter than `threshold` bytes are stored uncompressed
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_level(mut self, level: u32) -> Self {
+        self.level = Compression::new(level);
+        self
+    }
+
+    // values decompressing to more than `max_size` bytes fail to read
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn compress(&self, value: &str) -> String {
+        if value.len() < self.threshold {
+            return Self::verbatim(value);
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), self.level);
+        encoder
+            .write_all(value.as_bytes())
+            .expect("writing to a Vec can't fail");
+        let compressed = encoder.finish().expect("writing to a Vec can't fail");
+
+        let metadata = Metadata {
+            compressed: true,
+            ..Metadata::default()
+        };
+        let encoded = envelope::seal(&metadata, &BASE64.encode(compressed));
+        match encoded.len() < value.len() {
+            true => encoded,
+            false => Self::verbatim(value),
+        }
+    }
+
+    fn verbatim(value: &str) -> String {
+        match value.starts_with(LEGACY_MARKER) || envelope::is_sealed(value) {
+            true => envelope::seal(&Metadata::default(), value),
+            false => value.to_string(),
+        }
+    }
+
+    fn decompress<E>(&self, key: &str, value: String) -> Result<String, CompressedReadError<E>> {
+        let encoded = match value.strip_prefix(LEGACY_MARKER) {
+            Some(legacy) => match legacy.strip_prefix(':') {
+                Some(verbatim) => return Ok(verbatim.to_string()),
+                None => legacy,
+            },
+            None if !envelope::is_sealed(&value) => return Ok(value),
+            None => {
+                let (metadata, encoded) = envelope::open(&value)
+                    .ok_or_else(|| CompressedReadError::InvalidEnvelope(key.to_string()))?;
+                if !metadata.compressed {
+                    return Ok(encoded.to_string());
+                }
+                encoded
+            }
+        };
+        let compressed = BASE64.decode(encoded)?;
+        let mut decompressed = Vec::new();
+        let limit = u64::try_from(self.max_size).unwrap_or(u64::MAX);
+        GzDecoder::new(&compressed[..])
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut decompressed)?;
+        if decompressed.len() > self.max_size {
+            return Err(CompressedReadError::TooLarge(self.max_size));
+        }
+        Ok(String::from_utf8(decompressed)?)
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for CompressedKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = CompressedReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let stored = self.inner.read(key).map_err(CompressedReadError::Inner)?;
+        self.decompress(key, stored)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.write(key, &self.compress(value))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(CompressedReadError::Inner)?;
+        let entries = page
+            .entries
+            .into_iter()
+            .map(|(key, stored)| {
+                let value = self.decompress(&key, stored)?;
+                Ok((key, value))
+            })
+            .collect::<Result<_, Self::ReadErrorType>>()?;
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete(key)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete_prefix(prefix)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for CompressedKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge()
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for CompressedKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.inner.write_with_ttl(key, &self.compress(value), ttl)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner.expire_at(key, at)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CompressedReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Compressed value is not valid base64")]
+    Decode(#[from] base64::DecodeError),
+
+    #[error("Error decompressing value")]
+    Decompress(#[from] io::Error),
+
+    #[error("Decompressed value is not valid utf-8")]
+    Utf8(#[from] FromUtf8Error),
+
+    #[error("Decompressed value is larger than {0} bytes")]
+    TooLarge(usize),
+
+    #[error("Envelope of key '{0}' is not valid")]
+    InvalidEnvelope(String),
+}
+
+impl<E: IsNotFound> IsNotFound for CompressedReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            CompressedReadError::Inner(e) => e.is_not_found(),
+            _ => false,
+        }
+    }
+}
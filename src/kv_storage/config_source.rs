@@ -0,0 +1,98 @@
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use config::{ConfigError, Map, Source, Value};
+use std::fmt;
+use std::fmt::Display;
+use std::sync::Arc;
+
+// the store's entries as a layer of configuration, keys are split into config paths on '/':
+//
+//     let settings = Config::builder()
+//         .add_source(File::with_name("settings"))
+//         .add_source(KvConfigSource::new(store).with_prefix("config/"))
+//         .add_source(Environment::with_prefix("APP"))
+//         .build()?;
+//
+// so "config/server/port" is "server.port". values are strings, config converts them when
+// they're deserialized
+pub struct KvConfigSource<S> {
+    inner: Arc<S>,
+    prefix: String,
+    separator: String,
+}
+
+impl<S> KvConfigSource<S> {
+    pub fn new(inner: S) -> Self {
+        KvConfigSource {
+            inner: Arc::new(inner),
+            prefix: String::new(),
+            separator: "/".to_string(),
+        }
+    }
+
+    // only keys starting with it are used, without it
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    // what keys are split into config paths on, '/' by default
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S> Clone for KvConfigSource<S> {
+    fn clone(&self) -> Self {
+        KvConfigSource {
+            inner: self.inner.clone(),
+            prefix: self.prefix.clone(),
+            separator: self.separator.clone(),
+        }
+    }
+}
+
+// `Source` needs it, stores don't have to implement it
+impl<S> fmt::Debug for KvConfigSource<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KvConfigSource")
+            .field("prefix", &self.prefix)
+            .field("separator", &self.separator)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Source for KvConfigSource<S>
+where
+    S: KvStorage + Send + Sync + 'static,
+    S::ReadErrorType: Display,
+{
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let entries = self
+            .inner
+            .scan_all()
+            .map_err(|e| ConfigError::Message(format!("could not read the store: {e}")))?;
+        let origin = "kv storage".to_string();
+        let values = entries
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let path = key.strip_prefix(&self.prefix)?;
+                let path = if self.separator.is_empty() {
+                    path.to_string()
+                } else {
+                    path.replace(self.separator.as_str(), ".")
+                };
+                Some((path, Value::new(Some(&origin), value)))
+            })
+            .collect();
+        Ok(values)
+    }
+}
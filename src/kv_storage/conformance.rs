@@ -0,0 +1,242 @@
+use crate::kv_storage::{IsNotFound, KvStorage, KvStorageExt};
+use std::fmt::Debug;
+
+// checks shared by every backend, run through `kv_storage_conformance_tests!`. each check only
+// touches keys under its own "conformance/<check>/" prefix, so they can run in parallel against
+// one store and leave other data alone
+
+fn key(check: &str, key: &str) -> String {
+    format!("conformance/{check}/{key}")
+}
+
+fn write<S: KvStorage>(store: &S, key: &str, value: &str)
+where
+    S::WriteErrorType: Debug,
+{
+    if let Err(e) = store.write(key, value) {
+        panic!("writing '{key}' failed: {e:?}");
+    }
+}
+
+fn read<S: KvStorage>(store: &S, key: &str) -> Option<String>
+where
+    S::ReadErrorType: Debug,
+{
+    match store.read_opt(key) {
+        Ok(value) => value,
+        Err(e) => panic!("reading '{key}' failed: {e:?}"),
+    }
+}
+
+fn delete<S: KvStorage>(store: &S, key: &str)
+where
+    S::WriteErrorType: Debug,
+{
+    if let Err(e) = store.delete(key) {
+        panic!("deleting '{key}' failed: {e:?}");
+    }
+}
+
+pub fn missing_key<S: KvStorage>(store: &S)
+where
+    S::ReadErrorType: Debug,
+{
+    let key = key("missing_key", "never-written");
+    match store.read(&key) {
+        Ok(value) => panic!("missing key '{key}' read as '{value}'"),
+        Err(e) => assert!(e.is_not_found(), "missing key '{key}' failed with {e:?}"),
+    }
+}
+
+pub fn read_after_write<S: KvStorage>(store: &S)
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    let key = key("read_after_write", "key");
+    write(store, &key, "value");
+    assert_eq!(read(store, &key).as_deref(), Some("value"));
+    delete(store, &key);
+}
+
+pub fn overwrite<S: KvStorage>(store: &S)
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    let key = key("overwrite", "key");
+    write(store, &key, "first");
+    write(store, &key, "second");
+    assert_eq!(read(store, &key).as_deref(), Some("second"));
+    delete(store, &key);
+}
+
+pub fn empty_value<S: KvStorage>(store: &S)
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    let key = key("empty_value", "key");
+    write(store, &key, "");
+    assert_eq!(read(store, &key).as_deref(), Some(""));
+    delete(store, &key);
+}
+
+pub fn delete_removes<S: KvStorage>(store: &S)
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    let key = key("delete_removes", "key");
+    write(store, &key, "value");
+    delete(store, &key);
+    assert_eq!(read(store, &key), None);
+    // deleting again, now that the key is missing, is not an error either
+    delete(store, &key);
+}
+
+pub fn delete_prefix<S: KvStorage>(store: &S)
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    let inside = [key("delete_prefix", "in/a"), key("delete_prefix", "in/b")];
+    let outside = key("delete_prefix", "out");
+    for key in inside.iter().chain([&outside]) {
+        write(store, key, "value");
+    }
+    if let Err(e) = store.delete_prefix(&key("delete_prefix", "in/")) {
+        panic!("deleting prefix failed: {e:?}");
+    }
+    for key in &inside {
+        assert_eq!(read(store, key), None, "'{key}' survived delete_prefix");
+    }
+    assert_eq!(read(store, &outside).as_deref(), Some("value"));
+    delete(store, &outside);
+}
+
+pub fn unicode<S: KvStorage>(store: &S)
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    let key = key("unicode", "ключ-鍵-🔑");
+    let value = "zażółć gęślą jaźń, 日本語, 🦀\u{200d}🔥, e\u{301}";
+    write(store, &key, value);
+    assert_eq!(read(store, &key).as_deref(), Some(value));
+    delete(store, &key);
+}
+
+pub fn special_characters<S: KvStorage>(store: &S)
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    let key = key("special_characters", "a b;c=d&e%20f?g#h");
+    let value = "line\nbreak\ttab \"quotes\" 'single' \\ ; = & %";
+    write(store, &key, value);
+    assert_eq!(read(store, &key).as_deref(), Some(value));
+    delete(store, &key);
+}
+
+pub fn large_value<S: KvStorage>(store: &S)
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    let key = key("large_value", "key");
+    let value: String = (0..1 << 20)
+        .map(|i| char::from(b'a' + (i % 26) as u8))
+        .collect();
+    write(store, &key, &value);
+    let read = read(store, &key).expect("large value is missing");
+    assert!(
+        read == value,
+        "large value changed, read {} bytes",
+        read.len()
+    );
+    delete(store, &key);
+}
+
+pub fn scan_pages<S: KvStorage>(store: &S)
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    let prefix = key("scan_pages", "");
+    let mut written: Vec<_> = (0..25).map(|i| key("scan_pages", &i.to_string())).collect();
+    for key in &written {
+        write(store, key, "value");
+    }
+    written.sort();
+
+    let mut scanned = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = match store.scan(cursor.as_deref(), 4) {
+            Ok(page) => page,
+            Err(e) => panic!("scanning failed: {e:?}"),
+        };
+        assert!(page.entries.len() <= 4, "page exceeds the limit");
+        scanned.extend(page.entries.into_iter().map(|(key, _)| key));
+        match page.cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    let mut sorted = scanned.clone();
+    sorted.sort();
+    assert_eq!(scanned, sorted, "scan is not in key order");
+    sorted.dedup();
+    assert_eq!(scanned.len(), sorted.len(), "scan returned duplicates");
+    let scanned: Vec<_> = scanned
+        .into_iter()
+        .filter(|key| key.starts_with(&prefix))
+        .collect();
+    assert_eq!(scanned, written);
+
+    for key in &written {
+        delete(store, key);
+    }
+}
+
+// expands to one #[test] per check, each evaluating the expression for its own store. place it
+// in a test module or integration test of the backend:
+//
+//     easy_storage::kv_storage_conformance_tests!(MyBackend::default());
+//
+// pass a module name first to run it for several backends in one file
+#[macro_export]
+macro_rules! kv_storage_conformance_tests {
+    ($store:expr) => {
+        $crate::kv_storage_conformance_tests!(kv_storage_conformance, $store);
+    };
+    ($name:ident, $store:expr) => {
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $crate::kv_storage_conformance_tests!(@checks $store;
+                missing_key,
+                read_after_write,
+                overwrite,
+                empty_value,
+                delete_removes,
+                delete_prefix,
+                unicode,
+                special_characters,
+                large_value,
+                scan_pages,
+            );
+        }
+    };
+    (@checks $store:expr; $($check:ident,)*) => {
+        $(
+            #[test]
+            fn $check() {
+                let store = $store;
+                $crate::kv_storage::conformance::$check(&store);
+            }
+        )*
+    };
+}
@@ -0,0 +1,117 @@
+use crate::kv_storage::{self, KvStorageExt};
+use thiserror::Error;
+
+const PAGE_SIZE: usize = 128;
+
+pub type CopyResult<S, D> = Result<
+    CopyStats,
+    CopyError<
+        <S as kv_storage::KvStorage>::ReadErrorType,
+        <D as kv_storage::KvStorage>::ReadErrorType,
+        <D as kv_storage::KvStorage>::WriteErrorType,
+    >,
+>;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    // replace values already present in the destination
+    #[default]
+    Overwrite,
+    // keep values already present in the destination
+    Skip,
+    // stop with `CopyError::Exists` at the first key present in the destination
+    Fail,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CopyOptions {
+    prefixes: Vec<String>,
+    overwrite: OverwritePolicy,
+}
+
+impl CopyOptions {
+    // only copy keys starting with `prefix`, can be given multiple times
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefixes.push(prefix.to_string());
+        self
+    }
+
+    pub fn with_overwrite_policy(mut self, overwrite: OverwritePolicy) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    fn matches(&self, key: &str) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|prefix| key.starts_with(prefix))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CopyStats {
+    pub copied: usize,
+    pub skipped: usize,
+}
+
+pub fn copy_all<S, D>(src: &S, dst: &D) -> CopyResult<S, D>
+where
+    S: kv_storage::KvStorage + ?Sized,
+    D: kv_storage::KvStorage + ?Sized,
+{
+    copy_all_with(src, dst, &CopyOptions::default())
+}
+
+// copies every matching entry of `src` into `dst`, entries copied before an error are kept
+pub fn copy_all_with<S, D>(src: &S, dst: &D, options: &CopyOptions) -> CopyResult<S, D>
+where
+    S: kv_storage::KvStorage + ?Sized,
+    D: kv_storage::KvStorage + ?Sized,
+{
+    let mut stats = CopyStats::default();
+    let mut cursor = None;
+    loop {
+        let page = src
+            .scan(cursor.as_deref(), PAGE_SIZE)
+            .map_err(CopyError::Source)?;
+
+        for (key, value) in page.entries {
+            if !options.matches(&key) {
+                continue;
+            }
+            if options.overwrite != OverwritePolicy::Overwrite
+                && dst
+                    .read_opt(&key)
+                    .map_err(CopyError::DestinationRead)?
+                    .is_some()
+            {
+                if options.overwrite == OverwritePolicy::Fail {
+                    return Err(CopyError::Exists(key));
+                }
+                stats.skipped += 1;
+                continue;
+            }
+            dst.write(&key, &value)
+                .map_err(|e| CopyError::DestinationWrite(key, e))?;
+            stats.copied += 1;
+        }
+
+        match page.cursor {
+            Some(next) => cursor = Some(next),
+            None => return Ok(stats),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CopyError<S, R, W> {
+    #[error("Could not read from source storage")]
+    Source(#[source] S),
+
+    #[error("Could not read from destination storage")]
+    DestinationRead(#[source] R),
+
+    #[error("Could not write key '{0}' to destination storage")]
+    DestinationWrite(String, #[source] W),
+
+    #[error("Key '{0}' already exists in destination storage")]
+    Exists(String),
+}
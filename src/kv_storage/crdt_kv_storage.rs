@@ -0,0 +1,384 @@
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use crate::time;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+// every key is stored as a last-write-wins register tagged with a vector clock:
+// MARKER + "<replica>=<counter>,..." + ";<unix millis>;<writing replica>;" + ":value", or "-"
+// for deletes. values without the marker count as written by no replica at the epoch
+const MARKER: &str = "\u{1}crdt:";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Register {
+    clock: BTreeMap<String, u64>,
+    modified: u64,
+    replica: String,
+    // `None` for tombstones
+    value: Option<String>,
+}
+
+impl Register {
+    fn parse(stored: &str) -> Option<Register> {
+        let Some(record) = stored.strip_prefix(MARKER) else {
+            return Some(Register {
+                clock: BTreeMap::new(),
+                modified: 0,
+                replica: String::new(),
+                value: Some(stored.to_string()),
+            });
+        };
+        let mut parts = record.splitn(4, ';');
+        let (clock, modified, replica, payload) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        let clock = clock
+            .split(',')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (replica, counter) = entry.split_once('=')?;
+                Some((replica.to_string(), counter.parse().ok()?))
+            })
+            .collect::<Option<_>>()?;
+        let value = match payload {
+            "-" => None,
+            payload => Some(payload.strip_prefix(':')?.to_string()),
+        };
+        Some(Register {
+            clock,
+            modified: modified.parse().ok()?,
+            replica: replica.to_string(),
+            value,
+        })
+    }
+
+    fn seal(&self) -> String {
+        let clock = self
+            .clock
+            .iter()
+            .map(|(replica, counter)| format!("{replica}={counter}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        let payload = match &self.value {
+            Some(value) => format!(":{value}"),
+            None => "-".to_string(),
+        };
+        format!(
+            "{MARKER}{clock};{};{};{payload}",
+            self.modified, self.replica
+        )
+    }
+
+    // `Less` if `other` has seen every change this register has, `None` if both saw changes
+    // the other didn't
+    fn compare(&self, other: &Register) -> Option<Ordering> {
+        let mut ordering = Ordering::Equal;
+        for replica in self.clock.keys().chain(other.clock.keys()) {
+            let mine = self.clock.get(replica).copied().unwrap_or(0);
+            let theirs = other.clock.get(replica).copied().unwrap_or(0);
+            match (ordering, mine.cmp(&theirs)) {
+                (_, Ordering::Equal) => {}
+                (Ordering::Equal, next) => ordering = next,
+                (current, next) if current != next => return None,
+                _ => {}
+            }
+        }
+        Some(ordering)
+    }
+
+    // combines concurrent registers, the later write wins and the replica id breaks ties, so
+    // every replica picks the same one
+    fn merge(self, other: Register) -> Register {
+        let later = |register: &Register| {
+            (
+                register.modified,
+                register.replica.clone(),
+                register.value.clone(),
+            )
+        };
+        let (mut winner, loser) = if later(&self) >= later(&other) {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        for (replica, counter) in loser.clock {
+            let merged = winner.clock.entry(replica).or_default();
+            *merged = (*merged).max(counter);
+        }
+        winner
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    // keys whose register was replaced or combined with the other one
+    pub updated: Vec<String>,
+    // keys changed on both replicas without either seeing the other change
+    pub concurrent: Vec<String>,
+}
+
+pub type MergeResult<S, T> = Result<
+    MergeReport,
+    CrdtMergeError<
+        <T as kv_storage::KvStorage>::ReadErrorType,
+        <S as kv_storage::KvStorage>::ReadErrorType,
+        <S as kv_storage::KvStorage>::WriteErrorType,
+    >,
+>;
+
+// lets several replicas of a store be edited offline and merged later without losing track of
+// which edits saw each other. every replica needs its own id, changes one replica made after
+// seeing another's always win over those, truly concurrent edits are resolved last-write-wins
+// the same way on every replica. deletes leave tombstones so they merge like writes
+pub struct CrdtKvStorage<S> {
+    inner: S,
+    replica: String,
+}
+
+impl<S> CrdtKvStorage<S> {
+    pub fn new(inner: S, replica: &str) -> Self {
+        assert!(
+            !replica.is_empty() && !replica.contains([';', ',', '=']),
+            "replica ids can't be empty or contain ';', ',' or '='"
+        );
+        CrdtKvStorage {
+            inner,
+            replica: replica.to_string(),
+        }
+    }
+
+    pub fn replica(&self) -> &str {
+        &self.replica
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn open<E>(key: &str, stored: &str) -> Result<Option<String>, CrdtReadError<E>> {
+        let register =
+            Register::parse(stored).ok_or_else(|| CrdtReadError::InvalidRecord(key.to_string()))?;
+        Ok(register.value)
+    }
+}
+
+impl<S: kv_storage::KvStorage> CrdtKvStorage<S> {
+    // the register replacing the current one of `key`, the read and the following write are not
+    // atomic, so only one handle per replica should write at a time
+    fn next(
+        &self,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<String, CrdtWriteError<S::ReadErrorType, S::WriteErrorType>> {
+        let mut clock = match self.inner.read_opt(key).map_err(CrdtWriteError::Read)? {
+            Some(stored) => {
+                Register::parse(&stored)
+                    .ok_or_else(|| CrdtWriteError::InvalidRecord(key.to_string()))?
+                    .clock
+            }
+            None => BTreeMap::new(),
+        };
+        *clock.entry(self.replica.clone()).or_default() += 1;
+        let modified = time::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Ok(Register {
+            clock,
+            modified,
+            replica: self.replica.clone(),
+            value: value.map(str::to_string),
+        }
+        .seal())
+    }
+
+    // pulls the changes of `other` into this replica, merge the other way round as well to
+    // bring both up to date. merging is idempotent and the order replicas are merged in
+    // doesn't matter
+    pub fn merge_from<T: kv_storage::KvStorage>(
+        &self,
+        other: &CrdtKvStorage<T>,
+    ) -> MergeResult<S, T> {
+        let mine: BTreeMap<_, _> = self
+            .inner
+            .scan_all()
+            .map_err(CrdtMergeError::Read)?
+            .into_iter()
+            .collect();
+        let mut report = MergeReport::default();
+        for (key, stored) in other.inner.scan_all().map_err(CrdtMergeError::Other)? {
+            let theirs = Register::parse(&stored)
+                .ok_or_else(|| CrdtMergeError::InvalidRecord(key.clone()))?;
+            let merged = match mine.get(&key) {
+                None => theirs,
+                Some(current) if *current == stored => continue,
+                Some(current) => {
+                    let current = Register::parse(current)
+                        .ok_or_else(|| CrdtMergeError::InvalidRecord(key.clone()))?;
+                    match current.compare(&theirs) {
+                        Some(Ordering::Less) => theirs,
+                        Some(Ordering::Greater) => continue,
+                        // equal clocks with different contents mean two replicas share an id,
+                        // still resolve them the same way everywhere
+                        Some(Ordering::Equal) | None => {
+                            report.concurrent.push(key.clone());
+                            let merged = current.clone().merge(theirs);
+                            if merged == current {
+                                continue;
+                            }
+                            merged
+                        }
+                    }
+                }
+            };
+            self.inner
+                .write(&key, &merged.seal())
+                .map_err(CrdtMergeError::Write)?;
+            report.updated.push(key);
+        }
+        Ok(report)
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for CrdtKvStorage<S> {
+    type WriteErrorType = CrdtWriteError<S::ReadErrorType, S::WriteErrorType>;
+    type ReadErrorType = CrdtReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let stored = self.inner.read(key).map_err(CrdtReadError::Inner)?;
+        Self::open(key, &stored)?.ok_or_else(|| CrdtReadError::Deleted(key.to_string()))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        let register = self.next(key, Some(value))?;
+        self.inner
+            .write(key, &register)
+            .map_err(CrdtWriteError::Inner)
+    }
+
+    // tombstones are left out, so pages can be shorter than `limit`
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(CrdtReadError::Inner)?;
+        let mut entries = Vec::with_capacity(page.entries.len());
+        for (key, stored) in page.entries {
+            if let Some(value) = Self::open(&key, &stored)? {
+                entries.push((key, value));
+            }
+        }
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        let register = self.next(key, None)?;
+        self.inner
+            .write(key, &register)
+            .map_err(CrdtWriteError::Inner)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        for (key, stored) in self.inner.scan_all().map_err(CrdtWriteError::Read)? {
+            let is_live = Register::parse(&stored).is_none_or(|register| register.value.is_some());
+            if key.starts_with(prefix) && is_live {
+                self.delete(&key)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+// tombstones are removed too, merging with a replica that still has the keys brings them back
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for CrdtKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge().map_err(CrdtWriteError::Inner)
+    }
+}
+
+// entries expiring in the wrapped store leave no tombstone, so merging can bring them back
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for CrdtKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        let register = self.next(key, Some(value))?;
+        self.inner
+            .write_with_ttl(key, &register, ttl)
+            .map_err(CrdtWriteError::Inner)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner.expire_at(key, at).map_err(CrdtWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key).map_err(CrdtWriteError::Inner)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CrdtReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Key '{0}' was deleted")]
+    Deleted(String),
+
+    #[error("Register of key '{0}' is not valid")]
+    InvalidRecord(String),
+}
+
+impl<E: IsNotFound> IsNotFound for CrdtReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            CrdtReadError::Inner(e) => e.is_not_found(),
+            CrdtReadError::Deleted(_) => true,
+            CrdtReadError::InvalidRecord(_) => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CrdtWriteError<R, W> {
+    #[error("Could not read current register")]
+    Read(#[source] R),
+
+    #[error("Register of key '{0}' is not valid")]
+    InvalidRecord(String),
+
+    #[error(transparent)]
+    Inner(W),
+}
+
+#[derive(Error, Debug)]
+pub enum CrdtMergeError<O, R, W> {
+    #[error("Could not read other replica")]
+    Other(#[source] O),
+
+    #[error("Could not read this replica")]
+    Read(#[source] R),
+
+    #[error("Could not write merged register")]
+    Write(#[source] W),
+
+    #[error("Register of key '{0}' is not valid")]
+    InvalidRecord(String),
+}
@@ -0,0 +1,255 @@
+use crate::kv_storage::observed_kv_storage::{Change, ObservedKvStorage};
+use crate::kv_storage::wal_kv_storage::Intent;
+use crate::kv_storage::{self, KvStorageExt};
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use web_sys::wasm_bindgen::closure::Closure;
+use web_sys::wasm_bindgen::JsCast;
+
+// the local storage key changes are broadcast under, other tabs get a `storage` event for it
+pub const CHANNEL_KEY: &str = "__easy_storage/change";
+
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+static NEXT_MESSAGE: AtomicU64 = AtomicU64::new(0);
+
+fn to_intent(change: &Change) -> Intent {
+    match change.clone() {
+        Change::Written { key, value } => Intent::Write(key, value),
+        Change::Deleted { key } => Intent::Delete(key),
+        Change::DeletedPrefix { prefix } => Intent::DeletePrefix(prefix),
+    }
+}
+
+fn to_change(intent: Intent) -> Change {
+    match intent {
+        Intent::Write(key, value) => Change::Written { key, value },
+        Intent::Delete(key) => Change::Deleted { key },
+        Intent::DeletePrefix(prefix) => Change::DeletedPrefix { prefix },
+    }
+}
+
+// how other tabs' changes are found out about, the closures have to live as long as the
+// browser may call them
+enum Source {
+    Events(Closure<dyn FnMut(web_sys::StorageEvent)>),
+    #[allow(dead_code)] // the closure is only kept alive
+    Polling(i32, Closure<dyn FnMut()>),
+}
+
+// lets the listeners of a store see the changes made to it in the page's other tabs, e.g. so a
+// `use_kv_storage` hook shows a value another tab wrote. changes are broadcast through local
+// storage to the other tabs' stores connected with `connect`. without local storage, or with
+// `connect_polling`, the store is read every interval instead and compared with what this tab
+// knows, which also sees changes not made through a connected store, e.g. cookies set by the
+// server. the store can be shared in whatever way the app does, e.g. in an `Rc` for the yew
+// hooks or an `Arc` for the leptos signals. stops when dropped
+pub struct CrossTab {
+    source: Source,
+    unsubscribe: Option<Box<dyn FnOnce()>>,
+}
+
+impl CrossTab {
+    pub fn connect<S, P>(store: P) -> Self
+    where
+        S: kv_storage::KvStorage + 'static,
+        P: Deref<Target = ObservedKvStorage<S>> + Clone + 'static,
+    {
+        match local_storage() {
+            Some(_) => Self::connect_events(store),
+            None => {
+                log::info!("local storage is not available, polling for changes of other tabs");
+                Self::connect_polling(store, DEFAULT_POLL_INTERVAL)
+            }
+        }
+    }
+
+    fn connect_events<S, P>(store: P) -> Self
+    where
+        S: kv_storage::KvStorage + 'static,
+        P: Deref<Target = ObservedKvStorage<S>> + Clone + 'static,
+    {
+        // set while other tabs' changes are passed on, so they aren't broadcast back
+        let relaying = Arc::new(AtomicBool::new(false));
+
+        // tabs are told apart by when they connected, it only keeps messages unique
+        let tab = crate::time::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let listener = store.subscribe({
+            let relaying = relaying.clone();
+            move |change| {
+                if relaying.load(Ordering::Relaxed) {
+                    return;
+                }
+                // local storage only reports changed values, so every message is unique
+                let id = NEXT_MESSAGE.fetch_add(1, Ordering::Relaxed);
+                let message = format!("{tab}-{id}:{}", to_intent(change).encode());
+                // listeners have to be Send, so local storage is looked up every time
+                let sent = local_storage()
+                    .map(|local_storage| local_storage.set_item(CHANNEL_KEY, &message).is_ok());
+                if sent != Some(true) {
+                    log::warn!("could not broadcast change to other tabs");
+                }
+            }
+        });
+
+        let on_storage = Closure::<dyn FnMut(_)>::new({
+            let store = store.clone();
+            move |event: web_sys::StorageEvent| {
+                if event.key().as_deref() != Some(CHANNEL_KEY) {
+                    return;
+                }
+                let Some(message) = event.new_value() else {
+                    return;
+                };
+                let Some(intent) = message
+                    .split_once(':')
+                    .and_then(|(_, intent)| Intent::decode(intent))
+                else {
+                    log::warn!("ignoring invalid change broadcast by another tab");
+                    return;
+                };
+                relaying.store(true, Ordering::Relaxed);
+                store.notify(to_change(intent));
+                relaying.store(false, Ordering::Relaxed);
+            }
+        });
+        if let Some(window) = web_sys::window() {
+            let added = window
+                .add_event_listener_with_callback("storage", on_storage.as_ref().unchecked_ref());
+            if added.is_err() {
+                log::warn!("could not listen for changes of other tabs");
+            }
+        }
+
+        CrossTab {
+            source: Source::Events(on_storage),
+            unsubscribe: Some(Box::new(move || {
+                store.unsubscribe(listener);
+            })),
+        }
+    }
+
+    pub fn connect_polling<S, P>(store: P, interval: Duration) -> Self
+    where
+        S: kv_storage::KvStorage + 'static,
+        P: Deref<Target = ObservedKvStorage<S>> + Clone + 'static,
+    {
+        let known = Arc::new(Mutex::new(read_entries(&store).unwrap_or_default()));
+
+        // this tab's changes are known without polling, so they aren't reported twice
+        let listener = store.subscribe({
+            let known = known.clone();
+            move |change| {
+                let mut known = known.lock().unwrap_or_else(PoisonError::into_inner);
+                match change {
+                    Change::Written { key, value } => {
+                        known.insert(key.clone(), value.clone());
+                    }
+                    Change::Deleted { key } => {
+                        known.remove(key);
+                    }
+                    Change::DeletedPrefix { prefix } => {
+                        known.retain(|key, _| !key.starts_with(prefix.as_str()));
+                    }
+                }
+            }
+        });
+
+        let poll = Closure::<dyn FnMut()>::new({
+            let store = store.clone();
+            move || {
+                let Some(current) = read_entries(&store) else {
+                    return;
+                };
+                let changes = {
+                    let mut known = known.lock().unwrap_or_else(PoisonError::into_inner);
+                    let changes = diff(&known, &current);
+                    *known = current;
+                    changes
+                };
+                // without holding the lock, listeners may change the store
+                for change in changes {
+                    store.notify(change);
+                }
+            }
+        });
+        let handle = web_sys::window()
+            .and_then(|window| {
+                window
+                    .set_interval_with_callback_and_timeout_and_arguments_0(
+                        poll.as_ref().unchecked_ref(),
+                        interval.as_millis().try_into().unwrap_or(i32::MAX),
+                    )
+                    .ok()
+            })
+            .unwrap_or_else(|| {
+                log::warn!("could not poll for changes of other tabs");
+                -1
+            });
+
+        CrossTab {
+            source: Source::Polling(handle, poll),
+            unsubscribe: Some(Box::new(move || {
+                store.unsubscribe(listener);
+            })),
+        }
+    }
+}
+
+impl Drop for CrossTab {
+    fn drop(&mut self) {
+        if let Some(unsubscribe) = self.unsubscribe.take() {
+            unsubscribe();
+        }
+        let Some(window) = web_sys::window() else {
+            return;
+        };
+        match &self.source {
+            Source::Events(on_storage) => {
+                let _ = window.remove_event_listener_with_callback(
+                    "storage",
+                    on_storage.as_ref().unchecked_ref(),
+                );
+            }
+            Source::Polling(handle, _) => window.clear_interval_with_handle(*handle),
+        }
+    }
+}
+
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+// `None` if the read failed, it's retried with the next poll
+fn read_entries<S: kv_storage::KvStorage>(
+    store: &ObservedKvStorage<S>,
+) -> Option<BTreeMap<String, String>> {
+    match store.inner().scan_all() {
+        Ok(entries) => Some(entries.into_iter().collect()),
+        Err(_) => {
+            log::warn!("could not poll for changes of other tabs");
+            None
+        }
+    }
+}
+
+fn diff(known: &BTreeMap<String, String>, current: &BTreeMap<String, String>) -> Vec<Change> {
+    let deleted = known
+        .keys()
+        .filter(|key| !current.contains_key(*key))
+        .map(|key| Change::Deleted { key: key.clone() });
+    let written = current
+        .iter()
+        .filter(|(key, value)| known.get(*key) != Some(*value))
+        .map(|(key, value)| Change::Written {
+            key: key.clone(),
+            value: value.clone(),
+        });
+    deleted.chain(written).collect()
+}
@@ -0,0 +1,287 @@
+use crate::kv_storage;
+use crate::time::Instant;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Debounce {
+    // flush once no write happened for this long
+    pub quiet_period: Duration,
+    // flush at the latest once the oldest pending write is this old, even while writes keep
+    // coming in
+    pub max_delay: Duration,
+}
+
+impl Default for Debounce {
+    fn default() -> Self {
+        Debounce {
+            quiet_period: Duration::from_millis(500),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    writes: BTreeMap<String, String>,
+    first: Option<Instant>,
+    last: Option<Instant>,
+    shutdown: bool,
+}
+
+struct Shared<S> {
+    inner: S,
+    debounce: Debounce,
+    pending: Mutex<Pending>,
+    wake: Condvar,
+    // held while writing to `inner`, so a flush can't resurrect a key deleted meanwhile
+    flushing: Mutex<()>,
+}
+
+impl<S: kv_storage::KvStorage> Shared<S> {
+    fn pending(&self) -> MutexGuard<'_, Pending> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn flush(&self) -> Result<(), S::WriteErrorType> {
+        let _flushing = self.flushing.lock().unwrap_or_else(PoisonError::into_inner);
+        let writes = self.pending().writes.clone();
+        for (key, value) in writes {
+            self.inner.write(&key, &value)?;
+            // pending values stay readable until they're written, and a newer write since the
+            // snapshot stays pending
+            let mut pending = self.pending();
+            if pending.writes.get(&key) == Some(&value) {
+                pending.writes.remove(&key);
+            }
+            if pending.writes.is_empty() {
+                pending.first = None;
+            }
+        }
+        Ok(())
+    }
+
+    fn run(&self) {
+        let mut pending = self.pending();
+        loop {
+            if pending.shutdown {
+                return;
+            }
+            let (Some(first), Some(last)) = (pending.first, pending.last) else {
+                pending = self
+                    .wake
+                    .wait(pending)
+                    .unwrap_or_else(PoisonError::into_inner);
+                continue;
+            };
+
+            let deadline = (last + self.debounce.quiet_period).min(first + self.debounce.max_delay);
+            let now = Instant::now();
+            if now < deadline {
+                pending = self
+                    .wake
+                    .wait_timeout(pending, deadline - now)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0;
+                continue;
+            }
+
+            drop(pending);
+            if self.flush().is_err() {
+                log::warn!("Could not flush pending writes, retrying after the quiet period");
+                let mut pending = self.pending();
+                pending.first = Some(Instant::now());
+                pending.last = pending.first;
+            }
+            pending = self.pending();
+        }
+    }
+}
+
+// keeps writes in memory and flushes them from a background thread once writes pause for
+// `Debounce::quiet_period`, so e.g. dragging a slider doesn't write every intermediate value.
+// reads see pending writes, deletes and ttl writes go to the wrapped store directly. pending
+// writes are also flushed on `flush`, `into_inner` and drop
+pub struct DebouncedKvStorage<S: kv_storage::KvStorage> {
+    shared: Arc<Shared<S>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<S> DebouncedKvStorage<S>
+where
+    S: kv_storage::KvStorage + Send + Sync + 'static,
+{
+    pub fn new(inner: S, debounce: Debounce) -> Self {
+        let shared = Arc::new(Shared {
+            inner,
+            debounce,
+            pending: Mutex::default(),
+            wake: Condvar::new(),
+            flushing: Mutex::new(()),
+        });
+        let worker = {
+            let shared = shared.clone();
+            thread::spawn(move || shared.run())
+        };
+        DebouncedKvStorage {
+            shared,
+            worker: Some(worker),
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorage> DebouncedKvStorage<S> {
+    pub fn inner(&self) -> &S {
+        &self.shared.inner
+    }
+
+    // stops the background thread and flushes the remaining writes
+    pub fn into_inner(mut self) -> Result<S, S::WriteErrorType> {
+        self.stop();
+        self.shared.flush()?;
+        let shared = self.shared.clone();
+        drop(self);
+        match Arc::try_unwrap(shared) {
+            Ok(shared) => Ok(shared.inner),
+            Err(_) => unreachable!("the worker was joined"),
+        }
+    }
+
+    pub fn pending_writes(&self) -> usize {
+        self.shared.pending().writes.len()
+    }
+
+    pub fn flush(&self) -> Result<(), S::WriteErrorType> {
+        self.shared.flush()
+    }
+
+    fn stop(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.shared.pending().shutdown = true;
+            self.shared.wake.notify_all();
+            let _ = worker.join();
+        }
+    }
+
+    fn discard(&self, matches: impl Fn(&str) -> bool) {
+        let mut pending = self.shared.pending();
+        pending.writes.retain(|key, _| !matches(key));
+        if pending.writes.is_empty() {
+            pending.first = None;
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorage> Drop for DebouncedKvStorage<S> {
+    fn drop(&mut self) {
+        self.stop();
+        if self.shared.flush().is_err() {
+            log::warn!("Could not flush pending writes on drop");
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for DebouncedKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        if let Some(value) = self.shared.pending().writes.get(key) {
+            return Ok(value.clone());
+        }
+        self.shared.inner.read(key)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        let now = Instant::now();
+        let mut pending = self.shared.pending();
+        pending.writes.insert(key.to_string(), value.to_string());
+        pending.first.get_or_insert(now);
+        pending.last = Some(now);
+        drop(pending);
+        self.shared.wake.notify_all();
+        Ok(())
+    }
+
+    // pending values replace stored ones, keys that were never flushed only show up once they are
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let mut page = self.shared.inner.scan(cursor, limit)?;
+        let pending = self.shared.pending();
+        for (key, value) in &mut page.entries {
+            if let Some(pending) = pending.writes.get(key) {
+                value.clone_from(pending);
+            }
+        }
+        Ok(page)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        let _flushing = self
+            .shared
+            .flushing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.discard(|pending| pending == key);
+        self.shared.inner.delete(key)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        let _flushing = self
+            .shared
+            .flushing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.discard(|pending| pending.starts_with(prefix));
+        self.shared.inner.delete_prefix(prefix)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.shared.inner.health_check()
+    }
+}
+
+// pending writes are dropped too
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for DebouncedKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        let _flushing = self
+            .shared
+            .flushing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.discard(|_| true);
+        self.shared.inner.purge()
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for DebouncedKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        let _flushing = self
+            .shared
+            .flushing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.discard(|pending| pending == key);
+        self.shared.inner.write_with_ttl(key, value, ttl)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.flush()?;
+        self.shared.inner.expire_at(key, at)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.flush()?;
+        self.shared.inner.touch(key)
+    }
+}
@@ -0,0 +1,338 @@
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// keys of deduplicated values hold MARKER + "<hex sha-256>", the value itself is stored once
+// under "<blob prefix><hash>" and the number of keys pointing to it under
+// "<blob prefix><hash>.refs"
+const MARKER: &str = "\u{1}blob:";
+
+// stores values of at least `min_size` bytes once per distinct content, e.g. identical images
+// or documents under many keys, smaller values are kept inline. a blob is removed once the
+// last key pointing to it is written over or deleted. the blob prefix is hidden from scans
+pub struct DedupKvStorage<S> {
+    inner: S,
+    min_size: usize,
+    blob_prefix: String,
+    // reference counts are read and written back, so changes through this handle are serialized
+    refs: Mutex<()>,
+}
+
+impl<S> DedupKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        DedupKvStorage {
+            inner,
+            min_size: 1024,
+            blob_prefix: "__blobs/".to_string(),
+            refs: Mutex::new(()),
+        }
+    }
+
+    pub fn with_min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn with_blob_prefix(mut self, prefix: &str) -> Self {
+        self.blob_prefix = prefix.to_string();
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn blob_key(&self, hash: &str) -> String {
+        format!("{}{hash}", self.blob_prefix)
+    }
+
+    fn refs_key(&self, hash: &str) -> String {
+        format!("{}{hash}.refs", self.blob_prefix)
+    }
+
+    fn hash(value: &str) -> String {
+        Sha256::digest(value.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    // values looking like a pointer are deduplicated regardless of their size, so they can't
+    // be mistaken for one
+    fn deduplicated(&self, value: &str) -> bool {
+        value.len() >= self.min_size || value.starts_with(MARKER)
+    }
+}
+
+impl<S: kv_storage::KvStorage> DedupKvStorage<S> {
+    fn pointed_to(&self, key: &str) -> Result<Option<String>, DedupWriteErrorOf<S>> {
+        let stored = self.inner.read_opt(key).map_err(DedupWriteError::Read)?;
+        Ok(stored.and_then(|stored| stored.strip_prefix(MARKER).map(str::to_string)))
+    }
+
+    fn refs(&self, hash: &str) -> Result<usize, DedupWriteErrorOf<S>> {
+        match self
+            .inner
+            .read_opt(self.refs_key(hash))
+            .map_err(DedupWriteError::Read)?
+        {
+            Some(refs) => refs
+                .parse()
+                .map_err(|_| DedupWriteError::InvalidRefCount(hash.to_string())),
+            None => Ok(0),
+        }
+    }
+
+    fn retain(&self, hash: &str, value: &str) -> Result<(), DedupWriteErrorOf<S>> {
+        let refs = self.refs(hash)?;
+        if refs == 0 {
+            self.inner
+                .write(&self.blob_key(hash), value)
+                .map_err(DedupWriteError::Inner)?;
+        }
+        self.inner
+            .write(&self.refs_key(hash), &(refs + 1).to_string())
+            .map_err(DedupWriteError::Inner)
+    }
+
+    fn release(&self, hash: &str) -> Result<(), DedupWriteErrorOf<S>> {
+        match self.refs(hash)? {
+            // already gone, e.g. removed together with the keys pointing to it
+            0 => Ok(()),
+            1 => {
+                self.inner
+                    .delete(&self.blob_key(hash))
+                    .map_err(DedupWriteError::Inner)?;
+                self.inner
+                    .delete(&self.refs_key(hash))
+                    .map_err(DedupWriteError::Inner)
+            }
+            refs => self
+                .inner
+                .write(&self.refs_key(hash), &(refs - 1).to_string())
+                .map_err(DedupWriteError::Inner),
+        }
+    }
+
+    fn replace(
+        &self,
+        key: &str,
+        value: &str,
+        write: impl FnOnce(&str) -> Result<(), S::WriteErrorType>,
+    ) -> Result<(), DedupWriteErrorOf<S>> {
+        let _refs = self.refs.lock().unwrap_or_else(PoisonError::into_inner);
+        let old = self.pointed_to(key)?;
+        let new = self.deduplicated(value).then(|| Self::hash(value));
+        if let Some(new) = new.as_ref().filter(|new| old.as_ref() != Some(new)) {
+            self.retain(new, value)?;
+        }
+        match &new {
+            Some(new) => write(&format!("{MARKER}{new}")),
+            None => write(value),
+        }
+        .map_err(DedupWriteError::Inner)?;
+        match old {
+            Some(old) if new.as_ref() != Some(&old) => self.release(&old),
+            _ => Ok(()),
+        }
+    }
+
+    // recounts the keys pointing to every blob and removes blobs nothing points to anymore,
+    // e.g. after pointers expired. returns the number of blobs removed
+    pub fn remove_unreferenced(&self) -> Result<usize, DedupWriteErrorOf<S>> {
+        let _refs = self.refs.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut counted: HashMap<String, usize> = HashMap::new();
+        let mut stored_refs = HashMap::new();
+        for (key, stored) in self.inner.scan_all().map_err(DedupWriteError::Read)? {
+            if let Some(blob) = key.strip_prefix(self.blob_prefix.as_str()) {
+                if let Some(hash) = blob.strip_suffix(".refs") {
+                    stored_refs.insert(hash.to_string(), stored);
+                }
+            } else if let Some(hash) = stored.strip_prefix(MARKER) {
+                *counted.entry(hash.to_string()).or_default() += 1;
+            }
+        }
+
+        let mut removed = 0;
+        for (hash, stored) in stored_refs {
+            match counted.remove(&hash) {
+                None => {
+                    self.inner
+                        .delete(&self.blob_key(&hash))
+                        .map_err(DedupWriteError::Inner)?;
+                    self.inner
+                        .delete(&self.refs_key(&hash))
+                        .map_err(DedupWriteError::Inner)?;
+                    removed += 1;
+                }
+                Some(refs) if stored != refs.to_string() => self
+                    .inner
+                    .write(&self.refs_key(&hash), &refs.to_string())
+                    .map_err(DedupWriteError::Inner)?,
+                Some(_) => {}
+            }
+        }
+        Ok(removed)
+    }
+}
+
+type DedupWriteErrorOf<S> = DedupWriteError<
+    <S as kv_storage::KvStorage>::ReadErrorType,
+    <S as kv_storage::KvStorage>::WriteErrorType,
+>;
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for DedupKvStorage<S> {
+    type WriteErrorType = DedupWriteErrorOf<S>;
+    type ReadErrorType = DedupReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let stored = self.inner.read(key).map_err(DedupReadError::Inner)?;
+        let Some(hash) = stored.strip_prefix(MARKER) else {
+            return Ok(stored);
+        };
+        match self.inner.read(&self.blob_key(hash)) {
+            Err(e) if e.is_not_found() => Err(DedupReadError::MissingBlob(key.to_string())),
+            result => result.map_err(DedupReadError::Inner),
+        }
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.replace(key, value, |stored| self.inner.write(key, stored))
+    }
+
+    // blobs are left out, so pages can be shorter than `limit`
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(DedupReadError::Inner)?;
+        let mut entries = Vec::with_capacity(page.entries.len());
+        for (key, stored) in page.entries {
+            if key.starts_with(self.blob_prefix.as_str()) {
+                continue;
+            }
+            let value = match stored.strip_prefix(MARKER) {
+                Some(_) => self.read(&key)?,
+                None => stored,
+            };
+            entries.push((key, value));
+        }
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        let _refs = self.refs.lock().unwrap_or_else(PoisonError::into_inner);
+        let old = self.pointed_to(key)?;
+        self.inner.delete(key).map_err(DedupWriteError::Inner)?;
+        match old {
+            Some(old) => self.release(&old),
+            None => Ok(()),
+        }
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        let _refs = self.refs.lock().unwrap_or_else(PoisonError::into_inner);
+        let released: Vec<_> = self
+            .inner
+            .scan_all()
+            .map_err(DedupWriteError::Read)?
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(prefix) && !key.starts_with(&self.blob_prefix))
+            .filter_map(|(_, stored)| stored.strip_prefix(MARKER).map(str::to_string))
+            .collect();
+        self.inner
+            .delete_prefix(prefix)
+            .map_err(DedupWriteError::Inner)?;
+        for hash in released {
+            self.release(&hash)?;
+        }
+        Ok(())
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for DedupKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        let _refs = self.refs.lock().unwrap_or_else(PoisonError::into_inner);
+        self.inner.purge().map_err(DedupWriteError::Inner)
+    }
+}
+
+// expiring pointers would never release their blob, so values written with a ttl are always
+// stored inline
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for DedupKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        let _refs = self.refs.lock().unwrap_or_else(PoisonError::into_inner);
+        let old = self.pointed_to(key)?;
+        self.inner
+            .write_with_ttl(key, value, ttl)
+            .map_err(DedupWriteError::Inner)?;
+        match old {
+            Some(old) => self.release(&old),
+            None => Ok(()),
+        }
+    }
+
+    // the blob of a key that expires stays until `remove_unreferenced`
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .expire_at(key, at)
+            .map_err(DedupWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key).map_err(DedupWriteError::Inner)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DedupReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Value of key '{0}' is missing")]
+    MissingBlob(String),
+}
+
+impl<E: IsNotFound> IsNotFound for DedupReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            DedupReadError::Inner(e) => e.is_not_found(),
+            DedupReadError::MissingBlob(_) => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DedupWriteError<R, W> {
+    #[error("Could not read existing entries")]
+    Read(#[source] R),
+
+    #[error("Reference count of blob {0} is not valid")]
+    InvalidRefCount(String),
+
+    #[error(transparent)]
+    Inner(W),
+}
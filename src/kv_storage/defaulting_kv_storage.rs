@@ -0,0 +1,130 @@
+use crate::kv_storage::{self, IsNotFound};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+pub struct DefaultingKvStorage<S> {
+    inner: S,
+    defaults: HashMap<String, String>,
+    prefix_defaults: Vec<(String, String)>,
+}
+
+impl<S> DefaultingKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        DefaultingKvStorage {
+            inner,
+            defaults: HashMap::new(),
+            prefix_defaults: Vec::new(),
+        }
+    }
+
+    pub fn with_default(mut self, key: &str, value: &str) -> Self {
+        self.defaults.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn with_prefix_default(mut self, prefix: &str, value: &str) -> Self {
+        self.prefix_defaults
+            .retain(|(registered, _)| registered.as_str() != prefix);
+        self.prefix_defaults
+            .push((prefix.to_string(), value.to_string()));
+        self
+    }
+
+    // an exact key wins over prefixes, and longer prefixes win over shorter ones
+    pub fn default_for(&self, key: &str) -> Option<&str> {
+        if let Some(value) = self.defaults.get(key) {
+            return Some(value);
+        }
+        self.prefix_defaults
+            .iter()
+            .filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: kv_storage::KvStorage> DefaultingKvStorage<S> {
+    pub fn read_or_default(&self, key: &str) -> Result<String, S::ReadErrorType> {
+        match self.inner.read(key) {
+            Err(e) if e.is_not_found() => match self.default_for(key) {
+                Some(value) => Ok(value.to_string()),
+                None => Err(e),
+            },
+            result => result,
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for DefaultingKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.inner.read(key)
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.inner.read_with(key, f)
+    }
+
+    fn read_into(&self, key: &str, buf: &mut String) -> Result<usize, Self::ReadErrorType> {
+        self.inner.read_into(key, buf)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.write(key, value)
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.inner.scan(cursor, limit)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete(key)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete_prefix(prefix)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for DefaultingKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge()
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for DefaultingKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.inner.write_with_ttl(key, value, ttl)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner.expire_at(key, at)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key)
+    }
+}
@@ -0,0 +1,436 @@
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// a delta encoded value is stored as a full copy under "<key>.base" and the changes made since
+// under "<key>.delta1".."<key>.delta<n>", with the index record MARKER + n under the key
+// itself. a snapshot stores the full value under the key again, which replaces the index in a
+// single write. each delta is "<unchanged prefix bytes>:<unchanged suffix bytes>:<replacement>"
+const MARKER: &str = "\u{1}delta:";
+
+// stores updates of large values as the changed range instead of rewriting the whole value,
+// e.g. a big json document where a few fields change per write. every write reads the current
+// value, deltas only pay off if writing is much more expensive than reading. after
+// `snapshot_every` deltas, or if a delta isn't much smaller than the value, the full value is
+// stored again
+pub struct DeltaKvStorage<S> {
+    inner: S,
+    min_size: usize,
+    snapshot_every: usize,
+}
+
+struct Current {
+    value: String,
+    // number of deltas the value is made of, `None` if it's stored in full
+    deltas: Option<usize>,
+}
+
+struct Delta<'a> {
+    prefix: usize,
+    suffix: usize,
+    replacement: &'a str,
+}
+
+impl<'a> Delta<'a> {
+    fn between(old: &str, new: &'a str) -> Delta<'a> {
+        let mut prefix = old
+            .bytes()
+            .zip(new.bytes())
+            .take_while(|(old, new)| old == new)
+            .count();
+        while !new.is_char_boundary(prefix) {
+            prefix -= 1;
+        }
+        let mut suffix = old[prefix..]
+            .bytes()
+            .rev()
+            .zip(new[prefix..].bytes().rev())
+            .take_while(|(old, new)| old == new)
+            .count();
+        while !new.is_char_boundary(new.len() - suffix) {
+            suffix -= 1;
+        }
+        Delta {
+            prefix,
+            suffix,
+            replacement: &new[prefix..new.len() - suffix],
+        }
+    }
+
+    fn parse(stored: &'a str) -> Option<Delta<'a>> {
+        let (prefix, rest) = stored.split_once(':')?;
+        let (suffix, replacement) = rest.split_once(':')?;
+        Some(Delta {
+            prefix: prefix.parse().ok()?,
+            suffix: suffix.parse().ok()?,
+            replacement,
+        })
+    }
+
+    fn encode(&self) -> String {
+        format!("{}:{}:{}", self.prefix, self.suffix, self.replacement)
+    }
+
+    fn apply(&self, old: &str) -> Option<String> {
+        let suffix_start = old.len().checked_sub(self.suffix)?;
+        let prefix = old.get(..self.prefix)?;
+        let suffix = old.get(suffix_start.max(self.prefix)..)?;
+        Some([prefix, self.replacement, suffix].concat())
+    }
+}
+
+impl<S> DeltaKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        DeltaKvStorage {
+            inner,
+            min_size: 4096,
+            snapshot_every: 16,
+        }
+    }
+
+    // smaller values are always stored in full
+    pub fn with_min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn with_snapshot_every(mut self, deltas: usize) -> Self {
+        self.snapshot_every = deltas;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn base_key(key: &str) -> String {
+        format!("{key}.base")
+    }
+
+    fn delta_key(key: &str, delta: usize) -> String {
+        format!("{key}.delta{delta}")
+    }
+
+    fn parse_side_key(key: &str) -> Option<&str> {
+        if let Some(base) = key.strip_suffix(".base") {
+            return Some(base);
+        }
+        let (base, delta) = key.rsplit_once(".delta")?;
+        (!delta.is_empty() && delta.bytes().all(|c| c.is_ascii_digit())).then_some(base)
+    }
+
+    fn parse_index(stored: &str) -> Option<Option<usize>> {
+        match stored.strip_prefix(MARKER) {
+            Some(deltas) => deltas.parse().ok().map(Some),
+            None => Some(None),
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorage> DeltaKvStorage<S> {
+    fn current(&self, key: &str) -> Result<Option<Current>, DeltaReadError<S::ReadErrorType>> {
+        let stored = match self.inner.read(key) {
+            Ok(stored) => stored,
+            Err(e) if e.is_not_found() => return Ok(None),
+            Err(e) => return Err(DeltaReadError::Inner(e)),
+        };
+        let deltas = Self::parse_index(&stored)
+            .ok_or_else(|| DeltaReadError::InvalidIndex(key.to_string()))?;
+        let value = match deltas {
+            Some(deltas) => self.assemble(key, deltas)?,
+            None => stored,
+        };
+        Ok(Some(Current { value, deltas }))
+    }
+
+    fn read_part(&self, part_key: &str) -> Result<String, DeltaReadError<S::ReadErrorType>> {
+        match self.inner.read(part_key) {
+            Err(e) if e.is_not_found() => Err(DeltaReadError::MissingPart(part_key.to_string())),
+            result => result.map_err(DeltaReadError::Inner),
+        }
+    }
+
+    fn assemble(
+        &self,
+        key: &str,
+        deltas: usize,
+    ) -> Result<String, DeltaReadError<S::ReadErrorType>> {
+        let mut value = self.read_part(&Self::base_key(key))?;
+        for delta in 1..=deltas {
+            let delta_key = Self::delta_key(key, delta);
+            let stored = self.read_part(&delta_key)?;
+            value = Delta::parse(&stored)
+                .and_then(|delta| delta.apply(&value))
+                .ok_or(DeltaReadError::InvalidDelta(delta_key))?;
+        }
+        Ok(value)
+    }
+
+    fn remove_parts(&self, key: &str, deltas: usize) -> Result<(), S::WriteErrorType> {
+        self.inner.delete(&Self::base_key(key))?;
+        for delta in 1..=deltas {
+            self.inner.delete(&Self::delta_key(key, delta))?;
+        }
+        Ok(())
+    }
+
+    fn write_full(
+        &self,
+        key: &str,
+        value: &str,
+        old_deltas: Option<usize>,
+        write: impl Fn(&str, &str) -> Result<(), S::WriteErrorType>,
+    ) -> Result<(), DeltaWriteErrorOf<S>> {
+        // a value looking like an index is stored as a base without deltas instead
+        if value.starts_with(MARKER) {
+            write(&Self::base_key(key), value).map_err(DeltaWriteError::Inner)?;
+            write(key, &format!("{MARKER}0")).map_err(DeltaWriteError::Inner)?;
+            for delta in 1..=old_deltas.unwrap_or(0) {
+                self.inner
+                    .delete(&Self::delta_key(key, delta))
+                    .map_err(DeltaWriteError::Inner)?;
+            }
+            return Ok(());
+        }
+        write(key, value).map_err(DeltaWriteError::Inner)?;
+        match old_deltas {
+            Some(deltas) => self
+                .remove_parts(key, deltas)
+                .map_err(DeltaWriteError::Inner),
+            None => Ok(()),
+        }
+    }
+}
+
+type DeltaWriteErrorOf<S> = DeltaWriteError<
+    DeltaReadError<<S as kv_storage::KvStorage>::ReadErrorType>,
+    <S as kv_storage::KvStorage>::WriteErrorType,
+>;
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for DeltaKvStorage<S> {
+    type WriteErrorType = DeltaWriteErrorOf<S>;
+    type ReadErrorType = DeltaReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let stored = self.inner.read(key).map_err(DeltaReadError::Inner)?;
+        match Self::parse_index(&stored) {
+            Some(Some(deltas)) => self.assemble(key, deltas),
+            Some(None) => Ok(stored),
+            None => Err(DeltaReadError::InvalidIndex(key.to_string())),
+        }
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        let write = |key: &str, value: &str| self.inner.write(key, value);
+        let Some(Current {
+            value: old,
+            deltas: old_deltas,
+        }) = self.current(key).map_err(DeltaWriteError::Read)?
+        else {
+            return self.write_full(key, value, None, write);
+        };
+        let deltas = old_deltas.unwrap_or(0);
+        let delta = Delta::between(&old, value);
+        if value.len() < self.min_size
+            || deltas >= self.snapshot_every
+            || delta.replacement.len() > value.len() / 4
+        {
+            return self.write_full(key, value, old_deltas, write);
+        }
+
+        if old_deltas.is_none() {
+            write(&Self::base_key(key), &old).map_err(DeltaWriteError::Inner)?;
+        }
+        // the index goes last, so a failed write doesn't leave it pointing at a missing delta
+        write(&Self::delta_key(key, deltas + 1), &delta.encode())
+            .map_err(DeltaWriteError::Inner)?;
+        write(key, &format!("{MARKER}{}", deltas + 1)).map_err(DeltaWriteError::Inner)
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(DeltaReadError::Inner)?;
+
+        let mut is_delta_encoded = HashMap::new();
+        let mut entries = Vec::with_capacity(page.entries.len());
+        for (key, stored) in page.entries {
+            if let Some(base) = Self::parse_side_key(&key) {
+                if !is_delta_encoded.contains_key(base) {
+                    let encoded = match self.inner.read(base) {
+                        Ok(stored) => stored.starts_with(MARKER),
+                        Err(e) if e.is_not_found() => false,
+                        Err(e) => return Err(DeltaReadError::Inner(e)),
+                    };
+                    is_delta_encoded.insert(base.to_string(), encoded);
+                }
+                if is_delta_encoded[base] {
+                    continue;
+                }
+            }
+            let value = match Self::parse_index(&stored) {
+                Some(Some(deltas)) => self.assemble(&key, deltas)?,
+                Some(None) => stored,
+                None => return Err(DeltaReadError::InvalidIndex(key)),
+            };
+            entries.push((key, value));
+        }
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        let deltas = match self.inner.read(key) {
+            Ok(stored) => Self::parse_index(&stored).flatten(),
+            Err(e) if e.is_not_found() => None,
+            Err(e) => return Err(DeltaWriteError::Read(DeltaReadError::Inner(e))),
+        };
+        self.inner.delete(key).map_err(DeltaWriteError::Inner)?;
+        match deltas {
+            Some(deltas) => self
+                .remove_parts(key, deltas)
+                .map_err(DeltaWriteError::Inner),
+            None => Ok(()),
+        }
+    }
+
+    // the base and deltas share the prefix of their key, so they go with it
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .delete_prefix(prefix)
+            .map_err(DeltaWriteError::Inner)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> DeltaKvStorage<S> {
+    fn for_key_and_parts(
+        &self,
+        key: &str,
+        f: impl Fn(&str) -> Result<(), S::WriteErrorType>,
+    ) -> Result<(), DeltaWriteErrorOf<S>> {
+        let deltas = match self.inner.read(key) {
+            Ok(stored) => Self::parse_index(&stored).flatten(),
+            Err(e) if e.is_not_found() => None,
+            Err(e) => return Err(DeltaWriteError::Read(DeltaReadError::Inner(e))),
+        };
+        f(key).map_err(DeltaWriteError::Inner)?;
+        if let Some(deltas) = deltas {
+            f(&Self::base_key(key)).map_err(DeltaWriteError::Inner)?;
+            for delta in 1..=deltas {
+                f(&Self::delta_key(key, delta)).map_err(DeltaWriteError::Inner)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for DeltaKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge().map_err(DeltaWriteError::Inner)
+    }
+}
+
+// stores every delta encoded value in full again, dropping its base and deltas
+impl<S: kv_storage::KvStorage> kv_storage::KvStorageCompact for DeltaKvStorage<S> {
+    fn compact(&self) -> Result<u64, Self::WriteErrorType> {
+        let read_error = |e| DeltaWriteError::Read(DeltaReadError::Inner(e));
+        let mut reclaimed = 0;
+        for (key, stored) in self.inner.scan_all().map_err(read_error)? {
+            let Some(Some(deltas)) = Self::parse_index(&stored) else {
+                continue;
+            };
+            let value = self.assemble(&key, deltas).map_err(DeltaWriteError::Read)?;
+            let mut before = stored.len();
+            for part_key in (1..=deltas)
+                .map(|delta| Self::delta_key(&key, delta))
+                .chain([Self::base_key(&key)])
+            {
+                before += self
+                    .read_part(&part_key)
+                    .map_err(DeltaWriteError::Read)?
+                    .len();
+            }
+            self.write_full(&key, &value, Some(deltas), |key, value| {
+                self.inner.write(key, value)
+            })?;
+            reclaimed += before.saturating_sub(value.len()) as u64;
+        }
+        Ok(reclaimed)
+    }
+}
+
+// values written with a ttl are always stored in full
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for DeltaKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        let old_deltas = match self.inner.read(key) {
+            Ok(stored) => Self::parse_index(&stored).flatten(),
+            Err(e) if e.is_not_found() => None,
+            Err(e) => return Err(DeltaWriteError::Read(DeltaReadError::Inner(e))),
+        };
+        self.write_full(key, value, old_deltas, |key, value| {
+            self.inner.write_with_ttl(key, value, ttl)
+        })
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.for_key_and_parts(key, |key| self.inner.expire_at(key, at))
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.for_key_and_parts(key, |key| self.inner.touch(key))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DeltaReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Part '{0}' is missing")]
+    MissingPart(String),
+
+    #[error("Delta index of key '{0}' is not valid")]
+    InvalidIndex(String),
+
+    #[error("Delta '{0}' does not apply")]
+    InvalidDelta(String),
+}
+
+impl<E: IsNotFound> IsNotFound for DeltaReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            DeltaReadError::Inner(e) => e.is_not_found(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DeltaWriteError<R, W> {
+    #[error("Could not read current value")]
+    Read(#[source] R),
+
+    #[error(transparent)]
+    Inner(W),
+}
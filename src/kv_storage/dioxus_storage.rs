@@ -0,0 +1,117 @@
+use crate::kv_storage::observed_kv_storage::{ListenerId, ObservedKvStorage};
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use dioxus::dioxus_core::{use_drop, use_hook};
+use dioxus::prelude::*;
+use std::sync::OnceLock;
+
+// where `use_storage` keeps values: cookies on the web and files on windows and android. the
+// other platforms have no persistent backend yet, their values are kept in memory
+#[cfg(target_family = "wasm")]
+pub type PlatformKvStorage = crate::kv_storage::wasm_cookies_kv_storage::WasmCookiesKvStorage;
+#[cfg(any(target_family = "unix", target_family = "windows"))]
+pub type PlatformKvStorage = crate::kv_storage::file_based_kv_storage::FileBasedKvStorage;
+#[cfg(not(any(
+    target_family = "wasm",
+    target_family = "unix",
+    target_family = "windows"
+)))]
+pub type PlatformKvStorage = crate::kv_storage::memory_kv_storage::MemoryKvStorage;
+
+// shared by every component, so they all see each other's changes
+pub fn platform_storage() -> &'static ObservedKvStorage<PlatformKvStorage> {
+    static STORE: OnceLock<ObservedKvStorage<PlatformKvStorage>> = OnceLock::new();
+    STORE.get_or_init(|| {
+        #[cfg(not(any(
+            target_family = "wasm",
+            target_family = "unix",
+            target_family = "windows"
+        )))]
+        log::warn!("no persistent backend on this platform, stored values are kept in memory");
+        ObservedKvStorage::new(PlatformKvStorage::default())
+    })
+}
+
+pub struct StorageHandle<S: 'static> {
+    store: &'static ObservedKvStorage<S>,
+    key: String,
+    value: SyncSignal<Option<String>>,
+}
+
+impl<S> Clone for StorageHandle<S> {
+    fn clone(&self) -> Self {
+        StorageHandle {
+            store: self.store,
+            key: self.key.clone(),
+            value: self.value,
+        }
+    }
+}
+
+impl<S: KvStorage + Send + Sync + 'static> StorageHandle<S> {
+    // subscribes the component reading it, like reading any other signal
+    pub fn value(&self) -> Option<String> {
+        self.value.cloned()
+    }
+
+    pub fn signal(&self) -> ReadSignal<Option<String>, SyncStorage> {
+        self.value.into()
+    }
+
+    // every component using the key re-renders with the new value
+    pub fn set(&self, value: &str) -> Result<(), S::WriteErrorType> {
+        self.store.write(&self.key, value)
+    }
+
+    pub fn delete(&self) -> Result<(), S::WriteErrorType> {
+        self.store.delete(&self.key)
+    }
+}
+
+// the value of `key` in `platform_storage()`, the component re-renders whenever the key is
+// changed, e.g. by another component:
+//
+//     #[component]
+//     fn Volume() -> Element {
+//         let volume = use_storage("volume");
+//         let louder = {
+//             let volume = volume.clone();
+//             move |_| {
+//                 let _ = volume.set("11");
+//             }
+//         };
+//         rsx! { button { onclick: louder, {volume.value().unwrap_or("3".to_string())} } }
+//     }
+pub fn use_storage(key: &str) -> StorageHandle<PlatformKvStorage> {
+    use_storage_in(platform_storage(), key)
+}
+
+// like `use_storage` with another store. the store and key of the first render are kept
+pub fn use_storage_in<S>(store: &'static ObservedKvStorage<S>, key: &str) -> StorageHandle<S>
+where
+    S: KvStorage + Send + Sync + 'static,
+{
+    let (handle, listener): (StorageHandle<S>, ListenerId) = use_hook(|| {
+        let initial = store.read_opt(key).unwrap_or_else(|_| {
+            log::warn!("could not read '{key}'");
+            None
+        });
+        let value = SyncSignal::new_maybe_sync(initial);
+        let listened = key.to_string();
+        let listener = store.subscribe(move |change| {
+            if change.affects(&listened) {
+                let mut value = value;
+                value.set(change.value().map(str::to_string));
+            }
+        });
+        let handle = StorageHandle {
+            store,
+            key: key.to_string(),
+            value,
+        };
+        (handle, listener)
+    });
+    use_drop(move || {
+        store.unsubscribe(listener);
+    });
+    handle
+}
@@ -0,0 +1,84 @@
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use std::collections::BTreeMap;
+
+// eframe's `Storage` on top of a store, e.g. to keep an app's state with the rest of its data:
+//
+//     let mut storage = EguiStorage::new(store).with_prefix("egui/");
+//     let app: MyApp = eframe::get_value(&storage, eframe::APP_KEY).unwrap_or_default();
+//     ...
+//     eframe::App::save(&mut app, &mut storage);
+//     eframe::Storage::flush(&mut storage);
+//
+// `get_value` and `set_value` need eframe's `persistence` feature. like eframe's own storage,
+// changes are kept in memory until `flush`
+pub struct EguiStorage<S> {
+    inner: S,
+    prefix: String,
+    // `None` for removed keys
+    pending: BTreeMap<String, Option<String>>,
+}
+
+impl<S> EguiStorage<S> {
+    pub fn new(inner: S) -> Self {
+        EguiStorage {
+            inner,
+            prefix: String::new(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    // prepended to every key eframe uses
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    // changes that haven't been flushed are lost
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+}
+
+impl<S: KvStorage> eframe::Storage for EguiStorage<S> {
+    fn get_string(&self, key: &str) -> Option<String> {
+        let key = self.key(key);
+        if let Some(pending) = self.pending.get(&key) {
+            return pending.clone();
+        }
+        self.inner.read_opt(&key).unwrap_or_else(|_| {
+            log::warn!("could not read '{key}'");
+            None
+        })
+    }
+
+    fn set_string(&mut self, key: &str, value: String) {
+        self.pending.insert(self.key(key), Some(value));
+    }
+
+    fn remove_string(&mut self, key: &str) {
+        self.pending.insert(self.key(key), None);
+    }
+
+    // changes that fail are kept and retried on the next flush
+    fn flush(&mut self) {
+        let inner = &self.inner;
+        self.pending.retain(|key, value| {
+            let result = match value {
+                Some(value) => inner.write(key, value),
+                None => inner.delete(key),
+            };
+            if result.is_err() {
+                log::warn!("could not flush '{key}'");
+            }
+            result.is_err()
+        });
+    }
+}
@@ -0,0 +1,136 @@
+use crate::kv_storage;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+
+// stores every key as the lowercase hex of its utf-8 bytes, which any backend accepts: no
+// separators, whitespace or unicode, and no clashes on case insensitive file systems. unlike
+// hashing this keeps keys enumerable, and hex preserves byte order and prefixes, so `scan`
+// and `delete_prefix` map directly onto the wrapped store
+pub struct EncodedKeyKvStorage<S>(S);
+
+impl<S> EncodedKeyKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        EncodedKeyKvStorage(inner)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+pub fn encode_key(key: &str) -> String {
+    key.bytes()
+        .fold(String::with_capacity(key.len() * 2), |mut encoded, byte| {
+            let _ = write!(encoded, "{byte:02x}");
+            encoded
+        })
+}
+
+// `None` for keys that weren't written through `encode_key`
+pub fn decode_key(encoded: &str) -> Option<String> {
+    if !encoded.len().is_multiple_of(2) || encoded.bytes().any(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+    let bytes = (0..encoded.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(encoded.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for EncodedKeyKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.0.read(&encode_key(key))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.write(&encode_key(key), value)
+    }
+
+    // keys stored by something else are skipped
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let mut entries = Vec::new();
+        let mut inner_cursor = cursor.map(encode_key);
+        loop {
+            let page = self.0.scan(inner_cursor.as_deref(), limit)?;
+            entries.extend(
+                page.entries
+                    .into_iter()
+                    .filter_map(|(key, value)| Some((decode_key(&key)?, value))),
+            );
+
+            if entries.len() >= limit {
+                entries.truncate(limit);
+                let cursor = entries.last().map(|(key, _)| key.clone());
+                return Ok(kv_storage::Page { entries, cursor });
+            }
+            match page.cursor {
+                // the cursor has to be a decodable key, keep going past foreign ones
+                Some(next) => match decode_key(&next) {
+                    Some(cursor) => {
+                        return Ok(kv_storage::Page {
+                            entries,
+                            cursor: Some(cursor),
+                        })
+                    }
+                    None => inner_cursor = Some(next),
+                },
+                None => {
+                    return Ok(kv_storage::Page {
+                        entries,
+                        cursor: None,
+                    })
+                }
+            }
+        }
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.delete(&encode_key(key))
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.delete_prefix(&encode_key(prefix))
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.0.health_check()
+    }
+}
+
+// keys not written through this wrapper are removed too
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for EncodedKeyKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.0.purge()
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for EncodedKeyKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.0.write_with_ttl(&encode_key(key), value, ttl)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.0.expire_at(&encode_key(key), at)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.touch(&encode_key(key))
+    }
+}
@@ -0,0 +1,316 @@
+use crate::kv_storage::enveloped_kv_storage::{self as envelope, Metadata};
+use crate::kv_storage::secret::{self, SecretString};
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use std::collections::BTreeMap;
+use std::str::Utf8Error;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+const NONCE_LEN: usize = 12;
+
+// values are stored as base64(nonce || ciphertext) in an envelope flagged as encrypted, with the
+// key name as associated data so an encrypted value can't be moved to a different key
+// unnoticed. the encryption keys are kept in numbered slots, values of slots other than 0 are
+// prefixed with "<slot>$", which base64 doesn't use. values written before the envelope are the
+// same without it and still read
+pub struct EncryptedKvStorage<S> {
+    inner: S,
+    ciphers: BTreeMap<u32, ChaCha20Poly1305>,
+    // the slot new values are encrypted with
+    current: u32,
+}
+
+impl<S> EncryptedKvStorage<S> {
+    // `key` goes into slot 0, which is also where stores from before slots keep theirs
+    pub fn new(inner: S, key: &[u8; 32]) -> Self {
+        EncryptedKvStorage {
+            inner,
+            ciphers: BTreeMap::from([(0, ChaCha20Poly1305::new(key.into()))]),
+            current: 0,
+        }
+    }
+
+    // puts `key` into `slot` and encrypts new values with it, the other slots' keys still
+    // decrypt what they encrypted. to rotate, add the new key in a new slot, `rewrap_all` and
+    // drop the old key once nothing uses it, e.g. `new(inner, &old).with_key(1, &new)`
+    pub fn with_key(mut self, slot: u32, key: &[u8; 32]) -> Self {
+        self.ciphers.insert(slot, ChaCha20Poly1305::new(key.into()));
+        self.current = slot;
+        self
+    }
+
+    // a store with only these slots, e.g. once `rewrap_all` moved everything off the old key:
+    // `with_keys(inner, &[(1, &new)], 1)`
+    pub fn with_keys(inner: S, keys: &[(u32, &[u8; 32])], current: u32) -> Self {
+        EncryptedKvStorage {
+            inner,
+            ciphers: keys
+                .iter()
+                .map(|(slot, key)| (*slot, ChaCha20Poly1305::new((*key).into())))
+                .collect(),
+            current,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn encrypt<E>(&self, key: &str, value: &str) -> Result<String, EncryptedWriteError<E>> {
+        let cipher = self
+            .ciphers
+            .get(&self.current)
+            .ok_or(EncryptedWriteError::UnknownKeySlot(self.current))?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: value.as_bytes(),
+            aad: key.as_bytes(),
+        };
+        let ciphertext = cipher
+            .encrypt(&nonce, payload)
+            .map_err(|_| EncryptedWriteError::Encrypt)?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        let sealed = match self.current {
+            0 => BASE64.encode(sealed),
+            slot => format!("{slot}${}", BASE64.encode(sealed)),
+        };
+        let metadata = Metadata {
+            encrypted: true,
+            ..Metadata::default()
+        };
+        Ok(envelope::seal(&metadata, &sealed))
+    }
+
+    // the slot a stored value was encrypted in and the value without the envelope and prefix
+    fn slot<'a, E>(key: &str, value: &'a str) -> Result<(u32, &'a str), EncryptedReadError<E>> {
+        let value = match envelope::is_sealed(value) {
+            true => match envelope::open(value) {
+                Some((metadata, value)) if metadata.encrypted => value,
+                _ => return Err(EncryptedReadError::InvalidEnvelope(key.to_string())),
+            },
+            false => value,
+        };
+        Ok(value
+            .split_once('$')
+            .and_then(|(slot, sealed)| Some((slot.parse().ok()?, sealed)))
+            .unwrap_or((0, value)))
+    }
+
+    fn decrypt<E>(&self, key: &str, value: &str) -> Result<String, EncryptedReadError<E>> {
+        let (slot, value) = Self::slot(key, value)?;
+        let cipher = self
+            .ciphers
+            .get(&slot)
+            .ok_or(EncryptedReadError::UnknownKeySlot(slot))?;
+        let sealed = BASE64.decode(value)?;
+        if sealed.len() < NONCE_LEN {
+            return Err(EncryptedReadError::Decrypt);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: key.as_bytes(),
+        };
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| EncryptedReadError::Decrypt)?;
+        Ok(secret::into_string(plaintext)?)
+    }
+}
+
+impl<S: kv_storage::KvStorage> EncryptedKvStorage<S> {
+    // `read` with the value wiped from memory once it's dropped
+    pub fn read_secret(
+        &self,
+        key: &str,
+    ) -> Result<SecretString, EncryptedReadError<S::ReadErrorType>> {
+        let sealed = self.inner.read(key).map_err(EncryptedReadError::Inner)?;
+        self.decrypt(key, &sealed).map(SecretString::new)
+    }
+
+    pub fn write_secret(
+        &self,
+        key: &str,
+        value: &SecretString,
+    ) -> Result<(), EncryptedWriteError<S::WriteErrorType>> {
+        kv_storage::KvStorage::write(self, key, value.expose())
+    }
+
+    // re-encrypts every value that isn't in the current slot with its key and returns how many
+    // there were. stops at the first value that can't be decrypted, the ones before stay
+    // rewrapped
+    pub fn rewrap_all(
+        &self,
+    ) -> Result<usize, EncryptedRewrapError<S::ReadErrorType, S::WriteErrorType>> {
+        let entries = self
+            .inner
+            .scan_all()
+            .map_err(|e| EncryptedRewrapError::Read(EncryptedReadError::Inner(e)))?;
+        let mut rewrapped = 0;
+        for (key, sealed) in entries {
+            let slot = Self::slot(&key, &sealed)
+                .map_err(EncryptedRewrapError::Read)?
+                .0;
+            if slot == self.current {
+                continue;
+            }
+            let value = self
+                .decrypt(&key, &sealed)
+                .map(SecretString::new)
+                .map_err(EncryptedRewrapError::Read)?;
+            let resealed = self
+                .encrypt(&key, value.expose())
+                .map_err(EncryptedRewrapError::Write)?;
+            self.inner
+                .write(&key, &resealed)
+                .map_err(|e| EncryptedRewrapError::Write(EncryptedWriteError::Inner(e)))?;
+            rewrapped += 1;
+        }
+        Ok(rewrapped)
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for EncryptedKvStorage<S> {
+    type WriteErrorType = EncryptedWriteError<S::WriteErrorType>;
+    type ReadErrorType = EncryptedReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let sealed = self.inner.read(key).map_err(EncryptedReadError::Inner)?;
+        self.decrypt(key, &sealed)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        let sealed = self.encrypt(key, value)?;
+        self.inner
+            .write(key, &sealed)
+            .map_err(EncryptedWriteError::Inner)
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(EncryptedReadError::Inner)?;
+        let entries = page
+            .entries
+            .into_iter()
+            .map(|(key, sealed)| {
+                let value = self.decrypt(&key, &sealed)?;
+                Ok((key, value))
+            })
+            .collect::<Result<_, Self::ReadErrorType>>()?;
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete(key).map_err(EncryptedWriteError::Inner)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .delete_prefix(prefix)
+            .map_err(EncryptedWriteError::Inner)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for EncryptedKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge().map_err(EncryptedWriteError::Inner)
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for EncryptedKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        let sealed = self.encrypt(key, value)?;
+        self.inner
+            .write_with_ttl(key, &sealed, ttl)
+            .map_err(EncryptedWriteError::Inner)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .expire_at(key, at)
+            .map_err(EncryptedWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key).map_err(EncryptedWriteError::Inner)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum EncryptedReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Stored value is not valid base64")]
+    Decode(#[from] base64::DecodeError),
+
+    #[error("Could not decrypt value (wrong key or tampered data)")]
+    Decrypt,
+
+    #[error("Value was encrypted with key slot {0}, which the store has no key for")]
+    UnknownKeySlot(u32),
+
+    #[error("Decrypted value is not valid utf-8")]
+    Utf8(#[from] Utf8Error),
+
+    #[error("Envelope of key '{0}' is not valid")]
+    InvalidEnvelope(String),
+}
+
+impl<E: IsNotFound> IsNotFound for EncryptedReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            EncryptedReadError::Inner(e) => e.is_not_found(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum EncryptedWriteError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Could not encrypt value")]
+    Encrypt,
+
+    #[error("Store has no key in slot {0} to encrypt with")]
+    UnknownKeySlot(u32),
+}
+
+#[derive(Error, Debug)]
+pub enum EncryptedRewrapError<R, W> {
+    #[error("Could not read value to rewrap")]
+    Read(#[source] EncryptedReadError<R>),
+
+    #[error("Could not write rewrapped value")]
+    Write(#[source] EncryptedWriteError<W>),
+}
@@ -0,0 +1,306 @@
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use crate::time;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+// MARKER + "<created>:<updated>:<flags>:<content type len>:<content type>" + value, times as
+// unix millis or empty if unknown, flags 'z' for compressed and 'e' for encrypted. the content
+// type's length is given so it can hold any character
+const MARKER: &str = "\u{1}env:";
+
+// what the envelope says about a value, everything is optional so values stored without one
+// have the default metadata
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub content_type: Option<String>,
+    pub compressed: bool,
+    pub encrypted: bool,
+    pub created: Option<SystemTime>,
+    pub updated: Option<SystemTime>,
+}
+
+fn millis(time: Option<SystemTime>) -> String {
+    time.map(|time| {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis()
+            .to_string()
+    })
+    .unwrap_or_default()
+}
+
+fn parse_millis(millis: &str) -> Option<Option<SystemTime>> {
+    if millis.is_empty() {
+        return Some(None);
+    }
+    Some(Some(
+        UNIX_EPOCH + Duration::from_millis(millis.parse().ok()?),
+    ))
+}
+
+// the format is shared, so wrappers that want to keep metadata with a value can write envelopes
+// other wrappers understand instead of a prefix of their own. `CompressedKvStorage` and
+// `EncryptedKvStorage` do, a wrapper storing values that could start with MARKER puts them in an
+// envelope without flags
+pub fn seal(metadata: &Metadata, value: &str) -> String {
+    let content_type = metadata.content_type.as_deref().unwrap_or_default();
+    let mut flags = String::new();
+    if metadata.compressed {
+        flags.push('z');
+    }
+    if metadata.encrypted {
+        flags.push('e');
+    }
+    format!(
+        "{MARKER}{}:{}:{flags}:{}:{content_type}{value}",
+        millis(metadata.created),
+        millis(metadata.updated),
+        content_type.len(),
+    )
+}
+
+pub fn is_sealed(stored: &str) -> bool {
+    stored.starts_with(MARKER)
+}
+
+// the metadata and the value, `None` if the envelope is malformed
+pub fn open(stored: &str) -> Option<(Metadata, &str)> {
+    let Some(envelope) = stored.strip_prefix(MARKER) else {
+        return Some((Metadata::default(), stored));
+    };
+    let mut fields = envelope.splitn(5, ':');
+    let created = parse_millis(fields.next()?)?;
+    let updated = parse_millis(fields.next()?)?;
+    let flags = fields.next()?;
+    let content_type_len: usize = fields.next()?.parse().ok()?;
+    let rest = fields.next()?;
+    let (content_type, value) = (rest.get(..content_type_len)?, rest.get(content_type_len..)?);
+    if !flags.chars().all(|flag| flag == 'z' || flag == 'e') {
+        return None;
+    }
+    let metadata = Metadata {
+        content_type: Some(content_type.to_string())
+            .filter(|content_type| !content_type.is_empty()),
+        compressed: flags.contains('z'),
+        encrypted: flags.contains('e'),
+        created,
+        updated,
+    };
+    Some((metadata, value))
+}
+
+// keeps every value in an envelope with its content type and when it was first and last
+// written. under a wrapper that writes envelopes itself, e.g. `CompressedKvStorage`, its flags
+// show up in `metadata`. over one, or over a backend that compresses or encrypts by itself, this
+// can't tell, so the flags are set with `with_encoding`
+pub struct EnvelopedKvStorage<S> {
+    inner: S,
+    content_type: Option<String>,
+    compressed: bool,
+    encrypted: bool,
+}
+
+impl<S> EnvelopedKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        EnvelopedKvStorage {
+            inner,
+            content_type: None,
+            compressed: false,
+            encrypted: false,
+        }
+    }
+
+    // of values written without one
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    pub fn with_encoding(mut self, compressed: bool, encrypted: bool) -> Self {
+        self.compressed = compressed;
+        self.encrypted = encrypted;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: kv_storage::KvStorage> EnvelopedKvStorage<S> {
+    // `None` if the key is missing
+    pub fn metadata(
+        &self,
+        key: &str,
+    ) -> Result<Option<Metadata>, EnvelopeReadError<S::ReadErrorType>> {
+        let Some(stored) = self.inner.read_opt(key).map_err(EnvelopeReadError::Inner)? else {
+            return Ok(None);
+        };
+        let (mut metadata, value) =
+            open(&stored).ok_or_else(|| EnvelopeReadError::InvalidEnvelope(key.to_string()))?;
+        if is_sealed(value) {
+            let (nested, _) =
+                open(value).ok_or_else(|| EnvelopeReadError::InvalidEnvelope(key.to_string()))?;
+            metadata.compressed |= nested.compressed;
+            metadata.encrypted |= nested.encrypted;
+        }
+        Ok(Some(metadata))
+    }
+
+    // overwriting keeps the time the key was created
+    pub fn write_with_content_type(
+        &self,
+        key: &str,
+        value: &str,
+        content_type: Option<&str>,
+    ) -> Result<(), EnvelopeWriteErrorOf<S>> {
+        let sealed = self.seal_for(key, value, content_type)?;
+        self.inner
+            .write(key, &sealed)
+            .map_err(EnvelopeWriteError::Inner)
+    }
+
+    fn seal_for(
+        &self,
+        key: &str,
+        value: &str,
+        content_type: Option<&str>,
+    ) -> Result<String, EnvelopeWriteErrorOf<S>> {
+        let created = match self.inner.read_opt(key).map_err(EnvelopeWriteError::Read)? {
+            Some(stored) => open(&stored).and_then(|(metadata, _)| metadata.created),
+            None => None,
+        };
+        let now = time::now();
+        let metadata = Metadata {
+            content_type: content_type.map(str::to_string),
+            compressed: self.compressed,
+            encrypted: self.encrypted,
+            created: created.or(Some(now)),
+            updated: Some(now),
+        };
+        Ok(seal(&metadata, value))
+    }
+
+    fn unseal(key: &str, stored: &str) -> Result<String, EnvelopeReadError<S::ReadErrorType>> {
+        let (_, value) =
+            open(stored).ok_or_else(|| EnvelopeReadError::InvalidEnvelope(key.to_string()))?;
+        Ok(value.to_string())
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for EnvelopedKvStorage<S> {
+    type WriteErrorType = EnvelopeWriteErrorOf<S>;
+    type ReadErrorType = EnvelopeReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let stored = self.inner.read(key).map_err(EnvelopeReadError::Inner)?;
+        Self::unseal(key, &stored)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.write_with_content_type(key, value, self.content_type.as_deref())
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(EnvelopeReadError::Inner)?;
+        let entries = page
+            .entries
+            .into_iter()
+            .map(|(key, stored)| {
+                let value = Self::unseal(&key, &stored)?;
+                Ok((key, value))
+            })
+            .collect::<Result<_, Self::ReadErrorType>>()?;
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete(key).map_err(EnvelopeWriteError::Inner)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .delete_prefix(prefix)
+            .map_err(EnvelopeWriteError::Inner)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for EnvelopedKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge().map_err(EnvelopeWriteError::Inner)
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for EnvelopedKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        let sealed = self.seal_for(key, value, self.content_type.as_deref())?;
+        self.inner
+            .write_with_ttl(key, &sealed, ttl)
+            .map_err(EnvelopeWriteError::Inner)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .expire_at(key, at)
+            .map_err(EnvelopeWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key).map_err(EnvelopeWriteError::Inner)
+    }
+}
+
+type EnvelopeWriteErrorOf<S> = EnvelopeWriteError<
+    <S as kv_storage::KvStorage>::ReadErrorType,
+    <S as kv_storage::KvStorage>::WriteErrorType,
+>;
+
+#[derive(Error, Debug)]
+pub enum EnvelopeReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Envelope of key '{0}' is not valid")]
+    InvalidEnvelope(String),
+}
+
+impl<E: IsNotFound> IsNotFound for EnvelopeReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            EnvelopeReadError::Inner(e) => e.is_not_found(),
+            EnvelopeReadError::InvalidEnvelope(_) => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum EnvelopeWriteError<R, W> {
+    #[error("Could not read current envelope")]
+    Read(#[source] R),
+
+    #[error(transparent)]
+    Inner(W),
+}
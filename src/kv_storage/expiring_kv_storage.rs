@@ -0,0 +1,229 @@
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use crate::time;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+// entries with an expiry are stored as MARKER + "<unix millis>:" + value, entries without one
+// as is. values that happen to start with the marker get an empty expiry so they can't be
+// mistaken for an envelope
+const MARKER: &str = "\u{1}exp:";
+
+pub struct ExpiringKvStorage<S> {
+    inner: S,
+    default_ttl: Option<Duration>,
+    lazy_deletion: bool,
+}
+
+impl<S> ExpiringKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        ExpiringKvStorage {
+            inner,
+            default_ttl: None,
+            lazy_deletion: false,
+        }
+    }
+
+    // applied to plain writes and used by `touch`
+    pub fn with_default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = Some(ttl);
+        self
+    }
+
+    // delete expired entries from the wrapped store when they are read
+    pub fn with_lazy_deletion(mut self, lazy_deletion: bool) -> Self {
+        self.lazy_deletion = lazy_deletion;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn seal(value: &str, expires: Option<SystemTime>) -> String {
+        match expires {
+            Some(expires) => {
+                let millis = expires
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                format!("{MARKER}{millis}:{value}")
+            }
+            None if value.starts_with(MARKER) => format!("{MARKER}:{value}"),
+            None => value.to_string(),
+        }
+    }
+
+    // returns the value and its expiry, `None` if the envelope is malformed
+    fn open(stored: &str) -> Option<(&str, Option<SystemTime>)> {
+        let Some(envelope) = stored.strip_prefix(MARKER) else {
+            return Some((stored, None));
+        };
+        let (millis, value) = envelope.split_once(':')?;
+        if millis.is_empty() {
+            return Some((value, None));
+        }
+        let expires = UNIX_EPOCH + Duration::from_millis(millis.parse().ok()?);
+        Some((value, Some(expires)))
+    }
+}
+
+impl<S: kv_storage::KvStorage> ExpiringKvStorage<S> {
+    fn unseal(
+        &self,
+        key: &str,
+        stored: &str,
+        now: SystemTime,
+    ) -> Result<Option<String>, ExpiringReadError<S::ReadErrorType>> {
+        let (value, expires) = Self::open(stored)
+            .ok_or_else(|| ExpiringReadError::InvalidEnvelope(key.to_string()))?;
+        if expires.is_some_and(|expires| expires <= now) {
+            if self.lazy_deletion && self.inner.delete(key).is_err() {
+                log::warn!("Could not delete expired key '{key}'");
+            }
+            return Ok(None);
+        }
+        Ok(Some(value.to_string()))
+    }
+
+    fn default_expiry(&self) -> Option<SystemTime> {
+        self.default_ttl.map(|ttl| time::now() + ttl)
+    }
+
+    // rewrites the current value of `key` with a new expiry, missing and expired keys are left
+    // alone
+    fn reseal(
+        &self,
+        key: &str,
+        expires: Option<SystemTime>,
+    ) -> Result<(), ExpiringWriteError<S::ReadErrorType, S::WriteErrorType>> {
+        let Some(stored) = self.inner.read_opt(key).map_err(ExpiringWriteError::Read)? else {
+            return Ok(());
+        };
+        let Some((value, current)) = Self::open(&stored) else {
+            return Ok(());
+        };
+        if current.is_some_and(|current| current <= time::now()) {
+            return Ok(());
+        }
+        self.inner
+            .write(key, &Self::seal(value, expires))
+            .map_err(ExpiringWriteError::Inner)
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for ExpiringKvStorage<S> {
+    type WriteErrorType = ExpiringWriteError<S::ReadErrorType, S::WriteErrorType>;
+    type ReadErrorType = ExpiringReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let stored = self.inner.read(key).map_err(ExpiringReadError::Inner)?;
+        self.unseal(key, &stored, time::now())?
+            .ok_or_else(|| ExpiringReadError::Expired(key.to_string()))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .write(key, &Self::seal(value, self.default_expiry()))
+            .map_err(ExpiringWriteError::Inner)
+    }
+
+    // expired entries are left out, so pages can be shorter than `limit`
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(ExpiringReadError::Inner)?;
+        let now = time::now();
+        let mut entries = Vec::with_capacity(page.entries.len());
+        for (key, stored) in page.entries {
+            if let Some(value) = self.unseal(&key, &stored, now)? {
+                entries.push((key, value));
+            }
+        }
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete(key).map_err(ExpiringWriteError::Inner)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .delete_prefix(prefix)
+            .map_err(ExpiringWriteError::Inner)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for ExpiringKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge().map_err(ExpiringWriteError::Inner)
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorageTtl for ExpiringKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .write(key, &Self::seal(value, Some(time::now() + ttl)))
+            .map_err(ExpiringWriteError::Inner)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.reseal(key, Some(at))
+    }
+
+    // without a default ttl this removes the expiry
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.reseal(key, self.default_expiry())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ExpiringReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Key '{0}' has expired")]
+    Expired(String),
+
+    #[error("Expiry of key '{0}' is not valid")]
+    InvalidEnvelope(String),
+}
+
+impl<E: IsNotFound> IsNotFound for ExpiringReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            ExpiringReadError::Inner(e) => e.is_not_found(),
+            ExpiringReadError::Expired(_) => true,
+            ExpiringReadError::InvalidEnvelope(_) => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ExpiringWriteError<R, W> {
+    #[error("Could not read current value")]
+    Read(#[source] R),
+
+    #[error(transparent)]
+    Inner(W),
+}
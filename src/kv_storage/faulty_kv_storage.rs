@@ -0,0 +1,240 @@
+use crate::kv_storage::retrying_kv_storage::default_sleep;
+use crate::kv_storage::{self, IsNotFound};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// splitmix64, good enough to pick faults and reproducible from the seed on every platform
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    pub(crate) fn fraction(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        match bound {
+            0 => 0,
+            bound => self.next() % bound,
+        }
+    }
+
+    // uniform in [min, max]
+    pub(crate) fn duration(&mut self, min: Duration, max: Duration) -> Duration {
+        let spread = u64::try_from((max - min).as_nanos()).unwrap_or(u64::MAX);
+        min + Duration::from_nanos(self.below(spread.saturating_add(1)))
+    }
+}
+
+// makes operations on the wrapped store fail, slow down or lose data, to check how an
+// application copes with a misbehaving store. faults are picked by a seeded rng, so a run with
+// the same seed and the same sequence of calls injects the same faults
+pub struct FaultyKvStorage<S> {
+    inner: S,
+    rng: Mutex<Rng>,
+    failure_rate: f64,
+    truncation_rate: f64,
+    latency: (Duration, Duration),
+    sleep: Box<dyn Fn(Duration) + Send + Sync>,
+    faults: AtomicUsize,
+}
+
+impl<S> FaultyKvStorage<S> {
+    // injects nothing until configured
+    pub fn new(inner: S, seed: u64) -> Self {
+        FaultyKvStorage {
+            inner,
+            rng: Mutex::new(Rng(seed)),
+            failure_rate: 0.0,
+            truncation_rate: 0.0,
+            latency: (Duration::ZERO, Duration::ZERO),
+            sleep: Box::new(default_sleep),
+            faults: AtomicUsize::new(0),
+        }
+    }
+
+    // fraction of operations failing with `Injected` instead of reaching the wrapped store
+    pub fn with_failure_rate(mut self, rate: f64) -> Self {
+        assert!((0.0..=1.0).contains(&rate), "failure rate must be in 0..=1");
+        self.failure_rate = rate;
+        self
+    }
+
+    // fraction of writes storing only a random prefix of the value while reporting success
+    pub fn with_truncation_rate(mut self, rate: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&rate),
+            "truncation rate must be in 0..=1"
+        );
+        self.truncation_rate = rate;
+        self
+    }
+
+    // every operation is delayed by a random duration between `min` and `max`
+    pub fn with_latency(mut self, min: Duration, max: Duration) -> Self {
+        assert!(min <= max, "minimum latency must not exceed the maximum");
+        self.latency = (min, max);
+        self
+    }
+
+    // replaces the blocking sleep used for latency, e.g. for tests
+    pub fn with_sleep(mut self, sleep: impl Fn(Duration) + Send + Sync + 'static) -> Self {
+        self.sleep = Box::new(sleep);
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // number of failures and truncations injected so far
+    pub fn faults(&self) -> usize {
+        self.faults.load(Ordering::Relaxed)
+    }
+
+    fn rng(&self) -> MutexGuard<'_, Rng> {
+        self.rng.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // waits out the injected latency, then decides whether the operation fails
+    fn inject(&self, op: &'static str) -> Result<(), Injected> {
+        let (delay, fail) = {
+            let mut rng = self.rng();
+            let (min, max) = self.latency;
+            (rng.duration(min, max), rng.fraction() < self.failure_rate)
+        };
+        if !delay.is_zero() {
+            (self.sleep)(delay);
+        }
+        if !fail {
+            return Ok(());
+        }
+        self.faults.fetch_add(1, Ordering::Relaxed);
+        log::debug!("Injecting failure into storage {op}");
+        Err(Injected(op))
+    }
+
+    fn truncate<'a>(&self, key: &str, value: &'a str) -> &'a str {
+        let cut = {
+            let mut rng = self.rng();
+            if rng.fraction() >= self.truncation_rate {
+                return value;
+            }
+            rng.below(value.len() as u64) as usize
+        };
+        self.faults.fetch_add(1, Ordering::Relaxed);
+        log::debug!("Truncating value of key '{key}'");
+        let cut = (0..=cut)
+            .rev()
+            .find(|&cut| value.is_char_boundary(cut))
+            .unwrap_or(0);
+        &value[..cut]
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for FaultyKvStorage<S> {
+    type WriteErrorType = FaultyError<S::WriteErrorType>;
+    type ReadErrorType = FaultyError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.inject("read")?;
+        self.inner.read(key).map_err(FaultyError::Inner)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.inject("write")?;
+        self.inner
+            .write(key, self.truncate(key, value))
+            .map_err(FaultyError::Inner)
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.inject("scan")?;
+        self.inner.scan(cursor, limit).map_err(FaultyError::Inner)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inject("delete")?;
+        self.inner.delete(key).map_err(FaultyError::Inner)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inject("delete_prefix")?;
+        self.inner.delete_prefix(prefix).map_err(FaultyError::Inner)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for FaultyKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inject("purge")?;
+        self.inner.purge().map_err(FaultyError::Inner)
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for FaultyKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.inject("write_with_ttl")?;
+        self.inner
+            .write_with_ttl(key, self.truncate(key, value), ttl)
+            .map_err(FaultyError::Inner)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inject("expire_at")?;
+        self.inner.expire_at(key, at).map_err(FaultyError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inject("touch")?;
+        self.inner.touch(key).map_err(FaultyError::Inner)
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Injected failure of storage {0}")]
+pub struct Injected(pub &'static str);
+
+#[derive(Error, Debug)]
+pub enum FaultyError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error(transparent)]
+    Injected(#[from] Injected),
+}
+
+impl<E: IsNotFound> IsNotFound for FaultyError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            FaultyError::Inner(e) => e.is_not_found(),
+            FaultyError::Injected(_) => false,
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use figment::value::{Dict, Map, Value};
+use figment::{Error, Metadata, Profile, Provider};
+use std::fmt::Display;
+
+// the store's entries as a figment provider, keys are split into nested dicts on '/':
+//
+//     let config: Config = Figment::new()
+//         .merge(Toml::file("App.toml"))
+//         .merge(KvProvider::new(store).with_prefix("config/").with_profiles())
+//         .merge(Env::prefixed("APP_"))
+//         .select(Profile::from_env_or("APP_PROFILE", "default"))
+//         .extract()?;
+//
+// values are parsed like figment's `Env` does, so "8080" is a number and "true" a bool
+pub struct KvProvider<S> {
+    inner: S,
+    prefix: String,
+    separator: String,
+    profiles: bool,
+}
+
+impl<S> KvProvider<S> {
+    pub fn new(inner: S) -> Self {
+        KvProvider {
+            inner,
+            prefix: String::new(),
+            separator: "/".to_string(),
+            profiles: false,
+        }
+    }
+
+    // only keys starting with it are used, without it
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    // what keys are split on, '/' by default
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    // the first part of every key names its profile, e.g. "release/port" is `port` in the
+    // release profile. without it everything is in the default profile
+    pub fn with_profiles(mut self) -> Self {
+        self.profiles = true;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+// a value and a dict under the same path can't both be kept, whichever comes later in key
+// order wins
+fn insert(dict: &mut Dict, path: &[&str], value: Value) {
+    match path {
+        [] => {}
+        [field] => {
+            dict.insert(field.to_string(), value);
+        }
+        [field, rest @ ..] => {
+            let nested = dict
+                .entry(field.to_string())
+                .or_insert_with(|| Dict::new().into());
+            if !matches!(nested, Value::Dict(..)) {
+                *nested = Dict::new().into();
+            }
+            if let Value::Dict(_, nested) = nested {
+                insert(nested, rest, value);
+            }
+        }
+    }
+}
+
+impl<S> Provider for KvProvider<S>
+where
+    S: KvStorage,
+    S::ReadErrorType: Display,
+{
+    fn metadata(&self) -> Metadata {
+        Metadata::named("kv storage")
+    }
+
+    fn data(&self) -> Result<Map<Profile, Dict>, Error> {
+        let entries = self
+            .inner
+            .scan_all()
+            .map_err(|e| Error::from(format!("could not read the store: {e}")))?;
+        let mut data = Map::new();
+        for (key, value) in entries {
+            let Some(path) = key.strip_prefix(&self.prefix) else {
+                continue;
+            };
+            let path: Vec<&str> = if self.separator.is_empty() {
+                vec![path]
+            } else {
+                path.split(self.separator.as_str()).collect()
+            };
+            let (profile, path) = match path.split_first() {
+                Some((profile, path)) if self.profiles => (Profile::new(profile), path),
+                _ => (Profile::Default, &path[..]),
+            };
+            let Ok(value) = value.parse::<Value>();
+            insert(data.entry(profile).or_default(), path, value);
+        }
+        Ok(data)
+    }
+}
@@ -0,0 +1,43 @@
+use crate::kv_storage;
+use serde_json::{Map, Value};
+use std::io::Read;
+use thiserror::Error;
+
+// a fixture is a json object of keys to values, e.g. {"settings/theme": "dark", "count": 3}.
+// strings are stored as they are, any other value as its json text
+pub(crate) fn parse(reader: impl Read) -> Result<Vec<(String, String)>, serde_json::Error> {
+    let entries: Map<String, Value> = serde_json::from_reader(reader)?;
+    Ok(entries
+        .into_iter()
+        .map(|(key, value)| match value {
+            Value::String(value) => (key, value),
+            value => (key, value.to_string()),
+        })
+        .collect())
+}
+
+// writes every entry of the fixture into `store`, returns the number of entries. the fixture
+// is parsed completely before the first write
+pub fn load_fixture<S, R>(store: &S, reader: R) -> Result<usize, FixtureError<S::WriteErrorType>>
+where
+    S: kv_storage::KvStorage + ?Sized,
+    R: Read,
+{
+    let entries = parse(reader)?;
+    let count = entries.len();
+    for (key, value) in entries {
+        store
+            .write(&key, &value)
+            .map_err(|e| FixtureError::Write(key, e))?;
+    }
+    Ok(count)
+}
+
+#[derive(Error, Debug)]
+pub enum FixtureError<E> {
+    #[error("Fixture is not a valid json object")]
+    Parse(#[from] serde_json::Error),
+
+    #[error("Could not write key '{0}'")]
+    Write(String, #[source] E),
+}
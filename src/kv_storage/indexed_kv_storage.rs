@@ -0,0 +1,345 @@
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// "$.address.city" or "$.tags[0]" as a json pointer, "/address/city" and "/tags/0"
+pub(crate) fn to_pointer(path: &str) -> String {
+    let path = path.strip_prefix('$').unwrap_or(path);
+    let mut pointer = String::new();
+    for segment in path.split('.').filter(|segment| !segment.is_empty()) {
+        let (field, indexes) = segment.split_at(segment.find('[').unwrap_or(segment.len()));
+        if !field.is_empty() {
+            pointer.push('/');
+            pointer.push_str(&field.replace('~', "~0").replace('/', "~1"));
+        }
+        for index in indexes.split('[').filter(|index| !index.is_empty()) {
+            pointer.push('/');
+            pointer.push_str(index.trim_end_matches(']'));
+        }
+    }
+    pointer
+}
+
+// '/' ends the index name and value in an entry's key, so they can't hold one
+fn escape(part: &str) -> String {
+    part.replace('%', "%25").replace('/', "%2F")
+}
+
+// keeps secondary indexes over json values, so keys can be found by what their values hold,
+// e.g. a user by `find_by_index("email", "x@y.z")` with an index on "$.email". an entry
+// "<index prefix><name>/<value>/<key>" is stored next to the values for every indexed value,
+// strings as they are and numbers and booleans as json. arrays index each of their elements,
+// values that aren't json or don't hold the path aren't indexed. the index prefix is hidden
+// from scans. the values found are read again, so entries a crash left behind aren't returned
+pub struct IndexedKvStorage<S> {
+    inner: S,
+    // name and json pointer
+    indexes: Vec<(String, String)>,
+    index_prefix: String,
+    // a value's old entries are read before its new ones are written, so changes through this
+    // handle are serialized
+    lock: Mutex<()>,
+}
+
+impl<S> IndexedKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        IndexedKvStorage {
+            inner,
+            indexes: Vec::new(),
+            index_prefix: "__indexes/".to_string(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    // values already stored are only indexed by `rebuild_indexes`
+    pub fn with_index(mut self, name: &str, path: &str) -> Self {
+        self.indexes.push((name.to_string(), to_pointer(path)));
+        self
+    }
+
+    pub fn with_index_prefix(mut self, prefix: &str) -> Self {
+        self.index_prefix = prefix.to_string();
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn entry_prefix(&self, name: &str, value: &str) -> String {
+        format!("{}{}/{}/", self.index_prefix, escape(name), escape(value))
+    }
+
+    // what the value holds at the index's path
+    fn indexed(pointer: &str, value: &Value) -> Vec<String> {
+        let scalar = |value: &Value| match value {
+            Value::String(value) => Some(value.clone()),
+            Value::Number(_) | Value::Bool(_) => Some(value.to_string()),
+            _ => None,
+        };
+        match value.pointer(pointer) {
+            Some(Value::Array(values)) => values.iter().filter_map(scalar).collect(),
+            Some(value) => scalar(value).into_iter().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // the keys of the entries indexing `value`
+    fn entries(&self, key: &str, value: Option<&str>) -> BTreeSet<String> {
+        let Some(value) = value.and_then(|value| serde_json::from_str::<Value>(value).ok()) else {
+            return BTreeSet::new();
+        };
+        if key.starts_with(self.index_prefix.as_str()) {
+            return BTreeSet::new();
+        }
+        self.indexes
+            .iter()
+            .flat_map(|(name, pointer)| {
+                Self::indexed(pointer, &value)
+                    .into_iter()
+                    .map(move |indexed| format!("{}{key}", self.entry_prefix(name, &indexed)))
+            })
+            .collect()
+    }
+}
+
+impl<S: kv_storage::KvStorage> IndexedKvStorage<S> {
+    // the keys and values holding `value` at the path of index `name`, in key order
+    pub fn find_by_index(
+        &self,
+        name: &str,
+        value: &str,
+    ) -> Result<Vec<(String, String)>, IndexReadError<S::ReadErrorType>> {
+        let Some((_, pointer)) = self.indexes.iter().find(|(index, _)| index == name) else {
+            return Err(IndexReadError::UnknownIndex(name.to_string()));
+        };
+        let prefix = self.entry_prefix(name, value);
+        let mut found = Vec::new();
+        for entry in self.scan_prefix(&prefix)? {
+            let key = &entry[prefix.len()..];
+            let Some(stored) = self.inner.read_opt(key).map_err(IndexReadError::Inner)? else {
+                continue;
+            };
+            let still_indexed = serde_json::from_str::<Value>(&stored)
+                .is_ok_and(|stored| Self::indexed(pointer, &stored).iter().any(|v| v == value));
+            if still_indexed {
+                found.push((key.to_string(), stored));
+            }
+        }
+        Ok(found)
+    }
+
+    // drops every entry and indexes the stored values again, e.g. after an index was added or
+    // the store was changed without this wrapper. returns how many values were indexed
+    pub fn rebuild_indexes(&self) -> Result<usize, IndexWriteErrorOf<S>> {
+        let _lock = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        self.rebuild()
+    }
+
+    fn rebuild(&self) -> Result<usize, IndexWriteErrorOf<S>> {
+        self.inner
+            .delete_prefix(&self.index_prefix)
+            .map_err(IndexWriteError::Inner)?;
+        let mut indexed = 0;
+        for (key, value) in self.inner.scan_all().map_err(IndexWriteError::Read)? {
+            let entries = self.entries(&key, Some(&value));
+            if entries.is_empty() {
+                continue;
+            }
+            for entry in entries {
+                self.inner
+                    .write(&entry, "")
+                    .map_err(IndexWriteError::Inner)?;
+            }
+            indexed += 1;
+        }
+        Ok(indexed)
+    }
+
+    // the keys of the inner store under `prefix`
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<String>, IndexReadError<S::ReadErrorType>> {
+        let mut keys = Vec::new();
+        let mut cursor = prefix.to_string();
+        loop {
+            let page = self
+                .inner
+                .scan(Some(&cursor), 128)
+                .map_err(IndexReadError::Inner)?;
+            let scanned = page.entries.len();
+            let before = keys.len();
+            keys.extend(
+                page.entries
+                    .into_iter()
+                    .map(|(key, _)| key)
+                    .take_while(|key| key.starts_with(prefix)),
+            );
+            let past_prefix = keys.len() - before < scanned;
+            match page.cursor {
+                Some(next) if !past_prefix => cursor = next,
+                _ => return Ok(keys),
+            }
+        }
+    }
+
+    // new entries are written before the value and old ones deleted after it, so a failure
+    // leaves at most entries that are filtered out when they're found
+    fn update(
+        &self,
+        key: &str,
+        value: &str,
+        write: impl FnOnce() -> Result<(), S::WriteErrorType>,
+    ) -> Result<(), IndexWriteErrorOf<S>> {
+        let _lock = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        let old = self.inner.read_opt(key).map_err(IndexWriteError::Read)?;
+        let old = self.entries(key, old.as_deref());
+        let new = self.entries(key, Some(value));
+        for entry in new.difference(&old) {
+            self.inner
+                .write(entry, "")
+                .map_err(IndexWriteError::Inner)?;
+        }
+        write().map_err(IndexWriteError::Inner)?;
+        for entry in old.difference(&new) {
+            self.inner.delete(entry).map_err(IndexWriteError::Inner)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for IndexedKvStorage<S> {
+    type WriteErrorType = IndexWriteErrorOf<S>;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.inner.read(key)
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.inner.read_with(key, f)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.update(key, value, || self.inner.write(key, value))
+    }
+
+    // index entries are left out, so pages can be shorter than `limit`
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let mut page = self.inner.scan(cursor, limit)?;
+        page.entries
+            .retain(|(key, _)| !key.starts_with(self.index_prefix.as_str()));
+        Ok(page)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        let _lock = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        let old = self.inner.read_opt(key).map_err(IndexWriteError::Read)?;
+        self.inner.delete(key).map_err(IndexWriteError::Inner)?;
+        for entry in self.entries(key, old.as_deref()) {
+            self.inner.delete(&entry).map_err(IndexWriteError::Inner)?;
+        }
+        Ok(())
+    }
+
+    // a prefix of the index prefix also deletes entries of values outside of it, so they're
+    // indexed again
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        let _lock = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.index_prefix.starts_with(prefix) {
+            self.inner
+                .delete_prefix(prefix)
+                .map_err(IndexWriteError::Inner)?;
+            return self.rebuild().map(|_| ());
+        }
+        let entries: Vec<_> = self
+            .inner
+            .scan_all()
+            .map_err(IndexWriteError::Read)?
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .flat_map(|(key, value)| self.entries(&key, Some(&value)))
+            .collect();
+        self.inner
+            .delete_prefix(prefix)
+            .map_err(IndexWriteError::Inner)?;
+        for entry in entries {
+            self.inner.delete(&entry).map_err(IndexWriteError::Inner)?;
+        }
+        Ok(())
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for IndexedKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge().map_err(IndexWriteError::Inner)
+    }
+}
+
+// entries don't expire with their values, but values that are gone are skipped when they're
+// found
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for IndexedKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.update(key, value, || self.inner.write_with_ttl(key, value, ttl))
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .expire_at(key, at)
+            .map_err(IndexWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key).map_err(IndexWriteError::Inner)
+    }
+}
+
+type IndexWriteErrorOf<S> = IndexWriteError<
+    <S as kv_storage::KvStorage>::ReadErrorType,
+    <S as kv_storage::KvStorage>::WriteErrorType,
+>;
+
+#[derive(Error, Debug)]
+pub enum IndexReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("No index is named '{0}'")]
+    UnknownIndex(String),
+}
+
+impl<E: IsNotFound> IsNotFound for IndexReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            IndexReadError::Inner(e) => e.is_not_found(),
+            IndexReadError::UnknownIndex(_) => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum IndexWriteError<R, W> {
+    #[error("Could not read the indexed value")]
+    Read(#[source] R),
+
+    #[error(transparent)]
+    Inner(W),
+}
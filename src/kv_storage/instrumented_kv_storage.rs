@@ -0,0 +1,171 @@
+use crate::kv_storage::{self, IsNotFound};
+use crate::time::Instant;
+use std::fmt::Display;
+use std::time::{Duration, SystemTime};
+
+// emits a `kv_storage` span per operation with an event recording the outcome, value size and
+// latency. missing keys are reported at debug level, other errors as warnings
+pub struct InstrumentedKvStorage<S> {
+    inner: S,
+    backend: &'static str,
+}
+
+impl<S> InstrumentedKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        let backend = std::any::type_name::<S>();
+        InstrumentedKvStorage {
+            inner,
+            backend: backend.rsplit("::").next().unwrap_or(backend),
+        }
+    }
+
+    // label recorded as the `backend` field, defaults to the backend's type name
+    pub fn with_backend_name(mut self, backend: &'static str) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn instrument<T, E: Display>(
+        &self,
+        op: &'static str,
+        key: &str,
+        is_not_found: impl Fn(&E) -> bool,
+        bytes: impl Fn(&T) -> usize,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let span = tracing::debug_span!("kv_storage", backend = self.backend, op, key);
+        let _entered = span.enter();
+
+        let started = Instant::now();
+        let result = f();
+        let latency_us = started.elapsed().as_micros() as u64;
+
+        match &result {
+            Ok(value) => tracing::debug!(bytes = bytes(value), latency_us, outcome = "ok"),
+            Err(e) if is_not_found(e) => {
+                tracing::debug!(latency_us, outcome = "not_found")
+            }
+            Err(e) => tracing::warn!(latency_us, outcome = "error", error = %e),
+        }
+        result
+    }
+}
+
+impl<S> kv_storage::KvStorage for InstrumentedKvStorage<S>
+where
+    S: kv_storage::KvStorage,
+    S::ReadErrorType: Display,
+    S::WriteErrorType: Display,
+{
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.instrument("read", key, IsNotFound::is_not_found, String::len, || {
+            self.inner.read(key)
+        })
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.instrument(
+            "write",
+            key,
+            |_| false,
+            |_| value.len(),
+            || self.inner.write(key, value),
+        )
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let bytes = |page: &kv_storage::Page| {
+            page.entries
+                .iter()
+                .map(|(key, value)| key.len() + value.len())
+                .sum()
+        };
+        self.instrument(
+            "scan",
+            cursor.unwrap_or_default(),
+            IsNotFound::is_not_found,
+            bytes,
+            || self.inner.scan(cursor, limit),
+        )
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.instrument("delete", key, |_| false, |_| 0, || self.inner.delete(key))
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.instrument(
+            "delete_prefix",
+            prefix,
+            |_| false,
+            |_| 0,
+            || self.inner.delete_prefix(prefix),
+        )
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S> kv_storage::KvStoragePurge for InstrumentedKvStorage<S>
+where
+    S: kv_storage::KvStoragePurge,
+    S::ReadErrorType: Display,
+    S::WriteErrorType: Display,
+{
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.instrument("purge", "", |_| false, |_| 0, || self.inner.purge())
+    }
+}
+
+impl<S> kv_storage::KvStorageTtl for InstrumentedKvStorage<S>
+where
+    S: kv_storage::KvStorageTtl,
+    S::ReadErrorType: Display,
+    S::WriteErrorType: Display,
+{
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.instrument(
+            "write_with_ttl",
+            key,
+            |_| false,
+            |_| value.len(),
+            || self.inner.write_with_ttl(key, value, ttl),
+        )
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.instrument(
+            "expire_at",
+            key,
+            |_| false,
+            |_| 0,
+            || self.inner.expire_at(key, at),
+        )
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.instrument("touch", key, |_| false, |_| 0, || self.inner.touch(key))
+    }
+}
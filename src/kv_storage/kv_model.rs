@@ -0,0 +1,97 @@
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+// structs kept field by field in a store, usually implemented with `#[derive(KvModel)]`:
+//
+//     #[derive(KvModel)]
+//     #[kv(prefix = "settings")]
+//     struct Settings {
+//         // in "settings/volume", without a value loading fails
+//         volume: u8,
+//         // `None` without a value
+//         device: Option<String>,
+//         // in "settings/theme_name", `Default::default()` without a value
+//         #[kv(rename = "theme_name", default)]
+//         theme: String,
+//         // in "legacy_language", `default_language()` without a value
+//         #[kv(key = "legacy_language", default = "default_language")]
+//         language: String,
+//         // neither loaded nor saved
+//         #[kv(skip)]
+//         dirty: bool,
+//     }
+//
+// the fields are stored as json, like `KvStorageStructExt` does
+pub trait KvModel: Sized {
+    fn load<S: kv_storage::KvStorage + ?Sized>(
+        store: &S,
+    ) -> Result<Self, KvModelReadError<S::ReadErrorType>>;
+
+    fn save<S: kv_storage::KvStorage + ?Sized>(
+        &self,
+        store: &S,
+    ) -> Result<(), KvModelWriteError<S::WriteErrorType>>;
+}
+
+// used by the derived implementations
+#[doc(hidden)]
+pub fn load_field<T: DeserializeOwned, S: kv_storage::KvStorage + ?Sized>(
+    store: &S,
+    key: &str,
+) -> Result<Option<T>, KvModelReadError<S::ReadErrorType>> {
+    match store.read_opt(key).map_err(KvModelReadError::Inner)? {
+        Some(field) => serde_json::from_str(&field)
+            .map(Some)
+            .map_err(|e| KvModelReadError::Decode(key.to_string(), e)),
+        None => Ok(None),
+    }
+}
+
+#[doc(hidden)]
+pub fn required<T, E>(key: &str, field: Option<T>) -> Result<T, KvModelReadError<E>> {
+    field.ok_or_else(|| KvModelReadError::Missing(key.to_string()))
+}
+
+#[doc(hidden)]
+pub fn save_field<T: Serialize + ?Sized, S: kv_storage::KvStorage + ?Sized>(
+    store: &S,
+    key: &str,
+    field: &T,
+) -> Result<(), KvModelWriteError<S::WriteErrorType>> {
+    let field =
+        serde_json::to_string(field).map_err(|e| KvModelWriteError::Encode(key.to_string(), e))?;
+    store.write(key, &field).map_err(KvModelWriteError::Inner)
+}
+
+#[derive(Error, Debug)]
+pub enum KvModelReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("No value for required field '{0}'")]
+    Missing(String),
+
+    #[error("Could not decode field '{0}'")]
+    Decode(String, #[source] serde_json::Error),
+}
+
+impl<E: IsNotFound> IsNotFound for KvModelReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            KvModelReadError::Inner(e) => e.is_not_found(),
+            KvModelReadError::Missing(_) => true,
+            KvModelReadError::Decode(..) => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum KvModelWriteError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Could not encode field '{0}'")]
+    Encode(String, #[source] serde_json::Error),
+}
@@ -0,0 +1,168 @@
+use crate::kv_storage::codec::{Codec, CodecReadError, CodecWriteError, JsonCodec};
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ops::{Deref, DerefMut};
+
+type LoadError<P, C> =
+    CodecReadError<<<P as Deref>::Target as KvStorage>::ReadErrorType, <C as Codec>::Error>;
+type SaveError<P, C> =
+    CodecWriteError<<<P as Deref>::Target as KvStorage>::WriteErrorType, <C as Codec>::Error>;
+
+// a value bound to a key, e.g. an app's settings struct. it's read when it's loaded, derefs to
+// the value, and writes it back on `save` or when it's dropped, if it was borrowed mutably
+// since. a value that encodes to what's stored isn't written again. the store can be borrowed
+// or shared, e.g. `&store` or an `Arc`. errors while writing on drop are only logged, call
+// `save` to see them
+pub struct KvValue<P, T, C = JsonCodec>
+where
+    P: Deref,
+    P::Target: KvStorage,
+    T: Serialize,
+    C: Codec,
+{
+    store: P,
+    key: String,
+    codec: C,
+    value: T,
+    // the value as it's stored, `None` if the key isn't
+    stored: Option<String>,
+    dirty: bool,
+}
+
+impl<P, T> KvValue<P, T>
+where
+    P: Deref,
+    P::Target: KvStorage,
+    T: Serialize + DeserializeOwned + Default,
+{
+    // as json, with `T::default()` if the key isn't stored
+    pub fn load(store: P, key: &str) -> Result<Self, LoadError<P, JsonCodec>> {
+        Self::load_with(store, key, JsonCodec::default(), T::default)
+    }
+}
+
+impl<P, T, C> KvValue<P, T, C>
+where
+    P: Deref,
+    P::Target: KvStorage,
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    // with the value of `default` if the key isn't stored, it's only written once it's changed
+    pub fn load_with(
+        store: P,
+        key: &str,
+        codec: C,
+        default: impl FnOnce() -> T,
+    ) -> Result<Self, LoadError<P, C>> {
+        let stored = store.read_opt(key).map_err(CodecReadError::Inner)?;
+        let value = match &stored {
+            Some(encoded) => codec.decode(encoded).map_err(CodecReadError::Decode)?,
+            None => default(),
+        };
+        Ok(KvValue {
+            store,
+            key: key.to_string(),
+            codec,
+            value,
+            stored,
+            dirty: false,
+        })
+    }
+
+    // reads the value again, dropping changes that weren't saved
+    pub fn reload(&mut self) -> Result<(), LoadError<P, C>> {
+        let stored = self
+            .store
+            .read_opt(&self.key)
+            .map_err(CodecReadError::Inner)?;
+        if let Some(encoded) = &stored {
+            self.value = self.codec.decode(encoded).map_err(CodecReadError::Decode)?;
+        }
+        self.stored = stored;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl<P, T, C> KvValue<P, T, C>
+where
+    P: Deref,
+    P::Target: KvStorage,
+    T: Serialize,
+    C: Codec,
+{
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    // whether the value was borrowed mutably since it was loaded or saved
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn save(&mut self) -> Result<(), SaveError<P, C>> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let encoded = self
+            .codec
+            .encode(&self.value)
+            .map_err(CodecWriteError::Encode)?;
+        if self.stored.as_ref() != Some(&encoded) {
+            self.store
+                .write(&self.key, &encoded)
+                .map_err(CodecWriteError::Inner)?;
+            self.stored = Some(encoded);
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    // drops the handle without writing the changes
+    pub fn discard(mut self) {
+        self.dirty = false;
+    }
+}
+
+impl<P, T, C> Deref for KvValue<P, T, C>
+where
+    P: Deref,
+    P::Target: KvStorage,
+    T: Serialize,
+    C: Codec,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<P, T, C> DerefMut for KvValue<P, T, C>
+where
+    P: Deref,
+    P::Target: KvStorage,
+    T: Serialize,
+    C: Codec,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        self.dirty = true;
+        &mut self.value
+    }
+}
+
+impl<P, T, C> Drop for KvValue<P, T, C>
+where
+    P: Deref,
+    P::Target: KvStorage,
+    T: Serialize,
+    C: Codec,
+{
+    fn drop(&mut self) {
+        if self.save().is_err() {
+            log::warn!("Could not save the value of key '{}'", self.key);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::kv_storage::faulty_kv_storage::Rng;
+use crate::kv_storage::memory_kv_storage::MemoryKvStorage;
+use crate::kv_storage::retrying_kv_storage::default_sleep;
+use crate::kv_storage::{self, KvStorage, KvStoragePurge, KvStorageTtl};
+use std::convert::Infallible;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Read,
+    Write,
+    Scan,
+    Delete,
+}
+
+// an in-memory store where every operation takes a configured time, e.g. to exercise the
+// loading states of a ui without a real remote backend. the jitter added on top comes from a
+// seeded rng, so the same seed and sequence of calls gives the same delays. `with_sleep`
+// replaces the blocking sleep, e.g. to advance a fake clock instead of waiting
+pub struct LatencyKvStorage {
+    store: MemoryKvStorage,
+    latency: [Duration; 4],
+    jitter: Duration,
+    rng: Mutex<Rng>,
+    sleep: Box<dyn Fn(Operation, Duration) + Send + Sync>,
+    simulated: Mutex<Duration>,
+}
+
+impl Default for LatencyKvStorage {
+    fn default() -> Self {
+        LatencyKvStorage::new(MemoryKvStorage::new())
+    }
+}
+
+impl LatencyKvStorage {
+    pub fn new(store: MemoryKvStorage) -> Self {
+        LatencyKvStorage {
+            store,
+            latency: [Duration::ZERO; 4],
+            jitter: Duration::ZERO,
+            rng: Mutex::new(Rng(0)),
+            sleep: Box::new(|_, delay| default_sleep(delay)),
+            simulated: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    // writing with a ttl, changing expiries and purging count as writes, deleting a prefix
+    // as a delete
+    pub fn with_latency(mut self, operation: Operation, latency: Duration) -> Self {
+        self.latency[operation as usize] = latency;
+        self
+    }
+
+    // applies the same latency to every operation
+    pub fn with_uniform_latency(mut self, latency: Duration) -> Self {
+        self.latency = [latency; 4];
+        self
+    }
+
+    // up to this much is added to every delay
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_seed(self, seed: u64) -> Self {
+        *self.rng.lock().unwrap_or_else(PoisonError::into_inner) = Rng(seed);
+        self
+    }
+
+    pub fn with_sleep(
+        mut self,
+        sleep: impl Fn(Operation, Duration) + Send + Sync + 'static,
+    ) -> Self {
+        self.sleep = Box::new(sleep);
+        self
+    }
+
+    pub fn store(&self) -> &MemoryKvStorage {
+        &self.store
+    }
+
+    pub fn into_store(self) -> MemoryKvStorage {
+        self.store
+    }
+
+    // sum of all delays so far
+    pub fn simulated(&self) -> Duration {
+        *self
+            .simulated
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn delay(&self, operation: Operation) {
+        let delay = {
+            let mut rng = self.rng.lock().unwrap_or_else(PoisonError::into_inner);
+            self.latency[operation as usize] + rng.duration(Duration::ZERO, self.jitter)
+        };
+        *self
+            .simulated
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) += delay;
+        if !delay.is_zero() {
+            (self.sleep)(operation, delay);
+        }
+    }
+}
+
+impl KvStorage for LatencyKvStorage {
+    type WriteErrorType = Infallible;
+    type ReadErrorType = kv_storage::ReadError;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.delay(Operation::Read);
+        self.store.read(key)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.delay(Operation::Write);
+        self.store.write(key, value)
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.delay(Operation::Scan);
+        self.store.scan(cursor, limit)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.delay(Operation::Delete);
+        self.store.delete(key)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.delay(Operation::Delete);
+        self.store.delete_prefix(prefix)
+    }
+}
+
+impl KvStoragePurge for LatencyKvStorage {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.delay(Operation::Write);
+        self.store.purge()
+    }
+}
+
+impl KvStorageTtl for LatencyKvStorage {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.delay(Operation::Write);
+        self.store.write_with_ttl(key, value, ttl)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.delay(Operation::Write);
+        self.store.expire_at(key, at)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.delay(Operation::Write);
+        self.store.touch(key)
+    }
+}
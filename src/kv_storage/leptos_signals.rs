@@ -0,0 +1,87 @@
+use crate::kv_storage::observed_kv_storage::ObservedKvStorage;
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use leptos::prelude::*;
+use std::sync::Arc;
+
+pub struct KvSignal<S> {
+    store: Arc<ObservedKvStorage<S>>,
+    key: String,
+    value: RwSignal<Option<String>>,
+}
+
+impl<S> Clone for KvSignal<S> {
+    fn clone(&self) -> Self {
+        KvSignal {
+            store: self.store.clone(),
+            key: self.key.clone(),
+            value: self.value,
+        }
+    }
+}
+
+impl<S: KvStorage> KvSignal<S> {
+    // tracked, like reading any other signal
+    pub fn get(&self) -> Option<String> {
+        self.value.get()
+    }
+
+    pub fn signal(&self) -> Signal<Option<String>> {
+        self.value.read_only().into()
+    }
+
+    // every signal for the key is updated with the new value
+    pub fn set(&self, value: &str) -> Result<(), S::WriteErrorType> {
+        self.store.write(&self.key, value)
+    }
+
+    pub fn delete(&self) -> Result<(), S::WriteErrorType> {
+        self.store.delete(&self.key)
+    }
+}
+
+// a signal holding the value of `key`, updated whenever the key is changed through `store`.
+// the subscription ends with the reactive owner, e.g. the component calling this:
+//
+//     #[component]
+//     fn Volume(store: Arc<ObservedKvStorage<WasmCookiesKvStorage>>) -> impl IntoView {
+//         let volume = use_kv_storage(store, "volume");
+//         let louder = {
+//             let volume = volume.clone();
+//             move |_| {
+//                 let _ = volume.set("11");
+//             }
+//         };
+//         view! {
+//             <button on:click=louder>{move || volume.get().unwrap_or("3".to_string())}</button>
+//         }
+//     }
+pub fn use_kv_storage<S>(store: Arc<ObservedKvStorage<S>>, key: &str) -> KvSignal<S>
+where
+    S: KvStorage + Send + Sync + 'static,
+{
+    let initial = store.read_opt(key).unwrap_or_else(|_| {
+        log::warn!("could not read '{key}'");
+        None
+    });
+    let value = RwSignal::new(initial);
+
+    let listened = key.to_string();
+    let listener = store.subscribe(move |change| {
+        if change.affects(&listened) {
+            // the signal may already be disposed if the change races the cleanup
+            value.try_set(change.value().map(str::to_string));
+        }
+    });
+    {
+        let store = store.clone();
+        on_cleanup(move || {
+            store.unsubscribe(listener);
+        });
+    }
+
+    KvSignal {
+        store,
+        key: key.to_string(),
+        value,
+    }
+}
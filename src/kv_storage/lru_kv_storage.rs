@@ -0,0 +1,243 @@
+use crate::kv_storage::{self, KvStorageExt};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+#[derive(Default)]
+struct Index {
+    // key -> (entry size, last use)
+    entries: HashMap<String, (usize, u64)>,
+    by_use: BTreeMap<u64, String>,
+    bytes: usize,
+    clock: u64,
+}
+
+impl Index {
+    fn touch(&mut self, key: &str) {
+        self.clock += 1;
+        if let Some((_, used)) = self.entries.get_mut(key) {
+            self.by_use.remove(used);
+            *used = self.clock;
+            self.by_use.insert(self.clock, key.to_string());
+        }
+    }
+
+    fn insert(&mut self, key: &str, size: usize) {
+        self.remove(key);
+        self.clock += 1;
+        self.entries.insert(key.to_string(), (size, self.clock));
+        self.by_use.insert(self.clock, key.to_string());
+        self.bytes += size;
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((size, used)) = self.entries.remove(key) {
+            self.by_use.remove(&used);
+            self.bytes -= size;
+        }
+    }
+
+    fn least_recently_used(&self) -> Option<&str> {
+        self.by_use.values().next().map(String::as_str)
+    }
+}
+
+// bounds the wrapped store by entry count and/or total size of keys and values, evicting the
+// least recently read or written entries. recency is tracked in memory, after a restart the
+// existing entries are treated as used in key order. entries expiring or changing behind the
+// wrapper's back are only noticed when the wrapper touches them
+pub struct LruKvStorage<S> {
+    inner: S,
+    max_entries: Option<usize>,
+    max_bytes: Option<usize>,
+    index: Mutex<Option<Index>>,
+}
+
+impl<S> LruKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        LruKvStorage {
+            inner,
+            max_entries: None,
+            max_bytes: None,
+            index: Mutex::new(None),
+        }
+    }
+
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    // a single entry larger than this is still stored, after evicting everything else
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn over_capacity(&self, index: &Index) -> bool {
+        self.max_entries
+            .is_some_and(|max| index.entries.len() > max)
+            || self.max_bytes.is_some_and(|max| index.bytes > max)
+    }
+}
+
+impl<S: kv_storage::KvStorage> LruKvStorage<S> {
+    fn with_index<T, E>(
+        &self,
+        map_err: impl FnOnce(S::ReadErrorType) -> E,
+        f: impl FnOnce(&mut Index) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut index = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        if index.is_none() {
+            let mut loaded = Index::default();
+            for (key, value) in self.inner.scan_all().map_err(map_err)? {
+                loaded.insert(&key, key.len() + value.len());
+            }
+            *index = Some(loaded);
+        }
+        f(index.as_mut().expect("index was just loaded"))
+    }
+
+    pub fn len(&self) -> Result<usize, S::ReadErrorType> {
+        self.with_index(|e| e, |index| Ok(index.entries.len()))
+    }
+
+    pub fn is_empty(&self) -> Result<bool, S::ReadErrorType> {
+        Ok(self.len()? == 0)
+    }
+
+    // total size of keys and values
+    pub fn bytes(&self) -> Result<usize, S::ReadErrorType> {
+        self.with_index(|e| e, |index| Ok(index.bytes))
+    }
+
+    fn written(
+        &self,
+        key: &str,
+        value: &str,
+        write: impl FnOnce() -> Result<(), S::WriteErrorType>,
+    ) -> Result<(), LruWriteError<S::ReadErrorType, S::WriteErrorType>> {
+        self.with_index(LruWriteError::Read, |index| {
+            write().map_err(LruWriteError::Inner)?;
+            index.insert(key, key.len() + value.len());
+
+            while self.over_capacity(index) {
+                let Some(evicted) = index.least_recently_used().map(str::to_string) else {
+                    break;
+                };
+                if evicted == key {
+                    break;
+                }
+                self.inner.delete(&evicted).map_err(LruWriteError::Inner)?;
+                index.remove(&evicted);
+                log::debug!("Evicted key '{evicted}'");
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for LruKvStorage<S> {
+    type WriteErrorType = LruWriteError<S::ReadErrorType, S::WriteErrorType>;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.with_index(
+            |e| e,
+            |index| {
+                let value = self.inner.read(key)?;
+                index.touch(key);
+                Ok(value)
+            },
+        )
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.written(key, value, || self.inner.write(key, value))
+    }
+
+    // scanning doesn't count as use
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.inner.scan(cursor, limit)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.with_index(LruWriteError::Read, |index| {
+            self.inner.delete(key).map_err(LruWriteError::Inner)?;
+            index.remove(key);
+            Ok(())
+        })
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.with_index(LruWriteError::Read, |index| {
+            self.inner
+                .delete_prefix(prefix)
+                .map_err(LruWriteError::Inner)?;
+            let removed: Vec<_> = index
+                .entries
+                .keys()
+                .filter(|key| key.starts_with(prefix))
+                .cloned()
+                .collect();
+            for key in removed {
+                index.remove(&key);
+            }
+            Ok(())
+        })
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for LruKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        let mut index = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        self.inner.purge().map_err(LruWriteError::Inner)?;
+        *index = None;
+        Ok(())
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for LruKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.written(key, value, || self.inner.write_with_ttl(key, value, ttl))
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner.expire_at(key, at).map_err(LruWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key).map_err(LruWriteError::Inner)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LruWriteError<R, W> {
+    #[error("Could not load existing entries")]
+    Read(#[source] R),
+
+    #[error(transparent)]
+    Inner(W),
+}
@@ -0,0 +1,295 @@
+use crate::kv_storage;
+#[cfg(feature = "std")]
+use crate::time::now;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use core::cell::{RefCell, RefMut};
+use core::convert::Infallible;
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "std")]
+use std::time::{Duration, SystemTime};
+
+// without std there's no clock and nothing expires, entries are always live
+#[cfg(not(feature = "std"))]
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+struct SystemTime;
+#[cfg(not(feature = "std"))]
+fn now() -> SystemTime {
+    SystemTime
+}
+
+#[cfg(feature = "std")]
+type Entries = Mutex<BTreeMap<String, Entry>>;
+// not `Sync` without std, firmware usually has one thread touching the store
+#[cfg(not(feature = "std"))]
+type Entries = RefCell<BTreeMap<String, Entry>>;
+
+struct Entry {
+    value: String,
+    expires: Option<SystemTime>,
+    // changes with every write, see `KvStorageRevision`
+    revision: usize,
+}
+
+impl Entry {
+    fn is_live(&self, now: SystemTime) -> bool {
+        self.expires.is_none_or(|expires| expires > now)
+    }
+}
+
+// keeps everything in a map in memory, e.g. for tests or as the upper tier of a
+// `TieredKvStorage`. expired entries are dropped when they're next touched
+#[derive(Default)]
+pub struct MemoryKvStorage {
+    entries: Entries,
+    #[cfg(feature = "std")]
+    default_ttl: Option<Duration>,
+    // of the last write, counted for the whole store so a key that's deleted and written again
+    // doesn't get back the revision it had
+    last_revision: AtomicUsize,
+}
+
+impl MemoryKvStorage {
+    pub fn new() -> Self {
+        MemoryKvStorage::default()
+    }
+
+    // used by `touch`, plain writes don't expire
+    #[cfg(feature = "std")]
+    pub fn with_default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_entries<K: Into<String>, V: Into<String>>(
+        self,
+        entries: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        {
+            let mut map = self.entries();
+            for (key, value) in entries {
+                let entry = Entry {
+                    value: value.into(),
+                    expires: None,
+                    revision: self.next_revision(),
+                };
+                map.insert(key.into(), entry);
+            }
+        }
+        self
+    }
+
+    // a store holding the entries of a fixture, see `fixture::load_fixture`, e.g.
+    // `MemoryKvStorage::from_json(include_str!("fixture.json"))`
+    #[cfg(feature = "test-util")]
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let entries = crate::kv_storage::fixture::parse(json.as_bytes())?;
+        Ok(MemoryKvStorage::new().with_entries(entries))
+    }
+
+    pub fn len(&self) -> usize {
+        let now = now();
+        self.entries()
+            .values()
+            .filter(|entry| entry.is_live(now))
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // every entry that hasn't expired, in key order
+    pub fn to_map(&self) -> BTreeMap<String, String> {
+        let now = now();
+        self.entries()
+            .iter()
+            .filter(|(_, entry)| entry.is_live(now))
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect()
+    }
+
+    #[cfg(feature = "std")]
+    fn entries(&self) -> MutexGuard<'_, BTreeMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn entries(&self) -> RefMut<'_, BTreeMap<String, Entry>> {
+        self.entries.borrow_mut()
+    }
+
+    // only called with the entries locked, so a load and a store do, which every target has
+    fn next_revision(&self) -> usize {
+        let revision = self.last_revision.load(Ordering::Relaxed).wrapping_add(1);
+        self.last_revision.store(revision, Ordering::Relaxed);
+        revision
+    }
+
+    fn live_value<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&Entry) -> T,
+    ) -> Result<T, kv_storage::ReadError> {
+        let now = now();
+        let mut entries = self.entries();
+        match entries.get(key) {
+            Some(entry) if entry.is_live(now) => Ok(f(entry)),
+            Some(_) => {
+                entries.remove(key);
+                Err(kv_storage::ReadError::NotFound)
+            }
+            None => Err(kv_storage::ReadError::NotFound),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str, expires: Option<SystemTime>) {
+        let mut entries = self.entries();
+        let entry = Entry {
+            value: value.to_string(),
+            expires,
+            revision: self.next_revision(),
+        };
+        entries.insert(key.to_string(), entry);
+    }
+
+    #[cfg(feature = "std")]
+    fn set_expiry(&self, key: &str, expires: Option<SystemTime>) {
+        let now = now();
+        let mut entries = self.entries();
+        match entries.get_mut(key) {
+            Some(entry) if entry.is_live(now) => entry.expires = expires,
+            Some(_) => {
+                entries.remove(key);
+            }
+            None => {}
+        }
+    }
+}
+
+impl kv_storage::KvStorage for MemoryKvStorage {
+    type WriteErrorType = Infallible;
+    type ReadErrorType = kv_storage::ReadError;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.live_value(key, |entry| entry.value.clone())
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.live_value(key, |entry| f(&entry.value))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.set(key, value, None);
+        Ok(())
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let now = now();
+        let entries = self.entries();
+        let mut live = entries
+            .iter()
+            .filter(|(key, _)| cursor.is_none_or(|cursor| key.as_str() > cursor))
+            .filter(|(_, entry)| entry.is_live(now));
+        let page: Vec<_> = live
+            .by_ref()
+            .take(limit)
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect();
+        let cursor = match live.next() {
+            Some(_) => page.last().map(|(key, _)| key.clone()),
+            None => None,
+        };
+        Ok(kv_storage::Page {
+            entries: page,
+            cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.entries().remove(key);
+        Ok(())
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.entries().retain(|key, _| !key.starts_with(prefix));
+        Ok(())
+    }
+}
+
+impl kv_storage::KvStorageRevision for MemoryKvStorage {
+    fn read_revision(
+        &self,
+        key: &str,
+    ) -> Result<(String, kv_storage::Revision), Self::ReadErrorType> {
+        self.live_value(key, |entry| {
+            let revision = kv_storage::Revision::new(entry.revision.to_string());
+            (entry.value.clone(), revision)
+        })
+    }
+
+    // the entry stops expiring, like with `write`
+    fn write_if(
+        &self,
+        key: &str,
+        value: &str,
+        revision: Option<&kv_storage::Revision>,
+    ) -> Result<kv_storage::Revision, kv_storage::ConditionalWriteError<Self::WriteErrorType>> {
+        let now = now();
+        let mut entries = self.entries();
+        let current = entries
+            .get(key)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| entry.revision.to_string());
+        if current.as_deref() != revision.map(kv_storage::Revision::as_str) {
+            return Err(kv_storage::ConditionalWriteError::Conflict(key.to_string()));
+        }
+        let entry = Entry {
+            value: value.to_string(),
+            expires: None,
+            revision: self.next_revision(),
+        };
+        let revision = kv_storage::Revision::new(entry.revision.to_string());
+        entries.insert(key.to_string(), entry);
+        Ok(revision)
+    }
+}
+
+impl kv_storage::KvStoragePurge for MemoryKvStorage {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.entries().clear();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl kv_storage::KvStorageTtl for MemoryKvStorage {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.set(key, value, Some(now() + ttl));
+        Ok(())
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.set_expiry(key, Some(at));
+        Ok(())
+    }
+
+    // without a default ttl the entry stops expiring
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.set_expiry(key, self.default_ttl.map(|ttl| now() + ttl));
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use crate::kv_storage::{self, IsNotFound};
+use crate::time::Instant;
+use std::time::{Duration, SystemTime};
+
+const OPERATIONS: &str = "kv_storage_operations_total";
+const ERRORS: &str = "kv_storage_errors_total";
+const DURATION: &str = "kv_storage_operation_duration_seconds";
+
+// records through the `metrics` facade, labelled by backend and operation:
+// - `kv_storage_operations_total` by outcome (`ok`, `not_found`, `error`)
+// - `kv_storage_errors_total`, missing keys are not counted as errors
+// - `kv_storage_operation_duration_seconds` histogram
+pub struct MeteredKvStorage<S> {
+    inner: S,
+    backend: &'static str,
+}
+
+impl<S> MeteredKvStorage<S> {
+    pub fn new(inner: S, backend: &'static str) -> Self {
+        MeteredKvStorage { inner, backend }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn measure<T, E>(
+        &self,
+        op: &'static str,
+        is_not_found: impl Fn(&E) -> bool,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let started = Instant::now();
+        let result = f();
+        let elapsed = started.elapsed();
+
+        let outcome = match &result {
+            Ok(_) => "ok",
+            Err(e) if is_not_found(e) => "not_found",
+            Err(_) => "error",
+        };
+        let backend = self.backend;
+        metrics::counter!(OPERATIONS, "backend" => backend, "op" => op, "outcome" => outcome)
+            .increment(1);
+        if outcome == "error" {
+            metrics::counter!(ERRORS, "backend" => backend, "op" => op).increment(1);
+        }
+        metrics::histogram!(DURATION, "backend" => backend, "op" => op)
+            .record(elapsed.as_secs_f64());
+        result
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for MeteredKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.measure("read", IsNotFound::is_not_found, || self.inner.read(key))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.measure("write", |_| false, || self.inner.write(key, value))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.measure("scan", IsNotFound::is_not_found, || {
+            self.inner.scan(cursor, limit)
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.measure("delete", |_| false, || self.inner.delete(key))
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.measure(
+            "delete_prefix",
+            |_| false,
+            || self.inner.delete_prefix(prefix),
+        )
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for MeteredKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.measure("purge", |_| false, || self.inner.purge())
+    }
+}
+
+impl<S: kv_storage::KvStorageCompact> kv_storage::KvStorageCompact for MeteredKvStorage<S> {
+    fn compact(&self) -> Result<u64, Self::WriteErrorType> {
+        self.measure("compact", |_| false, || self.inner.compact())
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for MeteredKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.measure(
+            "write_with_ttl",
+            |_| false,
+            || self.inner.write_with_ttl(key, value, ttl),
+        )
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.measure("expire_at", |_| false, || self.inner.expire_at(key, at))
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.measure("touch", |_| false, || self.inner.touch(key))
+    }
+}
@@ -0,0 +1,139 @@
+use crate::kv_storage::{self, KvStorageExt};
+use std::error::Error;
+use thiserror::Error;
+
+pub const DEFAULT_VERSION_KEY: &str = "__schema_version";
+
+pub type BoxError = Box<dyn Error + Send + Sync>;
+
+type Migration<S> = Box<dyn Fn(&S) -> Result<(), BoxError>>;
+
+// migration `n` (counting from 1) upgrades a store from schema version `n - 1` to `n`, the
+// version is recorded after every migration so a failed one is retried on the next run
+// without repeating the ones before it
+pub struct Migrator<S> {
+    version_key: String,
+    migrations: Vec<Migration<S>>,
+}
+
+impl<S: kv_storage::KvStorage> Default for Migrator<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: kv_storage::KvStorage> Migrator<S> {
+    pub fn new() -> Self {
+        Migrator {
+            version_key: DEFAULT_VERSION_KEY.to_string(),
+            migrations: Vec::new(),
+        }
+    }
+
+    pub fn with_version_key(mut self, version_key: &str) -> Self {
+        self.version_key = version_key.to_string();
+        self
+    }
+
+    pub fn with_migration(
+        mut self,
+        migration: impl Fn(&S) -> Result<(), BoxError> + 'static,
+    ) -> Self {
+        self.migrations.push(Box::new(migration));
+        self
+    }
+
+    pub fn latest_version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+
+    pub fn version(
+        &self,
+        store: &S,
+    ) -> Result<u32, MigrationError<S::ReadErrorType, S::WriteErrorType>> {
+        match store
+            .read_opt(&self.version_key)
+            .map_err(MigrationError::Read)?
+        {
+            Some(version) => version
+                .trim()
+                .parse()
+                .map_err(|_| MigrationError::InvalidVersion(version)),
+            None => Ok(0),
+        }
+    }
+
+    // runs every pending migration and returns the resulting schema version
+    pub fn run(
+        &self,
+        store: &S,
+    ) -> Result<u32, MigrationError<S::ReadErrorType, S::WriteErrorType>> {
+        let current = self.version(store)?;
+        let latest = self.latest_version();
+        if current > latest {
+            return Err(MigrationError::FutureVersion { current, latest });
+        }
+
+        for (version, migration) in (current + 1..).zip(&self.migrations[current as usize..]) {
+            log::info!("Migrating storage to schema version {version}");
+            migration(store).map_err(|source| MigrationError::Migration { version, source })?;
+            store
+                .write(&self.version_key, &version.to_string())
+                .map_err(MigrationError::Write)?;
+        }
+        Ok(latest)
+    }
+}
+
+// moves the value of `from` to `to`, missing keys are skipped
+pub fn rename<S>(store: &S, from: &str, to: &str) -> Result<(), BoxError>
+where
+    S: kv_storage::KvStorage,
+    S::ReadErrorType: Error + Send + Sync + 'static,
+    S::WriteErrorType: Error + Send + Sync + 'static,
+{
+    if let Some(value) = store.read_opt(from)? {
+        store.write(to, &value)?;
+        store.delete(from)?;
+    }
+    Ok(())
+}
+
+// replaces the value of `key` with `f(value)`, missing keys are skipped
+pub fn map_value<S>(
+    store: &S,
+    key: &str,
+    f: impl FnOnce(String) -> Result<String, BoxError>,
+) -> Result<(), BoxError>
+where
+    S: kv_storage::KvStorage,
+    S::ReadErrorType: Error + Send + Sync + 'static,
+    S::WriteErrorType: Error + Send + Sync + 'static,
+{
+    if let Some(value) = store.read_opt(key)? {
+        store.write(key, &f(value)?)?;
+    }
+    Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum MigrationError<R, W> {
+    #[error("Could not read schema version")]
+    Read(#[source] R),
+
+    #[error("Could not record schema version")]
+    Write(#[source] W),
+
+    #[error("Stored schema version '{0}' is not a number")]
+    InvalidVersion(String),
+
+    #[error("Store has schema version {current}, this build only knows up to {latest}")]
+    FutureVersion { current: u32, latest: u32 },
+
+    #[error("Migration to schema version {version} failed")]
+    Migration {
+        version: u32,
+        #[source]
+        source: BoxError,
+    },
+}
@@ -0,0 +1,386 @@
+use crate::kv_storage::enveloped_kv_storage;
+use crate::kv_storage::{self, IsNotFound};
+use std::cmp::Reverse;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailurePolicy {
+    // every replica has to succeed
+    #[default]
+    All,
+    // the primary has to succeed, other replicas are best effort
+    Primary,
+    // at least this many replicas have to succeed
+    AtLeast(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadPolicy {
+    // only the primary is read
+    #[default]
+    Primary,
+    // the replicas are read in order until one answers, a missing key is an answer too
+    FirstSuccess,
+    // what more than half of the replicas answer, a missing key is an answer too
+    Majority,
+    // the value written last, by the time an `EnvelopedKvStorage` over this one keeps with it,
+    // e.g. when a replica missed writes while it was offline. values without a time are older
+    // than any with one, ties go to the earlier replica. a key missing from some replicas
+    // counts as not written there yet, so deletes have to reach every replica
+    Newest,
+}
+
+// mirrors every mutation to all replicas, reads are served as the read policy says, by default
+// by the primary (the first replica). a failed operation is not rolled back on the replicas
+// where it succeeded. with read repair, replicas whose answer lost a majority or newest read
+// are given the one that won. scans aren't compared, under any policy but `Primary` the first
+// replica that can scan serves them
+//
+// use `BoxedKvStorage` to mirror to backends of different types
+pub struct MirroredKvStorage<S> {
+    replicas: Vec<S>,
+    policy: FailurePolicy,
+    read_policy: ReadPolicy,
+    read_repair: bool,
+}
+
+impl<S> MirroredKvStorage<S> {
+    pub fn new(primary: S) -> Self {
+        MirroredKvStorage {
+            replicas: vec![primary],
+            policy: FailurePolicy::default(),
+            read_policy: ReadPolicy::default(),
+            read_repair: false,
+        }
+    }
+
+    pub fn with_replica(mut self, replica: S) -> Self {
+        self.replicas.push(replica);
+        self
+    }
+
+    pub fn with_failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_read_policy(mut self, policy: ReadPolicy) -> Self {
+        self.read_policy = policy;
+        self
+    }
+
+    // a failed repair is logged, the read still succeeds
+    pub fn with_read_repair(mut self, read_repair: bool) -> Self {
+        self.read_repair = read_repair;
+        self
+    }
+
+    pub fn primary(&self) -> &S {
+        &self.replicas[0]
+    }
+
+    pub fn replicas(&self) -> &[S] {
+        &self.replicas
+    }
+
+    pub fn into_inner(self) -> Vec<S> {
+        self.replicas
+    }
+
+    fn mirror<E>(
+        &self,
+        op: &str,
+        key: &str,
+        apply: impl Fn(&S) -> Result<(), E>,
+    ) -> Result<(), MirroredWriteError<E>> {
+        let failures: Vec<_> = self
+            .replicas
+            .iter()
+            .enumerate()
+            .filter_map(|(index, replica)| apply(replica).err().map(|e| (index, e)))
+            .collect();
+
+        let succeeded = self.replicas.len() - failures.len();
+        let acceptable = match self.policy {
+            FailurePolicy::All => failures.is_empty(),
+            FailurePolicy::Primary => failures.first().is_none_or(|(index, _)| *index != 0),
+            FailurePolicy::AtLeast(required) => succeeded >= required,
+        };
+
+        if !acceptable {
+            return Err(MirroredWriteError {
+                failures,
+                replicas: self.replicas.len(),
+            });
+        }
+        for (index, _) in &failures {
+            log::warn!("Replica {index} failed to {op} '{key}'");
+        }
+        Ok(())
+    }
+}
+
+// a replica's answer, `Err` if the key is missing, and the replicas that failed to give one
+type Answer<E> = (usize, Result<String, E>);
+type Answers<E> = (Vec<Answer<E>>, Vec<(usize, E)>);
+
+impl<S: kv_storage::KvStorage> MirroredKvStorage<S> {
+    // every replica's answer, and the errors of those that failed
+    fn read_all(&self, key: &str) -> Answers<S::ReadErrorType> {
+        let mut answers = Vec::new();
+        let mut failures = Vec::new();
+        for (index, replica) in self.replicas.iter().enumerate() {
+            match replica.read(key) {
+                Err(e) if !e.is_not_found() => failures.push((index, e)),
+                answer => answers.push((index, answer)),
+            }
+        }
+        (answers, failures)
+    }
+
+    fn read_first(&self, key: &str) -> Result<String, MirroredReadError<S::ReadErrorType>> {
+        let mut first_error = None;
+        for replica in &self.replicas {
+            match replica.read(key) {
+                Err(e) if !e.is_not_found() => {
+                    first_error.get_or_insert(e);
+                }
+                answer => return answer.map_err(MirroredReadError::Inner),
+            }
+        }
+        Err(MirroredReadError::Inner(
+            first_error.expect("there is at least one replica"),
+        ))
+    }
+
+    fn read_majority(&self, key: &str) -> Result<String, MirroredReadError<S::ReadErrorType>> {
+        let (mut answers, failures) = self.read_all(key);
+        let quorum = self.replicas.len() / 2 + 1;
+        let agree = |a: &Answer<S::ReadErrorType>, b: &Answer<S::ReadErrorType>| {
+            a.1.as_ref().ok() == b.1.as_ref().ok()
+        };
+        let Some(winner) = answers
+            .iter()
+            .position(|a| answers.iter().filter(|b| agree(a, b)).count() >= quorum)
+        else {
+            return Err(MirroredReadError::NoQuorum {
+                failures,
+                replicas: self.replicas.len(),
+            });
+        };
+        let (_, answer) = answers.swap_remove(winner);
+        self.repair(key, answer.as_deref().ok(), &answers);
+        answer.map_err(MirroredReadError::Inner)
+    }
+
+    fn read_newest(&self, key: &str) -> Result<String, MirroredReadError<S::ReadErrorType>> {
+        let (mut answers, failures) = self.read_all(key);
+        let newest = answers
+            .iter()
+            .enumerate()
+            .filter_map(|(position, (index, answer))| {
+                let value = answer.as_ref().ok()?;
+                let updated =
+                    enveloped_kv_storage::open(value).and_then(|(metadata, _)| metadata.updated);
+                Some((position, (updated, Reverse(*index))))
+            })
+            .max_by_key(|(_, order)| *order)
+            .map(|(position, _)| position);
+        let Some(newest) = newest else {
+            // no replica has the key, or none could be read
+            return match answers.into_iter().next() {
+                Some((_, answer)) => answer.map_err(MirroredReadError::Inner),
+                None => Err(MirroredReadError::Inner(
+                    failures
+                        .into_iter()
+                        .next()
+                        .expect("there is at least one replica")
+                        .1,
+                )),
+            };
+        };
+        let (_, answer) = answers.swap_remove(newest);
+        self.repair(key, answer.as_deref().ok(), &answers);
+        answer.map_err(MirroredReadError::Inner)
+    }
+
+    // gives the replicas that answered something else the winning answer
+    fn repair(&self, key: &str, winner: Option<&str>, answers: &[Answer<S::ReadErrorType>]) {
+        if !self.read_repair {
+            return;
+        }
+        for (index, answer) in answers {
+            if answer.as_deref().ok() == winner {
+                continue;
+            }
+            let replica = &self.replicas[*index];
+            let repaired = match winner {
+                Some(value) => replica.write(key, value).is_ok(),
+                None => replica.delete(key).is_ok(),
+            };
+            if repaired {
+                log::info!("Repaired '{key}' on replica {index}");
+            } else {
+                log::warn!("Replica {index} failed to repair '{key}'");
+            }
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for MirroredKvStorage<S> {
+    type WriteErrorType = MirroredWriteError<S::WriteErrorType>;
+    type ReadErrorType = MirroredReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        match self.read_policy {
+            ReadPolicy::Primary => self.primary().read(key).map_err(MirroredReadError::Inner),
+            ReadPolicy::FirstSuccess => self.read_first(key),
+            ReadPolicy::Majority => self.read_majority(key),
+            ReadPolicy::Newest => self.read_newest(key),
+        }
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.mirror("write", key, |replica| replica.write(key, value))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        if self.read_policy == ReadPolicy::Primary {
+            return self
+                .primary()
+                .scan(cursor, limit)
+                .map_err(MirroredReadError::Inner);
+        }
+        let mut first_error = None;
+        for replica in &self.replicas {
+            match replica.scan(cursor, limit) {
+                Ok(page) => return Ok(page),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(MirroredReadError::Inner(
+            first_error.expect("there is at least one replica"),
+        ))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.mirror("delete", key, |replica| replica.delete(key))
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.mirror("delete prefix", prefix, |replica| {
+            replica.delete_prefix(prefix)
+        })
+    }
+
+    // reads depend on the replicas the read policy needs, writes on as many as the failure
+    // policy requires
+    fn health_check(&self) -> kv_storage::Health {
+        let healths: Vec<_> = self
+            .replicas
+            .iter()
+            .map(kv_storage::KvStorage::health_check)
+            .collect();
+        let readable = healths
+            .iter()
+            .filter(|health| !matches!(health, kv_storage::Health::Unavailable(_)))
+            .count();
+        let required = match self.read_policy {
+            ReadPolicy::Primary => 0,
+            ReadPolicy::FirstSuccess | ReadPolicy::Newest => 1,
+            ReadPolicy::Majority => healths.len() / 2 + 1,
+        };
+        let writable = healths.iter().filter(|health| health.is_writable()).count();
+        let acceptable = match self.policy {
+            FailurePolicy::All => writable == healths.len(),
+            FailurePolicy::Primary => healths[0].is_writable(),
+            FailurePolicy::AtLeast(required) => writable >= required,
+        };
+        let unhealthy = healths.iter().filter(|health| !health.is_healthy()).count();
+
+        let mut health = match self.read_policy {
+            ReadPolicy::Primary => healths[0].clone(),
+            _ if readable < required => kv_storage::Health::Unavailable(format!(
+                "only {readable} of {} replicas are readable",
+                healths.len()
+            )),
+            _ => kv_storage::Health::Healthy,
+        };
+        if !acceptable {
+            health = health.worst(kv_storage::Health::ReadOnly(format!(
+                "only {writable} of {} replicas are writable",
+                healths.len()
+            )));
+        } else if unhealthy > 0 {
+            health = health.worst(kv_storage::Health::Degraded(format!(
+                "{unhealthy} of {} replicas are unhealthy",
+                healths.len()
+            )));
+        }
+        health
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for MirroredKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.mirror("purge", "", |replica| replica.purge())
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for MirroredKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.mirror("write", key, |replica| {
+            replica.write_with_ttl(key, value, ttl)
+        })
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.mirror("expire", key, |replica| replica.expire_at(key, at))
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.mirror("touch", key, |replica| replica.touch(key))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MirroredReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("No answer was given by a majority of {replicas} replicas, {} failed", .failures.len())]
+    NoQuorum {
+        // replica index and the error it returned
+        failures: Vec<(usize, E)>,
+        replicas: usize,
+    },
+}
+
+impl<E: IsNotFound> IsNotFound for MirroredReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            MirroredReadError::Inner(e) => e.is_not_found(),
+            MirroredReadError::NoQuorum { .. } => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("{} of {replicas} replicas failed", .failures.len())]
+pub struct MirroredWriteError<E> {
+    // replica index and the error it returned
+    pub failures: Vec<(usize, E)>,
+    pub replicas: usize,
+}
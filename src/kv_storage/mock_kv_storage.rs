@@ -0,0 +1,273 @@
+use crate::kv_storage::memory_kv_storage::MemoryKvStorage;
+use crate::kv_storage::{self, IsNotFound, KvStorage, KvStoragePurge, KvStorageTtl};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call {
+    Read(String),
+    Write {
+        key: String,
+        value: String,
+    },
+    Scan {
+        cursor: Option<String>,
+        limit: usize,
+    },
+    Delete(String),
+    DeletePrefix(String),
+    Purge,
+    WriteWithTtl {
+        key: String,
+        value: String,
+        ttl: Duration,
+    },
+    ExpireAt {
+        key: String,
+        at: SystemTime,
+    },
+    Touch(String),
+}
+
+#[derive(Default)]
+struct Script {
+    reads: HashMap<String, VecDeque<Result<String, MockError>>>,
+    write_errors: HashMap<String, VecDeque<MockError>>,
+    delete_errors: HashMap<String, VecDeque<MockError>>,
+    scan_errors: VecDeque<MockError>,
+}
+
+fn next<T>(queue: &mut HashMap<String, VecDeque<T>>, key: &str) -> Option<T> {
+    let queued = queue.get_mut(key)?;
+    let next = queued.pop_front();
+    if queued.is_empty() {
+        queue.remove(key);
+    }
+    next
+}
+
+// a store for tests of code using a `KvStorage`. responses queued for a key are handed out
+// first, one per call, after that calls go to an in-memory store. every call is recorded and
+// can be asserted on afterwards
+#[derive(Default)]
+pub struct MockKvStorage {
+    store: MemoryKvStorage,
+    script: Mutex<Script>,
+    calls: Mutex<Vec<Call>>,
+}
+
+impl MockKvStorage {
+    pub fn new() -> Self {
+        MockKvStorage::default()
+    }
+
+    pub fn with_entries<K: Into<String>, V: Into<String>>(
+        mut self,
+        entries: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        self.store = self.store.with_entries(entries);
+        self
+    }
+
+    // the stored value is left alone, the response is only returned by the next read of `key`
+    pub fn enqueue_read(&self, key: &str, response: Result<&str, MockError>) {
+        let response = response.map(str::to_string);
+        self.script()
+            .reads
+            .entry(key.to_string())
+            .or_default()
+            .push_back(response);
+    }
+
+    // fails the next write of `key`, with or without a ttl
+    pub fn enqueue_write_error(&self, key: &str, error: MockError) {
+        self.script()
+            .write_errors
+            .entry(key.to_string())
+            .or_default()
+            .push_back(error);
+    }
+
+    // fails the next delete of `key`
+    pub fn enqueue_delete_error(&self, key: &str, error: MockError) {
+        self.script()
+            .delete_errors
+            .entry(key.to_string())
+            .or_default()
+            .push_back(error);
+    }
+
+    pub fn enqueue_scan_error(&self, error: MockError) {
+        self.script().scan_errors.push_back(error);
+    }
+
+    // the in-memory store used once no responses are queued
+    pub fn store(&self) -> &MemoryKvStorage {
+        &self.store
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.recorded().clone()
+    }
+
+    pub fn take_calls(&self) -> Vec<Call> {
+        std::mem::take(&mut *self.recorded())
+    }
+
+    // panics with the recorded calls if `call` isn't one of them
+    pub fn assert_called(&self, call: &Call) {
+        let calls = self.recorded();
+        assert!(
+            calls.contains(call),
+            "expected call {call:?}, recorded calls: {calls:#?}"
+        );
+    }
+
+    pub fn assert_not_called(&self, call: &Call) {
+        let calls = self.recorded();
+        assert!(
+            !calls.contains(call),
+            "unexpected call {call:?}, recorded calls: {calls:#?}"
+        );
+    }
+
+    // panics unless exactly `expected` were recorded, in that order
+    pub fn assert_calls(&self, expected: &[Call]) {
+        let calls = self.recorded();
+        assert_eq!(calls.as_slice(), expected, "recorded calls don't match");
+    }
+
+    fn script(&self) -> MutexGuard<'_, Script> {
+        self.script.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn recorded(&self) -> MutexGuard<'_, Vec<Call>> {
+        self.calls.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn record(&self, call: Call) {
+        self.recorded().push(call);
+    }
+
+    fn check_write(&self, key: &str) -> Result<(), MockError> {
+        match next(&mut self.script().write_errors, key) {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl KvStorage for MockKvStorage {
+    type WriteErrorType = MockError;
+    type ReadErrorType = MockError;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.record(Call::Read(key.to_string()));
+        if let Some(response) = next(&mut self.script().reads, key) {
+            return response;
+        }
+        self.store.read(key).map_err(|_| MockError::NotFound)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.record(Call::Write {
+            key: key.to_string(),
+            value: value.to_string(),
+        });
+        self.check_write(key)?;
+        let Ok(()) = self.store.write(key, value);
+        Ok(())
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.record(Call::Scan {
+            cursor: cursor.map(str::to_string),
+            limit,
+        });
+        if let Some(error) = self.script().scan_errors.pop_front() {
+            return Err(error);
+        }
+        self.store
+            .scan(cursor, limit)
+            .map_err(|_| MockError::NotFound)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.record(Call::Delete(key.to_string()));
+        if let Some(error) = next(&mut self.script().delete_errors, key) {
+            return Err(error);
+        }
+        let Ok(()) = self.store.delete(key);
+        Ok(())
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.record(Call::DeletePrefix(prefix.to_string()));
+        let Ok(()) = self.store.delete_prefix(prefix);
+        Ok(())
+    }
+}
+
+impl KvStoragePurge for MockKvStorage {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.record(Call::Purge);
+        let Ok(()) = self.store.purge();
+        Ok(())
+    }
+}
+
+impl KvStorageTtl for MockKvStorage {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.record(Call::WriteWithTtl {
+            key: key.to_string(),
+            value: value.to_string(),
+            ttl,
+        });
+        self.check_write(key)?;
+        let Ok(()) = self.store.write_with_ttl(key, value, ttl);
+        Ok(())
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.record(Call::ExpireAt {
+            key: key.to_string(),
+            at,
+        });
+        self.check_write(key)?;
+        let Ok(()) = self.store.expire_at(key, at);
+        Ok(())
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.record(Call::Touch(key.to_string()));
+        self.check_write(key)?;
+        let Ok(()) = self.store.touch(key);
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MockError {
+    #[error("Key not found")]
+    NotFound,
+
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl IsNotFound for MockError {
+    fn is_not_found(&self) -> bool {
+        matches!(self, MockError::NotFound)
+    }
+}
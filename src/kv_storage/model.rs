@@ -0,0 +1,136 @@
+use crate::kv_storage::memory_kv_storage::MemoryKvStorage;
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use proptest::prelude::*;
+use proptest::test_runner::{TestCaseError, TestError, TestRunner};
+use std::fmt::Debug;
+
+// so callers don't need proptest themselves to configure `run_model_test`
+pub use proptest::test_runner::Config;
+
+// every key the model test touches lives under this prefix, it is cleared before each case
+const PREFIX: &str = "model/";
+
+// keys from a small alphabet, so generated operations keep hitting the same keys and each
+// other's prefixes. includes '/', '.', a space and non-ascii characters
+pub fn key() -> impl Strategy<Value = String> {
+    "[ab/.é ]{1,4}".prop_map(|key| key.to_string())
+}
+
+// mostly short values, with the odd empty, multi-line or large one
+pub fn value() -> impl Strategy<Value = String> {
+    prop_oneof![
+        8 => "\\PC{0,16}",
+        1 => Just(String::new()),
+        1 => "[a-z\n\t\"\\\\]{0,8}",
+        1 => "[a-z]{1,8}".prop_map(|chunk| chunk.repeat(1024)),
+    ]
+}
+
+#[derive(Debug, Clone)]
+pub enum Op {
+    Read(String),
+    Write(String, String),
+    Delete(String),
+    DeletePrefix(String),
+    Scan(usize),
+}
+
+pub fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => key().prop_map(Op::Read),
+        4 => (key(), value()).prop_map(|(key, value)| Op::Write(key, value)),
+        2 => key().prop_map(Op::Delete),
+        1 => "[ab/]{0,2}".prop_map(|prefix| Op::DeletePrefix(prefix.to_string())),
+        1 => (1usize..8).prop_map(Op::Scan),
+    ]
+}
+
+pub fn ops(max_len: usize) -> impl Strategy<Value = Vec<Op>> {
+    proptest::collection::vec(op(), 1..=max_len)
+}
+
+fn failed(op: &Op, e: impl Debug) -> TestCaseError {
+    TestCaseError::fail(format!("{op:?} failed: {e:?}"))
+}
+
+// runs `ops` against `store` and a `MemoryKvStorage` and fails on the first operation where
+// the two disagree. keys are used under PREFIX, so `store` doesn't have to be empty. reads of
+// the model can't fail
+pub fn check_against_model<S: KvStorage>(store: &S, ops: &[Op]) -> Result<(), TestCaseError>
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    let model = MemoryKvStorage::new();
+    store
+        .delete_prefix(PREFIX)
+        .map_err(|e| failed(&Op::DeletePrefix(PREFIX.to_string()), e))?;
+    for op in ops {
+        match op {
+            Op::Read(key) => {
+                let key = format!("{PREFIX}{key}");
+                let read = store.read_opt(&key).map_err(|e| failed(op, e))?;
+                let expected = model.read_opt(&key).unwrap_or_default();
+                prop_assert_eq!(read, expected, "{:?}", op);
+            }
+            Op::Write(key, value) => {
+                let key = format!("{PREFIX}{key}");
+                store.write(&key, value).map_err(|e| failed(op, e))?;
+                let Ok(()) = model.write(&key, value);
+            }
+            Op::Delete(key) => {
+                let key = format!("{PREFIX}{key}");
+                store.delete(&key).map_err(|e| failed(op, e))?;
+                let Ok(()) = model.delete(&key);
+            }
+            Op::DeletePrefix(prefix) => {
+                let prefix = format!("{PREFIX}{prefix}");
+                store.delete_prefix(&prefix).map_err(|e| failed(op, e))?;
+                let Ok(()) = model.delete_prefix(&prefix);
+            }
+            // pages of the given size, only the entries under PREFIX are compared
+            Op::Scan(limit) => {
+                let mut scanned = Vec::new();
+                let mut cursor = None;
+                loop {
+                    let page = store
+                        .scan(cursor.as_deref(), *limit)
+                        .map_err(|e| failed(op, e))?;
+                    prop_assert!(page.entries.len() <= *limit, "{:?} exceeds limit", op);
+                    scanned.extend(page.entries);
+                    match page.cursor {
+                        Some(next) => cursor = Some(next),
+                        None => break,
+                    }
+                }
+                scanned.retain(|(key, _)| key.starts_with(PREFIX));
+                let expected = model.scan_all().unwrap_or_default();
+                prop_assert_eq!(scanned, expected, "{:?}", op);
+            }
+        }
+    }
+    Ok(())
+}
+
+// generates `config.cases` operation sequences and checks each against a store from
+// `new_store`, panicking with the minimal failing sequence, e.g.
+//
+//     #[test]
+//     fn matches_model() {
+//         model::run_model_test(model::Config::default(), 32, || MyBackend::default());
+//     }
+pub fn run_model_test<S: KvStorage>(config: Config, max_ops: usize, new_store: impl Fn() -> S)
+where
+    S::ReadErrorType: Debug,
+    S::WriteErrorType: Debug,
+{
+    let mut runner = TestRunner::new(config);
+    let result = runner.run(&ops(max_ops), |ops| check_against_model(&new_store(), &ops));
+    match result {
+        Ok(()) => {}
+        Err(TestError::Fail(reason, ops)) => {
+            panic!("store differs from the model: {reason}\nminimal operations: {ops:#?}")
+        }
+        Err(e) => panic!("{e}"),
+    }
+}
@@ -0,0 +1,219 @@
+use crate::kv_storage;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Written { key: String, value: String },
+    Deleted { key: String },
+    DeletedPrefix { prefix: String },
+}
+
+impl Change {
+    // whether the change affects `key`
+    pub fn affects(&self, key: &str) -> bool {
+        match self {
+            Change::Written { key: changed, .. } | Change::Deleted { key: changed } => {
+                changed == key
+            }
+            Change::DeletedPrefix { prefix } => key.starts_with(prefix.as_str()),
+        }
+    }
+
+    // the value of an affected key after the change
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            Change::Written { value, .. } => Some(value),
+            Change::Deleted { .. } | Change::DeletedPrefix { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListenerId(u64);
+
+type Listener = Arc<dyn Fn(&Change) + Send + Sync>;
+
+#[derive(Default)]
+struct Listeners {
+    next_id: u64,
+    listeners: Vec<(ListenerId, Listener)>,
+}
+
+// calls the registered listeners after every successful mutation made through this handle.
+// changes made directly to the wrapped store, or by another process, are not observed
+pub struct ObservedKvStorage<S> {
+    inner: S,
+    listeners: Mutex<Listeners>,
+}
+
+impl<S> ObservedKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        ObservedKvStorage {
+            inner,
+            listeners: Mutex::new(Listeners::default()),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // listeners run on the thread making the change, in the order they were subscribed
+    pub fn subscribe(&self, listener: impl Fn(&Change) + Send + Sync + 'static) -> ListenerId {
+        let mut listeners = self
+            .listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let id = ListenerId(listeners.next_id);
+        listeners.next_id += 1;
+        listeners.listeners.push((id, Arc::new(listener)));
+        id
+    }
+
+    // returns whether the listener was still subscribed
+    pub fn unsubscribe(&self, id: ListenerId) -> bool {
+        let mut listeners = self
+            .listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let before = listeners.listeners.len();
+        listeners.listeners.retain(|(listener, _)| *listener != id);
+        listeners.listeners.len() != before
+    }
+
+    // also used by `CrossTab` for the changes of other tabs
+    pub(crate) fn notify(&self, change: Change) {
+        // called without holding the lock, so listeners can (un)subscribe
+        let listeners: Vec<_> = {
+            let listeners = self
+                .listeners
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            listeners
+                .listeners
+                .iter()
+                .map(|(_, listener)| listener.clone())
+                .collect()
+        };
+        for listener in listeners {
+            listener(&change);
+        }
+    }
+
+    fn observed<E>(
+        &self,
+        change: impl FnOnce() -> Change,
+        f: impl FnOnce() -> Result<(), E>,
+    ) -> Result<(), E> {
+        f()?;
+        self.notify(change());
+        Ok(())
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for ObservedKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.inner.read(key)
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.inner.read_with(key, f)
+    }
+
+    fn read_into(&self, key: &str, buf: &mut String) -> Result<usize, Self::ReadErrorType> {
+        self.inner.read_into(key, buf)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.observed(
+            || Change::Written {
+                key: key.to_string(),
+                value: value.to_string(),
+            },
+            || self.inner.write(key, value),
+        )
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.inner.scan(cursor, limit)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.observed(
+            || Change::Deleted {
+                key: key.to_string(),
+            },
+            || self.inner.delete(key),
+        )
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.observed(
+            || Change::DeletedPrefix {
+                prefix: prefix.to_string(),
+            },
+            || self.inner.delete_prefix(prefix),
+        )
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+// reported as deleting every key
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for ObservedKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.observed(
+            || Change::DeletedPrefix {
+                prefix: String::new(),
+            },
+            || self.inner.purge(),
+        )
+    }
+}
+
+// values don't change, so nothing is reported
+impl<S: kv_storage::KvStorageCompact> kv_storage::KvStorageCompact for ObservedKvStorage<S> {
+    fn compact(&self) -> Result<u64, Self::WriteErrorType> {
+        self.inner.compact()
+    }
+}
+
+// changing only the expiry of an entry is not reported
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for ObservedKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.observed(
+            || Change::Written {
+                key: key.to_string(),
+                value: value.to_string(),
+            },
+            || self.inner.write_with_ttl(key, value, ttl),
+        )
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner.expire_at(key, at)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key)
+    }
+}
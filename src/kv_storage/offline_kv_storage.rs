@@ -0,0 +1,361 @@
+use crate::kv_storage::wal_kv_storage::Intent;
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use thiserror::Error;
+
+// a queued change and what the remote holds for its key when it's replayed, `queued` is `None`
+// for a delete
+pub struct Conflict<'a> {
+    pub key: &'a str,
+    pub queued: Option<&'a str>,
+    pub remote: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    // replays the change as it was queued
+    Queued,
+    // drops the change
+    Remote,
+    // writes this value instead
+    Merged(String),
+}
+
+type Classifier<E> = Box<dyn Fn(&E) -> bool + Send + Sync>;
+type ConflictHandler = Box<dyn Fn(&Conflict<'_>) -> Resolution + Send + Sync>;
+type ReplayError<S, Q> = OfflineReplayError<
+    <S as kv_storage::KvStorage>::ReadErrorType,
+    <S as kv_storage::KvStorage>::WriteErrorType,
+    <Q as kv_storage::KvStorage>::WriteErrorType,
+>;
+
+struct Pending {
+    // queue key and change, oldest first
+    changes: VecDeque<(String, Intent)>,
+    sequence: u64,
+}
+
+// for remote stores that are often unreachable, e.g. an http backend in a field-service app.
+// changes the remote fails to take are queued in the local queue store, e.g. a
+// `FileBasedKvStorage` of its own, and replayed in order by `replay`, or by the next change
+// once the remote is back. while changes are queued, new ones are queued after them so the
+// order is kept, and reads see the queued values
+//
+// every remote error counts as the remote being unreachable unless `offline_if` says otherwise,
+// queued changes the remote then rejects are dropped
+pub struct OfflineKvStorage<S: kv_storage::KvStorage, Q> {
+    remote: S,
+    queue: Q,
+    pending: Mutex<Pending>,
+    is_offline: Classifier<S::WriteErrorType>,
+    on_conflict: Option<ConflictHandler>,
+}
+
+impl<S: kv_storage::KvStorage, Q: kv_storage::KvStorage> OfflineKvStorage<S, Q> {
+    // loads the changes left in `queue`, they're replayed with the next change or `replay`
+    pub fn open(
+        remote: S,
+        queue: Q,
+    ) -> Result<Self, OfflineQueueError<Q::ReadErrorType, Q::WriteErrorType>> {
+        let mut changes = VecDeque::new();
+        let mut sequence = 0;
+        // queue keys are zero padded, so key order is the order the changes were made in
+        for (entry_key, entry) in queue.scan_all().map_err(OfflineQueueError::Read)? {
+            if let Ok(entry_sequence) = entry_key.parse::<u64>() {
+                sequence = sequence.max(entry_sequence + 1);
+            }
+            match Intent::decode(&entry) {
+                Some(intent) => changes.push_back((entry_key, intent)),
+                None => {
+                    log::warn!("Discarding invalid queue entry '{entry_key}'");
+                    queue.delete(&entry_key).map_err(OfflineQueueError::Write)?;
+                }
+            }
+        }
+        if !changes.is_empty() {
+            log::info!("{} changes are queued for the remote", changes.len());
+        }
+        Ok(OfflineKvStorage {
+            remote,
+            queue,
+            pending: Mutex::new(Pending { changes, sequence }),
+            is_offline: Box::new(|_| true),
+            on_conflict: None,
+        })
+    }
+
+    // which remote errors mean the remote is unreachable and the change should be queued
+    pub fn offline_if(
+        mut self,
+        is_offline: impl Fn(&S::WriteErrorType) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.is_offline = Box::new(is_offline);
+        self
+    }
+
+    // called before a queued write or delete is replayed over a value that differs from the
+    // queued one. the remote can't say whether it changed while the change was queued, so a
+    // delete is resolved whenever the key still exists. without it the queued change wins
+    pub fn on_conflict(
+        mut self,
+        on_conflict: impl Fn(&Conflict<'_>) -> Resolution + Send + Sync + 'static,
+    ) -> Self {
+        self.on_conflict = Some(Box::new(on_conflict));
+        self
+    }
+
+    pub fn remote(&self) -> &S {
+        &self.remote
+    }
+
+    pub fn queue(&self) -> &Q {
+        &self.queue
+    }
+
+    pub fn into_inner(self) -> (S, Q) {
+        (self.remote, self.queue)
+    }
+
+    // the number of changes waiting for the remote
+    pub fn queued(&self) -> usize {
+        self.pending().changes.len()
+    }
+
+    // replays the queued changes in order and returns how many the remote took. stops at the
+    // first one that fails because the remote is unreachable, it stays queued
+    pub fn replay(&self) -> Result<usize, ReplayError<S, Q>> {
+        self.replay_pending(&mut self.pending())
+    }
+
+    fn pending(&self) -> MutexGuard<'_, Pending> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn replay_pending(&self, pending: &mut Pending) -> Result<usize, ReplayError<S, Q>> {
+        let mut replayed = 0;
+        while let Some((entry_key, intent)) = pending.changes.front() {
+            let applied = match self.resolve(intent)? {
+                Resolution::Queued => intent.apply(&self.remote),
+                Resolution::Remote => Ok(()),
+                Resolution::Merged(value) => match intent {
+                    Intent::Write(key, _) | Intent::Delete(key) => self.remote.write(key, &value),
+                    Intent::DeletePrefix(_) => unreachable!("prefix deletes are not resolved"),
+                },
+            };
+            match applied {
+                Ok(()) => replayed += 1,
+                Err(e) if (self.is_offline)(&e) => return Err(OfflineReplayError::Remote(e)),
+                Err(_) => log::error!("Remote rejected queued change '{entry_key}', dropping it"),
+            }
+            self.queue
+                .delete(entry_key)
+                .map_err(OfflineReplayError::Queue)?;
+            pending.changes.pop_front();
+        }
+        if replayed > 0 {
+            log::info!("Replayed {replayed} queued changes");
+        }
+        Ok(replayed)
+    }
+
+    fn resolve(&self, intent: &Intent) -> Result<Resolution, ReplayError<S, Q>> {
+        let (Some(on_conflict), Intent::Write(key, _) | Intent::Delete(key)) =
+            (&self.on_conflict, intent)
+        else {
+            return Ok(Resolution::Queued);
+        };
+        let queued = match intent {
+            Intent::Write(_, value) => Some(value.as_str()),
+            _ => None,
+        };
+        let remote = self
+            .remote
+            .read_opt(key)
+            .map_err(OfflineReplayError::RemoteRead)?;
+        let Some(remote) = remote.filter(|remote| Some(remote.as_str()) != queued) else {
+            return Ok(Resolution::Queued);
+        };
+        Ok(on_conflict(&Conflict {
+            key,
+            queued,
+            remote: &remote,
+        }))
+    }
+
+    fn submit(
+        &self,
+        intent: Intent,
+    ) -> Result<(), OfflineWriteError<S::WriteErrorType, Q::WriteErrorType>> {
+        let mut pending = self.pending();
+        if !pending.changes.is_empty() {
+            // the remote is likely still unreachable, the change is queued after the others
+            if self.replay_pending(&mut pending).is_err() {
+                log::debug!("Could not replay queued changes");
+            }
+        }
+        if pending.changes.is_empty() {
+            match intent.apply(&self.remote) {
+                Ok(()) => return Ok(()),
+                Err(e) if !(self.is_offline)(&e) => return Err(OfflineWriteError::Remote(e)),
+                Err(_) => log::warn!("Remote is unreachable, queueing changes"),
+            }
+        }
+        let entry_key = format!("{:020}", pending.sequence);
+        self.queue
+            .write(&entry_key, &intent.encode())
+            .map_err(OfflineWriteError::Queue)?;
+        pending.sequence += 1;
+        pending.changes.push_back((entry_key, intent));
+        Ok(())
+    }
+}
+
+// the queued change of `key`, `Some(None)` if it's deleted
+fn queued<'a>(pending: &'a Pending, key: &str) -> Option<Option<&'a str>> {
+    pending
+        .changes
+        .iter()
+        .rev()
+        .find_map(|(_, intent)| match intent {
+            Intent::Write(queued, value) if queued == key => Some(Some(value.as_str())),
+            Intent::Delete(queued) if queued == key => Some(None),
+            Intent::DeletePrefix(prefix) if key.starts_with(prefix.as_str()) => Some(None),
+            _ => None,
+        })
+}
+
+impl<S, Q> kv_storage::KvStorage for OfflineKvStorage<S, Q>
+where
+    S: kv_storage::KvStorage,
+    Q: kv_storage::KvStorage,
+{
+    type WriteErrorType = OfflineWriteError<S::WriteErrorType, Q::WriteErrorType>;
+    type ReadErrorType = OfflineReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        match queued(&self.pending(), key) {
+            Some(Some(value)) => return Ok(value.to_string()),
+            Some(None) => return Err(OfflineReadError::Deleted(key.to_string())),
+            None => {}
+        }
+        self.remote.read(key).map_err(OfflineReadError::Remote)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.submit(Intent::Write(key.to_string(), value.to_string()))
+    }
+
+    // the remote's page with the queued changes applied, so pages can be shorter than `limit`
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .remote
+            .scan(cursor, limit)
+            .map_err(OfflineReadError::Remote)?;
+        let pending = self.pending();
+        if pending.changes.is_empty() {
+            return Ok(page);
+        }
+
+        // the page covers the keys after `cursor` up to its own cursor
+        let covers = |key: &str| {
+            cursor.is_none_or(|cursor| key > cursor)
+                && page.cursor.as_deref().is_none_or(|end| key <= end)
+        };
+        let mut entries: BTreeMap<_, _> = page.entries.into_iter().collect();
+        for (_, intent) in &pending.changes {
+            match intent {
+                Intent::Write(key, value) if covers(key) => {
+                    entries.insert(key.clone(), value.clone());
+                }
+                Intent::Write(..) => {}
+                Intent::Delete(key) => {
+                    entries.remove(key);
+                }
+                Intent::DeletePrefix(prefix) => {
+                    entries.retain(|key, _| !key.starts_with(prefix.as_str()));
+                }
+            }
+        }
+
+        let mut entries: Vec<_> = entries.into_iter().collect();
+        let cursor = if entries.len() > limit {
+            entries.truncate(limit);
+            entries.last().map(|(key, _)| key.clone())
+        } else {
+            page.cursor
+        };
+        Ok(kv_storage::Page { entries, cursor })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.submit(Intent::Delete(key.to_string()))
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.submit(Intent::DeletePrefix(prefix.to_string()))
+    }
+
+    // changes can still be made while the remote is unreachable
+    fn health_check(&self) -> kv_storage::Health {
+        let queued = self.queued();
+        match self.remote.health_check() {
+            health if queued == 0 && health.is_writable() => health,
+            health => kv_storage::Health::Degraded(format!(
+                "{queued} changes are queued, the remote is {health:?}"
+            )),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum OfflineReadError<E> {
+    #[error(transparent)]
+    Remote(E),
+
+    #[error("Key '{0}' is deleted by a queued change")]
+    Deleted(String),
+}
+
+impl<E: IsNotFound> IsNotFound for OfflineReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            OfflineReadError::Remote(e) => e.is_not_found(),
+            OfflineReadError::Deleted(_) => true,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum OfflineWriteError<R, Q> {
+    #[error(transparent)]
+    Remote(R),
+
+    #[error("Could not queue change")]
+    Queue(#[source] Q),
+}
+
+#[derive(Error, Debug)]
+pub enum OfflineQueueError<R, W> {
+    #[error("Could not read offline queue")]
+    Read(#[source] R),
+
+    #[error("Could not remove invalid queue entry")]
+    Write(#[source] W),
+}
+
+#[derive(Error, Debug)]
+pub enum OfflineReplayError<RR, RW, QW> {
+    #[error("Could not read remote value of a conflicting change")]
+    RemoteRead(#[source] RR),
+
+    #[error("Remote is unreachable")]
+    Remote(#[source] RW),
+
+    #[error("Could not remove replayed change from the queue")]
+    Queue(#[source] QW),
+}
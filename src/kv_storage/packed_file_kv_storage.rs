@@ -0,0 +1,515 @@
+use crate::kv_storage;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+// the file starts with the format's name and its version, as a big endian u16
+const MAGIC: &[u8; 6] = b"ESPACK";
+const FORMAT_VERSION: u16 = 1;
+const HEADER_LEN: u64 = MAGIC.len() as u64 + 2;
+
+const PUT: u8 = 1;
+const DELETE: u8 = 2;
+
+// op, key length and value length
+const RECORD_HEADER_LEN: u64 = 9;
+
+// below it compacting isn't worth rewriting the file
+const MIN_COMPACTION_GARBAGE: u64 = 1024 * 1024;
+
+// every entry in one file, for stores with many small values. changes are appended as records
+// ("<op><key len><value len><key><value>", lengths as little endian u32) and an index of where
+// each value is is kept in memory. records that have been overwritten or deleted are garbage
+// until `compact` rewrites the file with only the live ones, which happens on its own once
+// they're outweighing the rest. the file is locked while the store is open, so only one
+// process can use it at a time
+pub struct PackedFileKvStorage {
+    path: PathBuf,
+    sync: bool,
+    auto_compaction: bool,
+    packed: Mutex<Packed>,
+}
+
+struct Packed {
+    file: fs::File,
+    index: BTreeMap<String, Location>,
+    // where the next record goes
+    len: u64,
+    // bytes taken by records that no longer count
+    garbage: u64,
+}
+
+#[derive(Clone, Copy)]
+struct Location {
+    record: u64,
+    value_len: u32,
+}
+
+impl Location {
+    fn value(&self, key: &str) -> u64 {
+        self.record + RECORD_HEADER_LEN + key.len() as u64
+    }
+
+    fn record_len(&self, key: &str) -> u64 {
+        record_len(key, self.value_len)
+    }
+}
+
+fn record_len(key: &str, value_len: u32) -> u64 {
+    RECORD_HEADER_LEN + key.len() as u64 + u64::from(value_len)
+}
+
+fn encode_record(buf: &mut Vec<u8>, op: u8, key: &str, value: &[u8]) -> io::Result<()> {
+    let too_long = |_| {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            "keys and values are limited to 4 GiB",
+        )
+    };
+    let key_len = u32::try_from(key.len()).map_err(too_long)?;
+    let value_len = u32::try_from(value.len()).map_err(too_long)?;
+    buf.push(op);
+    buf.extend_from_slice(&key_len.to_le_bytes());
+    buf.extend_from_slice(&value_len.to_le_bytes());
+    buf.extend_from_slice(key.as_bytes());
+    buf.extend_from_slice(value);
+    Ok(())
+}
+
+impl PackedFileKvStorage {
+    // a torn record at the end, left by a crash while appending, is cut off
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        file.try_lock().map_err(io::Error::from)?;
+
+        let file_len = file.metadata()?.len();
+        if file_len == 0 {
+            write_header(&mut file)?;
+        } else {
+            let mut header = [0; HEADER_LEN as usize];
+            if file.read_exact(&mut header).is_err() || !header.starts_with(MAGIC) {
+                return Err(io::Error::new(ErrorKind::InvalidData, "not a packed store"));
+            }
+            let version = u16::from_be_bytes([header[6], header[7]]);
+            upgrade(&mut file, version)?;
+        }
+
+        let (index, len, garbage) = load_index(&mut file, file_len.max(HEADER_LEN))?;
+        if len < file_len {
+            log::warn!("cutting a torn record off the end of {path:?}");
+            file.set_len(len)?;
+        }
+        Ok(PackedFileKvStorage {
+            path,
+            sync: false,
+            auto_compaction: true,
+            packed: Mutex::new(Packed {
+                file,
+                index,
+                len,
+                garbage,
+            }),
+        })
+    }
+
+    // fsync every change before returning
+    pub fn with_sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    // compact once garbage is more than half the file, on by default
+    pub fn with_auto_compaction(mut self, auto_compaction: bool) -> Self {
+        self.auto_compaction = auto_compaction;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // bytes compacting would free
+    pub fn garbage(&self) -> u64 {
+        self.packed().garbage
+    }
+
+    fn packed(&self) -> MutexGuard<'_, Packed> {
+        self.packed.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn append(&self, packed: &mut Packed, records: &[u8]) -> io::Result<()> {
+        let result = packed
+            .file
+            .seek(SeekFrom::Start(packed.len))
+            .and_then(|_| packed.file.write_all(records))
+            .and_then(|()| match self.sync {
+                true => packed.file.sync_data(),
+                false => Ok(()),
+            });
+        if result.is_err() {
+            // don't leave a torn record behind for the next append to follow
+            let _ = packed.file.set_len(packed.len);
+            return result;
+        }
+        packed.len += records.len() as u64;
+        Ok(())
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> io::Result<()> {
+        let mut record = Vec::new();
+        encode_record(&mut record, PUT, key, value)?;
+        let mut packed = self.packed();
+        let location = Location {
+            record: packed.len,
+            value_len: value.len() as u32,
+        };
+        self.append(&mut packed, &record)?;
+        if let Some(replaced) = packed.index.insert(key.to_string(), location) {
+            packed.garbage += replaced.record_len(key);
+        }
+        self.maybe_compact(&mut packed)
+    }
+
+    // up to `limit` keys after `cursor` and where their values are
+    fn page(
+        packed: &Packed,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> (Vec<(String, Location)>, Option<String>) {
+        let start = match cursor {
+            Some(cursor) => Bound::Excluded(cursor),
+            None => Bound::Unbounded,
+        };
+        let mut locations: Vec<(String, Location)> = packed
+            .index
+            .range::<str, _>((start, Bound::Unbounded))
+            .take(limit.saturating_add(1))
+            .map(|(key, location)| (key.clone(), *location))
+            .collect();
+        let cursor = if locations.len() > limit && limit > 0 {
+            locations.truncate(limit);
+            locations.last().map(|(key, _)| key.clone())
+        } else {
+            locations.truncate(limit);
+            None
+        };
+        (locations, cursor)
+    }
+
+    fn maybe_compact(&self, packed: &mut Packed) -> io::Result<()> {
+        let due = packed.garbage >= MIN_COMPACTION_GARBAGE && packed.garbage > packed.len / 2;
+        if self.auto_compaction && due {
+            self.compact_locked(packed)?;
+        }
+        Ok(())
+    }
+
+    // returns the bytes reclaimed
+    fn compact_locked(&self, packed: &mut Packed) -> io::Result<u64> {
+        if packed.garbage == 0 {
+            return Ok(0);
+        }
+        let temp_path = self.path.with_extension("compacting");
+        let result = (|| {
+            let temp = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&temp_path)?;
+            temp.try_lock().map_err(io::Error::from)?;
+            let mut writer = BufWriter::new(temp);
+            write_header(&mut writer)?;
+
+            let mut index = BTreeMap::new();
+            let mut len = HEADER_LEN;
+            let mut record = Vec::new();
+            for (key, location) in &packed.index {
+                let value = read_raw_value(&mut packed.file, key, location)?;
+                record.clear();
+                encode_record(&mut record, PUT, key, &value)?;
+                writer.write_all(&record)?;
+                let location = Location {
+                    record: len,
+                    value_len: location.value_len,
+                };
+                index.insert(key.clone(), location);
+                len += record.len() as u64;
+            }
+            let temp = writer
+                .into_inner()
+                .map_err(io::IntoInnerError::into_error)?;
+            temp.sync_all()?;
+            fs::rename(&temp_path, &self.path)?;
+            Ok((temp, index, len))
+        })();
+        let (file, index, len) = match result {
+            Ok(compacted) => compacted,
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                return Err(e);
+            }
+        };
+
+        #[cfg(unix)]
+        if let Some(dir) = self.path.parent().filter(|_| self.sync) {
+            fs::File::open(dir)?.sync_all()?;
+        }
+        let reclaimed = packed.len.saturating_sub(len);
+        *packed = Packed {
+            file,
+            index,
+            len,
+            garbage: 0,
+        };
+        Ok(reclaimed)
+    }
+}
+
+fn write_header(writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_be_bytes())
+}
+
+// rewrites a file in an older format in the current one, and refuses ones from newer versions.
+// version 1 is the first, so there's nothing to upgrade from yet
+fn upgrade(_file: &mut fs::File, version: u16) -> io::Result<()> {
+    match version {
+        FORMAT_VERSION => Ok(()),
+        found if found > FORMAT_VERSION => {
+            let unsupported = kv_storage::UnsupportedFormat {
+                found: found.into(),
+                supported: FORMAT_VERSION.into(),
+            };
+            Err(io::Error::new(ErrorKind::InvalidData, unsupported))
+        }
+        _ => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "packed store has an unknown format version",
+        )),
+    }
+}
+
+// returns the index, where the last complete record ends and the garbage
+fn load_index(
+    file: &mut fs::File,
+    file_len: u64,
+) -> io::Result<(BTreeMap<String, Location>, u64, u64)> {
+    let mut reader = BufReader::new(&mut *file);
+    reader.seek(SeekFrom::Start(HEADER_LEN))?;
+    let mut index: BTreeMap<String, Location> = BTreeMap::new();
+    let mut len = HEADER_LEN;
+    let mut garbage = 0;
+
+    loop {
+        let mut header = [0; RECORD_HEADER_LEN as usize];
+        match reader.read_exact(&mut header) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            result => result?,
+        }
+        let op = header[0];
+        let key_len = u32::from_le_bytes(header[1..5].try_into().unwrap());
+        let value_len = u32::from_le_bytes(header[5..9].try_into().unwrap());
+        let end = len + RECORD_HEADER_LEN + u64::from(key_len) + u64::from(value_len);
+        if end > file_len {
+            break;
+        }
+        let mut key = vec![0; key_len as usize];
+        reader.read_exact(&mut key)?;
+        let key = String::from_utf8(key)
+            .map_err(|_| io::Error::new(ErrorKind::InvalidData, "key is not utf-8"))?;
+        reader.seek_relative(i64::from(value_len))?;
+
+        let replaced = match op {
+            PUT => index.insert(
+                key.clone(),
+                Location {
+                    record: len,
+                    value_len,
+                },
+            ),
+            DELETE => {
+                garbage += end - len;
+                index.remove(&key)
+            }
+            _ => return Err(io::Error::new(ErrorKind::InvalidData, "unknown record")),
+        };
+        if let Some(replaced) = replaced {
+            garbage += replaced.record_len(&key);
+        }
+        len = end;
+    }
+    Ok((index, len, garbage))
+}
+
+fn read_raw_value(file: &mut fs::File, key: &str, location: &Location) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(location.value(key)))?;
+    let mut value = vec![0; location.value_len as usize];
+    file.read_exact(&mut value)?;
+    Ok(value)
+}
+
+// raw writes can leave values that aren't
+fn read_value(file: &mut fs::File, key: &str, location: &Location) -> io::Result<String> {
+    String::from_utf8(read_raw_value(file, key, location)?)
+        .map_err(|_| io::Error::new(ErrorKind::InvalidData, "value is not utf-8"))
+}
+
+impl kv_storage::KvStorage for PackedFileKvStorage {
+    type WriteErrorType = io::Error;
+    type ReadErrorType = io::Error;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let mut packed = self.packed();
+        let location = *packed.index.get(key).ok_or(ErrorKind::NotFound)?;
+        read_value(&mut packed.file, key, &location)
+    }
+
+    fn read_into(&self, key: &str, buf: &mut String) -> Result<usize, Self::ReadErrorType> {
+        buf.clear();
+        let mut packed = self.packed();
+        let location = *packed.index.get(key).ok_or(ErrorKind::NotFound)?;
+        packed.file.seek(SeekFrom::Start(location.value(key)))?;
+        let len = (&mut packed.file)
+            .take(u64::from(location.value_len))
+            .read_to_string(buf)?;
+        if len != location.value_len as usize {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        Ok(len)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.put(key, value.as_bytes())
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let mut packed = self.packed();
+        let (locations, cursor) = Self::page(&packed, cursor, limit);
+        let entries = locations
+            .into_iter()
+            .map(|(key, location)| {
+                let value = read_value(&mut packed.file, &key, &location)?;
+                Ok((key, value))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(kv_storage::Page { entries, cursor })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        let mut packed = self.packed();
+        let Some(location) = packed.index.get(key).copied() else {
+            return Ok(());
+        };
+        let mut record = Vec::new();
+        encode_record(&mut record, DELETE, key, &[])?;
+        self.append(&mut packed, &record)?;
+        packed.index.remove(key);
+        packed.garbage += location.record_len(key) + record.len() as u64;
+        self.maybe_compact(&mut packed)
+    }
+
+    // a single append for every deleted key
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        let mut packed = self.packed();
+        let deleted: Vec<(String, Location)> = packed
+            .index
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, location)| (key.clone(), *location))
+            .collect();
+        if deleted.is_empty() {
+            return Ok(());
+        }
+        let mut records = Vec::new();
+        for (key, _) in &deleted {
+            encode_record(&mut records, DELETE, key, &[])?;
+        }
+        self.append(&mut packed, &records)?;
+        for (key, location) in &deleted {
+            packed.index.remove(key);
+            packed.garbage += location.record_len(key) + record_len(key, 0);
+        }
+        self.maybe_compact(&mut packed)
+    }
+}
+
+impl kv_storage::RawKvStorage for PackedFileKvStorage {
+    type WriteErrorType = io::Error;
+    type ReadErrorType = io::Error;
+
+    fn read_raw(&self, key: &str) -> Result<Vec<u8>, Self::ReadErrorType> {
+        let mut packed = self.packed();
+        let location = *packed.index.get(key).ok_or(ErrorKind::NotFound)?;
+        read_raw_value(&mut packed.file, key, &location)
+    }
+
+    fn write_raw(&self, key: &str, value: &[u8]) -> Result<(), Self::WriteErrorType> {
+        self.put(key, value)
+    }
+
+    fn scan_raw(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::RawPage, Self::ReadErrorType> {
+        let mut packed = self.packed();
+        let (locations, cursor) = Self::page(&packed, cursor, limit);
+        let entries = locations
+            .into_iter()
+            .map(|(key, location)| {
+                let value = read_raw_value(&mut packed.file, &key, &location)?;
+                Ok((key, value))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(kv_storage::RawPage { entries, cursor })
+    }
+
+    fn delete_raw(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        kv_storage::KvStorage::delete(self, key)
+    }
+
+    fn delete_prefix_raw(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        kv_storage::KvStorage::delete_prefix(self, prefix)
+    }
+}
+
+// rewrites the file with only the live records, through a temporary file that's renamed over
+// the old one
+impl kv_storage::KvStorageCompact for PackedFileKvStorage {
+    fn compact(&self) -> Result<u64, Self::WriteErrorType> {
+        let mut packed = self.packed();
+        self.compact_locked(&mut packed)
+    }
+}
+
+// truncates the file, which leaves the old records on disk until they're overwritten
+impl kv_storage::KvStoragePurge for PackedFileKvStorage {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        let mut packed = self.packed();
+        packed.file.set_len(HEADER_LEN)?;
+        if self.sync {
+            packed.file.sync_all()?;
+        }
+        packed.index.clear();
+        packed.len = HEADER_LEN;
+        packed.garbage = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use crate::kv_storage::{self, KvStorageExt};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// runs `f` over `items` on up to `concurrency` threads, the results are in the order of `items`
+fn par_map<T, R>(items: &[T], concurrency: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+{
+    let workers = concurrency.clamp(1, items.len().max(1));
+    if workers == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else {
+                            return results;
+                        };
+                        results.push((i, f(item)));
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))
+            })
+            .collect()
+    });
+    results.sort_unstable_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// batch operations running up to `concurrency` keys at once, e.g. for bulk imports into stores
+// that spend most of their time waiting on io. every key is handled independently, so one
+// failure doesn't stop the others, and the results are in the order the keys were given in
+pub trait KvStorageParallelExt: kv_storage::KvStorage + Sync {
+    #[allow(clippy::type_complexity)]
+    fn par_read_many<K>(
+        &self,
+        keys: &[K],
+        concurrency: usize,
+    ) -> Vec<(String, Result<Option<String>, Self::ReadErrorType>)>
+    where
+        K: AsRef<str> + Sync,
+        Self::ReadErrorType: Send,
+    {
+        par_map(keys, concurrency, |key| {
+            let key = key.as_ref();
+            (key.to_string(), self.read_opt(key))
+        })
+    }
+
+    fn par_write_many<K, V>(
+        &self,
+        entries: &[(K, V)],
+        concurrency: usize,
+    ) -> Vec<(String, Result<(), Self::WriteErrorType>)>
+    where
+        K: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+        Self::WriteErrorType: Send,
+    {
+        par_map(entries, concurrency, |(key, value)| {
+            let key = key.as_ref();
+            (key.to_string(), self.write(key, value.as_ref()))
+        })
+    }
+
+    fn par_delete_many<K>(
+        &self,
+        keys: &[K],
+        concurrency: usize,
+    ) -> Vec<(String, Result<(), Self::WriteErrorType>)>
+    where
+        K: AsRef<str> + Sync,
+        Self::WriteErrorType: Send,
+    {
+        par_map(keys, concurrency, |key| {
+            let key = key.as_ref();
+            (key.to_string(), self.delete(key))
+        })
+    }
+}
+
+impl<S: kv_storage::KvStorage + Sync + ?Sized> KvStorageParallelExt for S {}
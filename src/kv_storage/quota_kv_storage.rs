@@ -0,0 +1,233 @@
+use crate::kv_storage::{self, KvStorageExt};
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+#[derive(Default)]
+struct Usage {
+    sizes: HashMap<String, usize>,
+    namespaces: HashMap<String, usize>,
+}
+
+// caps the total size of keys and values per namespace, the part of a key before the first
+// separator ("user42" for "user42/settings"). keys without a separator share the "" namespace.
+// usage is computed from the wrapped store on first use and then tracked in memory
+pub struct QuotaKvStorage<S> {
+    inner: S,
+    quota: usize,
+    quotas: HashMap<String, usize>,
+    separator: char,
+    usage: Mutex<Option<Usage>>,
+}
+
+impl<S> QuotaKvStorage<S> {
+    pub fn new(inner: S, quota: usize) -> Self {
+        QuotaKvStorage {
+            inner,
+            quota,
+            quotas: HashMap::new(),
+            separator: '/',
+            usage: Mutex::new(None),
+        }
+    }
+
+    pub fn with_separator(mut self, separator: char) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    // overrides the default quota for one namespace
+    pub fn with_namespace_quota(mut self, namespace: &str, quota: usize) -> Self {
+        self.quotas.insert(namespace.to_string(), quota);
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    pub fn namespace<'a>(&self, key: &'a str) -> &'a str {
+        key.split_once(self.separator)
+            .map_or("", |(namespace, _)| namespace)
+    }
+
+    pub fn quota(&self, namespace: &str) -> usize {
+        self.quotas.get(namespace).copied().unwrap_or(self.quota)
+    }
+
+    fn set_size(&self, usage: &mut Usage, key: &str, size: Option<usize>) {
+        let old = match size {
+            Some(size) => usage.sizes.insert(key.to_string(), size),
+            None => usage.sizes.remove(key),
+        };
+        let used = usage
+            .namespaces
+            .entry(self.namespace(key).to_string())
+            .or_default();
+        *used = *used - old.unwrap_or(0) + size.unwrap_or(0);
+    }
+}
+
+impl<S: kv_storage::KvStorage> QuotaKvStorage<S> {
+    fn with_usage<T, E>(
+        &self,
+        map_err: impl FnOnce(S::ReadErrorType) -> E,
+        f: impl FnOnce(&mut Usage) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut usage = self.usage.lock().unwrap_or_else(PoisonError::into_inner);
+        if usage.is_none() {
+            let mut loaded = Usage::default();
+            for (key, value) in self.inner.scan_all().map_err(map_err)? {
+                self.set_size(&mut loaded, &key, Some(key.len() + value.len()));
+            }
+            *usage = Some(loaded);
+        }
+        f(usage.as_mut().expect("usage was just loaded"))
+    }
+
+    // bytes stored in `namespace`
+    pub fn usage(&self, namespace: &str) -> Result<usize, S::ReadErrorType> {
+        self.with_usage(
+            |e| e,
+            |usage| Ok(usage.namespaces.get(namespace).copied().unwrap_or(0)),
+        )
+    }
+
+    fn checked_write(
+        &self,
+        key: &str,
+        value: &str,
+        write: impl FnOnce() -> Result<(), S::WriteErrorType>,
+    ) -> Result<(), QuotaWriteError<S::ReadErrorType, S::WriteErrorType>> {
+        self.with_usage(QuotaWriteError::Read, |usage| {
+            let namespace = self.namespace(key);
+            let size = key.len() + value.len();
+            let used = usage.namespaces.get(namespace).copied().unwrap_or(0)
+                - usage.sizes.get(key).copied().unwrap_or(0);
+            let quota = self.quota(namespace);
+            if used + size > quota {
+                return Err(QuotaWriteError::QuotaExceeded {
+                    namespace: namespace.to_string(),
+                    used,
+                    requested: size,
+                    quota,
+                });
+            }
+
+            write().map_err(QuotaWriteError::Inner)?;
+            self.set_size(usage, key, Some(size));
+            Ok(())
+        })
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for QuotaKvStorage<S> {
+    type WriteErrorType = QuotaWriteError<S::ReadErrorType, S::WriteErrorType>;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.inner.read(key)
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.inner.read_with(key, f)
+    }
+
+    fn read_into(&self, key: &str, buf: &mut String) -> Result<usize, Self::ReadErrorType> {
+        self.inner.read_into(key, buf)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.checked_write(key, value, || self.inner.write(key, value))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.inner.scan(cursor, limit)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.with_usage(QuotaWriteError::Read, |usage| {
+            self.inner.delete(key).map_err(QuotaWriteError::Inner)?;
+            self.set_size(usage, key, None);
+            Ok(())
+        })
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.with_usage(QuotaWriteError::Read, |usage| {
+            self.inner
+                .delete_prefix(prefix)
+                .map_err(QuotaWriteError::Inner)?;
+            let removed: Vec<_> = usage
+                .sizes
+                .keys()
+                .filter(|key| key.starts_with(prefix))
+                .cloned()
+                .collect();
+            for key in removed {
+                self.set_size(usage, &key, None);
+            }
+            Ok(())
+        })
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for QuotaKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        let mut usage = self.usage.lock().unwrap_or_else(PoisonError::into_inner);
+        self.inner.purge().map_err(QuotaWriteError::Inner)?;
+        *usage = None;
+        Ok(())
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for QuotaKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.checked_write(key, value, || self.inner.write_with_ttl(key, value, ttl))
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .expire_at(key, at)
+            .map_err(QuotaWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key).map_err(QuotaWriteError::Inner)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum QuotaWriteError<R, W> {
+    #[error("Could not load existing entries")]
+    Read(#[source] R),
+
+    #[error("Writing {requested} bytes exceeds quota of namespace '{namespace}' ({used}/{quota})")]
+    QuotaExceeded {
+        namespace: String,
+        used: usize,
+        requested: usize,
+        quota: usize,
+    },
+
+    #[error(transparent)]
+    Inner(W),
+}
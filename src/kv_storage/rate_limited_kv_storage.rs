@@ -0,0 +1,183 @@
+use crate::kv_storage::{self, IsNotFound};
+use crate::time::Instant;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RateLimitMode {
+    // fail with `RateLimitedError::RateLimited` when no token is available
+    #[default]
+    Reject,
+    // wait for the next token, not available on wasm since it can't block
+    #[cfg(not(target_arch = "wasm32"))]
+    Block,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+// token bucket shared by all operations, each operation takes one token
+pub struct RateLimitedKvStorage<S> {
+    inner: S,
+    burst: f64,
+    per_second: f64,
+    mode: RateLimitMode,
+    bucket: Mutex<Bucket>,
+}
+
+impl<S> RateLimitedKvStorage<S> {
+    // allows `per_second` operations on average and bursts of up to `burst` operations
+    pub fn new(inner: S, per_second: f64, burst: u32) -> Self {
+        assert!(per_second > 0.0, "rate limit has to be positive");
+        let burst = f64::from(burst.max(1));
+        RateLimitedKvStorage {
+            inner,
+            burst,
+            per_second,
+            mode: RateLimitMode::default(),
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: RateLimitMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // without `RateLimitMode::Block` there is nothing to loop for
+    #[cfg_attr(target_arch = "wasm32", allow(clippy::never_loop))]
+    fn acquire<E>(&self) -> Result<(), RateLimitedError<E>> {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+                let now = Instant::now();
+                let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+                bucket.refilled_at = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return Ok(());
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_second)
+            };
+
+            match self.mode {
+                RateLimitMode::Reject => {
+                    return Err(RateLimitedError::RateLimited { retry_after: wait });
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                RateLimitMode::Block => std::thread::sleep(wait),
+            }
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for RateLimitedKvStorage<S> {
+    type WriteErrorType = RateLimitedError<S::WriteErrorType>;
+    type ReadErrorType = RateLimitedError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.acquire()?;
+        self.inner.read(key).map_err(RateLimitedError::Inner)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.acquire()?;
+        self.inner
+            .write(key, value)
+            .map_err(RateLimitedError::Inner)
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.acquire()?;
+        self.inner
+            .scan(cursor, limit)
+            .map_err(RateLimitedError::Inner)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.acquire()?;
+        self.inner.delete(key).map_err(RateLimitedError::Inner)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.acquire()?;
+        self.inner
+            .delete_prefix(prefix)
+            .map_err(RateLimitedError::Inner)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for RateLimitedKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.acquire()?;
+        self.inner.purge().map_err(RateLimitedError::Inner)
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for RateLimitedKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.acquire()?;
+        self.inner
+            .write_with_ttl(key, value, ttl)
+            .map_err(RateLimitedError::Inner)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.acquire()?;
+        self.inner
+            .expire_at(key, at)
+            .map_err(RateLimitedError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.acquire()?;
+        self.inner.touch(key).map_err(RateLimitedError::Inner)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RateLimitedError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Rate limit exceeded, retry after {retry_after:?}")]
+    RateLimited { retry_after: Duration },
+}
+
+impl<E: IsNotFound> IsNotFound for RateLimitedError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            RateLimitedError::Inner(e) => e.is_not_found(),
+            RateLimitedError::RateLimited { .. } => false,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::kv_storage;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+pub struct ReadOnlyKvStorage<S>(S);
+
+impl<S> ReadOnlyKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        ReadOnlyKvStorage(inner)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for ReadOnlyKvStorage<S> {
+    type WriteErrorType = ReadOnly;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.0.read(key)
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.0.read_with(key, f)
+    }
+
+    fn read_into(&self, key: &str, buf: &mut String) -> Result<usize, Self::ReadErrorType> {
+        self.0.read_into(key, buf)
+    }
+
+    fn write(&self, _key: &str, _value: &str) -> Result<(), Self::WriteErrorType> {
+        Err(ReadOnly)
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.0.scan(cursor, limit)
+    }
+
+    fn delete(&self, _key: &str) -> Result<(), Self::WriteErrorType> {
+        Err(ReadOnly)
+    }
+
+    fn delete_prefix(&self, _prefix: &str) -> Result<(), Self::WriteErrorType> {
+        Err(ReadOnly)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        match self.0.health_check() {
+            kv_storage::Health::Healthy | kv_storage::Health::Degraded(_) => {
+                kv_storage::Health::ReadOnly("the store is read only".to_string())
+            }
+            health => health,
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for ReadOnlyKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        _key: &str,
+        _value: &str,
+        _ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        Err(ReadOnly)
+    }
+
+    fn expire_at(&self, _key: &str, _at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        Err(ReadOnly)
+    }
+
+    fn touch(&self, _key: &str) -> Result<(), Self::WriteErrorType> {
+        Err(ReadOnly)
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Storage is read only")]
+pub struct ReadOnly;
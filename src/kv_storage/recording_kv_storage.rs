@@ -0,0 +1,260 @@
+use crate::kv_storage::{self, IsNotFound};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::io::Write;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    Read {
+        key: String,
+    },
+    Write {
+        key: String,
+        value: String,
+    },
+    Scan {
+        cursor: Option<String>,
+        limit: usize,
+    },
+    Delete {
+        key: String,
+    },
+    DeletePrefix {
+        prefix: String,
+    },
+    Purge,
+    WriteWithTtl {
+        key: String,
+        value: String,
+        ttl_millis: u64,
+    },
+    ExpireAt {
+        key: String,
+        at_millis: u64,
+    },
+    Touch {
+        key: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Outcome {
+    Done,
+    Value {
+        value: String,
+    },
+    Page {
+        entries: Vec<(String, String)>,
+        next_cursor: Option<String>,
+    },
+    // errors are kept as their message, and whether they meant a missing key
+    Error {
+        message: String,
+        not_found: bool,
+    },
+}
+
+impl Outcome {
+    fn read_error(e: &(impl Display + IsNotFound)) -> Self {
+        Outcome::Error {
+            message: e.to_string(),
+            not_found: e.is_not_found(),
+        }
+    }
+
+    fn write_error(e: &impl Display) -> Self {
+        Outcome::Error {
+            message: e.to_string(),
+            not_found: false,
+        }
+    }
+}
+
+// one line of a recording: {"op": "read", "key": "a", "result": "value", "value": "1"}. no
+// field of an operation shares its name with a field of the outcome it can have
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    #[serde(flatten)]
+    pub operation: Operation,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+// writes every operation on the wrapped store and its result to `log` as one json line, e.g.
+// to capture what an application did in the field and reproduce it with `ReplayKvStorage`.
+// failing to write the log is logged and doesn't fail the operation
+pub struct RecordingKvStorage<S, W> {
+    inner: S,
+    log: Mutex<W>,
+}
+
+impl<S, W: Write> RecordingKvStorage<S, W> {
+    pub fn new(inner: S, log: W) -> Self {
+        RecordingKvStorage {
+            inner,
+            log: Mutex::new(log),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> (S, W) {
+        let log = self
+            .log
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        (self.inner, log)
+    }
+
+    fn record(&self, operation: Operation, outcome: Outcome) {
+        let record = Record { operation, outcome };
+        let mut log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+        let written = serde_json::to_writer(&mut *log, &record)
+            .map_err(std::io::Error::from)
+            .and_then(|()| log.write_all(b"\n"))
+            .and_then(|()| log.flush());
+        if let Err(e) = written {
+            log::warn!("Could not record storage operation: {e}");
+        }
+    }
+
+    fn record_write<E: Display>(
+        &self,
+        operation: Operation,
+        result: Result<(), E>,
+    ) -> Result<(), E> {
+        let outcome = match &result {
+            Ok(()) => Outcome::Done,
+            Err(e) => Outcome::write_error(e),
+        };
+        self.record(operation, outcome);
+        result
+    }
+}
+
+impl<S, W> kv_storage::KvStorage for RecordingKvStorage<S, W>
+where
+    S: kv_storage::KvStorage,
+    S::ReadErrorType: Display,
+    S::WriteErrorType: Display,
+    W: Write,
+{
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let result = self.inner.read(key);
+        let outcome = match &result {
+            Ok(value) => Outcome::Value {
+                value: value.clone(),
+            },
+            Err(e) => Outcome::read_error(e),
+        };
+        let key = key.to_string();
+        self.record(Operation::Read { key }, outcome);
+        result
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        let operation = Operation::Write {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        self.record_write(operation, self.inner.write(key, value))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let result = self.inner.scan(cursor, limit);
+        let outcome = match &result {
+            Ok(page) => Outcome::Page {
+                entries: page.entries.clone(),
+                next_cursor: page.cursor.clone(),
+            },
+            Err(e) => Outcome::read_error(e),
+        };
+        let cursor = cursor.map(str::to_string);
+        self.record(Operation::Scan { cursor, limit }, outcome);
+        result
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        let operation = Operation::Delete {
+            key: key.to_string(),
+        };
+        self.record_write(operation, self.inner.delete(key))
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        let operation = Operation::DeletePrefix {
+            prefix: prefix.to_string(),
+        };
+        self.record_write(operation, self.inner.delete_prefix(prefix))
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S, W> kv_storage::KvStoragePurge for RecordingKvStorage<S, W>
+where
+    S: kv_storage::KvStoragePurge,
+    S::ReadErrorType: Display,
+    S::WriteErrorType: Display,
+    W: Write,
+{
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.record_write(Operation::Purge, self.inner.purge())
+    }
+}
+
+impl<S, W> kv_storage::KvStorageTtl for RecordingKvStorage<S, W>
+where
+    S: kv_storage::KvStorageTtl,
+    S::ReadErrorType: Display,
+    S::WriteErrorType: Display,
+    W: Write,
+{
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        let operation = Operation::WriteWithTtl {
+            key: key.to_string(),
+            value: value.to_string(),
+            ttl_millis: millis(ttl),
+        };
+        self.record_write(operation, self.inner.write_with_ttl(key, value, ttl))
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        let operation = Operation::ExpireAt {
+            key: key.to_string(),
+            at_millis: millis(at.duration_since(UNIX_EPOCH).unwrap_or_default()),
+        };
+        self.record_write(operation, self.inner.expire_at(key, at))
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        let operation = Operation::Touch {
+            key: key.to_string(),
+        };
+        self.record_write(operation, self.inner.touch(key))
+    }
+}
@@ -0,0 +1,221 @@
+use crate::kv_storage::recording_kv_storage::{Operation, Outcome, Record};
+use crate::kv_storage::{self, IsNotFound};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// answers every operation with the next result of a recording made by `RecordingKvStorage`,
+// without storing anything. operations have to come in the recorded order and with the
+// recorded arguments, except the time passed to `expire_at`, which usually depends on when
+// the call was made
+pub struct ReplayKvStorage {
+    records: Mutex<VecDeque<Record>>,
+}
+
+impl ReplayKvStorage {
+    pub fn new(records: impl IntoIterator<Item = Record>) -> Self {
+        ReplayKvStorage {
+            records: Mutex::new(records.into_iter().collect()),
+        }
+    }
+
+    // reads a recording of one json record per line, empty lines are skipped
+    pub fn from_reader(reader: impl Read) -> Result<Self, ReplayLoadError> {
+        let mut records = VecDeque::new();
+        for (number, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str(&line).map_err(|e| ReplayLoadError::Parse {
+                line: number + 1,
+                source: e,
+            })?;
+            records.push_back(record);
+        }
+        Ok(ReplayKvStorage {
+            records: Mutex::new(records),
+        })
+    }
+
+    // number of recorded operations not replayed yet
+    pub fn remaining(&self) -> usize {
+        self.records
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    fn replay(&self, operation: Operation) -> Result<Outcome, ReplayError> {
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(next) = records.front() else {
+            return Err(ReplayError::Exhausted(operation));
+        };
+        let matches = match (&next.operation, &operation) {
+            (
+                Operation::ExpireAt { key: recorded, .. },
+                Operation::ExpireAt { key: replayed, .. },
+            ) => recorded == replayed,
+            (recorded, replayed) => recorded == replayed,
+        };
+        if !matches {
+            return Err(ReplayError::Mismatch {
+                expected: next.operation.clone(),
+                actual: operation,
+            });
+        }
+        let record = records.pop_front().expect("front was checked");
+        match record.outcome {
+            Outcome::Error { message, not_found } => {
+                Err(ReplayError::Recorded { message, not_found })
+            }
+            outcome => Ok(outcome),
+        }
+    }
+
+    fn replay_write(&self, operation: Operation) -> Result<(), ReplayError> {
+        match self.replay(operation.clone())? {
+            Outcome::Done => Ok(()),
+            _ => Err(ReplayError::UnexpectedOutcome(operation)),
+        }
+    }
+}
+
+impl kv_storage::KvStorage for ReplayKvStorage {
+    type WriteErrorType = ReplayError;
+    type ReadErrorType = ReplayError;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let operation = Operation::Read {
+            key: key.to_string(),
+        };
+        match self.replay(operation.clone())? {
+            Outcome::Value { value } => Ok(value),
+            _ => Err(ReplayError::UnexpectedOutcome(operation)),
+        }
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.replay_write(Operation::Write {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let operation = Operation::Scan {
+            cursor: cursor.map(str::to_string),
+            limit,
+        };
+        match self.replay(operation.clone())? {
+            Outcome::Page {
+                entries,
+                next_cursor,
+            } => Ok(kv_storage::Page {
+                entries,
+                cursor: next_cursor,
+            }),
+            _ => Err(ReplayError::UnexpectedOutcome(operation)),
+        }
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.replay_write(Operation::Delete {
+            key: key.to_string(),
+        })
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.replay_write(Operation::DeletePrefix {
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+impl kv_storage::KvStoragePurge for ReplayKvStorage {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.replay_write(Operation::Purge)
+    }
+}
+
+impl kv_storage::KvStorageTtl for ReplayKvStorage {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.replay_write(Operation::WriteWithTtl {
+            key: key.to_string(),
+            value: value.to_string(),
+            ttl_millis: u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX),
+        })
+    }
+
+    fn expire_at(&self, key: &str, _at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.replay_write(Operation::ExpireAt {
+            key: key.to_string(),
+            at_millis: 0,
+        })
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.replay_write(Operation::Touch {
+            key: key.to_string(),
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ReplayLoadError {
+    #[error("Could not read recording")]
+    Io(#[from] io::Error),
+
+    #[error("Line {line} of the recording is not valid")]
+    Parse {
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    // the error the recorded store returned
+    #[error("{message}")]
+    Recorded { message: String, not_found: bool },
+
+    #[error("Expected {expected:?} but got {actual:?}")]
+    Mismatch {
+        expected: Operation,
+        actual: Operation,
+    },
+
+    #[error("Recording has no more operations, got {0:?}")]
+    Exhausted(Operation),
+
+    #[error("Recorded result of {0:?} doesn't fit the operation")]
+    UnexpectedOutcome(Operation),
+}
+
+impl IsNotFound for ReplayError {
+    fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            ReplayError::Recorded {
+                not_found: true,
+                ..
+            }
+        )
+    }
+}
@@ -0,0 +1,169 @@
+use crate::kv_storage;
+use crate::kv_storage::observed_kv_storage::{Change, ListenerId, ObservedKvStorage};
+use crate::kv_storage::retrying_kv_storage::RetryPolicy;
+use crate::time::Instant;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Default)]
+struct Backlog {
+    // changes stay queued until the target accepted them
+    changes: VecDeque<Change>,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct Queue {
+    backlog: Mutex<Backlog>,
+    wake: Condvar,
+}
+
+impl Queue {
+    fn backlog(&self) -> MutexGuard<'_, Backlog> {
+        self.backlog.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// copies every change made through an `ObservedKvStorage` to a second store from a background
+// thread, so writes to the source never wait for a slow or remote target. changes are applied
+// one at a time in the order they were made, a failing change is retried with the backoff of
+// `RetryPolicy` until it goes through, without skipping it (`max_attempts` is ignored).
+// changes made before `start`, or not through the observed handle, are not replicated, copy
+// those with `copy::copy_all` first
+pub struct Replicator<T> {
+    queue: Arc<Queue>,
+    listener: ListenerId,
+    worker: Option<JoinHandle<T>>,
+}
+
+impl<T> Replicator<T>
+where
+    T: kv_storage::KvStorage + Send + 'static,
+{
+    pub fn start<S>(source: &ObservedKvStorage<S>, target: T, policy: RetryPolicy) -> Self {
+        let queue = Arc::new(Queue::default());
+        // the listener stays subscribed until `source.unsubscribe(replicator.listener())`, but
+        // does nothing once the replicator is gone
+        let listener = {
+            let queue = Arc::downgrade(&queue);
+            source.subscribe(move |change| {
+                if let Some(queue) = queue.upgrade() {
+                    queue.backlog().changes.push_back(change.clone());
+                    queue.wake.notify_all();
+                }
+            })
+        };
+        let worker = {
+            let queue = queue.clone();
+            thread::spawn(move || replicate(&queue, target, policy))
+        };
+        Replicator {
+            queue,
+            listener,
+            worker: Some(worker),
+        }
+    }
+}
+
+impl<T> Replicator<T> {
+    pub fn listener(&self) -> ListenerId {
+        self.listener
+    }
+
+    // changes not yet accepted by the target
+    pub fn pending(&self) -> usize {
+        self.queue.backlog().changes.len()
+    }
+
+    // waits until every change so far was replicated, returns false on timeout
+    pub fn wait_idle(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut backlog = self.queue.backlog();
+        while !backlog.changes.is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            backlog = self
+                .queue
+                .wake
+                .wait_timeout(backlog, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        true
+    }
+
+    // stops replicating after the change in flight and returns the target together with the
+    // changes it didn't get yet
+    pub fn stop(mut self) -> (T, Vec<Change>) {
+        let target = self.shutdown().expect("the worker is only joined once");
+        let changes = self.queue.backlog().changes.drain(..).collect();
+        (target, changes)
+    }
+
+    fn shutdown(&mut self) -> Option<T> {
+        let worker = self.worker.take()?;
+        self.queue.backlog().shutdown = true;
+        self.queue.wake.notify_all();
+        match worker.join() {
+            Ok(target) => Some(target),
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl<T> Drop for Replicator<T> {
+    fn drop(&mut self) {
+        if self.shutdown().is_some() {
+            let pending = self.queue.backlog().changes.len();
+            if pending > 0 {
+                log::warn!("Dropping replicator with {pending} changes not replicated");
+            }
+        }
+    }
+}
+
+fn replicate<T: kv_storage::KvStorage>(queue: &Queue, target: T, policy: RetryPolicy) -> T {
+    let mut retry = 0;
+    let mut backlog = queue.backlog();
+    loop {
+        if backlog.shutdown {
+            return target;
+        }
+        let Some(change) = backlog.changes.front().cloned() else {
+            backlog = queue
+                .wake
+                .wait(backlog)
+                .unwrap_or_else(PoisonError::into_inner);
+            continue;
+        };
+        drop(backlog);
+
+        let applied = match &change {
+            Change::Written { key, value } => target.write(key, value).is_ok(),
+            Change::Deleted { key } => target.delete(key).is_ok(),
+            Change::DeletedPrefix { prefix } => target.delete_prefix(prefix).is_ok(),
+        };
+        backlog = queue.backlog();
+        if applied {
+            backlog.changes.pop_front();
+            retry = 0;
+            // wakes `wait_idle`
+            queue.wake.notify_all();
+            continue;
+        }
+
+        let backoff = policy.backoff(retry);
+        retry = retry.saturating_add(1);
+        log::warn!("Could not replicate change, retrying in {backoff:?}");
+        // a shutdown ends the backoff early
+        backlog = queue
+            .wake
+            .wait_timeout_while(backlog, backoff, |backlog| !backlog.shutdown)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0;
+    }
+}
@@ -0,0 +1,216 @@
+use crate::kv_storage::access_controlled_kv_storage::matches;
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use crate::time;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+// values of keys under a rule are stored as MARKER + "<unix millis>:" + value, with the time they
+// were written. other values are stored as is, unless they start with the marker, then they get
+// an empty time so they can't be mistaken for a stamped one
+const MARKER: &str = "\u{1}ret:";
+
+// deletes entries once they're older than the max age of the first rule matching their key,
+// e.g. telemetry that must not be kept for more than 30 days. rules are glob patterns like
+// `AccessControlledKvStorage`'s. expired entries are deleted when they're read or scanned, and
+// all at once by `enforce_retention`. the age is counted from the last write, changed rules
+// apply to what's already stored
+pub struct RetentionKvStorage<S> {
+    inner: S,
+    rules: Vec<(String, Duration)>,
+}
+
+impl<S> RetentionKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        RetentionKvStorage {
+            inner,
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn with_rule(mut self, pattern: &str, max_age: Duration) -> Self {
+        self.rules.push((pattern.to_string(), max_age));
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // how long `key` is kept, `None` if no rule matches it
+    pub fn max_age(&self, key: &str) -> Option<Duration> {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| matches(pattern, key))
+            .map(|(_, max_age)| *max_age)
+    }
+
+    fn seal(&self, key: &str, value: &str, now: SystemTime) -> String {
+        if self.max_age(key).is_some() {
+            let millis = now
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            format!("{MARKER}{millis}:{value}")
+        } else if value.starts_with(MARKER) {
+            format!("{MARKER}:{value}")
+        } else {
+            value.to_string()
+        }
+    }
+
+    // returns the value and when it was written, `None` if the envelope is malformed
+    fn open(stored: &str) -> Option<(&str, Option<SystemTime>)> {
+        let Some(envelope) = stored.strip_prefix(MARKER) else {
+            return Some((stored, None));
+        };
+        let (millis, value) = envelope.split_once(':')?;
+        if millis.is_empty() {
+            return Some((value, None));
+        }
+        let written = UNIX_EPOCH + Duration::from_millis(millis.parse().ok()?);
+        Some((value, Some(written)))
+    }
+
+    fn is_expired(&self, key: &str, written: Option<SystemTime>, now: SystemTime) -> bool {
+        match (self.max_age(key), written) {
+            (Some(max_age), Some(written)) => written + max_age <= now,
+            _ => false,
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorage> RetentionKvStorage<S> {
+    // deletes every expired entry and returns how many there were. entries under a rule
+    // without a time, e.g. written before the wrapper or the rule was added, are stamped with
+    // the current one, so they're deleted one max age later
+    pub fn enforce_retention(
+        &self,
+    ) -> Result<usize, RetentionWriteError<S::ReadErrorType, S::WriteErrorType>> {
+        let now = time::now();
+        let mut deleted = 0;
+        for (key, stored) in self.inner.scan_all().map_err(RetentionWriteError::Read)? {
+            let Some((value, written)) = Self::open(&stored) else {
+                continue;
+            };
+            if self.is_expired(&key, written, now) {
+                self.inner
+                    .delete(&key)
+                    .map_err(RetentionWriteError::Inner)?;
+                deleted += 1;
+            } else if written.is_none() && self.max_age(&key).is_some() {
+                self.inner
+                    .write(&key, &self.seal(&key, value, now))
+                    .map_err(RetentionWriteError::Inner)?;
+            }
+        }
+        Ok(deleted)
+    }
+
+    fn unseal(
+        &self,
+        key: &str,
+        stored: &str,
+        now: SystemTime,
+    ) -> Result<Option<String>, RetentionReadError<S::ReadErrorType>> {
+        let (value, written) = Self::open(stored)
+            .ok_or_else(|| RetentionReadError::InvalidEnvelope(key.to_string()))?;
+        if self.is_expired(key, written, now) {
+            if self.inner.delete(key).is_err() {
+                log::warn!("Could not delete expired key '{key}'");
+            }
+            return Ok(None);
+        }
+        Ok(Some(value.to_string()))
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for RetentionKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = RetentionReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let stored = self.inner.read(key).map_err(RetentionReadError::Inner)?;
+        self.unseal(key, &stored, time::now())?
+            .ok_or_else(|| RetentionReadError::Expired(key.to_string()))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.write(key, &self.seal(key, value, time::now()))
+    }
+
+    // expired entries are left out, so pages can be shorter than `limit`
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(RetentionReadError::Inner)?;
+        let now = time::now();
+        let mut entries = Vec::with_capacity(page.entries.len());
+        for (key, stored) in page.entries {
+            if let Some(value) = self.unseal(&key, &stored, now)? {
+                entries.push((key, value));
+            }
+        }
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete(key)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete_prefix(prefix)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for RetentionKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RetentionReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Key '{0}' is past its retention")]
+    Expired(String),
+
+    #[error("Write time of key '{0}' is not valid")]
+    InvalidEnvelope(String),
+}
+
+impl<E: IsNotFound> IsNotFound for RetentionReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            RetentionReadError::Inner(e) => e.is_not_found(),
+            RetentionReadError::Expired(_) => true,
+            RetentionReadError::InvalidEnvelope(_) => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RetentionWriteError<R, W> {
+    #[error("Could not read entries")]
+    Read(#[source] R),
+
+    #[error(transparent)]
+    Inner(W),
+}
@@ -0,0 +1,172 @@
+use crate::kv_storage::{self, IsNotFound};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    // including the first attempt
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // backoff before retry number `retry`, starting at 0
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.saturating_pow(retry);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+type Classifier<E> = Box<dyn Fn(&E) -> bool + Send + Sync>;
+
+pub struct RetryingKvStorage<S: kv_storage::KvStorage> {
+    inner: S,
+    policy: RetryPolicy,
+    retry_read: Classifier<S::ReadErrorType>,
+    retry_write: Classifier<S::WriteErrorType>,
+    sleep: Box<dyn Fn(Duration) + Send + Sync>,
+}
+
+impl<S: kv_storage::KvStorage> RetryingKvStorage<S> {
+    // by default every error except a missing key is retried
+    pub fn new(inner: S, policy: RetryPolicy) -> Self {
+        RetryingKvStorage {
+            inner,
+            policy,
+            retry_read: Box::new(|e| !e.is_not_found()),
+            retry_write: Box::new(|_| true),
+            sleep: Box::new(default_sleep),
+        }
+    }
+
+    pub fn retry_read_if(
+        mut self,
+        retryable: impl Fn(&S::ReadErrorType) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.retry_read = Box::new(retryable);
+        self
+    }
+
+    pub fn retry_write_if(
+        mut self,
+        retryable: impl Fn(&S::WriteErrorType) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.retry_write = Box::new(retryable);
+        self
+    }
+
+    // replaces the blocking sleep between attempts, e.g. for tests
+    pub fn with_sleep(mut self, sleep: impl Fn(Duration) + Send + Sync + 'static) -> Self {
+        self.sleep = Box::new(sleep);
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn retry<T, E>(
+        &self,
+        retryable: &Classifier<E>,
+        mut op: impl FnMut() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut retry = 0;
+        loop {
+            match op() {
+                Err(e) if retry + 1 < self.policy.max_attempts && retryable(&e) => {
+                    let backoff = self.policy.backoff(retry);
+                    log::debug!("Retrying storage operation in {backoff:?}");
+                    (self.sleep)(backoff);
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// wasm32-unknown-unknown can't block, retries happen right away there
+pub(crate) fn default_sleep(duration: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    std::thread::sleep(duration);
+    #[cfg(target_arch = "wasm32")]
+    let _ = duration;
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for RetryingKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.retry(&self.retry_read, || self.inner.read(key))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.retry(&self.retry_write, || self.inner.write(key, value))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.retry(&self.retry_read, || self.inner.scan(cursor, limit))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.retry(&self.retry_write, || self.inner.delete(key))
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.retry(&self.retry_write, || self.inner.delete_prefix(prefix))
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for RetryingKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.retry(&self.retry_write, || self.inner.purge())
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for RetryingKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.retry(&self.retry_write, || {
+            self.inner.write_with_ttl(key, value, ttl)
+        })
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.retry(&self.retry_write, || self.inner.expire_at(key, at))
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.retry(&self.retry_write, || self.inner.touch(key))
+    }
+}
@@ -0,0 +1,330 @@
+use crate::kv_storage::indexed_kv_storage::to_pointer;
+use crate::kv_storage::{self, KvStorageExt};
+use serde_json::Value;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+use tantivy::collector::TopDocs;
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, Schema, Value as _, STORED, STRING, TEXT};
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+use thiserror::Error;
+
+// the least tantivy's writer takes
+const WRITER_MEMORY: usize = 15_000_000;
+
+// indexes values for full-text search with tantivy, e.g. the notes of a note-taking app, and
+// finds their keys with `search`. json values can be narrowed down to some of their fields with
+// `with_field`, anything else is indexed as it is. the index is kept in memory or in a
+// directory of its own, changes are committed to it before the next search and when the store
+// is dropped. `rebuild_search_index` indexes what's stored again, e.g. when an index in memory
+// is opened over a store that has values
+pub struct SearchKvStorage<S> {
+    inner: S,
+    search: Search,
+    // json pointers of the indexed fields, everything if empty
+    fields: Vec<String>,
+}
+
+// commits when it's dropped, also when the store is taken apart with `into_inner`
+struct Search {
+    index: Index,
+    writer: Mutex<IndexWriter>,
+    reader: IndexReader,
+    key: Field,
+    body: Field,
+    // there are changes that aren't committed yet
+    dirty: AtomicBool,
+}
+
+impl Search {
+    fn new(index: Index) -> tantivy::Result<Self> {
+        let schema = index.schema();
+        let writer = index.writer_with_num_threads(1, WRITER_MEMORY)?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        Ok(Search {
+            key: schema.get_field("key")?,
+            body: schema.get_field("body")?,
+            index,
+            writer: Mutex::new(writer),
+            reader,
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    fn commit(&self) -> tantivy::Result<()> {
+        self.dirty.store(false, Ordering::Release);
+        if let Err(e) = self.writer().commit() {
+            self.dirty.store(true, Ordering::Release);
+            return Err(e);
+        }
+        self.reader.reload()
+    }
+
+    fn writer(&self) -> MutexGuard<'_, IndexWriter> {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn index(&self, key: &str, text: String) -> tantivy::Result<()> {
+        let writer = self.writer();
+        writer.delete_term(Term::from_field_text(self.key, key));
+        writer.add_document(doc!(self.key => key, self.body => text))?;
+        self.dirty.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn unindex(&self, key: &str) {
+        self.writer()
+            .delete_term(Term::from_field_text(self.key, key));
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    fn clear(&self) -> tantivy::Result<()> {
+        self.writer().delete_all_documents()?;
+        self.dirty.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        if self.dirty.load(Ordering::Acquire) {
+            if let Err(e) = self.commit() {
+                log::warn!("Could not commit the search index: {e}");
+            }
+        }
+    }
+}
+
+impl<S> SearchKvStorage<S> {
+    pub fn in_memory(inner: S) -> tantivy::Result<Self> {
+        Ok(SearchKvStorage {
+            inner,
+            search: Search::new(Index::create_in_ram(Self::schema()))?,
+            fields: Vec::new(),
+        })
+    }
+
+    // opens the index in `dir`, or creates it there
+    pub fn open(inner: S, dir: impl AsRef<Path>) -> tantivy::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        let dir = tantivy::directory::MmapDirectory::open(dir)?;
+        Ok(SearchKvStorage {
+            inner,
+            search: Search::new(Index::open_or_create(dir, Self::schema())?)?,
+            fields: Vec::new(),
+        })
+    }
+
+    fn schema() -> Schema {
+        let mut schema = Schema::builder();
+        schema.add_text_field("key", STRING | STORED);
+        schema.add_text_field("body", TEXT);
+        schema.build()
+    }
+
+    // a json path like `IndexedKvStorage`'s, e.g. "$.title". with several fields their text is
+    // searched together
+    pub fn with_field(mut self, path: &str) -> Self {
+        self.fields.push(to_pointer(path));
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // the keys whose values match `query`, best first. the query is in tantivy's syntax, e.g.
+    // "rust AND storage" or "\"exact phrase\""
+    pub fn search(&self, query: &str) -> Result<Vec<String>, SearchError> {
+        if self.search.dirty.load(Ordering::Acquire) {
+            self.commit()?;
+        }
+        let parser = QueryParser::for_index(&self.search.index, vec![self.search.body]);
+        let query = parser.parse_query(query)?;
+        let searcher = self.search.reader.searcher();
+        let limit = usize::try_from(searcher.num_docs())
+            .unwrap_or(usize::MAX)
+            .max(1);
+        let hits = searcher.search(&query, &TopDocs::with_limit(limit).order_by_score())?;
+        let mut keys = Vec::with_capacity(hits.len());
+        for (_, address) in hits {
+            let document: TantivyDocument = searcher.doc(address)?;
+            let key = document.get_first(self.search.key);
+            if let Some(key) = key.and_then(|key| key.as_str()) {
+                keys.push(key.to_string());
+            }
+        }
+        Ok(keys)
+    }
+
+    // makes the changes so far visible to searches, and durable for an index in a directory
+    pub fn commit(&self) -> tantivy::Result<()> {
+        self.search.commit()
+    }
+
+    // the text `value` is found by
+    fn text(&self, value: &str) -> String {
+        if self.fields.is_empty() {
+            return value.to_string();
+        }
+        let Ok(value) = serde_json::from_str::<Value>(value) else {
+            return value.to_string();
+        };
+        let mut text = Vec::new();
+        for field in &self.fields {
+            match value.pointer(field) {
+                Some(Value::String(field)) => text.push(field.clone()),
+                Some(Value::Array(values)) => text.extend(
+                    values
+                        .iter()
+                        .filter_map(|value| value.as_str().map(str::to_string)),
+                ),
+                Some(value @ (Value::Number(_) | Value::Bool(_))) => text.push(value.to_string()),
+                _ => {}
+            }
+        }
+        text.join("\n")
+    }
+
+    fn index_value(&self, key: &str, value: &str) -> tantivy::Result<()> {
+        self.search.index(key, self.text(value))
+    }
+}
+
+impl<S: kv_storage::KvStorage> SearchKvStorage<S> {
+    // drops the index's documents and indexes every stored value again. returns how many there
+    // were
+    pub fn rebuild_search_index(
+        &self,
+    ) -> Result<usize, SearchWriteError<S::ReadErrorType, S::WriteErrorType>> {
+        let entries = self.inner.scan_all().map_err(SearchWriteError::Read)?;
+        self.search.clear()?;
+        for (key, value) in &entries {
+            self.index_value(key, value)?;
+        }
+        self.commit()?;
+        Ok(entries.len())
+    }
+}
+
+// a value is written before it's indexed, so a failed index update leaves the value written.
+// `rebuild_search_index` catches the index up again
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for SearchKvStorage<S> {
+    type WriteErrorType = SearchWriteError<S::ReadErrorType, S::WriteErrorType>;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.inner.read(key)
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.inner.read_with(key, f)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .write(key, value)
+            .map_err(SearchWriteError::Inner)?;
+        Ok(self.index_value(key, value)?)
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.inner.scan(cursor, limit)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete(key).map_err(SearchWriteError::Inner)?;
+        self.search.unindex(key);
+        Ok(())
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        let keys: Vec<_> = self
+            .inner
+            .scan_all()
+            .map_err(SearchWriteError::Read)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .filter(|key| key.starts_with(prefix))
+            .collect();
+        self.inner
+            .delete_prefix(prefix)
+            .map_err(SearchWriteError::Inner)?;
+        for key in keys {
+            self.search.unindex(&key);
+        }
+        Ok(())
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for SearchKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge().map_err(SearchWriteError::Inner)?;
+        self.search.clear()?;
+        Ok(self.commit()?)
+    }
+}
+
+// expired values stay in the index until they're written over or deleted, `search` can return
+// their keys
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for SearchKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .write_with_ttl(key, value, ttl)
+            .map_err(SearchWriteError::Inner)?;
+        Ok(self.index_value(key, value)?)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .expire_at(key, at)
+            .map_err(SearchWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key).map_err(SearchWriteError::Inner)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SearchError {
+    #[error("Could not parse search query")]
+    Query(#[from] tantivy::query::QueryParserError),
+
+    #[error("Could not search the index")]
+    Index(#[from] tantivy::TantivyError),
+}
+
+#[derive(Error, Debug)]
+pub enum SearchWriteError<R, W> {
+    #[error("Could not read entries")]
+    Read(#[source] R),
+
+    #[error("Could not update the search index")]
+    Index(#[from] tantivy::TantivyError),
+
+    #[error(transparent)]
+    Inner(W),
+}
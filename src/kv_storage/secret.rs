@@ -0,0 +1,57 @@
+use std::fmt;
+use zeroize::Zeroize;
+
+// a string that is overwritten with zeroes when it's dropped, so a decrypted secret doesn't
+// stay behind in freed memory. copies made through `expose` aren't covered, and neither are
+// strings it was made from with `From<&str>`
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(value: String) -> Self {
+        SecretString(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        SecretString(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        SecretString(value.to_string())
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+// keeps the value out of logs
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretString(..)")
+    }
+}
+
+pub(crate) fn wipe(mut bytes: Vec<u8>) {
+    bytes.zeroize();
+}
+
+// like `String::from_utf8`, but the bytes are wiped when they aren't utf-8 instead of being
+// handed back in the error
+pub(crate) fn into_string(bytes: Vec<u8>) -> Result<String, std::str::Utf8Error> {
+    String::from_utf8(bytes).map_err(|e| {
+        let error = e.utf8_error();
+        wipe(e.into_bytes());
+        error
+    })
+}
@@ -0,0 +1,163 @@
+use crate::kv_storage::{self, IsNotFound};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// values are stored as "<base64 hmac-sha256 tag>:<value>", the tag covers the key and its
+// length too so a signed value can't be copied to a different key. values stay readable, use
+// `EncryptedKvStorage` when they have to be secret. values signed before the key's length was
+// covered are stored as "<tag>.<value>" and still verify, unless the key or value has a NUL,
+// which could move where the key ends
+pub struct SignedKvStorage<S> {
+    inner: S,
+    mac: HmacSha256,
+}
+
+impl<S> SignedKvStorage<S> {
+    pub fn new(inner: S, secret: &[u8]) -> Self {
+        SignedKvStorage {
+            inner,
+            mac: HmacSha256::new_from_slice(secret).expect("hmac accepts keys of any length"),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn mac(&self, key: &str, value: &str, legacy: bool) -> HmacSha256 {
+        let mut mac = self.mac.clone();
+        if legacy {
+            mac.update(key.as_bytes());
+            mac.update(&[0]);
+        } else {
+            mac.update(&(key.len() as u64).to_le_bytes());
+            mac.update(key.as_bytes());
+        }
+        mac.update(value.as_bytes());
+        mac
+    }
+
+    fn sign(&self, key: &str, value: &str) -> String {
+        let tag = self.mac(key, value, false).finalize().into_bytes();
+        format!("{}:{value}", BASE64.encode(tag))
+    }
+
+    fn verify<E>(&self, key: &str, stored: &str) -> Result<String, SignedReadError<E>> {
+        let tampered = || SignedReadError::Tampered(key.to_string());
+
+        // the tag is base64, so the first ':' or '.' ends it
+        let end = stored.find([':', '.']).ok_or_else(tampered)?;
+        let (tag, value) = (&stored[..end], &stored[end + 1..]);
+        let legacy = stored[end..].starts_with('.');
+        if legacy && (key.contains('\0') || value.contains('\0')) {
+            return Err(tampered());
+        }
+        let tag = BASE64.decode(tag).map_err(|_| tampered())?;
+        self.mac(key, value, legacy)
+            .verify_slice(&tag)
+            .map_err(|_| tampered())?;
+        Ok(value.to_string())
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for SignedKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = SignedReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let stored = self.inner.read(key).map_err(SignedReadError::Inner)?;
+        self.verify(key, &stored)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.write(key, &self.sign(key, value))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(SignedReadError::Inner)?;
+        let entries = page
+            .entries
+            .into_iter()
+            .map(|(key, stored)| {
+                let value = self.verify(&key, &stored)?;
+                Ok((key, value))
+            })
+            .collect::<Result<_, Self::ReadErrorType>>()?;
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete(key)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete_prefix(prefix)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for SignedKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge()
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for SignedKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.inner.write_with_ttl(key, &self.sign(key, value), ttl)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner.expire_at(key, at)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SignedReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Signature of key '{0}' does not match, the value was modified")]
+    Tampered(String),
+}
+
+impl<E: IsNotFound> IsNotFound for SignedReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            SignedReadError::Inner(e) => e.is_not_found(),
+            SignedReadError::Tampered(_) => false,
+        }
+    }
+}
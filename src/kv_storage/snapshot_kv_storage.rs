@@ -0,0 +1,185 @@
+use crate::kv_storage::{self, KvStorageExt};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+type Snapshot = BTreeMap<String, String>;
+
+// snapshots are copies of every entry held in memory, they don't survive a restart
+pub struct SnapshotKvStorage<S> {
+    inner: S,
+    snapshots: Mutex<HashMap<String, Snapshot>>,
+}
+
+impl<S> SnapshotKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        SnapshotKvStorage {
+            inner,
+            snapshots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    pub fn snapshots(&self) -> Vec<String> {
+        let snapshots = self
+            .snapshots
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        snapshots.keys().cloned().collect()
+    }
+
+    pub fn has_snapshot(&self, name: &str) -> bool {
+        let snapshots = self
+            .snapshots
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        snapshots.contains_key(name)
+    }
+
+    // returns whether a snapshot with that name existed
+    pub fn discard_snapshot(&self, name: &str) -> bool {
+        let mut snapshots = self
+            .snapshots
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        snapshots.remove(name).is_some()
+    }
+}
+
+impl<S: kv_storage::KvStorage> SnapshotKvStorage<S> {
+    // captures the current state under `name`, replacing an older snapshot with the same name
+    pub fn snapshot(&self, name: &str) -> Result<(), S::ReadErrorType> {
+        let snapshot = self.inner.scan_all()?.into_iter().collect();
+        let mut snapshots = self
+            .snapshots
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        snapshots.insert(name.to_string(), snapshot);
+        Ok(())
+    }
+
+    // restores the state captured by `name`: keys written since are deleted and changed values
+    // are written back. the snapshot is kept, so it can be rolled back to again
+    pub fn rollback(
+        &self,
+        name: &str,
+    ) -> Result<(), SnapshotError<S::ReadErrorType, S::WriteErrorType>> {
+        let snapshot = {
+            let snapshots = self
+                .snapshots
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            snapshots
+                .get(name)
+                .cloned()
+                .ok_or_else(|| SnapshotError::UnknownSnapshot(name.to_string()))?
+        };
+
+        let current: Snapshot = self
+            .inner
+            .scan_all()
+            .map_err(SnapshotError::Read)?
+            .into_iter()
+            .collect();
+        for key in current.keys().filter(|key| !snapshot.contains_key(*key)) {
+            self.inner.delete(key).map_err(SnapshotError::Write)?;
+        }
+        for (key, value) in &snapshot {
+            if current.get(key) != Some(value) {
+                self.inner.write(key, value).map_err(SnapshotError::Write)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for SnapshotKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.inner.read(key)
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.inner.read_with(key, f)
+    }
+
+    fn read_into(&self, key: &str, buf: &mut String) -> Result<usize, Self::ReadErrorType> {
+        self.inner.read_into(key, buf)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.write(key, value)
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        self.inner.scan(cursor, limit)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete(key)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete_prefix(prefix)
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+// snapshots hold copies of the values, so they are discarded too
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for SnapshotKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.snapshots
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        self.inner.purge()
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for SnapshotKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.inner.write_with_ttl(key, value, ttl)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner.expire_at(key, at)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SnapshotError<R, W> {
+    #[error("No snapshot named '{0}'")]
+    UnknownSnapshot(String),
+
+    #[error("Could not read current state")]
+    Read(#[source] R),
+
+    #[error("Could not restore snapshot")]
+    Write(#[source] W),
+}
@@ -0,0 +1,175 @@
+use crate::kv_storage::{self, IsNotFound};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+
+const SEPARATOR: char = '/';
+
+// keeps every field of a struct in its own key under a prefix, nested structs and maps are
+// flattened, so `cfg.audio.volume` saved under "settings" ends up in "settings/audio/volume".
+// the fields are stored as json, sequences as a whole
+pub trait KvStorageStructExt: kv_storage::KvStorage {
+    // unchanged fields aren't rewritten, keys of fields the struct no longer has are deleted
+    // after the new fields are written
+    fn save_struct<T: Serialize + ?Sized>(
+        &self,
+        prefix: impl AsRef<str>,
+        value: &T,
+    ) -> Result<(), StructWriteError<Self::ReadErrorType, Self::WriteErrorType>> {
+        let prefix = prefix.as_ref();
+        let Value::Object(object) =
+            serde_json::to_value(value).map_err(StructWriteError::Encode)?
+        else {
+            return Err(StructWriteError::NotAStruct);
+        };
+        let mut fields = Vec::new();
+        flatten(prefix, object, &mut fields)?;
+
+        let saved: BTreeMap<String, String> = scan_prefix(self, prefix)
+            .map_err(StructWriteError::Read)?
+            .into_iter()
+            .collect();
+        for (key, field) in &fields {
+            if saved.get(key) != Some(field) {
+                self.write(key, field).map_err(StructWriteError::Inner)?;
+            }
+        }
+        let written: BTreeSet<&str> = fields.iter().map(|(key, _)| key.as_str()).collect();
+        for key in saved.keys().filter(|key| !written.contains(key.as_str())) {
+            self.delete(key).map_err(StructWriteError::Inner)?;
+        }
+        Ok(())
+    }
+
+    // missing fields are left to serde, e.g. `#[serde(default)]`
+    fn load_struct<T: DeserializeOwned>(
+        &self,
+        prefix: impl AsRef<str>,
+    ) -> Result<T, StructReadError<Self::ReadErrorType>> {
+        let prefix = prefix.as_ref();
+        let mut object = Map::new();
+        for (key, field) in scan_prefix(self, prefix).map_err(StructReadError::Inner)? {
+            let value =
+                serde_json::from_str(&field).map_err(|e| StructReadError::Field(key.clone(), e))?;
+            let path: Vec<&str> = key[prefix.len() + 1..].split(SEPARATOR).collect();
+            insert(&mut object, &path, value);
+        }
+        serde_json::from_value(Value::Object(object)).map_err(StructReadError::Decode)
+    }
+}
+
+impl<S: kv_storage::KvStorage + ?Sized> KvStorageStructExt for S {}
+
+fn field_key(prefix: &str, name: &str) -> String {
+    format!("{prefix}{SEPARATOR}{name}")
+}
+
+fn flatten<R, W>(
+    prefix: &str,
+    object: Map<String, Value>,
+    fields: &mut Vec<(String, String)>,
+) -> Result<(), StructWriteError<R, W>> {
+    for (name, value) in object {
+        if name.is_empty() || name.contains(SEPARATOR) {
+            return Err(StructWriteError::InvalidField(name));
+        }
+        let key = field_key(prefix, &name);
+        match value {
+            // an empty struct or map has no fields to keep it, it is stored as a value instead
+            Value::Object(object) if object.is_empty() => fields.push((key, "{}".to_string())),
+            Value::Object(object) => flatten(&key, object, fields)?,
+            value => fields.push((key, value.to_string())),
+        }
+    }
+    Ok(())
+}
+
+fn insert(object: &mut Map<String, Value>, path: &[&str], value: Value) {
+    match path {
+        [] => {}
+        [name] => {
+            object.insert(name.to_string(), value);
+        }
+        [name, rest @ ..] => {
+            // keys sort before the keys under them, fields of a nested struct replace a
+            // leftover value for the struct
+            let nested = object
+                .entry(*name)
+                .and_modify(|nested| {
+                    if !nested.is_object() {
+                        *nested = Value::Object(Map::new());
+                    }
+                })
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(nested) = nested {
+                insert(nested, rest, value);
+            }
+        }
+    }
+}
+
+// the entries with keys under `prefix` and the separator
+fn scan_prefix<S: kv_storage::KvStorage + ?Sized>(
+    store: &S,
+    prefix: &str,
+) -> Result<Vec<(String, String)>, S::ReadErrorType> {
+    let start = field_key(prefix, "");
+    let mut entries = Vec::new();
+    let mut cursor = start.clone();
+    loop {
+        let page = store.scan(Some(&cursor), 128)?;
+        let scanned = page.entries.len();
+        let before = entries.len();
+        entries.extend(
+            page.entries
+                .into_iter()
+                .take_while(|(key, _)| key.starts_with(&start)),
+        );
+        let past_prefix = entries.len() - before < scanned;
+        match page.cursor {
+            Some(next) if !past_prefix => cursor = next,
+            _ => return Ok(entries),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum StructReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Could not decode field '{0}'")]
+    Field(String, #[source] serde_json::Error),
+
+    #[error("Could not build the struct from its fields")]
+    Decode(#[source] serde_json::Error),
+}
+
+impl<E: IsNotFound> IsNotFound for StructReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            StructReadError::Inner(e) => e.is_not_found(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum StructWriteError<R, W> {
+    #[error("Could not encode the struct")]
+    Encode(#[source] serde_json::Error),
+
+    #[error("Only structs and maps can be saved field by field")]
+    NotAStruct,
+
+    #[error("Field name '{0}' is empty or contains '/'")]
+    InvalidField(String),
+
+    #[error("Could not list the fields saved before")]
+    Read(#[source] R),
+
+    #[error(transparent)]
+    Inner(W),
+}
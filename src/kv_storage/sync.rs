@@ -0,0 +1,156 @@
+use crate::kv_storage::timestamped_kv_storage::{Record, TimestampedKvStorage};
+use crate::kv_storage::{self, KvStorageExt};
+use crate::time;
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    A,
+    B,
+}
+
+// a key changed on both sides since the previous sync, `winner` is the side whose record was
+// kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub key: String,
+    pub winner: Side,
+    pub a_modified: SystemTime,
+    pub b_modified: SystemTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncReport {
+    // keys copied from b to a
+    pub updated_a: Vec<String>,
+    // keys copied from a to b
+    pub updated_b: Vec<String>,
+    pub conflicts: Vec<Conflict>,
+    // pass to the next `sync_since` of the same two stores
+    pub synced_at: SystemTime,
+}
+
+pub type SyncResult<A, B> = Result<
+    SyncReport,
+    SyncError<
+        <A as kv_storage::KvStorage>::ReadErrorType,
+        <A as kv_storage::KvStorage>::WriteErrorType,
+        <B as kv_storage::KvStorage>::ReadErrorType,
+        <B as kv_storage::KvStorage>::WriteErrorType,
+    >,
+>;
+
+// `sync_since` without conflict detection
+pub fn sync<A, B>(a: &TimestampedKvStorage<A>, b: &TimestampedKvStorage<B>) -> SyncResult<A, B>
+where
+    A: kv_storage::KvStorage,
+    B: kv_storage::KvStorage,
+{
+    sync_since(a, b, None)
+}
+
+// reconciles both stores so they end up with the same records, for every key the most recent
+// write or delete wins. ties are broken by the record itself, so both stores always agree.
+// keys modified on both sides after `last_sync` are reported as conflicts. timestamps come
+// from the clocks of the writing devices, so keep them roughly in sync
+pub fn sync_since<A, B>(
+    a: &TimestampedKvStorage<A>,
+    b: &TimestampedKvStorage<B>,
+    last_sync: Option<SystemTime>,
+) -> SyncResult<A, B>
+where
+    A: kv_storage::KvStorage,
+    B: kv_storage::KvStorage,
+{
+    let synced_at = time::now();
+    let a_records: BTreeMap<_, _> = a
+        .inner()
+        .scan_all()
+        .map_err(SyncError::ReadA)?
+        .into_iter()
+        .collect();
+    let b_records: BTreeMap<_, _> = b
+        .inner()
+        .scan_all()
+        .map_err(SyncError::ReadB)?
+        .into_iter()
+        .collect();
+
+    let mut report = SyncReport {
+        updated_a: Vec::new(),
+        updated_b: Vec::new(),
+        conflicts: Vec::new(),
+        synced_at,
+    };
+    for (key, a_stored) in &a_records {
+        let Some(b_stored) = b_records.get(key) else {
+            b.inner().write(key, a_stored).map_err(SyncError::WriteB)?;
+            report.updated_b.push(key.clone());
+            continue;
+        };
+        if a_stored == b_stored {
+            continue;
+        }
+
+        // unparsable records lose against anything
+        let a_record = Record::parse(a_stored);
+        let b_record = Record::parse(b_stored);
+        let a_modified = a_record.as_ref().map(|record| record.modified);
+        let b_modified = b_record.as_ref().map(|record| record.modified);
+        let winner = match a_modified.cmp(&b_modified) {
+            std::cmp::Ordering::Greater => Side::A,
+            std::cmp::Ordering::Less => Side::B,
+            std::cmp::Ordering::Equal if a_stored > b_stored => Side::A,
+            std::cmp::Ordering::Equal => Side::B,
+        };
+
+        if let (Some(a_modified), Some(b_modified), Some(last_sync)) =
+            (a_modified, b_modified, last_sync)
+        {
+            if a_modified > last_sync && b_modified > last_sync {
+                report.conflicts.push(Conflict {
+                    key: key.clone(),
+                    winner,
+                    a_modified,
+                    b_modified,
+                });
+            }
+        }
+
+        match winner {
+            Side::A => {
+                b.inner().write(key, a_stored).map_err(SyncError::WriteB)?;
+                report.updated_b.push(key.clone());
+            }
+            Side::B => {
+                a.inner().write(key, b_stored).map_err(SyncError::WriteA)?;
+                report.updated_a.push(key.clone());
+            }
+        }
+    }
+
+    for (key, b_stored) in &b_records {
+        if !a_records.contains_key(key) {
+            a.inner().write(key, b_stored).map_err(SyncError::WriteA)?;
+            report.updated_a.push(key.clone());
+        }
+    }
+    Ok(report)
+}
+
+#[derive(Error, Debug)]
+pub enum SyncError<AR, AW, BR, BW> {
+    #[error("Could not read store a")]
+    ReadA(#[source] AR),
+
+    #[error("Could not write store a")]
+    WriteA(#[source] AW),
+
+    #[error("Could not read store b")]
+    ReadB(#[source] BR),
+
+    #[error("Could not write store b")]
+    WriteB(#[source] BW),
+}
@@ -0,0 +1,167 @@
+use crate::kv_storage;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// hands out one view per tenant of a shared store, every view only sees keys under its own
+// "<tenant id>/" prefix. tenant ids are restricted to ascii letters, digits, '-' and '_', so no
+// id can name a prefix of another tenant's keys
+pub struct TenantedStorage<S> {
+    inner: Arc<S>,
+}
+
+impl<S> TenantedStorage<S> {
+    pub fn new(inner: S) -> Self {
+        TenantedStorage {
+            inner: Arc::new(inner),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn tenant(&self, id: &str) -> Result<TenantKvStorage<S>, InvalidTenantId> {
+        let valid = !id.is_empty()
+            && id
+                .bytes()
+                .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_');
+        if !valid {
+            return Err(InvalidTenantId(id.to_string()));
+        }
+        Ok(TenantKvStorage {
+            inner: self.inner.clone(),
+            prefix: format!("{id}/"),
+        })
+    }
+}
+
+impl<S: kv_storage::KvStorage> TenantedStorage<S> {
+    // removes every key of the tenant
+    pub fn remove_tenant(&self, id: &str) -> Result<(), TenantedWriteError<S::WriteErrorType>> {
+        let tenant = self.tenant(id)?;
+        self.inner
+            .delete_prefix(&tenant.prefix)
+            .map_err(TenantedWriteError::Inner)
+    }
+}
+
+// can be moved to other threads and outlive the `TenantedStorage` it came from
+pub struct TenantKvStorage<S> {
+    inner: Arc<S>,
+    prefix: String,
+}
+
+impl<S> TenantKvStorage<S> {
+    pub fn tenant(&self) -> &str {
+        self.prefix.trim_end_matches('/')
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+}
+
+impl<S> Clone for TenantKvStorage<S> {
+    fn clone(&self) -> Self {
+        TenantKvStorage {
+            inner: self.inner.clone(),
+            prefix: self.prefix.clone(),
+        }
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for TenantKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.inner.read(&self.key(key))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.write(&self.key(key), value)
+    }
+
+    // starts right at the tenant's prefix, the scan ends with the first key of another tenant
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let cursor = match cursor {
+            Some(cursor) => self.key(cursor),
+            None => self.prefix.clone(),
+        };
+        let page = self.inner.scan(Some(&cursor), limit)?;
+        let mut entries = Vec::with_capacity(page.entries.len());
+        for (key, value) in page.entries {
+            match key.strip_prefix(self.prefix.as_str()) {
+                Some(key) => entries.push((key.to_string(), value)),
+                None => {
+                    return Ok(kv_storage::Page {
+                        entries,
+                        cursor: None,
+                    })
+                }
+            }
+        }
+        let cursor = page.cursor.and_then(|cursor| {
+            cursor
+                .strip_prefix(self.prefix.as_str())
+                .map(str::to_string)
+        });
+        Ok(kv_storage::Page { entries, cursor })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete(&self.key(key))
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete_prefix(&self.key(prefix))
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+// purging a view only removes the keys of its tenant
+impl<S: kv_storage::KvStorage> kv_storage::KvStoragePurge for TenantKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.delete_prefix(&self.prefix)
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for TenantKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.inner.write_with_ttl(&self.key(key), value, ttl)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner.expire_at(&self.key(key), at)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(&self.key(key))
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Tenant id '{0}' is not valid")]
+pub struct InvalidTenantId(pub String);
+
+#[derive(Error, Debug)]
+pub enum TenantedWriteError<E> {
+    #[error(transparent)]
+    InvalidTenantId(#[from] InvalidTenantId),
+
+    #[error(transparent)]
+    Inner(E),
+}
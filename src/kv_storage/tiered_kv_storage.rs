@@ -0,0 +1,220 @@
+use crate::kv_storage::{self, IsNotFound};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteTarget {
+    #[default]
+    Upper,
+    Lower,
+    Both,
+}
+
+// reads try `upper` first and fall back to `lower` when the key is missing there, more tiers
+// are built by nesting, e.g. `TieredKvStorage::new(env, TieredKvStorage::new(file, remote))`
+pub struct TieredKvStorage<U, L> {
+    upper: U,
+    lower: L,
+    write_target: WriteTarget,
+    promote_reads: bool,
+}
+
+impl<U, L> TieredKvStorage<U, L> {
+    pub fn new(upper: U, lower: L) -> Self {
+        TieredKvStorage {
+            upper,
+            lower,
+            write_target: WriteTarget::default(),
+            promote_reads: false,
+        }
+    }
+
+    pub fn with_write_target(mut self, write_target: WriteTarget) -> Self {
+        self.write_target = write_target;
+        self
+    }
+
+    // copy values found in the lower tier into the upper one
+    pub fn with_read_promotion(mut self) -> Self {
+        self.promote_reads = true;
+        self
+    }
+
+    pub fn upper(&self) -> &U {
+        &self.upper
+    }
+
+    pub fn lower(&self) -> &L {
+        &self.lower
+    }
+
+    pub fn into_inner(self) -> (U, L) {
+        (self.upper, self.lower)
+    }
+
+    fn apply<FU, FL>(
+        &self,
+        on_upper: FU,
+        on_lower: FL,
+    ) -> Result<(), TieredWriteError<U::WriteErrorType, L::WriteErrorType>>
+    where
+        U: kv_storage::KvStorage,
+        L: kv_storage::KvStorage,
+        FU: FnOnce(&U) -> Result<(), U::WriteErrorType>,
+        FL: FnOnce(&L) -> Result<(), L::WriteErrorType>,
+    {
+        if self.write_target != WriteTarget::Upper {
+            on_lower(&self.lower).map_err(TieredWriteError::Lower)?;
+        }
+        if self.write_target != WriteTarget::Lower {
+            on_upper(&self.upper).map_err(TieredWriteError::Upper)?;
+        }
+        Ok(())
+    }
+}
+
+impl<U, L> kv_storage::KvStorage for TieredKvStorage<U, L>
+where
+    U: kv_storage::KvStorage,
+    L: kv_storage::KvStorage,
+{
+    type WriteErrorType = TieredWriteError<U::WriteErrorType, L::WriteErrorType>;
+    type ReadErrorType = TieredReadError<U::ReadErrorType, L::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        match self.upper.read(key) {
+            Err(e) if e.is_not_found() => {}
+            result => return result.map_err(TieredReadError::Upper),
+        }
+
+        let value = self.lower.read(key).map_err(TieredReadError::Lower)?;
+        if self.promote_reads && self.upper.write(key, &value).is_err() {
+            log::warn!("Could not promote key '{key}' to the upper tier");
+        }
+        Ok(value)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.apply(
+            |upper| upper.write(key, value),
+            |lower| lower.write(key, value),
+        )
+    }
+
+    // merges both tiers, the upper tier wins for keys present in both
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let upper = self
+            .upper
+            .scan(cursor, limit)
+            .map_err(TieredReadError::Upper)?;
+        let lower = self
+            .lower
+            .scan(cursor, limit)
+            .map_err(TieredReadError::Lower)?;
+        let more = upper.cursor.is_some() || lower.cursor.is_some();
+
+        let mut entries = upper.entries;
+        entries.extend(lower.entries);
+        // stable sort keeps the upper entry first so dedup drops the lower one
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        entries.dedup_by(|(a, _), (b, _)| a == b);
+
+        let cursor = if entries.len() > limit || more {
+            entries.truncate(limit);
+            entries.last().map(|(key, _)| key.clone())
+        } else {
+            None
+        };
+        Ok(kv_storage::Page { entries, cursor })
+    }
+
+    // deletes from both tiers, otherwise the lower value would resurface
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.upper.delete(key).map_err(TieredWriteError::Upper)?;
+        self.lower.delete(key).map_err(TieredWriteError::Lower)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.upper
+            .delete_prefix(prefix)
+            .map_err(TieredWriteError::Upper)?;
+        self.lower
+            .delete_prefix(prefix)
+            .map_err(TieredWriteError::Lower)
+    }
+
+    // reads and deletes go to both tiers
+    fn health_check(&self) -> kv_storage::Health {
+        self.upper.health_check().worst(self.lower.health_check())
+    }
+}
+
+impl<U, L> kv_storage::KvStoragePurge for TieredKvStorage<U, L>
+where
+    U: kv_storage::KvStoragePurge,
+    L: kv_storage::KvStoragePurge,
+{
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.apply(|upper| upper.purge(), |lower| lower.purge())
+    }
+}
+
+impl<U, L> kv_storage::KvStorageTtl for TieredKvStorage<U, L>
+where
+    U: kv_storage::KvStorageTtl,
+    L: kv_storage::KvStorageTtl,
+{
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.apply(
+            |upper| upper.write_with_ttl(key, value, ttl),
+            |lower| lower.write_with_ttl(key, value, ttl),
+        )
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.apply(
+            |upper| upper.expire_at(key, at),
+            |lower| lower.expire_at(key, at),
+        )
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.apply(|upper| upper.touch(key), |lower| lower.touch(key))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TieredReadError<U, L> {
+    #[error(transparent)]
+    Upper(U),
+
+    #[error(transparent)]
+    Lower(L),
+}
+
+impl<U: IsNotFound, L: IsNotFound> IsNotFound for TieredReadError<U, L> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            TieredReadError::Upper(e) => e.is_not_found(),
+            TieredReadError::Lower(e) => e.is_not_found(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TieredWriteError<U, L> {
+    #[error(transparent)]
+    Upper(U),
+
+    #[error(transparent)]
+    Lower(L),
+}
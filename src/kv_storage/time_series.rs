@@ -0,0 +1,198 @@
+use crate::kv_storage::{self, KvStorageExt};
+use std::ops::Range;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+pub const DEFAULT_SEGMENT: Duration = Duration::from_secs(60 * 60);
+
+// '/' ends the series name in a segment's key, so it can't hold one
+fn escape(series: &str) -> String {
+    series.replace('%', "%25").replace('/', "%2F")
+}
+
+fn nanos(time: SystemTime) -> Option<u64> {
+    let since_epoch = time.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(since_epoch.as_nanos()).ok()
+}
+
+// a segment's points, "<nanoseconds since the segment's start> <value>" per line, in time order
+fn parse(segment: &str) -> Option<Vec<(u64, f64)>> {
+    segment
+        .lines()
+        .map(|line| {
+            let (offset, value) = line.split_once(' ')?;
+            Some((offset.parse().ok()?, value.parse().ok()?))
+        })
+        .collect()
+}
+
+fn encode(points: &[(u64, f64)]) -> String {
+    let mut segment = String::new();
+    for (offset, value) in points {
+        segment.push_str(&format!("{offset} {value}\n"));
+    }
+    segment
+}
+
+// appends timestamped numbers to named series, e.g. sensor readings or usage counters, and reads
+// them back by time range. a series is stored in segments of `with_segment` each, under
+// "<prefix><series>/<start>" with the start in nanoseconds since the unix epoch padded to 20
+// digits, so a range is one scan of the segments it overlaps in key order. points can be
+// appended out of order, they're kept sorted within their segment. wider segments mean fewer
+// keys but longer values to rewrite on every append
+pub struct TimeSeriesStore<S> {
+    inner: S,
+    prefix: String,
+    segment: u64,
+    // appends read their segment before writing it, so they're serialized
+    lock: Mutex<()>,
+}
+
+impl<S> TimeSeriesStore<S> {
+    pub fn new(inner: S) -> Self {
+        TimeSeriesStore {
+            inner,
+            prefix: "__series/".to_string(),
+            segment: DEFAULT_SEGMENT.as_nanos() as u64,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    // series already stored have to be read with the segment width they were written with
+    pub fn with_segment(mut self, segment: Duration) -> Self {
+        self.segment = u64::try_from(segment.as_nanos()).unwrap_or(u64::MAX).max(1);
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn series_prefix(&self, series: &str) -> String {
+        format!("{}{}/", self.prefix, escape(series))
+    }
+
+    fn segment_start(&self, nanos: u64) -> u64 {
+        nanos - nanos % self.segment
+    }
+
+    fn segment_key(&self, series: &str, start: u64) -> String {
+        format!("{}{start:020}", self.series_prefix(series))
+    }
+}
+
+impl<S: kv_storage::KvStorage> TimeSeriesStore<S> {
+    pub fn append(
+        &self,
+        series: &str,
+        timestamp: SystemTime,
+        value: f64,
+    ) -> Result<(), TimeSeriesWriteErrorOf<S>> {
+        let nanos = nanos(timestamp).ok_or(TimeSeriesWriteError::OutOfRange)?;
+        let start = self.segment_start(nanos);
+        let key = self.segment_key(series, start);
+        let _lock = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut points = match self.inner.read_opt(&key) {
+            Ok(Some(segment)) => parse(&segment).ok_or_else(|| {
+                TimeSeriesWriteError::Read(TimeSeriesReadError::Invalid(key.clone()))
+            })?,
+            Ok(None) => Vec::new(),
+            Err(e) => return Err(TimeSeriesWriteError::Read(TimeSeriesReadError::Inner(e))),
+        };
+        // after the points with the same timestamp, so they stay in the order they came in
+        let offset = nanos - start;
+        let at = points.partition_point(|(point, _)| *point <= offset);
+        points.insert(at, (offset, value));
+        self.inner
+            .write(&key, &encode(&points))
+            .map_err(TimeSeriesWriteError::Inner)
+    }
+
+    // the points from `range.start` up to, not including, `range.end`, oldest first
+    pub fn range(
+        &self,
+        series: &str,
+        range: Range<SystemTime>,
+    ) -> Result<Vec<(SystemTime, f64)>, TimeSeriesReadError<S::ReadErrorType>> {
+        let from = nanos(range.start).unwrap_or(0);
+        let Some(to) = nanos(range.end) else {
+            return Ok(Vec::new());
+        };
+        let prefix = self.series_prefix(series);
+        let start = self.segment_start(from);
+        // the cursor is exclusive, so it's the key before the first segment's
+        let mut cursor = match start {
+            0 => prefix.clone(),
+            start => self.segment_key(series, start - 1),
+        };
+        let mut points = Vec::new();
+        loop {
+            let page = self
+                .inner
+                .scan(Some(&cursor), 128)
+                .map_err(TimeSeriesReadError::Inner)?;
+            for (key, segment) in page.entries {
+                let Some(Ok(start)) = key.strip_prefix(prefix.as_str()).map(str::parse::<u64>)
+                else {
+                    return Ok(points);
+                };
+                if start >= to {
+                    return Ok(points);
+                }
+                let segment = parse(&segment).ok_or(TimeSeriesReadError::Invalid(key))?;
+                points.extend(
+                    segment
+                        .into_iter()
+                        .map(|(offset, value)| (start + offset, value))
+                        .filter(|(nanos, _)| (from..to).contains(nanos))
+                        .map(|(nanos, value)| (UNIX_EPOCH + Duration::from_nanos(nanos), value)),
+                );
+            }
+            match page.cursor {
+                Some(next) => cursor = next,
+                None => return Ok(points),
+            }
+        }
+    }
+
+    pub fn delete_series(&self, series: &str) -> Result<(), S::WriteErrorType> {
+        let _lock = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        self.inner.delete_prefix(&self.series_prefix(series))
+    }
+}
+
+type TimeSeriesWriteErrorOf<S> = TimeSeriesWriteError<
+    <S as kv_storage::KvStorage>::ReadErrorType,
+    <S as kv_storage::KvStorage>::WriteErrorType,
+>;
+
+#[derive(Error, Debug)]
+pub enum TimeSeriesReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Key '{0}' is not a valid time series segment")]
+    Invalid(String),
+}
+
+#[derive(Error, Debug)]
+pub enum TimeSeriesWriteError<R, W> {
+    #[error("Could not read the segment")]
+    Read(#[source] TimeSeriesReadError<R>),
+
+    #[error("Timestamp is before the unix epoch or too far after it")]
+    OutOfRange,
+
+    #[error(transparent)]
+    Inner(W),
+}
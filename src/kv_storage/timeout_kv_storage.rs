@@ -0,0 +1,153 @@
+use crate::kv_storage::{self, IsNotFound};
+use std::convert::Infallible;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// every operation runs on its own thread so the caller can stop waiting after `timeout`.
+// synchronous backends can't be cancelled, a timed out operation keeps running in the
+// background and may still complete later
+pub struct TimeoutKvStorage<S> {
+    inner: Arc<S>,
+    timeout: Duration,
+}
+
+impl<S> TimeoutKvStorage<S> {
+    pub fn new(inner: S, timeout: Duration) -> Self {
+        TimeoutKvStorage {
+            inner: Arc::new(inner),
+            timeout,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: Send + Sync + 'static> TimeoutKvStorage<S> {
+    fn run<T, E>(
+        &self,
+        op: impl FnOnce(&S) -> Result<T, E> + Send + 'static,
+    ) -> Result<T, TimeoutError<E>>
+    where
+        T: Send + 'static,
+        E: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let inner = Arc::clone(&self.inner);
+        thread::spawn(move || {
+            // the receiver is gone if the caller already timed out
+            let _ = sender.send(op(&inner));
+        });
+
+        match receiver.recv_timeout(self.timeout) {
+            Ok(result) => result.map_err(TimeoutError::Inner),
+            Err(RecvTimeoutError::Timeout) => Err(TimeoutError::TimedOut(self.timeout)),
+            Err(RecvTimeoutError::Disconnected) => panic!("storage operation panicked"),
+        }
+    }
+}
+
+impl<S> kv_storage::KvStorage for TimeoutKvStorage<S>
+where
+    S: kv_storage::KvStorage + Send + Sync + 'static,
+    S::ReadErrorType: Send + 'static,
+    S::WriteErrorType: Send + 'static,
+{
+    type WriteErrorType = TimeoutError<S::WriteErrorType>;
+    type ReadErrorType = TimeoutError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let key = key.to_string();
+        self.run(move |inner| inner.read(&key))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        let (key, value) = (key.to_string(), value.to_string());
+        self.run(move |inner| inner.write(&key, &value))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let cursor = cursor.map(str::to_string);
+        self.run(move |inner| inner.scan(cursor.as_deref(), limit))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        let key = key.to_string();
+        self.run(move |inner| inner.delete(&key))
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        let prefix = prefix.to_string();
+        self.run(move |inner| inner.delete_prefix(&prefix))
+    }
+
+    // a check that doesn't finish in time reports the store as unavailable
+    fn health_check(&self) -> kv_storage::Health {
+        self.run(|inner| Ok::<_, Infallible>(inner.health_check()))
+            .unwrap_or_else(|e| kv_storage::Health::Unavailable(e.to_string()))
+    }
+}
+
+impl<S> kv_storage::KvStoragePurge for TimeoutKvStorage<S>
+where
+    S: kv_storage::KvStoragePurge + Send + Sync + 'static,
+    S::ReadErrorType: Send + 'static,
+    S::WriteErrorType: Send + 'static,
+{
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.run(|inner| inner.purge())
+    }
+}
+
+impl<S> kv_storage::KvStorageTtl for TimeoutKvStorage<S>
+where
+    S: kv_storage::KvStorageTtl + Send + Sync + 'static,
+    S::ReadErrorType: Send + 'static,
+    S::WriteErrorType: Send + 'static,
+{
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        let (key, value) = (key.to_string(), value.to_string());
+        self.run(move |inner| inner.write_with_ttl(&key, &value, ttl))
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        let key = key.to_string();
+        self.run(move |inner| inner.expire_at(&key, at))
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        let key = key.to_string();
+        self.run(move |inner| inner.touch(&key))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TimeoutError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Storage operation timed out after {0:?}")]
+    TimedOut(Duration),
+}
+
+impl<E: IsNotFound> IsNotFound for TimeoutError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            TimeoutError::Inner(e) => e.is_not_found(),
+            TimeoutError::TimedOut(_) => false,
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use crate::time;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+// records are stored as MARKER + "<unix millis>:" + value, deletes leave a tombstone
+// MARKER + "<unix millis>-" so they can be synced too. values without the marker count as
+// written at the epoch
+const MARKER: &str = "\u{1}ts:";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Record<'a> {
+    pub(crate) modified: SystemTime,
+    // `None` for tombstones
+    pub(crate) value: Option<&'a str>,
+}
+
+impl<'a> Record<'a> {
+    pub(crate) fn parse(stored: &'a str) -> Option<Record<'a>> {
+        let Some(record) = stored.strip_prefix(MARKER) else {
+            return Some(Record {
+                modified: UNIX_EPOCH,
+                value: Some(stored),
+            });
+        };
+        let end = record.find([':', '-'])?;
+        let modified = UNIX_EPOCH + Duration::from_millis(record[..end].parse().ok()?);
+        let value = match &record[end..] {
+            "-" => None,
+            rest => Some(rest.strip_prefix(':')?),
+        };
+        Some(Record { modified, value })
+    }
+
+    fn seal(modified: SystemTime, value: Option<&str>) -> String {
+        let millis = modified
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        match value {
+            Some(value) => format!("{MARKER}{millis}:{value}"),
+            None => format!("{MARKER}{millis}-"),
+        }
+    }
+}
+
+// records when every key was last written or deleted, which `sync::sync` uses to reconcile two
+// stores. tombstones of deleted keys stay in the wrapped store until `purge_tombstones`
+pub struct TimestampedKvStorage<S>(S);
+
+impl<S> TimestampedKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        TimestampedKvStorage(inner)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+
+    fn open<E>(key: &str, stored: &str) -> Result<Option<String>, TimestampedReadError<E>> {
+        let record = Record::parse(stored)
+            .ok_or_else(|| TimestampedReadError::InvalidRecord(key.to_string()))?;
+        Ok(record.value.map(str::to_string))
+    }
+}
+
+impl<S: kv_storage::KvStorage> TimestampedKvStorage<S> {
+    // when `key` was last written or deleted, `None` if it never was
+    pub fn modified(
+        &self,
+        key: &str,
+    ) -> Result<Option<SystemTime>, TimestampedReadError<S::ReadErrorType>> {
+        let Some(stored) = self.0.read_opt(key).map_err(TimestampedReadError::Inner)? else {
+            return Ok(None);
+        };
+        let record = Record::parse(&stored)
+            .ok_or_else(|| TimestampedReadError::InvalidRecord(key.to_string()))?;
+        Ok(Some(record.modified))
+    }
+
+    // removes tombstones of keys deleted before `before`. a store synced with one that still
+    // has the key afterwards gets it back, so only purge what every peer has seen
+    pub fn purge_tombstones(
+        &self,
+        before: SystemTime,
+    ) -> Result<usize, TimestampedWriteError<S::ReadErrorType, S::WriteErrorType>> {
+        let mut purged = 0;
+        for (key, stored) in self.0.scan_all().map_err(TimestampedWriteError::Read)? {
+            let is_old_tombstone = Record::parse(&stored)
+                .is_some_and(|record| record.value.is_none() && record.modified < before);
+            if is_old_tombstone {
+                self.0.delete(&key).map_err(TimestampedWriteError::Inner)?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for TimestampedKvStorage<S> {
+    type WriteErrorType = TimestampedWriteError<S::ReadErrorType, S::WriteErrorType>;
+    type ReadErrorType = TimestampedReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let stored = self.0.read(key).map_err(TimestampedReadError::Inner)?;
+        Self::open(key, &stored)?.ok_or_else(|| TimestampedReadError::Deleted(key.to_string()))
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.0
+            .write(key, &Record::seal(time::now(), Some(value)))
+            .map_err(TimestampedWriteError::Inner)
+    }
+
+    // tombstones are left out, so pages can be shorter than `limit`
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .0
+            .scan(cursor, limit)
+            .map_err(TimestampedReadError::Inner)?;
+        let mut entries = Vec::with_capacity(page.entries.len());
+        for (key, stored) in page.entries {
+            if let Some(value) = Self::open(&key, &stored)? {
+                entries.push((key, value));
+            }
+        }
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.0
+            .write(key, &Record::seal(time::now(), None))
+            .map_err(TimestampedWriteError::Inner)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        let now = time::now();
+        for (key, stored) in self.0.scan_all().map_err(TimestampedWriteError::Read)? {
+            let is_live = Record::parse(&stored).is_none_or(|record| record.value.is_some());
+            if key.starts_with(prefix) && is_live {
+                self.0
+                    .write(&key, &Record::seal(now, None))
+                    .map_err(TimestampedWriteError::Inner)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.0.health_check()
+    }
+}
+
+// tombstones are removed too, a sync with a store that still has the keys brings them back
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for TimestampedKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.0.purge().map_err(TimestampedWriteError::Inner)
+    }
+}
+
+// entries expiring in the wrapped store leave no tombstone, so a sync can bring them back
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for TimestampedKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.0
+            .write_with_ttl(key, &Record::seal(time::now(), Some(value)), ttl)
+            .map_err(TimestampedWriteError::Inner)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.0
+            .expire_at(key, at)
+            .map_err(TimestampedWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.touch(key).map_err(TimestampedWriteError::Inner)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TimestampedReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Key '{0}' was deleted")]
+    Deleted(String),
+
+    #[error("Timestamp of key '{0}' is not valid")]
+    InvalidRecord(String),
+}
+
+impl<E: IsNotFound> IsNotFound for TimestampedReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            TimestampedReadError::Inner(e) => e.is_not_found(),
+            TimestampedReadError::Deleted(_) => true,
+            TimestampedReadError::InvalidRecord(_) => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TimestampedWriteError<R, W> {
+    #[error("Could not read existing records")]
+    Read(#[source] R),
+
+    #[error(transparent)]
+    Inner(W),
+}
@@ -0,0 +1,137 @@
+use crate::kv_storage::{KvStorageExt, KvStorageTtl};
+use async_trait::async_trait;
+use std::fmt;
+use std::fmt::Display;
+use std::time::SystemTime;
+use tower_sessions_core::session::{Id, Record};
+use tower_sessions_core::session_store;
+use tower_sessions_core::SessionStore;
+
+// tower-sessions' `SessionStore` on top of a store with ttls, e.g. for axum:
+//
+//     let store = KvSessionStore::new(store).with_prefix("sessions/");
+//     let app = Router::new().layer(SessionManagerLayer::new(store));
+//
+// sessions are stored as json and expire with their record. the store is called right on the
+// request's task, backends that block for long should be wrapped in something that doesn't
+pub struct KvSessionStore<S> {
+    inner: S,
+    prefix: String,
+}
+
+impl<S> KvSessionStore<S> {
+    pub fn new(inner: S) -> Self {
+        KvSessionStore {
+            inner,
+            prefix: "session/".to_string(),
+        }
+    }
+
+    // prepended to every session id, "session/" by default
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn key(&self, id: &Id) -> String {
+        format!("{}{id}", self.prefix)
+    }
+}
+
+impl<S: Clone> Clone for KvSessionStore<S> {
+    fn clone(&self) -> Self {
+        KvSessionStore {
+            inner: self.inner.clone(),
+            prefix: self.prefix.clone(),
+        }
+    }
+}
+
+// `SessionStore` needs it, stores don't have to implement it
+impl<S> fmt::Debug for KvSessionStore<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KvSessionStore")
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+fn backend_error(e: impl Display) -> session_store::Error {
+    session_store::Error::Backend(e.to_string())
+}
+
+impl<S> KvSessionStore<S>
+where
+    S: KvStorageTtl,
+    S::ReadErrorType: Display,
+    S::WriteErrorType: Display,
+{
+    fn save_record(&self, record: &Record) -> session_store::Result<()> {
+        let key = self.key(&record.id);
+        let ttl = match SystemTime::from(record.expiry_date).duration_since(SystemTime::now()) {
+            Ok(ttl) if !ttl.is_zero() => ttl,
+            // already expired, nothing to keep
+            _ => return self.inner.delete(&key).map_err(backend_error),
+        };
+        let value = serde_json::to_string(record)
+            .map_err(|e| session_store::Error::Encode(e.to_string()))?;
+        self.inner
+            .write_with_ttl(&key, &value, ttl)
+            .map_err(backend_error)
+    }
+
+    fn load_record(&self, id: &Id) -> session_store::Result<Option<Record>> {
+        let Some(value) = self.inner.read_opt(self.key(id)).map_err(backend_error)? else {
+            return Ok(None);
+        };
+        let record: Record = serde_json::from_str(&value)
+            .map_err(|e| session_store::Error::Decode(e.to_string()))?;
+        // backends may only drop expired entries once in a while
+        if SystemTime::from(record.expiry_date) <= SystemTime::now() {
+            return Ok(None);
+        }
+        Ok(Some(record))
+    }
+}
+
+#[async_trait]
+impl<S> SessionStore for KvSessionStore<S>
+where
+    S: KvStorageTtl + Send + Sync + 'static,
+    S::ReadErrorType: Display,
+    S::WriteErrorType: Display,
+{
+    // picks another id while the record's is taken. this isn't atomic, two requests creating
+    // the same random id at the same time is left to the odds
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        while self
+            .inner
+            .read_opt(self.key(&record.id))
+            .map_err(backend_error)?
+            .is_some()
+        {
+            record.id = Id::default();
+        }
+        self.save_record(record)
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        self.save_record(record)
+    }
+
+    async fn load(&self, id: &Id) -> session_store::Result<Option<Record>> {
+        self.load_record(id)
+    }
+
+    async fn delete(&self, id: &Id) -> session_store::Result<()> {
+        self.inner.delete(&self.key(id)).map_err(backend_error)
+    }
+}
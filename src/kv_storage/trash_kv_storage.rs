@@ -0,0 +1,266 @@
+use crate::kv_storage::{self, KvStorageExt};
+use crate::time;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+// makes deletes undoable, e.g. for notes a user removes by accident. `delete` and
+// `delete_prefix` move entries to "<trash prefix><key>", stored as "<unix millis>:" + value with
+// the time they were deleted, and `restore` moves them back. deleting a key again replaces what
+// the trash held for it. the trash prefix is hidden from scans and isn't moved to the trash
+// itself, `empty_trash` deletes what's been in it long enough. `purge` deletes the trash too
+pub struct TrashKvStorage<S> {
+    inner: S,
+    trash_prefix: String,
+}
+
+impl<S> TrashKvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        TrashKvStorage {
+            inner,
+            trash_prefix: "__trash/".to_string(),
+        }
+    }
+
+    pub fn with_trash_prefix(mut self, prefix: &str) -> Self {
+        self.trash_prefix = prefix.to_string();
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn trash_key(&self, key: &str) -> String {
+        format!("{}{key}", self.trash_prefix)
+    }
+
+    fn is_trash(&self, key: &str) -> bool {
+        key.starts_with(self.trash_prefix.as_str())
+    }
+}
+
+fn seal(value: &str, deleted: SystemTime) -> String {
+    let millis = deleted
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    format!("{millis}:{value}")
+}
+
+fn unseal(entry: &str) -> Option<(SystemTime, &str)> {
+    let (millis, value) = entry.split_once(':')?;
+    Some((
+        UNIX_EPOCH + Duration::from_millis(millis.parse().ok()?),
+        value,
+    ))
+}
+
+impl<S: kv_storage::KvStorage> TrashKvStorage<S> {
+    // the keys in the trash and when they were deleted, in key order
+    pub fn trashed(&self) -> Result<Vec<(String, SystemTime)>, S::ReadErrorType> {
+        let mut trashed = Vec::new();
+        for (entry, sealed) in self.scan_trash()? {
+            let key = &entry[self.trash_prefix.len()..];
+            match unseal(&sealed) {
+                Some((deleted, _)) => trashed.push((key.to_string(), deleted)),
+                None => log::warn!("Ignoring invalid trash entry of key '{key}'"),
+            }
+        }
+        Ok(trashed)
+    }
+
+    // moves `key` back out of the trash, returns false if it isn't in it. a key that was written
+    // again since it was deleted is left alone
+    pub fn restore(&self, key: &str) -> Result<bool, TrashWriteErrorOf<S>> {
+        let trash_key = self.trash_key(key);
+        let Some(sealed) = self
+            .inner
+            .read_opt(&trash_key)
+            .map_err(TrashWriteError::Read)?
+        else {
+            return Ok(false);
+        };
+        let (_, value) =
+            unseal(&sealed).ok_or_else(|| TrashWriteError::Invalid(key.to_string()))?;
+        if self
+            .inner
+            .read_opt(key)
+            .map_err(TrashWriteError::Read)?
+            .is_some()
+        {
+            return Err(TrashWriteError::Exists(key.to_string()));
+        }
+        self.inner
+            .write(key, value)
+            .map_err(TrashWriteError::Inner)?;
+        self.inner
+            .delete(&trash_key)
+            .map_err(TrashWriteError::Inner)?;
+        Ok(true)
+    }
+
+    // deletes the entries deleted more than `older_than` ago, `Duration::ZERO` empties the
+    // trash. invalid entries are deleted too. returns how many entries were deleted
+    pub fn empty_trash(&self, older_than: Duration) -> Result<usize, TrashWriteErrorOf<S>> {
+        let now = time::now();
+        let mut emptied = 0;
+        for (entry, sealed) in self.scan_trash().map_err(TrashWriteError::Read)? {
+            let expired = unseal(&sealed).is_none_or(|(deleted, _)| {
+                now.duration_since(deleted).unwrap_or_default() >= older_than
+            });
+            if expired {
+                self.inner.delete(&entry).map_err(TrashWriteError::Inner)?;
+                emptied += 1;
+            }
+        }
+        Ok(emptied)
+    }
+
+    fn scan_trash(&self) -> Result<Vec<(String, String)>, S::ReadErrorType> {
+        let mut entries = Vec::new();
+        let mut cursor = self.trash_prefix.clone();
+        loop {
+            let page = self.inner.scan(Some(&cursor), 128)?;
+            let scanned = page.entries.len();
+            let before = entries.len();
+            entries.extend(
+                page.entries
+                    .into_iter()
+                    .take_while(|(key, _)| self.is_trash(key)),
+            );
+            let past_prefix = entries.len() - before < scanned;
+            match page.cursor {
+                Some(next) if !past_prefix => cursor = next,
+                _ => return Ok(entries),
+            }
+        }
+    }
+
+    fn move_to_trash(
+        &self,
+        key: &str,
+        value: &str,
+        deleted: SystemTime,
+    ) -> Result<(), S::WriteErrorType> {
+        self.inner
+            .write(&self.trash_key(key), &seal(value, deleted))?;
+        self.inner.delete(key)
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for TrashKvStorage<S> {
+    type WriteErrorType = TrashWriteErrorOf<S>;
+    type ReadErrorType = S::ReadErrorType;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.inner.read(key)
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.inner.read_with(key, f)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.write(key, value).map_err(TrashWriteError::Inner)
+    }
+
+    // trash entries are left out, so pages can be shorter than `limit`
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let mut page = self.inner.scan(cursor, limit)?;
+        page.entries.retain(|(key, _)| !self.is_trash(key));
+        Ok(page)
+    }
+
+    // keys in the trash are deleted for good
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        if self.is_trash(key) {
+            return self.inner.delete(key).map_err(TrashWriteError::Inner);
+        }
+        match self.inner.read_opt(key).map_err(TrashWriteError::Read)? {
+            Some(value) => self
+                .move_to_trash(key, &value, time::now())
+                .map_err(TrashWriteError::Inner),
+            None => self.inner.delete(key).map_err(TrashWriteError::Inner),
+        }
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        if self.is_trash(prefix) {
+            return self
+                .inner
+                .delete_prefix(prefix)
+                .map_err(TrashWriteError::Inner);
+        }
+        let deleted = time::now();
+        let entries = self.inner.scan_all().map_err(TrashWriteError::Read)?;
+        for (key, value) in entries {
+            if key.starts_with(prefix) && !self.is_trash(&key) {
+                self.move_to_trash(&key, &value, deleted)
+                    .map_err(TrashWriteError::Inner)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for TrashKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge().map_err(TrashWriteError::Inner)
+    }
+}
+
+// entries that expire are gone without going through the trash
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for TrashKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .write_with_ttl(key, value, ttl)
+            .map_err(TrashWriteError::Inner)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner
+            .expire_at(key, at)
+            .map_err(TrashWriteError::Inner)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key).map_err(TrashWriteError::Inner)
+    }
+}
+
+type TrashWriteErrorOf<S> = TrashWriteError<
+    <S as kv_storage::KvStorage>::ReadErrorType,
+    <S as kv_storage::KvStorage>::WriteErrorType,
+>;
+
+#[derive(Error, Debug)]
+pub enum TrashWriteError<R, W> {
+    #[error("Could not read entries")]
+    Read(#[source] R),
+
+    #[error("Key '{0}' was written again since it was deleted")]
+    Exists(String),
+
+    #[error("Trash entry of key '{0}' is not valid")]
+    Invalid(String),
+
+    #[error(transparent)]
+    Inner(W),
+}
@@ -0,0 +1,95 @@
+use crate::kv_storage::{self, IsNotFound};
+use alloc::string::String;
+use alloc::vec::Vec;
+use thiserror::Error;
+
+// a `RawKvStorage` as a `KvStorage`, string values are stored as their utf-8 bytes and
+// reading one that isn't utf-8 fails. `read_bytes` and `write_bytes` pass bytes through as is
+pub struct Utf8KvStorage<S>(S);
+
+impl<S> Utf8KvStorage<S> {
+    pub fn new(inner: S) -> Self {
+        Utf8KvStorage(inner)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+fn decode<E>(key: &str, value: Vec<u8>) -> Result<String, Utf8ReadError<E>> {
+    String::from_utf8(value).map_err(|_| Utf8ReadError::InvalidUtf8(key.into()))
+}
+
+impl<S: kv_storage::RawKvStorage> kv_storage::KvStorage for Utf8KvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = Utf8ReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        let value = self.0.read_raw(key).map_err(Utf8ReadError::Inner)?;
+        decode(key, value)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.write_raw(key, value.as_bytes())
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let page = self
+            .0
+            .scan_raw(cursor, limit)
+            .map_err(Utf8ReadError::Inner)?;
+        let entries = page
+            .entries
+            .into_iter()
+            .map(|(key, value)| {
+                let value = decode(&key, value)?;
+                Ok((key, value))
+            })
+            .collect::<Result<_, Self::ReadErrorType>>()?;
+        Ok(kv_storage::Page {
+            entries,
+            cursor: page.cursor,
+        })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.delete_raw(key)
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.0.delete_prefix_raw(prefix)
+    }
+}
+
+impl<S: kv_storage::RawKvStorage> kv_storage::KvStorageBytes for Utf8KvStorage<S> {
+    fn read_bytes(&self, key: &str) -> Result<Vec<u8>, Self::ReadErrorType> {
+        self.0.read_raw(key).map_err(Utf8ReadError::Inner)
+    }
+
+    fn write_bytes(&self, key: &str, value: &[u8]) -> Result<(), Self::WriteErrorType> {
+        self.0.write_raw(key, value)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum Utf8ReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+    #[error("Value of '{0}' is not utf-8")]
+    InvalidUtf8(String),
+}
+
+impl<E: IsNotFound> IsNotFound for Utf8ReadError<E> {
+    fn is_not_found(&self) -> bool {
+        matches!(self, Utf8ReadError::Inner(e) if e.is_not_found())
+    }
+}
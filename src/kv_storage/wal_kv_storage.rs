@@ -0,0 +1,251 @@
+use crate::kv_storage::{self, IsNotFound, KvStorageExt};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+pub const DEFAULT_JOURNAL_PREFIX: &str = "__wal/";
+
+// a mutation as it's journaled, also used by `OfflineKvStorage` for its queue
+pub(crate) enum Intent {
+    Write(String, String),
+    Delete(String),
+    DeletePrefix(String),
+}
+
+impl Intent {
+    // "<op>:<key len>:<value len>:<key><value>", the lengths let a truncated entry be told
+    // apart from a complete one
+    pub(crate) fn encode(&self) -> String {
+        let (op, key, value) = match self {
+            Intent::Write(key, value) => ('w', key, value.as_str()),
+            Intent::Delete(key) => ('d', key, ""),
+            Intent::DeletePrefix(prefix) => ('p', prefix, ""),
+        };
+        format!("{op}:{}:{}:{key}{value}", key.len(), value.len())
+    }
+
+    pub(crate) fn decode(entry: &str) -> Option<Intent> {
+        let mut fields = entry.splitn(4, ':');
+        let op = fields.next()?;
+        let key_len: usize = fields.next()?.parse().ok()?;
+        let value_len: usize = fields.next()?.parse().ok()?;
+        let rest = fields.next()?;
+        // a corrupt entry can hold any lengths
+        if Some(rest.len()) != key_len.checked_add(value_len) {
+            return None;
+        }
+        let key = rest.get(..key_len)?.to_string();
+        let value = rest.get(key_len..)?.to_string();
+        match op {
+            "w" => Some(Intent::Write(key, value)),
+            "d" if value.is_empty() => Some(Intent::Delete(key)),
+            "p" if value.is_empty() => Some(Intent::DeletePrefix(key)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn apply<S: kv_storage::KvStorage + ?Sized>(
+        &self,
+        store: &S,
+    ) -> Result<(), S::WriteErrorType> {
+        match self {
+            Intent::Write(key, value) => store.write(key, value),
+            Intent::Delete(key) => store.delete(key),
+            Intent::DeletePrefix(prefix) => store.delete_prefix(prefix),
+        }
+    }
+}
+
+// every mutation is first journaled under the journal prefix of the wrapped store, then applied
+// and then removed from the journal. `open` replays whatever is left in the journal, so an
+// operation interrupted by a crash is completed on the next start. ttl operations are forwarded
+// without journaling. journal entries are hidden from reads and scans
+pub struct WalKvStorage<S> {
+    inner: S,
+    journal_prefix: String,
+    sequence: AtomicU64,
+}
+
+impl<S: kv_storage::KvStorage> WalKvStorage<S> {
+    pub fn open(inner: S) -> Result<Self, WalError<S::ReadErrorType, S::WriteErrorType>> {
+        Self::open_with_journal_prefix(inner, DEFAULT_JOURNAL_PREFIX)
+    }
+
+    pub fn open_with_journal_prefix(
+        inner: S,
+        journal_prefix: &str,
+    ) -> Result<Self, WalError<S::ReadErrorType, S::WriteErrorType>> {
+        let store = WalKvStorage {
+            inner,
+            journal_prefix: journal_prefix.to_string(),
+            sequence: AtomicU64::new(0),
+        };
+        store.replay()?;
+        Ok(store)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn is_journal(&self, key: &str) -> bool {
+        key.starts_with(&self.journal_prefix)
+    }
+
+    fn check_read<E>(&self, key: &str) -> Result<(), WalReadError<E>> {
+        match self.is_journal(key) {
+            true => Err(WalReadError::Journal(key.to_string())),
+            false => Ok(()),
+        }
+    }
+
+    fn journal_entries(&self) -> Result<Vec<(String, String)>, S::ReadErrorType> {
+        let mut entries = self.inner.scan_all()?;
+        entries.retain(|(key, _)| self.is_journal(key));
+        Ok(entries)
+    }
+
+    // returns the number of operations completed
+    fn replay(&self) -> Result<usize, WalError<S::ReadErrorType, S::WriteErrorType>> {
+        let mut replayed = 0;
+        let mut sequence = 0;
+        // journal keys are zero padded, so key order is the order the operations were made in
+        for (entry_key, entry) in self.journal_entries().map_err(WalError::Read)? {
+            // new entries go after the ones found, also those of other handles
+            if let Ok(entry_sequence) = entry_key[self.journal_prefix.len()..].parse::<u64>() {
+                sequence = sequence.max(entry_sequence.saturating_add(1));
+            }
+            match Intent::decode(&entry) {
+                Some(intent) => {
+                    intent.apply(&self.inner).map_err(WalError::Write)?;
+                    replayed += 1;
+                }
+                // the crash happened while journaling, so the operation was never applied
+                None => log::warn!("Discarding incomplete journal entry '{entry_key}'"),
+            }
+            self.inner.delete(&entry_key).map_err(WalError::Write)?;
+        }
+        self.sequence.fetch_max(sequence, Ordering::Relaxed);
+        if replayed > 0 {
+            log::info!("Replayed {replayed} journaled operations");
+        }
+        Ok(replayed)
+    }
+
+    fn journaled(&self, intent: Intent) -> Result<(), S::WriteErrorType> {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let entry_key = format!("{}{sequence:020}", self.journal_prefix);
+        self.inner.write(&entry_key, &intent.encode())?;
+
+        let result = intent.apply(&self.inner);
+        // a failed operation is dropped from the journal too, it must not be replayed later
+        let cleared = self.inner.delete(&entry_key);
+        result.and(cleared)
+    }
+}
+
+impl<S: kv_storage::KvStorage> kv_storage::KvStorage for WalKvStorage<S> {
+    type WriteErrorType = S::WriteErrorType;
+    type ReadErrorType = WalReadError<S::ReadErrorType>;
+
+    fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+        self.check_read(key)?;
+        self.inner.read(key).map_err(WalReadError::Inner)
+    }
+
+    fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+        self.check_read(key)?;
+        self.inner.read_with(key, f).map_err(WalReadError::Inner)
+    }
+
+    fn read_into(&self, key: &str, buf: &mut String) -> Result<usize, Self::ReadErrorType> {
+        self.check_read(key)?;
+        self.inner.read_into(key, buf).map_err(WalReadError::Inner)
+    }
+
+    fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
+        self.journaled(Intent::Write(key.to_string(), value.to_string()))
+    }
+
+    fn scan(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+        let mut page = self
+            .inner
+            .scan(cursor, limit)
+            .map_err(WalReadError::Inner)?;
+        page.entries.retain(|(key, _)| !self.is_journal(key));
+        Ok(page)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.journaled(Intent::Delete(key.to_string()))
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+        self.journaled(Intent::DeletePrefix(prefix.to_string()))
+    }
+
+    fn health_check(&self) -> kv_storage::Health {
+        self.inner.health_check()
+    }
+}
+
+// the journal lives in the wrapped store, so it goes too
+impl<S: kv_storage::KvStoragePurge> kv_storage::KvStoragePurge for WalKvStorage<S> {
+    fn purge(&self) -> Result<(), Self::WriteErrorType> {
+        self.inner.purge()
+    }
+}
+
+impl<S: kv_storage::KvStorageTtl> kv_storage::KvStorageTtl for WalKvStorage<S> {
+    fn write_with_ttl(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: Duration,
+    ) -> Result<(), Self::WriteErrorType> {
+        self.inner.write_with_ttl(key, value, ttl)
+    }
+
+    fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+        self.inner.expire_at(key, at)
+    }
+
+    fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+        self.inner.touch(key)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WalReadError<E> {
+    #[error(transparent)]
+    Inner(E),
+
+    #[error("Key '{0}' is a journal entry")]
+    Journal(String),
+}
+
+impl<E: IsNotFound> IsNotFound for WalReadError<E> {
+    fn is_not_found(&self) -> bool {
+        match self {
+            WalReadError::Inner(e) => e.is_not_found(),
+            WalReadError::Journal(_) => true,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WalError<R, W> {
+    #[error("Could not read journal")]
+    Read(#[source] R),
+
+    #[error("Could not replay journal")]
+    Write(#[source] W),
+}
@@ -0,0 +1,121 @@
+use crate::kv_storage::observed_kv_storage::ObservedKvStorage;
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use yew::prelude::*;
+
+// the store's listeners have to be Send, a component's state isn't. listeners only carry an id
+// to look the component up here, so changes made on other threads don't reach it
+thread_local! {
+    static COMPONENTS: RefCell<HashMap<u64, Callback<Option<String>>>> = RefCell::default();
+}
+
+static NEXT_COMPONENT: AtomicU64 = AtomicU64::new(0);
+
+fn notify(component: u64, value: Option<String>) {
+    let callback = COMPONENTS.with(|components| components.borrow().get(&component).cloned());
+    if let Some(callback) = callback {
+        callback.emit(value);
+    }
+}
+
+fn read<S: KvStorage>(store: &S, key: &str) -> Option<String> {
+    store.read_opt(key).unwrap_or_else(|_| {
+        log::warn!("could not read '{key}'");
+        None
+    })
+}
+
+pub struct UseKvStorageHandle<S> {
+    store: Rc<ObservedKvStorage<S>>,
+    key: String,
+    value: UseStateHandle<Option<String>>,
+}
+
+impl<S> Clone for UseKvStorageHandle<S> {
+    fn clone(&self) -> Self {
+        UseKvStorageHandle {
+            store: self.store.clone(),
+            key: self.key.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<S: KvStorage> UseKvStorageHandle<S> {
+    // the value when the component was rendered
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    // every component using the key re-renders with the new value
+    pub fn set(&self, value: &str) -> Result<(), S::WriteErrorType> {
+        self.store.write(&self.key, value)
+    }
+
+    pub fn delete(&self) -> Result<(), S::WriteErrorType> {
+        self.store.delete(&self.key)
+    }
+}
+
+// the value of `key`, the component re-renders whenever the key is changed through `store`,
+// e.g. by another component using the same key:
+//
+//     #[function_component]
+//     fn Volume(props: &Props) -> Html {
+//         let volume = use_kv_storage(&props.store, "volume");
+//         let louder = {
+//             let volume = volume.clone();
+//             move |_| {
+//                 let _ = volume.set("11");
+//             }
+//         };
+//         html! { <button onclick={louder}>{ volume.value().unwrap_or("3") }</button> }
+//     }
+#[hook]
+pub fn use_kv_storage<S>(store: &Rc<ObservedKvStorage<S>>, key: &str) -> UseKvStorageHandle<S>
+where
+    S: KvStorage + 'static,
+{
+    let value = {
+        let store = store.clone();
+        let key = key.to_string();
+        use_state(move || read(&*store, &key))
+    };
+
+    {
+        let store = store.clone();
+        let value = value.clone();
+        let dependencies = (Rc::as_ptr(&store) as *const () as usize, key.to_string());
+        use_effect_with(dependencies, move |(_, key)| {
+            let component = NEXT_COMPONENT.fetch_add(1, Ordering::Relaxed);
+            let update = {
+                let value = value.clone();
+                Callback::from(move |changed| value.set(changed))
+            };
+            COMPONENTS.with(|components| components.borrow_mut().insert(component, update));
+
+            let listened = key.clone();
+            let listener = store.subscribe(move |change| {
+                if change.affects(&listened) {
+                    notify(component, change.value().map(str::to_string));
+                }
+            });
+            // the key may have changed, or been written between rendering and subscribing
+            value.set(read(&*store, key));
+
+            move || {
+                store.unsubscribe(listener);
+                COMPONENTS.with(|components| components.borrow_mut().remove(&component));
+            }
+        });
+    }
+
+    UseKvStorageHandle {
+        store: store.clone(),
+        key: key.to_string(),
+        value,
+    }
+}
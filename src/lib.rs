@@ -1,8 +1,74 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+// without the default `std` feature only the traits and `MemoryKvStorage` are built, e.g. for
+// firmware bringing its own backends
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+// with std the wrappers are built, but without a backend there's nothing to keep the values in
+// but memory. most likely `default-features = false` dropped the one for this target
+#[cfg(all(
+    feature = "std",
+    not(any(
+        all(feature = "cookies", target_family = "wasm"),
+        all(
+            feature = "file",
+            any(target_family = "unix", target_family = "windows", target_os = "wasi")
+        ),
+        all(feature = "packed", not(target_family = "wasm")),
+    ))
+))]
+compile_error!(
+    "easy_storage has no persistent backend for this target, enable `cookies` (wasm), `file` (unix, \
+     windows and wasi) or `packed` (unix and windows), or turn off `std` to bring your own"
+);
+
+// has to be in the crate root, the exported types refer to its definitions
+#[cfg(all(feature = "uniffi", not(target_arch = "wasm32")))]
+uniffi::setup_scaffolding!();
+
+#[cfg(feature = "derive")]
+pub use easy_storage_derive::KvModel;
+pub use kv_storage::{Health, IsNotFound, KvStorage, KvStorageExt, Page, ReadError, WriteError};
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(all(feature = "js", target_arch = "wasm32"))]
+pub mod js;
+#[cfg(all(feature = "uniffi", not(target_arch = "wasm32")))]
+mod mobile;
+#[cfg(all(feature = "node", not(target_arch = "wasm32")))]
+mod node;
+pub mod prelude;
+#[cfg(all(feature = "python", not(target_arch = "wasm32")))]
+mod python;
+
+// std::time::Instant::now and SystemTime::now panic on wasm32-unknown-unknown
+#[cfg(feature = "std")]
+pub(crate) mod time {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) use std::time::Instant;
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) use web_time::Instant;
+
+    pub(crate) fn now() -> std::time::SystemTime {
+        #[cfg(not(target_arch = "wasm32"))]
+        return std::time::SystemTime::now();
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            let since_epoch = web_time::SystemTime::now()
+                .duration_since(web_time::UNIX_EPOCH)
+                .unwrap_or_default();
+            std::time::UNIX_EPOCH + since_epoch
+        }
+    }
 }
 
 pub mod kv_storage {
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+    #[cfg(feature = "std")]
+    use std::time::{Duration, SystemTime};
     use thiserror::Error;
 
     #[derive(Error, Debug)]
@@ -15,87 +81,2082 @@ pub mod kv_storage {
     pub enum ReadError {
         #[error("unknown")]
         Unknown,
+
+        #[error("key not found")]
+        NotFound,
+    }
+
+    // a store written by a newer version of this crate, opening it is refused instead of
+    // misreading it. the file backends return it inside an `io::Error` of kind `InvalidData`
+    #[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+    #[error("Store has format version {found}, only up to {supported} is supported")]
+    pub struct UnsupportedFormat {
+        pub found: u32,
+        pub supported: u32,
+    }
+
+    // lets generic code tell a missing key apart from a failed read
+    pub trait IsNotFound {
+        fn is_not_found(&self) -> bool;
+    }
+
+    impl IsNotFound for ReadError {
+        fn is_not_found(&self) -> bool {
+            matches!(self, ReadError::NotFound)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl IsNotFound for std::io::Error {
+        fn is_not_found(&self) -> bool {
+            self.kind() == std::io::ErrorKind::NotFound
+        }
+    }
+
+    impl IsNotFound for core::convert::Infallible {
+        fn is_not_found(&self) -> bool {
+            match *self {}
+        }
     }
 
     pub trait KvStorage {
         type WriteErrorType;
-        type ReadErrorType;
+        type ReadErrorType: IsNotFound;
 
         fn read(&self, key: &str) -> Result<String, Self::ReadErrorType>;
         fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType>;
+
+        // lends the value to `f` instead of returning a copy, where the backend can, e.g. for
+        // reads in a game loop. `f` may run while the store holds a lock and must not use it
+        fn read_with(&self, key: &str, f: &mut dyn FnMut(&str)) -> Result<(), Self::ReadErrorType> {
+            let value = self.read(key)?;
+            f(&value);
+            Ok(())
+        }
+
+        // reads the value into `buf`, replacing what it held, so a loop can reuse one buffer
+        // instead of allocating a string per read. returns the value's length
+        fn read_into(&self, key: &str, buf: &mut String) -> Result<usize, Self::ReadErrorType> {
+            buf.clear();
+            self.read_with(key, &mut |value| buf.push_str(value))?;
+            Ok(buf.len())
+        }
+
+        // returns up to `limit` entries with keys after `cursor`, in key order
+        fn scan(&self, cursor: Option<&str>, limit: usize) -> Result<Page, Self::ReadErrorType>;
+
+        // deleting a missing key is not an error
+        fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType>;
+        fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType>;
+
+        // whether the store can be used, so an application can report a problem at startup
+        // instead of on the first failed write. by default it only checks that a read works,
+        // backends that can tell more, e.g. whether their directory is writable, override it
+        fn health_check(&self) -> Health {
+            match self.scan(None, 1) {
+                Ok(_) => Health::Healthy,
+                Err(_) => Health::Unavailable("could not read from the store".to_string()),
+            }
+        }
+    }
+
+    // from best to worst, the strings say what's wrong
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Health {
+        Healthy,
+        // works, but not as well as it should, e.g. a replica is down
+        Degraded(String),
+        // reads work, writes don't
+        ReadOnly(String),
+        Unavailable(String),
+    }
+
+    impl Health {
+        pub fn is_healthy(&self) -> bool {
+            matches!(self, Health::Healthy)
+        }
+
+        // can writes be expected to work
+        pub fn is_writable(&self) -> bool {
+            matches!(self, Health::Healthy | Health::Degraded(_))
+        }
+
+        fn severity(&self) -> u8 {
+            match self {
+                Health::Healthy => 0,
+                Health::Degraded(_) => 1,
+                Health::ReadOnly(_) => 2,
+                Health::Unavailable(_) => 3,
+            }
+        }
+
+        // for stores made of several others, the first of two equally bad ones
+        pub fn worst(self, other: Health) -> Health {
+            if other.severity() > self.severity() {
+                other
+            } else {
+                self
+            }
+        }
+    }
+
+    // `KvStorage` takes `&str` keys so it can be boxed as `dyn KvStorage`, these take anything
+    // that is one, e.g. `String` or a key newtype implementing `AsRef<str>`
+    pub trait KvStorageExt: KvStorage {
+        fn get(&self, key: impl AsRef<str>) -> Result<String, Self::ReadErrorType> {
+            self.read(key.as_ref())
+        }
+
+        fn put(
+            &self,
+            key: impl AsRef<str>,
+            value: impl AsRef<str>,
+        ) -> Result<(), Self::WriteErrorType> {
+            self.write(key.as_ref(), value.as_ref())
+        }
+
+        fn remove(&self, key: impl AsRef<str>) -> Result<(), Self::WriteErrorType> {
+            self.delete(key.as_ref())
+        }
+
+        fn read_opt(&self, key: impl AsRef<str>) -> Result<Option<String>, Self::ReadErrorType> {
+            match self.read(key.as_ref()) {
+                Ok(value) => Ok(Some(value)),
+                Err(e) if e.is_not_found() => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+
+        // `read_with` without looking at the value
+        fn exists(&self, key: impl AsRef<str>) -> Result<bool, Self::ReadErrorType> {
+            match self.read_with(key.as_ref(), &mut |_| {}) {
+                Ok(()) => Ok(true),
+                Err(e) if e.is_not_found() => Ok(false),
+                Err(e) => Err(e),
+            }
+        }
+
+        // `read_with` returning what `f` makes of the value
+        fn read_map<T>(
+            &self,
+            key: impl AsRef<str>,
+            f: impl FnOnce(&str) -> T,
+        ) -> Result<T, Self::ReadErrorType> {
+            let mut f = Some(f);
+            let mut mapped = None;
+            self.read_with(key.as_ref(), &mut |value| {
+                mapped = f.take().map(|f| f(value));
+            })?;
+            Ok(mapped.expect("read_with calls `f` when it succeeds"))
+        }
+
+        // reads every key independently, so one failure doesn't discard the other values
+        #[allow(clippy::type_complexity)]
+        fn try_read_many<K: AsRef<str>>(
+            &self,
+            keys: impl IntoIterator<Item = K>,
+        ) -> Vec<(String, Result<Option<String>, Self::ReadErrorType>)> {
+            keys.into_iter()
+                .map(|key| {
+                    let key = key.as_ref();
+                    (key.to_string(), self.read_opt(key))
+                })
+                .collect()
+        }
+
+        // collects every entry, in key order
+        fn scan_all(&self) -> Result<Vec<(String, String)>, Self::ReadErrorType> {
+            let mut entries = Vec::new();
+            let mut cursor = None;
+            loop {
+                let page = self.scan(cursor.as_deref(), 128)?;
+                entries.extend(page.entries);
+                match page.cursor {
+                    Some(next) => cursor = Some(next),
+                    None => return Ok(entries),
+                }
+            }
+        }
+    }
+
+    impl<S: KvStorage + ?Sized> KvStorageExt for S {}
+
+    #[derive(Debug, Default, Clone, PartialEq, Eq)]
+    pub struct Page {
+        pub entries: Vec<(String, String)>,
+        // `None` once the scan is exhausted
+        pub cursor: Option<String>,
+    }
+
+    #[allow(dead_code)] // only used by the platform specific backends
+    pub(crate) fn page_keys(
+        mut keys: Vec<String>,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> (Vec<String>, Option<String>) {
+        keys.sort_unstable();
+        let start = match cursor {
+            Some(cursor) => keys.partition_point(|key| key.as_str() <= cursor),
+            None => 0,
+        };
+        let end = keys.len().min(start.saturating_add(limit));
+        let next_cursor = if end < keys.len() && end > start {
+            Some(keys[end - 1].clone())
+        } else {
+            None
+        };
+        keys.truncate(end);
+        keys.drain(..start);
+        (keys, next_cursor)
+    }
+
+    #[cfg(feature = "std")]
+    pub trait KvStorageTtl: KvStorage {
+        fn write_with_ttl(
+            &self,
+            key: &str,
+            value: &str,
+            ttl: Duration,
+        ) -> Result<(), Self::WriteErrorType>;
+
+        fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType>;
+
+        // refreshes the expiry of an existing entry using the store's default ttl
+        fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType>;
+    }
+
+    // backends that only hold strings get byte values through `Base64KvStorage`
+    pub trait KvStorageBytes: KvStorage {
+        fn read_bytes(&self, key: &str) -> Result<Vec<u8>, Self::ReadErrorType>;
+        fn write_bytes(&self, key: &str, value: &[u8]) -> Result<(), Self::WriteErrorType>;
+    }
+
+    // for backends holding bytes, e.g. flash or object stores, so they don't have to pretend
+    // every value is utf-8. `Utf8KvStorage` makes one a `KvStorage`. the methods are named
+    // apart from `KvStorage`'s so backends can implement both
+    pub trait RawKvStorage {
+        type WriteErrorType;
+        type ReadErrorType: IsNotFound;
+
+        fn read_raw(&self, key: &str) -> Result<Vec<u8>, Self::ReadErrorType>;
+        fn write_raw(&self, key: &str, value: &[u8]) -> Result<(), Self::WriteErrorType>;
+
+        // like `KvStorage::scan`
+        fn scan_raw(
+            &self,
+            cursor: Option<&str>,
+            limit: usize,
+        ) -> Result<RawPage, Self::ReadErrorType>;
+
+        // deleting a missing key is not an error
+        fn delete_raw(&self, key: &str) -> Result<(), Self::WriteErrorType>;
+        fn delete_prefix_raw(&self, prefix: &str) -> Result<(), Self::WriteErrorType>;
+    }
+
+    #[derive(Debug, Default, Clone, PartialEq, Eq)]
+    pub struct RawPage {
+        pub entries: Vec<(String, Vec<u8>)>,
+        // `None` once the scan is exhausted
+        pub cursor: Option<String>,
+    }
+
+    // removes everything the store holds, including records wrappers keep next to the values
+    // (chunks, blobs, journals, tombstones) and what they keep in memory, e.g. to honour a gdpr
+    // deletion request
+    pub trait KvStoragePurge: KvStorage {
+        fn purge(&self) -> Result<(), Self::WriteErrorType>;
     }
 
-    #[cfg(target_family = "wasm")]
+    // drops what a store keeps that no longer makes up any value, e.g. overwritten records of
+    // an append-only file or superseded deltas, say from a maintenance task. returns the bytes
+    // reclaimed
+    pub trait KvStorageCompact: KvStorage {
+        fn compact(&self) -> Result<u64, Self::WriteErrorType>;
+    }
+
+    // a number that changes whenever the store's contents do, also when another process changes
+    // them, so a cache can tell it's stale without reading the values again
+    pub trait KvStorageGeneration: KvStorage {
+        fn generation(&self) -> Result<u64, Self::ReadErrorType>;
+    }
+
+    // an opaque token for the value a key held when it was read, made from what the backend
+    // has, e.g. a counter or the file's modification time and a hash of its contents. a
+    // revision that's still the stored one means the value wasn't changed since
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Revision(String);
+
+    impl Revision {
+        pub fn new(token: impl Into<String>) -> Self {
+            Revision(token.into())
+        }
+
+        pub fn as_str(&self) -> &str {
+            &self.0
+        }
+    }
+
+    #[derive(Error, Debug)]
+    pub enum ConditionalWriteError<E> {
+        #[error("Key '{0}' is not at the expected revision")]
+        Conflict(String),
+
+        #[error(transparent)]
+        Inner(E),
+    }
+
+    // reads that tell which revision of the value they saw, and writes that only go through
+    // while it's still the stored one, so read-modify-write cycles in several processes don't
+    // lose each other's changes
+    pub trait KvStorageRevision: KvStorage {
+        fn read_revision(&self, key: &str) -> Result<(String, Revision), Self::ReadErrorType>;
+
+        // `revision` is `None` for a key that mustn't exist yet. returns the written revision
+        fn write_if(
+            &self,
+            key: &str,
+            value: &str,
+            revision: Option<&Revision>,
+        ) -> Result<Revision, ConditionalWriteError<Self::WriteErrorType>>;
+    }
+
+    // the built-in backends are `Send + Sync`, so one store can be shared between threads,
+    // e.g. in an `Arc`. this keeps it that way
+    #[cfg(feature = "std")]
+    #[allow(dead_code)] // only there to be type checked
+    fn backends_are_send_sync() {
+        fn send_sync<T: Send + Sync>() {}
+        send_sync::<memory_kv_storage::MemoryKvStorage>();
+        #[cfg(all(feature = "packed", not(target_family = "wasm")))]
+        send_sync::<packed_file_kv_storage::PackedFileKvStorage>();
+        #[cfg(all(feature = "cookies", target_family = "wasm"))]
+        send_sync::<wasm_cookies_kv_storage::WasmCookiesKvStorage>();
+        #[cfg(all(
+            feature = "file",
+            any(target_family = "unix", target_family = "windows", target_os = "wasi")
+        ))]
+        send_sync::<file_based_kv_storage::FileBasedKvStorage>();
+    }
+
+    #[cfg(feature = "std")]
+    pub mod access_controlled_kv_storage;
+    #[cfg(all(feature = "actix-session", not(target_arch = "wasm32")))]
+    pub mod actix_session_store;
+    #[cfg(feature = "age")]
+    pub mod age_kv_storage;
+    #[cfg(feature = "archive")]
+    pub mod archive;
+    #[cfg(feature = "audit")]
+    pub mod audited_kv_storage;
+    #[cfg(feature = "binary")]
+    pub mod base64_kv_storage;
+    #[cfg(all(feature = "bench", not(target_arch = "wasm32")))]
+    pub mod bench;
+    #[cfg(feature = "bevy")]
+    pub mod bevy_persistence;
+    #[cfg(feature = "std")]
+    pub mod boxed_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod cached_kv_storage;
+    #[cfg(all(feature = "std", not(target_family = "wasm")))]
+    pub mod change_bridge;
+    #[cfg(feature = "checksum")]
+    pub mod checksummed_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod chunked_kv_storage;
+    #[cfg(feature = "serde")]
+    pub mod codec;
+    #[cfg(feature = "compression")]
+    pub mod compressed_kv_storage;
+    #[cfg(feature = "config")]
+    pub mod config_source;
+    #[cfg(feature = "test-util")]
+    pub mod conformance;
+    #[cfg(feature = "std")]
+    pub mod copy;
+    #[cfg(feature = "std")]
+    pub mod crdt_kv_storage;
+    #[cfg(all(feature = "cross-tab", target_family = "wasm"))]
+    pub mod cross_tab;
+    #[cfg(all(feature = "std", not(target_family = "wasm")))]
+    pub mod debounced_kv_storage;
+    #[cfg(feature = "dedup")]
+    pub mod dedup_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod defaulting_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod delta_kv_storage;
+    #[cfg(feature = "dioxus")]
+    pub mod dioxus_storage;
+    #[cfg(feature = "egui")]
+    pub mod egui_storage;
+    #[cfg(feature = "std")]
+    pub mod encoded_key_kv_storage;
+    #[cfg(feature = "encryption")]
+    pub mod encrypted_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod enveloped_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod expiring_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod faulty_kv_storage;
+    #[cfg(feature = "figment")]
+    pub mod figment_provider;
+    #[cfg(feature = "test-util")]
+    pub mod fixture;
+    #[cfg(feature = "json")]
+    pub mod indexed_kv_storage;
+    #[cfg(feature = "tracing")]
+    pub mod instrumented_kv_storage;
+    #[cfg(feature = "derive")]
+    pub mod kv_model;
+    #[cfg(feature = "json")]
+    pub mod kv_value;
+    #[cfg(feature = "test-util")]
+    pub mod latency_kv_storage;
+    #[cfg(feature = "leptos")]
+    pub mod leptos_signals;
+    #[cfg(feature = "std")]
+    pub mod lru_kv_storage;
+    pub mod memory_kv_storage;
+    #[cfg(feature = "metrics")]
+    pub mod metered_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod migration;
+    #[cfg(feature = "std")]
+    pub mod mirrored_kv_storage;
+    #[cfg(feature = "test-util")]
+    pub mod mock_kv_storage;
+    #[cfg(all(feature = "test-util", not(target_arch = "wasm32")))]
+    pub mod model;
+    #[cfg(feature = "std")]
+    pub mod observed_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod offline_kv_storage;
+    #[cfg(all(feature = "packed", not(target_family = "wasm")))]
+    pub mod packed_file_kv_storage;
+    #[cfg(all(feature = "std", not(target_family = "wasm")))]
+    pub mod parallel;
+    #[cfg(feature = "std")]
+    pub mod quota_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod rate_limited_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod read_only_kv_storage;
+    #[cfg(feature = "record")]
+    pub mod recording_kv_storage;
+    #[cfg(feature = "record")]
+    pub mod replay_kv_storage;
+    #[cfg(all(feature = "std", not(target_family = "wasm")))]
+    pub mod replicator;
+    #[cfg(feature = "std")]
+    pub mod retention_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod retrying_kv_storage;
+    #[cfg(all(feature = "search", not(target_arch = "wasm32")))]
+    pub mod search_kv_storage;
+    #[cfg(any(feature = "encryption", feature = "age"))]
+    pub mod secret;
+    #[cfg(feature = "signing")]
+    pub mod signed_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod snapshot_kv_storage;
+    #[cfg(feature = "json")]
+    pub mod struct_storage;
+    #[cfg(feature = "std")]
+    pub mod sync;
+    #[cfg(feature = "std")]
+    pub mod tenanted_storage;
+    #[cfg(feature = "std")]
+    pub mod tiered_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod time_series;
+    #[cfg(all(feature = "std", not(target_family = "wasm")))]
+    pub mod timeout_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod timestamped_kv_storage;
+    #[cfg(all(feature = "tower-sessions", not(target_arch = "wasm32")))]
+    pub mod tower_session_store;
+    #[cfg(feature = "std")]
+    pub mod trash_kv_storage;
+    pub mod utf8_kv_storage;
+    #[cfg(feature = "std")]
+    pub mod wal_kv_storage;
+    #[cfg(feature = "yew")]
+    pub mod yew_hooks;
+
+    #[cfg(all(feature = "cookies", target_family = "wasm"))]
     pub mod wasm_cookies_kv_storage {
         use crate::kv_storage;
-        use core::convert::Infallible;
+        use std::collections::BTreeMap;
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
         use thiserror::Error;
         use wasm_cookies::cookies;
 
+        // browsers keep cookies up to about this long, name and value together
+        const MAX_COOKIE_LEN: usize = 4096;
+        // firefox keeps this many cookies per domain, chrome 180. past that the oldest ones are
+        // dropped without saying so
+        const MAX_COOKIES: usize = 150;
+
+        // values and expiry times in unix millis of the keys in a single cookie document
+        type Document = BTreeMap<String, (String, Option<u64>)>;
+
+        // every key is a cookie of its own, unless `with_single_cookie` keeps them all in one
         #[derive(Default)]
-        pub struct WasmCookiesKvStorage;
+        pub struct WasmCookiesKvStorage {
+            ttl: Option<Duration>,
+            document: Option<String>,
+        }
+
+        impl WasmCookiesKvStorage {
+            pub fn with_ttl(ttl: Duration) -> Self {
+                WasmCookiesKvStorage {
+                    ttl: Some(ttl),
+                    document: None,
+                }
+            }
+
+            // keeps every key in the cookie `name`, so the store doesn't run into the browser's
+            // limit on the number of cookies and sends one with every request instead of one per
+            // key. the cookie expires with the store's ttl, per key ttls can only be shorter.
+            // `migrate_to_single_cookie` moves what was stored one cookie per key into it
+            pub fn with_single_cookie(mut self, name: impl Into<String>) -> Self {
+                self.document = Some(name.into());
+                self
+            }
+
+            fn cookie_options(&self) -> cookies::CookieOptions<'static> {
+                let cookie_options = cookies::CookieOptions::default();
+                match self.ttl {
+                    Some(ttl) => cookie_options.expires_after(ttl),
+                    None => cookie_options,
+                }
+            }
+
+            // moves every other cookie of the page into the single cookie, also those not
+            // written through this store, and returns how many there were. their expiry times
+            // can't be read, so they're kept until they're deleted
+            pub fn migrate_to_single_cookie(&self) -> Result<usize, WasmCookieWriteError> {
+                let Some(name) = &self.document else {
+                    return Ok(0);
+                };
+                let cookies = wasm_cookies::all().map_err(WasmCookieReadError::from)?;
+                let mut document = self.document(name)?;
+                let mut moved = Vec::new();
+                for (key, value) in cookies {
+                    if key != *name {
+                        document.entry(key.clone()).or_insert((value, None));
+                        moved.push(key);
+                    }
+                }
+                self.save_document(name, &document)?;
+                moved.iter().for_each(|key| wasm_cookies::delete(key));
+                Ok(moved.len())
+            }
+
+            // without the expired keys
+            fn document(&self, name: &str) -> Result<Document, WasmCookieReadError> {
+                let mut document = match wasm_cookies::get(name) {
+                    Some(cookie) => decode_document(name, &cookie?)?,
+                    None => Document::new(),
+                };
+                let now = unix_millis(crate::time::now());
+                document.retain(|_, (_, expires)| expires.is_none_or(|expires| expires > now));
+                Ok(document)
+            }
+
+            fn save_document(
+                &self,
+                name: &str,
+                document: &Document,
+            ) -> Result<(), WasmCookieWriteError> {
+                if document.is_empty() {
+                    wasm_cookies::delete(name);
+                    return Ok(());
+                }
+                set_cookie(name, &encode_document(document), &self.cookie_options())
+            }
+
+            fn update_document(
+                &self,
+                name: &str,
+                update: impl FnOnce(&mut Document),
+            ) -> Result<(), WasmCookieWriteError> {
+                let mut document = self.document(name)?;
+                update(&mut document);
+                self.save_document(name, &document)
+            }
+
+            fn expires(&self) -> Option<u64> {
+                self.ttl.map(|ttl| unix_millis(crate::time::now() + ttl))
+            }
+
+            // the bytes of the page's cookies, of the single cookie in that mode, and how many
+            // the browser keeps
+            pub fn estimate(&self) -> StorageEstimate {
+                let cookies = wasm_cookies::all_raw();
+                let len = |(name, value): (&String, &String)| (name.len() + value.len()) as u64;
+                match &self.document {
+                    Some(name) => StorageEstimate {
+                        usage: cookies
+                            .get_key_value(urlencoding::encode(name).as_str())
+                            .map_or(0, len),
+                        quota: MAX_COOKIE_LEN as u64,
+                    },
+                    None => StorageEstimate {
+                        usage: cookies.iter().map(len).sum(),
+                        quota: (MAX_COOKIES * MAX_COOKIE_LEN) as u64,
+                    },
+                }
+            }
+        }
+
+        // in bytes
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct StorageEstimate {
+            pub usage: u64,
+            pub quota: u64,
+        }
+
+        impl StorageEstimate {
+            pub fn remaining(&self) -> u64 {
+                self.quota.saturating_sub(self.usage)
+            }
+        }
+
+        // what `navigator.storage.estimate()` says about the origin's storage, e.g. indexeddb
+        // and the cache api. cookies aren't counted, see `WasmCookiesKvStorage::estimate`.
+        // `None` if the browser doesn't tell
+        #[cfg(feature = "quota-estimate")]
+        pub async fn origin_estimate() -> Option<StorageEstimate> {
+            use web_sys::wasm_bindgen::JsCast;
+
+            let promise = web_sys::window()?.navigator().storage().estimate().ok()?;
+            let estimate: web_sys::StorageEstimate = wasm_bindgen_futures::JsFuture::from(promise)
+                .await
+                .ok()?
+                .unchecked_into();
+            Some(StorageEstimate {
+                usage: estimate.get_usage()? as u64,
+                quota: estimate.get_quota()? as u64,
+            })
+        }
+
+        fn unix_millis(at: SystemTime) -> u64 {
+            at.duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_millis() as u64)
+                .unwrap_or(0)
+        }
+
+        // browsers drop cookies that are too long without saying so
+        fn set_cookie(
+            name: &str,
+            value: &str,
+            cookie_options: &cookies::CookieOptions,
+        ) -> Result<(), WasmCookieWriteError> {
+            let encoded_name = urlencoding::encode(name);
+            let len = encoded_name.len() + urlencoding::encode(value).len();
+            if len > MAX_COOKIE_LEN {
+                return Err(WasmCookieWriteError::TooLarge {
+                    len,
+                    max: MAX_COOKIE_LEN,
+                });
+            }
+            let cookies = wasm_cookies::all_raw();
+            if cookies.len() >= MAX_COOKIES && !cookies.contains_key(encoded_name.as_str()) {
+                return Err(WasmCookieWriteError::QuotaExceeded {
+                    cookies: cookies.len(),
+                    max: MAX_COOKIES,
+                });
+            }
+            wasm_cookies::set(name, value, cookie_options);
+            Ok(())
+        }
+
+        // 'p' + records of "<key len>:<expiry or empty>:<value len>:<key><value>", or 'z' + the
+        // records compressed and base64 encoded when that's shorter
+        fn encode_document(document: &Document) -> String {
+            let mut records = String::new();
+            for (key, (value, expires)) in document {
+                let expires = expires.map(|expires| expires.to_string());
+                records.push_str(&format!(
+                    "{}:{}:{}:{key}{value}",
+                    key.len(),
+                    expires.unwrap_or_default(),
+                    value.len()
+                ));
+            }
+            let plain = format!("p{records}");
+            #[cfg(feature = "compression")]
+            {
+                use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+                use base64::Engine;
+                use std::io::Write;
+
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder
+                    .write_all(records.as_bytes())
+                    .expect("writing to a Vec can't fail");
+                let compressed = encoder.finish().expect("writing to a Vec can't fail");
+                let compressed = format!("z{}", BASE64.encode(compressed));
+                if compressed.len() < urlencoding::encode(&plain).len() {
+                    return compressed;
+                }
+            }
+            plain
+        }
+
+        fn decode_document(name: &str, cookie: &str) -> Result<Document, WasmCookieReadError> {
+            let invalid = || WasmCookieReadError::InvalidDocument(name.to_string());
+            let records = match (cookie.get(..1), cookie.get(1..)) {
+                (Some("p"), Some(records)) => records.to_string(),
+                // a compressed document can only be read with the compression feature
+                #[cfg(feature = "compression")]
+                (Some("z"), Some(encoded)) => {
+                    use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+                    use base64::Engine;
+                    use std::io::Read;
+
+                    let compressed = BASE64.decode(encoded).map_err(|_| invalid())?;
+                    let mut records = String::new();
+                    flate2::read::DeflateDecoder::new(&compressed[..])
+                        .read_to_string(&mut records)
+                        .map_err(|_| invalid())?;
+                    records
+                }
+                _ => return Err(invalid()),
+            };
+
+            let mut document = Document::new();
+            let mut rest = records.as_str();
+            while !rest.is_empty() {
+                let mut fields = rest.splitn(4, ':');
+                let mut next = || fields.next().ok_or_else(invalid);
+                let key_len: usize = next()?.parse().map_err(|_| invalid())?;
+                let expires = match next()? {
+                    "" => None,
+                    expires => Some(expires.parse().map_err(|_| invalid())?),
+                };
+                let value_len: usize = next()?.parse().map_err(|_| invalid())?;
+                let tail = next()?;
+                // the lengths come from the cookie, which anything can have edited
+                let end = key_len.checked_add(value_len).ok_or_else(invalid)?;
+                let key = tail.get(..key_len).ok_or_else(invalid)?;
+                let value = tail.get(key_len..end).ok_or_else(invalid)?;
+                document.insert(key.to_string(), (value.to_string(), expires));
+                rest = &tail[end..];
+            }
+            Ok(document)
+        }
 
         impl kv_storage::KvStorage for WasmCookiesKvStorage {
             type ReadErrorType = WasmCookieReadError;
-            type WriteErrorType = Infallible;
+            type WriteErrorType = WasmCookieWriteError;
 
             fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
+                if let Some(name) = &self.document {
+                    return match self.document(name)?.remove(key) {
+                        Some((value, _)) => Ok(value),
+                        None => Err(WasmCookieReadError::NotFound(key.to_string())),
+                    };
+                }
                 match wasm_cookies::get(key) {
                     Some(cookie) => cookie.map_err(Into::into),
-                    None => Ok("".to_string()),
+                    None => Err(WasmCookieReadError::NotFound(key.to_string())),
                 }
             }
 
             fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
-                let cookie_options = cookies::CookieOptions::default();
-                wasm_cookies::set(key, value, &cookie_options);
+                if let Some(name) = &self.document {
+                    let expires = self.expires();
+                    return self.update_document(name, |document| {
+                        document.insert(key.to_string(), (value.to_string(), expires));
+                    });
+                }
+                set_cookie(key, value, &self.cookie_options())
+            }
+
+            fn scan(
+                &self,
+                cursor: Option<&str>,
+                limit: usize,
+            ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+                let mut cookies = match &self.document {
+                    Some(name) => self
+                        .document(name)?
+                        .into_iter()
+                        .map(|(key, (value, _))| (key, value))
+                        .collect(),
+                    None => wasm_cookies::all()?,
+                };
+                let (keys, cursor) =
+                    kv_storage::page_keys(cookies.keys().cloned().collect(), cursor, limit);
+                let entries = keys
+                    .into_iter()
+                    .filter_map(|key| cookies.remove(&key).map(|value| (key, value)))
+                    .collect();
+                Ok(kv_storage::Page { entries, cursor })
+            }
+
+            fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+                if let Some(name) = &self.document {
+                    return self.update_document(name, |document| {
+                        document.remove(key);
+                    });
+                }
+                wasm_cookies::delete(key);
+                Ok(())
+            }
+
+            fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+                if let Some(name) = &self.document {
+                    return self.update_document(name, |document| {
+                        document.retain(|key, _| !key.starts_with(prefix));
+                    });
+                }
+                let encoded_prefix = urlencoding::encode(prefix);
+                wasm_cookies::all_raw()
+                    .keys()
+                    .filter(|name| name.starts_with(encoded_prefix.as_str()))
+                    .for_each(|name| wasm_cookies::delete_raw(name));
                 Ok(())
             }
         }
 
+        impl kv_storage::KvStorageTtl for WasmCookiesKvStorage {
+            fn write_with_ttl(
+                &self,
+                key: &str,
+                value: &str,
+                ttl: Duration,
+            ) -> Result<(), Self::WriteErrorType> {
+                if let Some(name) = &self.document {
+                    let expires = unix_millis(crate::time::now() + ttl);
+                    return self.update_document(name, |document| {
+                        document.insert(key.to_string(), (value.to_string(), Some(expires)));
+                    });
+                }
+                let cookie_options = cookies::CookieOptions::default().expires_after(ttl);
+                set_cookie(key, value, &cookie_options)
+            }
+
+            fn expire_at(&self, key: &str, at: SystemTime) -> Result<(), Self::WriteErrorType> {
+                if let Some(name) = &self.document {
+                    return self.update_document(name, |document| {
+                        if let Some((_, expires)) = document.get_mut(key) {
+                            *expires = Some(unix_millis(at));
+                        }
+                    });
+                }
+                // cookies can't be updated in place, re-set the current value with a new expiry
+                if let Some(Ok(value)) = wasm_cookies::get(key) {
+                    let cookie_options = cookies::CookieOptions::default()
+                        .expires_at_timestamp(unix_millis(at) as i64);
+                    set_cookie(key, &value, &cookie_options)?;
+                }
+                Ok(())
+            }
+
+            fn touch(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+                if let Some(name) = &self.document {
+                    let touched = self.expires();
+                    return self.update_document(name, |document| {
+                        if let Some((_, expires)) = document.get_mut(key) {
+                            *expires = touched;
+                        }
+                    });
+                }
+                if let Some(Ok(value)) = wasm_cookies::get(key) {
+                    set_cookie(key, &value, &self.cookie_options())?;
+                }
+                Ok(())
+            }
+        }
+
+        // expires every cookie of the page, also those not written through this store. in single
+        // cookie mode only that cookie
+        impl kv_storage::KvStoragePurge for WasmCookiesKvStorage {
+            fn purge(&self) -> Result<(), Self::WriteErrorType> {
+                if let Some(name) = &self.document {
+                    wasm_cookies::delete(name);
+                    return Ok(());
+                }
+                wasm_cookies::all_raw()
+                    .keys()
+                    .for_each(|name| wasm_cookies::delete_raw(name));
+                Ok(())
+            }
+        }
+
+        impl From<cookies::AllDecodeError> for WasmCookieReadError {
+            fn from(e: cookies::AllDecodeError) -> Self {
+                match e {
+                    cookies::AllDecodeError::Key(name, e)
+                    | cookies::AllDecodeError::Value(name, e) => {
+                        WasmCookieReadError::AllDecodeError(name, e)
+                    }
+                }
+            }
+        }
+
+        impl kv_storage::IsNotFound for WasmCookieReadError {
+            fn is_not_found(&self) -> bool {
+                match self {
+                    WasmCookieReadError::NotFound(_) => true,
+                    WasmCookieReadError::Other(e) => kv_storage::IsNotFound::is_not_found(e),
+                    _ => false,
+                }
+            }
+        }
+
         #[derive(Error, Debug)]
         pub enum WasmCookieReadError {
+            // an empty cookie is read as an empty value
+            #[error("Cookie '{0}' not found")]
+            NotFound(String),
+
             #[error("Error url decoding")]
             UrlDecodeError(#[from] wasm_cookies::FromUrlEncodingError),
 
+            #[error("Error url decoding cookie '{0}'")]
+            AllDecodeError(String, #[source] wasm_cookies::FromUrlEncodingError),
+
+            #[error("Cookie document '{0}' is not valid")]
+            InvalidDocument(String),
+
             #[error(transparent)]
             Other(#[from] kv_storage::ReadError),
         }
+
+        #[derive(Error, Debug)]
+        pub enum WasmCookieWriteError {
+            #[error("Cookie would be {len} bytes long, browsers keep at most {max}")]
+            TooLarge { len: usize, max: usize },
+
+            // a new cookie would make the browser drop another one
+            #[error("Page has {cookies} cookies, browsers keep at most {max}")]
+            QuotaExceeded { cookies: usize, max: usize },
+
+            #[error("Could not read cookie document")]
+            Read(#[from] WasmCookieReadError),
+        }
     }
 
-    #[cfg(any(target_os = "windows", target_os = "android"))]
+    #[cfg(all(
+        feature = "file",
+        any(target_family = "unix", target_family = "windows", target_os = "wasi")
+    ))]
     pub mod file_based_kv_storage {
         use crate::kv_storage;
+        use std::collections::BTreeMap;
         use std::fs;
-        use std::path::PathBuf;
+        use std::io::{self, ErrorKind, Read, Write};
+        use std::path::{Component, Path, PathBuf};
+        use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+        use std::sync::{Mutex, MutexGuard, PoisonError, RwLock, TryLockError};
 
-        const APP_NAME: &str = "PokeIpGo"; // TODO: get this programatically
+        // holds the lock file and temporaries, as a directory it is skipped by `scan`
+        const META_DIR: &str = ".easy_storage";
 
-        pub struct FileBasedKvStorage(PathBuf);
+        // kept in the meta directory, stores from before it was written are version 0
+        const FORMAT_VERSION: u32 = 1;
+
+        static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        // smaller values are cheaper to read than to map
+        #[cfg(feature = "mmap")]
+        const MMAP_THRESHOLD: u64 = 64 * 1024;
+
+        // advisory lock on the whole store, shared by readers and exclusive for writers, so
+        // processes sharing a store directory don't interleave their changes. threads sharing
+        // a `FileBasedKvStorage` take turns the same way, whatever the mode
+        #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+        pub enum LockMode {
+            // wait until the lock is available
+            #[default]
+            Blocking,
+            // fail with `ErrorKind::WouldBlock` if another process or thread holds the lock
+            NonBlocking,
+            // only threads are kept apart, for stores no other process uses
+            Disabled,
+        }
+
+        // how values are laid out in the store's directory
+        #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+        pub enum Layout {
+            // a file per key
+            #[default]
+            Flat,
+            // a file per key in one of 256 subdirectories picked by the key's hash, for stores
+            // with many keys. a flat store is moved over the first time it's opened this way
+            Sharded,
+        }
+
+        // who the store belongs to, picks its default directory the way `directories::ProjectDirs`
+        // does: "%APPDATA%\organization\application" on windows, "~/Library/Application
+        // Support/qualifier.organization.application" on apple platforms and
+        // "$XDG_DATA_HOME/application" on other unixes. android apps have a directory of their
+        // own, see `set_android_files_dir`, and ios apps pick one in their sandbox with
+        // `with_ios_directory`. wasi components get "application" in the first directory the
+        // host preopened, see `preopened_dirs`. see `Scope` for stores shared by every user
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct AppId {
+            // reverse domain, e.g. "com"
+            pub qualifier: String,
+            pub organization: String,
+            pub application: String,
+        }
+
+        impl AppId {
+            pub fn new(
+                qualifier: impl Into<String>,
+                organization: impl Into<String>,
+                application: impl Into<String>,
+            ) -> Self {
+                AppId {
+                    qualifier: qualifier.into(),
+                    organization: organization.into(),
+                    application: application.into(),
+                }
+            }
+
+            // the running executable's name, without a qualifier or organization
+            fn current() -> Self {
+                let application = std::env::current_exe()
+                    .ok()
+                    .and_then(|exe| exe.file_stem()?.to_str().map(str::to_string))
+                    .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string());
+                AppId::new("", "", application)
+            }
+        }
+
+        // whose directory `AppId` picks
+        #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+        pub enum Scope {
+            // the user running the program
+            #[default]
+            User,
+            // every user of the machine, e.g. for license data: "%PROGRAMDATA%\organization\
+            // application" on windows, where every user is given modify access to the directory
+            // when the store creates it, "/Library/Application Support/..." on apple platforms and
+            // "/var/lib/application" on other unixes, where creating it and its permissions are
+            // left to the installer. android and ios apps have no such directory and keep their
+            // own
+            Machine,
+        }
+
+        // where in the app's sandbox an ios store is kept, each in a directory named after the
+        // bundle id
+        #[cfg(target_os = "ios")]
+        #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+        pub enum IosDirectory {
+            // "Documents", backed up and shown in the files app if the app shares its documents
+            Documents,
+            // "Library/Application Support", backed up but hidden from the user
+            #[default]
+            Library,
+            // "Library/Caches", not backed up and deleted by the system when it runs out of space
+            Caches,
+        }
+
+        pub struct FileBasedKvStorage {
+            path: PathBuf,
+            // `None` once a path is given
+            app: Option<AppId>,
+            scope: Scope,
+            #[cfg(target_os = "ios")]
+            ios_directory: IosDirectory,
+            #[cfg(target_os = "ios")]
+            excluded_from_backup: bool,
+            sync: bool,
+            lock_mode: LockMode,
+            secure_erase: bool,
+            buffered: bool,
+            layout: Layout,
+            // flat files have been moved into their shards
+            sharded: AtomicBool,
+            // the store's format is known and current
+            format_checked: AtomicBool,
+            dirs_created: AtomicBool,
+            // `None` for deleted keys
+            pending: Mutex<BTreeMap<String, Option<Vec<u8>>>>,
+            // the lock file's counterpart for this process's threads
+            threads: RwLock<()>,
+        }
 
         impl Default for FileBasedKvStorage {
             fn default() -> Self {
-                log::info!("path: {:?}", Self::get_roaming_path());
-                FileBasedKvStorage(Self::get_roaming_path().into())
+                let app = AppId::current();
+                let mut store = FileBasedKvStorage::at(PathBuf::new());
+                store.path = store.app_dir(&app);
+                store.app = Some(app);
+                log::info!("path: {:?}", store.path);
+                store
             }
         }
 
         impl FileBasedKvStorage {
+            // a store in `path`, without looking up the default directory like `default` does
+            pub fn at(path: impl Into<PathBuf>) -> Self {
+                FileBasedKvStorage {
+                    path: path.into(),
+                    app: None,
+                    scope: Scope::default(),
+                    #[cfg(target_os = "ios")]
+                    ios_directory: IosDirectory::default(),
+                    #[cfg(target_os = "ios")]
+                    excluded_from_backup: false,
+                    sync: false,
+                    lock_mode: LockMode::default(),
+                    secure_erase: false,
+                    buffered: false,
+                    layout: Layout::default(),
+                    sharded: AtomicBool::new(false),
+                    format_checked: AtomicBool::new(false),
+                    dirs_created: AtomicBool::new(false),
+                    pending: Mutex::default(),
+                    threads: RwLock::default(),
+                }
+            }
+
+            // the store's directory, by default the one `AppId` picks for the running executable
+            pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+                self.path = path.into();
+                self.app = None;
+                self
+            }
+
+            // the directory `app` picks on this platform, replaces `with_path`
+            pub fn with_app_id(mut self, app: &AppId) -> Self {
+                self.path = self.app_dir(app);
+                self.app = Some(app.clone());
+                self
+            }
+
+            // picks the directory of the app id in `scope`. with a path of its own only the
+            // permissions a machine wide store is created with apply
+            pub fn with_scope(mut self, scope: Scope) -> Self {
+                self.scope = scope;
+                if let Some(app) = &self.app {
+                    self.path = self.app_dir(app);
+                }
+                self
+            }
+
+            // picks the directory of the app id in the app's sandbox
+            #[cfg(target_os = "ios")]
+            pub fn with_ios_directory(mut self, directory: IosDirectory) -> Self {
+                self.ios_directory = directory;
+                if let Some(app) = &self.app {
+                    self.path = self.app_dir(app);
+                }
+                self
+            }
+
+            // sets `isExcludedFromBackup` on the store's directory when it's created, so it isn't
+            // backed up to icloud or the computer, e.g. for data that can be downloaded again.
+            // turning it off clears the flag again
+            #[cfg(target_os = "ios")]
+            pub fn with_excluded_from_backup(mut self, excluded: bool) -> Self {
+                self.excluded_from_backup = excluded;
+                self
+            }
+
+            // fsync every written value, and on unix the directory holding it, before returning
+            pub fn with_sync(mut self, sync: bool) -> Self {
+                self.sync = sync;
+                self
+            }
+
+            pub fn with_lock_mode(mut self, lock_mode: LockMode) -> Self {
+                self.lock_mode = lock_mode;
+                self
+            }
+
+            // overwrite files with zeros before `purge` removes them. journaling and copy on
+            // write file systems or ssd wear levelling can still keep the old contents around
+            pub fn with_secure_erase(mut self, secure_erase: bool) -> Self {
+                self.secure_erase = secure_erase;
+                self
+            }
+
+            // keep writes and deletes in memory until `flush`, or until the store is dropped.
+            // scans and prefix deletes flush first
+            pub fn with_buffered_writes(mut self, buffered: bool) -> Self {
+                self.buffered = buffered;
+                self
+            }
+
+            pub fn with_layout(mut self, layout: Layout) -> Self {
+                self.layout = layout;
+                self
+            }
+
+            // persists buffered changes under a single lock. changes that fail are kept and
+            // retried on the next flush
+            pub fn flush(&self) -> io::Result<()> {
+                let mut pending = self.pending();
+                if pending.is_empty() {
+                    return Ok(());
+                }
+                self.locked(true, || {
+                    while let Some((key, value)) = pending.pop_first() {
+                        let result = self.key_path(&key).and_then(|path| match &value {
+                            Some(value) => self.write_atomic(&path, value),
+                            None => remove_existing(&path),
+                        });
+                        if let Err(e) = result {
+                            pending.insert(key, value);
+                            return Err(e);
+                        }
+                    }
+                    Ok(())
+                })
+            }
+
+            // the value's bytes without copying them into a `String`, large files are mapped
+            // into memory. values are replaced by renaming a new file over the old one, so a
+            // mapping keeps the value it was made from, but other programs editing the files
+            // in place change it under the mapping. on windows, writing the key fails while
+            // it's mapped
+            #[cfg(feature = "mmap")]
+            pub fn read_mapped(&self, key: &str) -> io::Result<MappedValue> {
+                if let Some(value) = self.pending().get(key) {
+                    let value = value
+                        .clone()
+                        .ok_or_else(|| io::Error::from(ErrorKind::NotFound))?;
+                    return Ok(MappedValue(MappedBytes::Read(value)));
+                }
+                let path = self.key_path(key)?;
+                self.locked(false, || {
+                    let mut file = fs::File::open(&path)?;
+                    let len = file.metadata()?.len();
+                    if len < MMAP_THRESHOLD {
+                        let mut value = Vec::with_capacity(len as usize);
+                        file.read_to_end(&mut value)?;
+                        return Ok(MappedValue(MappedBytes::Read(value)));
+                    }
+                    // safe as long as the file isn't changed in place, see above
+                    let map = unsafe { memmap2::Mmap::map(&file)? };
+                    Ok(MappedValue(MappedBytes::Mapped(map)))
+                })
+            }
+
+            fn pending(&self) -> MutexGuard<'_, BTreeMap<String, Option<Vec<u8>>>> {
+                self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+            }
+
             #[cfg(target_os = "windows")]
-            fn get_roaming_path() -> PathBuf {
+            fn app_dir(&self, app: &AppId) -> PathBuf {
                 const ROAMING_ENV: &str = "APPDATA";
+                const PROGRAM_DATA_ENV: &str = "PROGRAMDATA";
 
-                let mut path: PathBuf = std::env::var(ROAMING_ENV)
-                    .expect("could not get roaming dir")
-                    .into();
+                let mut path: PathBuf = match self.scope {
+                    Scope::User => std::env::var(ROAMING_ENV).expect("could not get roaming dir"),
+                    Scope::Machine => {
+                        std::env::var(PROGRAM_DATA_ENV).expect("could not get program data dir")
+                    }
+                }
+                .into();
 
-                path.push(APP_NAME);
+                if !app.organization.is_empty() {
+                    path.push(&app.organization);
+                }
+                path.push(&app.application);
                 path
             }
 
+            // the app's internal files directory, `Context.getFilesDir()`
             #[cfg(target_os = "android")]
-            fn get_roaming_path() -> PathBuf {
-                PathBuf::from("./store")
+            fn app_dir(&self, _app: &AppId) -> PathBuf {
+                if let Some(path) = ANDROID_FILES_DIR.get() {
+                    return path.clone();
+                }
+                android_files_dir().unwrap_or_else(|| {
+                    log::warn!("could not get the app's files dir, using ./store");
+                    PathBuf::from("./store")
+                })
+            }
+
+            #[cfg(all(target_vendor = "apple", not(target_os = "ios")))]
+            fn app_dir(&self, app: &AppId) -> PathBuf {
+                let mut path: PathBuf = match self.scope {
+                    Scope::User => std::env::var_os("HOME")
+                        .expect("could not get home dir")
+                        .into(),
+                    Scope::Machine => PathBuf::from("/"),
+                };
+                path.push("Library/Application Support");
+                path.push(bundle_id(app));
+                path
+            }
+
+            // the home directory is the app's sandbox
+            #[cfg(target_os = "ios")]
+            fn app_dir(&self, app: &AppId) -> PathBuf {
+                let mut path: PathBuf = std::env::var_os("HOME")
+                    .expect("could not get home dir")
+                    .into();
+                path.push(match self.ios_directory {
+                    IosDirectory::Documents => "Documents",
+                    IosDirectory::Library => "Library/Application Support",
+                    IosDirectory::Caches => "Library/Caches",
+                });
+                path.push(bundle_id(app));
+                path
+            }
+
+            // only preopened directories can be opened, e.g. with wasmtime's `--dir`
+            #[cfg(target_os = "wasi")]
+            fn app_dir(&self, app: &AppId) -> PathBuf {
+                let Some(mut path) = preopened_dirs().into_iter().next() else {
+                    log::warn!("the host preopened no directory, using ./store");
+                    return PathBuf::from("./store");
+                };
+                path.push(&app.application);
+                path
+            }
+
+            // the xdg data directory, e.g. on linux and the bsds
+            #[cfg(all(
+                target_family = "unix",
+                not(target_os = "android"),
+                not(target_vendor = "apple")
+            ))]
+            fn app_dir(&self, app: &AppId) -> PathBuf {
+                let mut path: PathBuf = match std::env::var_os("XDG_DATA_HOME") {
+                    _ if self.scope == Scope::Machine => PathBuf::from("/var/lib"),
+                    Some(data_home) if Path::new(&data_home).is_absolute() => data_home.into(),
+                    _ => {
+                        let mut home: PathBuf = std::env::var_os("HOME")
+                            .expect("could not get home dir")
+                            .into();
+                        home.push(".local/share");
+                        home
+                    }
+                };
+
+                // lowercase without spaces, like `ProjectDirs`
+                let application: String = app
+                    .application
+                    .chars()
+                    .filter(|c| !c.is_whitespace())
+                    .flat_map(char::to_lowercase)
+                    .collect();
+                path.push(application);
+                path
+            }
+
+            // moves values out of where versions before keys were encoded kept them, next to the
+            // store's directory instead of in it. only the given keys are looked for, as that
+            // directory holds other programs' files too. a key that already has a value in
+            // the store keeps it. returns how many were moved
+            pub fn migrate_legacy_keys<K: AsRef<str>>(
+                &self,
+                keys: impl IntoIterator<Item = K>,
+            ) -> io::Result<usize> {
+                let keys: Vec<String> = keys
+                    .into_iter()
+                    .map(|key| key.as_ref().to_string())
+                    .collect();
+                self.flush()?;
+                self.locked(true, || {
+                    let mut moved = 0;
+                    for key in &keys {
+                        // legacy paths were never checked, a key like "../x" pointed elsewhere
+                        let is_relative = Path::new(key)
+                            .components()
+                            .all(|component| matches!(component, Component::Normal(_)));
+                        if !is_relative {
+                            continue;
+                        }
+                        let legacy_dir = self.path.parent().unwrap_or(&self.path);
+                        let legacy = match self.layout {
+                            Layout::Flat => legacy_dir.join(key),
+                            Layout::Sharded => legacy_dir.join(shard(key)).join(key),
+                        };
+                        let path = self.key_path(key)?;
+                        if !legacy.is_file() || path.exists() {
+                            continue;
+                        }
+                        if let Some(dir) = path.parent() {
+                            fs::create_dir_all(dir)?;
+                        }
+                        fs::rename(&legacy, &path)?;
+                        moved += 1;
+                    }
+                    Ok(moved)
+                })
+            }
+
+            fn key_path(&self, key: &str) -> io::Result<PathBuf> {
+                let name = encode_key(key)?;
+                Ok(match self.layout {
+                    Layout::Flat => self.keys_dir().join(name),
+                    Layout::Sharded => self.keys_dir().join(shard(key)).join(name),
+                })
+            }
+
+            // the directories holding values
+            fn value_dirs(&self) -> io::Result<Vec<PathBuf>> {
+                let keys_dir = self.keys_dir().to_path_buf();
+                if self.layout == Layout::Flat {
+                    return Ok(vec![keys_dir]);
+                }
+                let entries = match fs::read_dir(&keys_dir) {
+                    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+                    entries => entries?,
+                };
+                let mut dirs = Vec::new();
+                for entry in entries {
+                    let entry = entry?;
+                    let is_shard = entry.file_name().to_str().is_some_and(is_shard);
+                    if is_shard && entry.file_type()?.is_dir() {
+                        dirs.push(entry.path());
+                    }
+                }
+                Ok(dirs)
+            }
+
+            fn list_keys(&self) -> io::Result<Vec<String>> {
+                let mut keys = Vec::new();
+                for dir in self.value_dirs()? {
+                    let entries = match fs::read_dir(dir) {
+                        Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                        entries => entries?,
+                    };
+                    for entry in entries {
+                        let entry = entry?;
+                        if !entry.file_type()?.is_file() {
+                            continue;
+                        }
+                        if let Some(key) = entry.file_name().to_str().and_then(decode_key) {
+                            keys.push(key);
+                        }
+                    }
+                }
+                Ok(keys)
+            }
+
+            // moves the files of a flat store into their shards
+            fn move_into_shards(&self) -> io::Result<()> {
+                let entries = match fs::read_dir(self.keys_dir()) {
+                    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+                    entries => entries?,
+                };
+                let temp_dir = self.temp_dir();
+                for entry in entries {
+                    let entry = entry?;
+                    if !entry.file_type()?.is_file() {
+                        continue;
+                    }
+                    let Some(key) = entry.file_name().to_str().and_then(decode_key) else {
+                        continue;
+                    };
+                    let mut from = entry.path();
+                    // a key named like a shard is in the way of its directory
+                    if is_shard(&key) {
+                        fs::create_dir_all(&temp_dir)?;
+                        let moved = temp_dir.join(format!("shard-{key}"));
+                        fs::rename(&from, &moved)?;
+                        from = moved;
+                    }
+                    let to = self.key_path(&key)?;
+                    if let Some(dir) = to.parent() {
+                        fs::create_dir_all(dir)?;
+                    }
+                    fs::rename(from, to)?;
+                }
+                Ok(())
+            }
+
+            fn keys_dir(&self) -> &Path {
+                &self.path
+            }
+
+            fn meta_dir(&self) -> PathBuf {
+                self.keys_dir().join(META_DIR)
+            }
+
+            fn temp_dir(&self) -> PathBuf {
+                self.meta_dir().join("tmp")
+            }
+
+            fn create_dirs(&self) -> io::Result<()> {
+                #[cfg(target_os = "windows")]
+                let created = !self.path.exists();
+                fs::create_dir_all(&self.path)?;
+                // only the directory's creator may change who can use it
+                #[cfg(target_os = "windows")]
+                if created && self.scope == Scope::Machine {
+                    if let Err(e) = share_with_users(&self.path) {
+                        log::warn!("could not give every user access to {:?}: {e}", self.path);
+                    }
+                }
+                #[cfg(target_os = "ios")]
+                if let Err(e) = set_excluded_from_backup(&self.path, self.excluded_from_backup) {
+                    log::warn!("could not set whether {:?} is backed up: {e}", self.path);
+                }
+                fs::create_dir_all(self.temp_dir())?;
+                self.dirs_created.store(true, Ordering::Release);
+                Ok(())
+            }
+
+            // the store's directories are created by the first operation, and again by one
+            // that finds them gone
+            fn locked<T>(
+                &self,
+                exclusive: bool,
+                mut f: impl FnMut() -> io::Result<T>,
+            ) -> io::Result<T> {
+                if !self.dirs_created.load(Ordering::Acquire) {
+                    self.create_dirs()?;
+                }
+                if !self.format_checked.load(Ordering::Acquire) {
+                    self.lock(true, || self.upgrade_format())?;
+                    self.format_checked.store(true, Ordering::Release);
+                }
+                if self.layout == Layout::Sharded && !self.sharded.load(Ordering::Acquire) {
+                    self.lock(true, || self.move_into_shards())?;
+                    self.sharded.store(true, Ordering::Release);
+                }
+                // even failed changes may have changed something, e.g. half a prefix delete
+                let mut f = || {
+                    let result = f();
+                    let bumped = if exclusive {
+                        self.bump_generation()
+                    } else {
+                        Ok(())
+                    };
+                    let value = result?;
+                    bumped?;
+                    Ok(value)
+                };
+                match self.lock(exclusive, &mut f) {
+                    // a missing key is the usual reason, the directory is only checked then
+                    Err(e) if e.kind() == ErrorKind::NotFound && !self.temp_dir().is_dir() => {
+                        self.create_dirs()?;
+                        self.lock(exclusive, f)
+                    }
+                    result => result,
+                }
+            }
+
+            fn format_path(&self) -> PathBuf {
+                self.meta_dir().join("format")
+            }
+
+            // brings older stores up to the current format, refusing ones from newer versions.
+            // version 0 differs only in not having the format file
+            fn upgrade_format(&self) -> io::Result<()> {
+                let found = match fs::read(self.format_path()) {
+                    Ok(bytes) => bytes
+                        .try_into()
+                        .map(u32::from_le_bytes)
+                        .map_err(|_| io::Error::new(ErrorKind::InvalidData, "torn format file"))?,
+                    Err(e) if e.kind() == ErrorKind::NotFound => 0,
+                    Err(e) => return Err(e),
+                };
+                if found > FORMAT_VERSION {
+                    let unsupported = kv_storage::UnsupportedFormat {
+                        found,
+                        supported: FORMAT_VERSION,
+                    };
+                    return Err(io::Error::new(ErrorKind::InvalidData, unsupported));
+                }
+                if found < FORMAT_VERSION {
+                    log::info!("upgrading {:?} from format {found}", self.path);
+                    self.write_atomic(&self.format_path(), &FORMAT_VERSION.to_le_bytes())?;
+                }
+                Ok(())
+            }
+
+            fn generation_path(&self) -> PathBuf {
+                self.meta_dir().join("generation")
+            }
+
+            // a missing or torn counter reads as 0
+            fn read_generation(&self) -> io::Result<u64> {
+                match fs::read(self.generation_path()) {
+                    Ok(bytes) => Ok(bytes.try_into().map(u64::from_le_bytes).unwrap_or(0)),
+                    Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+                    Err(e) => Err(e),
+                }
+            }
+
+            // only called under the exclusive lock, without file locks other processes' writes
+            // can lose bumps
+            fn bump_generation(&self) -> io::Result<()> {
+                let generation = self.read_generation()?.wrapping_add(1);
+                self.write_atomic(&self.generation_path(), &generation.to_le_bytes())
+            }
+
+            fn lock<T>(&self, exclusive: bool, f: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+                let non_blocking = self.lock_mode == LockMode::NonBlocking;
+                if exclusive {
+                    let _threads = match self.threads.try_write() {
+                        Ok(guard) => guard,
+                        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+                        Err(TryLockError::WouldBlock) if non_blocking => {
+                            return Err(ErrorKind::WouldBlock.into())
+                        }
+                        Err(TryLockError::WouldBlock) => {
+                            self.threads.write().unwrap_or_else(PoisonError::into_inner)
+                        }
+                    };
+                    self.lock_file(exclusive, f)
+                } else {
+                    let _threads = match self.threads.try_read() {
+                        Ok(guard) => guard,
+                        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+                        Err(TryLockError::WouldBlock) if non_blocking => {
+                            return Err(ErrorKind::WouldBlock.into())
+                        }
+                        Err(TryLockError::WouldBlock) => {
+                            self.threads.read().unwrap_or_else(PoisonError::into_inner)
+                        }
+                    };
+                    self.lock_file(exclusive, f)
+                }
+            }
+
+            fn lock_file<T>(
+                &self,
+                exclusive: bool,
+                f: impl FnOnce() -> io::Result<T>,
+            ) -> io::Result<T> {
+                // wasi has no file locks, only this instance's threads are kept apart
+                if self.lock_mode == LockMode::Disabled || cfg!(target_os = "wasi") {
+                    return f();
+                }
+
+                let file = fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(self.meta_dir().join("lock"))?;
+                match (self.lock_mode, exclusive) {
+                    (LockMode::NonBlocking, true) => file.try_lock().map_err(io::Error::from)?,
+                    (LockMode::NonBlocking, false) => {
+                        file.try_lock_shared().map_err(io::Error::from)?
+                    }
+                    (_, true) => file.lock()?,
+                    (_, false) => file.lock_shared()?,
+                }
+                // the lock is released when `file` is dropped
+                f()
+            }
+
+            // values are written to a temporary file which is then renamed over the old one, so
+            // a crash leaves either the old or the new value
+            fn write_atomic(&self, path: &Path, value: &[u8]) -> io::Result<()> {
+                let id = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+                let temp_path = self.temp_dir().join(format!("{}-{id}", process_id()));
+
+                let result = (|| {
+                    let mut file = fs::File::create(&temp_path)?;
+                    file.write_all(value)?;
+                    if self.sync {
+                        file.sync_all()?;
+                    }
+                    drop(file);
+                    match fs::rename(&temp_path, path) {
+                        // shards are created when the first value goes into them
+                        Err(e)
+                            if e.kind() == ErrorKind::NotFound
+                                && self.layout == Layout::Sharded =>
+                        {
+                            fs::create_dir_all(path.parent().unwrap_or(path))?;
+                            fs::rename(&temp_path, path)
+                        }
+                        result => result,
+                    }
+                })();
+                if result.is_err() {
+                    let _ = fs::remove_file(&temp_path);
+                }
+                result?;
+
+                #[cfg(unix)]
+                if self.sync {
+                    if let Some(dir) = path.parent() {
+                        fs::File::open(dir)?.sync_all()?;
+                    }
+                }
+                Ok(())
+            }
+
+            fn erase(&self, path: &Path) -> io::Result<()> {
+                if self.secure_erase {
+                    let mut file = fs::OpenOptions::new().write(true).open(path)?;
+                    let len = file.metadata()?.len();
+                    io::copy(&mut io::repeat(0).take(len), &mut file)?;
+                    file.sync_all()?;
+                }
+                fs::remove_file(path)
+            }
+
+            fn erase_files_in(&self, dir: &Path) -> io::Result<()> {
+                let entries = match fs::read_dir(dir) {
+                    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+                    entries => entries?,
+                };
+                for entry in entries {
+                    let entry = entry?;
+                    if entry.file_type()?.is_file() {
+                        self.erase(&entry.path())?;
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        #[cfg(feature = "mmap")]
+        pub struct MappedValue(MappedBytes);
+
+        #[cfg(feature = "mmap")]
+        enum MappedBytes {
+            Mapped(memmap2::Mmap),
+            Read(Vec<u8>),
+        }
+
+        #[cfg(feature = "mmap")]
+        impl MappedValue {
+            pub fn as_str(&self) -> Result<&str, std::str::Utf8Error> {
+                std::str::from_utf8(self)
+            }
+        }
+
+        #[cfg(feature = "mmap")]
+        impl std::ops::Deref for MappedValue {
+            type Target = [u8];
+
+            fn deref(&self) -> &[u8] {
+                match &self.0 {
+                    MappedBytes::Mapped(map) => map,
+                    MappedBytes::Read(value) => value,
+                }
+            }
+        }
+
+        #[cfg(feature = "mmap")]
+        impl AsRef<[u8]> for MappedValue {
+            fn as_ref(&self) -> &[u8] {
+                self
+            }
+        }
+
+        // the shards are part of the on-disk layout and revisions may be kept by other
+        // programs, so the hash can't change
+        fn fnv1a(bytes: &[u8]) -> u64 {
+            bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+            })
+        }
+
+        fn shard(key: &str) -> String {
+            let hash = fnv1a(key.as_bytes());
+            // folded, the top byte alone barely changes between short keys
+            let folded = hash
+                .to_le_bytes()
+                .into_iter()
+                .fold(0, |folded, byte| folded ^ byte);
+            format!("{folded:02x}")
+        }
+
+        // keys are percent-encoded into file names that are safe everywhere: only lowercase
+        // letters, so case-insensitive file systems keep "a" and "A" apart, no separators, no
+        // leading dot, which would also hide the file on unix, no trailing dot, which windows
+        // drops, and none of the names windows reserves for devices
+        fn encode_key(key: &str) -> io::Result<String> {
+            if key.is_empty() {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "keys can't be empty",
+                ));
+            }
+            let stem = key.split('.').next().unwrap_or(key);
+            let reserved = matches!(stem, "con" | "prn" | "aux" | "nul")
+                || (stem.len() == 4
+                    && (stem.starts_with("com") || stem.starts_with("lpt"))
+                    && matches!(stem.as_bytes()[3], b'1'..=b'9'));
+            let mut name = String::with_capacity(key.len());
+            for (i, byte) in key.bytes().enumerate() {
+                let plain = match byte {
+                    b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' => !(reserved && i == 0),
+                    b'.' => i != 0 && i != key.len() - 1,
+                    _ => false,
+                };
+                if plain {
+                    name.push(char::from(byte));
+                } else {
+                    name.push_str(&format!("%{byte:02X}"));
+                }
+            }
+            Ok(name)
+        }
+
+        // `None` for names `encode_key` doesn't make, e.g. files someone else put there
+        fn decode_key(name: &str) -> Option<String> {
+            let mut bytes = Vec::with_capacity(name.len());
+            let mut rest = name.as_bytes();
+            while let Some((&byte, tail)) = rest.split_first() {
+                if byte == b'%' {
+                    let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+                    bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                    rest = &tail[2..];
+                } else {
+                    bytes.push(byte);
+                    rest = tail;
+                }
+            }
+            let key = String::from_utf8(bytes).ok()?;
+            (encode_key(&key).ok()? == name).then_some(key)
+        }
+
+        fn is_shard(name: &str) -> bool {
+            name.len() == 2 && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        }
+
+        // tells temporary files apart from other processes'. wasi has no process ids, so
+        // instances are told apart by when they first asked
+        fn process_id() -> u32 {
+            #[cfg(not(target_os = "wasi"))]
+            return std::process::id();
+            #[cfg(target_os = "wasi")]
+            {
+                static ID: std::sync::OnceLock<u32> = std::sync::OnceLock::new();
+                *ID.get_or_init(|| {
+                    crate::time::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .subsec_nanos()
+                })
+            }
+        }
+
+        // the directories the host gave the component, in the order it gave them. paths
+        // outside of them can't be opened
+        #[cfg(target_os = "wasi")]
+        pub fn preopened_dirs() -> Vec<PathBuf> {
+            // the first file descriptors after stdin, stdout and stderr, up to the first that
+            // isn't preopened
+            let mut dirs = Vec::new();
+            for fd in 3.. {
+                // safe as the name is written into a buffer of the length the host gave
+                let name = unsafe {
+                    let Ok(prestat) = wasi::fd_prestat_get(fd) else {
+                        break;
+                    };
+                    if prestat.tag != wasi::PREOPENTYPE_DIR.raw() {
+                        continue;
+                    }
+                    let mut name = vec![0; prestat.u.dir.pr_name_len];
+                    if wasi::fd_prestat_dir_name(fd, name.as_mut_ptr(), name.len()).is_err() {
+                        continue;
+                    }
+                    name
+                };
+                match String::from_utf8(name) {
+                    Ok(name) => dirs.push(PathBuf::from(name.trim_end_matches('\0'))),
+                    Err(_) => log::warn!("ignoring preopened directory with an invalid name"),
+                }
+            }
+            dirs
+        }
+
+        // "qualifier.organization.application", leaving out empty parts
+        #[cfg(target_vendor = "apple")]
+        fn bundle_id(app: &AppId) -> String {
+            let parts: Vec<&str> = [&app.qualifier, &app.organization, &app.application]
+                .into_iter()
+                .map(|part| part.as_str())
+                .filter(|part| !part.is_empty())
+                .collect();
+            parts.join(".")
+        }
+
+        #[cfg(target_os = "ios")]
+        fn set_excluded_from_backup(path: &Path, excluded: bool) -> io::Result<()> {
+            use core_foundation_sys::base::{kCFAllocatorDefault, CFRelease};
+            use core_foundation_sys::number::{kCFBooleanFalse, kCFBooleanTrue};
+            use core_foundation_sys::url::{
+                kCFURLIsExcludedFromBackupKey, CFURLCreateFromFileSystemRepresentation,
+                CFURLSetResourcePropertyForKey,
+            };
+            use std::os::unix::ffi::OsStrExt;
+
+            let bytes = path.as_os_str().as_bytes();
+            let len = bytes
+                .len()
+                .try_into()
+                .map_err(|_| io::Error::from(ErrorKind::InvalidInput))?;
+            // safe as the url is released below and the keys and booleans are constants
+            unsafe {
+                let url = CFURLCreateFromFileSystemRepresentation(
+                    kCFAllocatorDefault,
+                    bytes.as_ptr(),
+                    len,
+                    1,
+                );
+                if url.is_null() {
+                    return Err(io::Error::from(ErrorKind::InvalidInput));
+                }
+                let value = if excluded {
+                    kCFBooleanTrue
+                } else {
+                    kCFBooleanFalse
+                };
+                let set = CFURLSetResourcePropertyForKey(
+                    url,
+                    kCFURLIsExcludedFromBackupKey,
+                    value.cast(),
+                    std::ptr::null_mut(),
+                );
+                CFRelease(url.cast());
+                if set == 0 {
+                    return Err(io::Error::other("CFURLSetResourcePropertyForKey failed"));
+                }
+            }
+            Ok(())
+        }
+
+        #[cfg(target_os = "android")]
+        static ANDROID_FILES_DIR: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
+
+        // used instead of asking the app's context for its files directory, e.g. when the
+        // library is loaded without ndk-context being initialized. has to be called before the
+        // first store picks its directory, returns whether it was
+        #[cfg(target_os = "android")]
+        pub fn set_android_files_dir(path: impl Into<PathBuf>) -> bool {
+            ANDROID_FILES_DIR.set(path.into()).is_ok()
+        }
+
+        // the context ndk-context was initialized with, e.g. by android-activity
+        #[cfg(target_os = "android")]
+        fn android_files_dir() -> Option<PathBuf> {
+            use jni::objects::{JObject, JString};
+            use jni::{jni_sig, jni_str};
+
+            // ndk-context panics when it wasn't initialized
+            let context = std::panic::catch_unwind(ndk_context::android_context).ok()?;
+            // safe as ndk-context hands out the vm and context of the running app
+            let vm = unsafe { jni::JavaVM::from_raw(context.vm().cast()) };
+            let path = vm.attach_current_thread(|env| -> jni::errors::Result<String> {
+                // a global reference owned by ndk-context, so it's handed back below
+                let context = unsafe { JObject::from_raw(env, context.context().cast()) };
+                let dir = env
+                    .call_method(
+                        &context,
+                        jni_str!("getFilesDir"),
+                        jni_sig!("()Ljava/io/File;"),
+                        &[],
+                    )?
+                    .l()?;
+                let path = env
+                    .call_method(
+                        &dir,
+                        jni_str!("getAbsolutePath"),
+                        jni_sig!("()Ljava/lang/String;"),
+                        &[],
+                    )?
+                    .l()?;
+                let _ = context.into_raw();
+                env.cast_local::<JString>(path)?.try_to_string(env)
+            });
+            match path {
+                Ok(path) => Some(path.into()),
+                Err(e) => {
+                    log::warn!("could not call getFilesDir: {e}");
+                    None
+                }
+            }
+        }
+
+        // gives the built in users group modify access to `path` and what's created in it, on top
+        // of what it inherits
+        #[cfg(target_os = "windows")]
+        fn share_with_users(path: &Path) -> io::Result<()> {
+            use std::os::windows::ffi::OsStrExt;
+            use windows::core::PCWSTR;
+            use windows::Win32::Foundation::{ERROR_SUCCESS, PSID};
+            use windows::Win32::Security::Authorization::{
+                ConvertStringSecurityDescriptorToSecurityDescriptorW, SetNamedSecurityInfoW,
+                SDDL_REVISION_1, SE_FILE_OBJECT,
+            };
+            use windows::Win32::Security::{
+                GetSecurityDescriptorDacl, ACL, DACL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR,
+                UNPROTECTED_DACL_SECURITY_INFORMATION,
+            };
+            use windows::Win32::System::Memory::LocalFree;
+
+            // allow read, write, execute and delete for builtin users, inherited by files and
+            // directories
+            let sddl: Vec<u16> = "D:(A;OICI;0x1301bf;;;BU)"
+                .encode_utf16()
+                .chain([0])
+                .collect();
+            let path: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+            // safe as the strings are nul terminated and the descriptor is freed once it's used
+            unsafe {
+                let mut descriptor = PSECURITY_DESCRIPTOR::default();
+                if !ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                    PCWSTR(sddl.as_ptr()),
+                    SDDL_REVISION_1,
+                    &mut descriptor,
+                    None,
+                )
+                .as_bool()
+                {
+                    return Err(io::Error::last_os_error());
+                }
+                let (mut present, mut defaulted) = (0, 0);
+                let mut dacl: *mut ACL = std::ptr::null_mut();
+                let result = if GetSecurityDescriptorDacl(
+                    descriptor,
+                    &mut present,
+                    &mut dacl,
+                    &mut defaulted,
+                )
+                .as_bool()
+                {
+                    let set = SetNamedSecurityInfoW(
+                        PCWSTR(path.as_ptr()),
+                        SE_FILE_OBJECT,
+                        DACL_SECURITY_INFORMATION | UNPROTECTED_DACL_SECURITY_INFORMATION,
+                        PSID::default(),
+                        PSID::default(),
+                        Some(dacl),
+                        None,
+                    );
+                    if set == ERROR_SUCCESS {
+                        Ok(())
+                    } else {
+                        Err(io::Error::from_raw_os_error(set.0 as i32))
+                    }
+                } else {
+                    Err(io::Error::last_os_error())
+                };
+                LocalFree(descriptor.0 as isize);
+                result
+            }
+        }
+
+        fn remove_existing(path: &Path) -> io::Result<()> {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+                result => result,
+            }
+        }
+
+        impl Drop for FileBasedKvStorage {
+            fn drop(&mut self) {
+                if let Err(e) = self.flush() {
+                    log::warn!("could not flush buffered writes on drop: {e}");
+                }
             }
         }
 
@@ -104,21 +2165,233 @@ pub mod kv_storage {
             type ReadErrorType = std::io::Error;
 
             fn read(&self, key: &str) -> Result<String, Self::ReadErrorType> {
-                fs::create_dir_all(&self.0)?;
-                let path = self.0.with_file_name(key);
-                fs::read_to_string(path).map_err(|e| {
-                    log::warn!(
-                        "Could not read path '{}', key '{key}': {e}",
-                        self.0.display()
-                    );
-                    e
+                if let Some(value) = self.pending().get(key) {
+                    let value = value
+                        .clone()
+                        .ok_or_else(|| io::Error::from(ErrorKind::NotFound))?;
+                    return String::from_utf8(value)
+                        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e));
+                }
+                let path = self.key_path(key)?;
+                self.locked(false, || fs::read_to_string(&path))
+            }
+
+            fn read_into(&self, key: &str, buf: &mut String) -> Result<usize, Self::ReadErrorType> {
+                buf.clear();
+                if let Some(value) = self.pending().get(key) {
+                    let value = value
+                        .as_deref()
+                        .ok_or_else(|| io::Error::from(ErrorKind::NotFound))?;
+                    let value = std::str::from_utf8(value)
+                        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+                    buf.push_str(value);
+                    return Ok(buf.len());
+                }
+                let path = self.key_path(key)?;
+                self.locked(false, || {
+                    buf.clear();
+                    fs::File::open(&path)?.read_to_string(buf)
                 })
             }
 
             fn write(&self, key: &str, value: &str) -> Result<(), Self::WriteErrorType> {
-                fs::create_dir_all(&self.0)?;
-                let path = self.0.with_file_name(key);
-                fs::write(path, value)
+                kv_storage::RawKvStorage::write_raw(self, key, value.as_bytes())
+            }
+
+            fn scan(
+                &self,
+                cursor: Option<&str>,
+                limit: usize,
+            ) -> Result<kv_storage::Page, Self::ReadErrorType> {
+                self.flush()?;
+                self.locked(false, || {
+                    let keys = self.list_keys()?;
+                    let (keys, cursor) = kv_storage::page_keys(keys, cursor, limit);
+                    let entries = keys
+                        .into_iter()
+                        .map(|key| {
+                            let value = fs::read_to_string(self.key_path(&key)?)?;
+                            Ok((key, value))
+                        })
+                        .collect::<Result<_, Self::ReadErrorType>>()?;
+                    Ok(kv_storage::Page { entries, cursor })
+                })
+            }
+
+            fn delete(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+                // also checks the key, before it's buffered
+                let path = self.key_path(key)?;
+                if self.buffered {
+                    self.pending().insert(key.to_string(), None);
+                    return Ok(());
+                }
+                self.locked(true, || remove_existing(&path))
+            }
+
+            fn delete_prefix(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+                self.flush()?;
+                self.locked(true, || {
+                    for key in self.list_keys()? {
+                        if key.starts_with(prefix) {
+                            remove_existing(&self.key_path(&key)?)?;
+                        }
+                    }
+                    Ok(())
+                })
+            }
+
+            // lists the keys, which also checks the store's format, then creates and removes a
+            // file next to where writes make theirs
+            fn health_check(&self) -> kv_storage::Health {
+                if let Err(e) = self.locked(false, || self.list_keys()) {
+                    return kv_storage::Health::Unavailable(e.to_string());
+                }
+                let id = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+                let probe = self.temp_dir().join(format!("{}-{id}-probe", process_id()));
+                match fs::File::create(&probe).and_then(|_| fs::remove_file(&probe)) {
+                    Ok(()) => kv_storage::Health::Healthy,
+                    Err(e) => kv_storage::Health::ReadOnly(e.to_string()),
+                }
+            }
+        }
+
+        // values written raw are read back as they were, `read` fails with `InvalidData` on
+        // ones that aren't utf-8
+        impl kv_storage::RawKvStorage for FileBasedKvStorage {
+            type WriteErrorType = std::io::Error;
+            type ReadErrorType = std::io::Error;
+
+            fn read_raw(&self, key: &str) -> Result<Vec<u8>, Self::ReadErrorType> {
+                if let Some(value) = self.pending().get(key) {
+                    return value
+                        .clone()
+                        .ok_or_else(|| io::Error::from(ErrorKind::NotFound));
+                }
+                let path = self.key_path(key)?;
+                self.locked(false, || fs::read(&path))
+            }
+
+            fn write_raw(&self, key: &str, value: &[u8]) -> Result<(), Self::WriteErrorType> {
+                // also checks the key, before it's buffered
+                let path = self.key_path(key)?;
+                if self.buffered {
+                    self.pending().insert(key.to_string(), Some(value.to_vec()));
+                    return Ok(());
+                }
+                self.locked(true, || self.write_atomic(&path, value))
+            }
+
+            fn scan_raw(
+                &self,
+                cursor: Option<&str>,
+                limit: usize,
+            ) -> Result<kv_storage::RawPage, Self::ReadErrorType> {
+                self.flush()?;
+                self.locked(false, || {
+                    let keys = self.list_keys()?;
+                    let (keys, cursor) = kv_storage::page_keys(keys, cursor, limit);
+                    let entries = keys
+                        .into_iter()
+                        .map(|key| {
+                            let value = fs::read(self.key_path(&key)?)?;
+                            Ok((key, value))
+                        })
+                        .collect::<Result<_, Self::ReadErrorType>>()?;
+                    Ok(kv_storage::RawPage { entries, cursor })
+                })
+            }
+
+            fn delete_raw(&self, key: &str) -> Result<(), Self::WriteErrorType> {
+                kv_storage::KvStorage::delete(self, key)
+            }
+
+            fn delete_prefix_raw(&self, prefix: &str) -> Result<(), Self::WriteErrorType> {
+                kv_storage::KvStorage::delete_prefix(self, prefix)
+            }
+        }
+
+        // bumped by every change made through a `FileBasedKvStorage`, by any process. with
+        // `LockMode::Disabled` changes made by two processes at once can bump it only once
+        impl kv_storage::KvStorageGeneration for FileBasedKvStorage {
+            fn generation(&self) -> Result<u64, Self::ReadErrorType> {
+                self.read_generation()
+            }
+        }
+
+        // the file's modification time tells apart writes of the same value, the hash of its
+        // contents writes within the file system's timestamp resolution
+        fn revision(path: &Path, value: &[u8]) -> io::Result<kv_storage::Revision> {
+            let modified = fs::metadata(path)?
+                .modified()?
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos();
+            Ok(kv_storage::Revision::new(format!(
+                "{modified:x}-{:016x}",
+                fnv1a(value)
+            )))
+        }
+
+        // buffered changes are flushed first, revisions are those of the files
+        impl kv_storage::KvStorageRevision for FileBasedKvStorage {
+            fn read_revision(
+                &self,
+                key: &str,
+            ) -> Result<(String, kv_storage::Revision), Self::ReadErrorType> {
+                self.flush()?;
+                let path = self.key_path(key)?;
+                self.locked(false, || {
+                    let value = fs::read(&path)?;
+                    let revision = revision(&path, &value)?;
+                    let value = String::from_utf8(value)
+                        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+                    Ok((value, revision))
+                })
+            }
+
+            // checked and written under the store's lock, without it, e.g. with
+            // `LockMode::Disabled` or on wasi, only this process's threads are kept apart
+            fn write_if(
+                &self,
+                key: &str,
+                value: &str,
+                expected: Option<&kv_storage::Revision>,
+            ) -> Result<kv_storage::Revision, kv_storage::ConditionalWriteError<io::Error>>
+            {
+                self.flush()
+                    .map_err(kv_storage::ConditionalWriteError::Inner)?;
+                let path = self
+                    .key_path(key)
+                    .map_err(kv_storage::ConditionalWriteError::Inner)?;
+                let written = self
+                    .locked(true, || {
+                        let current = match fs::read(&path) {
+                            Ok(current) => Some(revision(&path, &current)?),
+                            Err(e) if e.kind() == ErrorKind::NotFound => None,
+                            Err(e) => return Err(e),
+                        };
+                        if current.as_ref() != expected {
+                            return Ok(None);
+                        }
+                        self.write_atomic(&path, value.as_bytes())?;
+                        revision(&path, value.as_bytes()).map(Some)
+                    })
+                    .map_err(kv_storage::ConditionalWriteError::Inner)?;
+                written.ok_or_else(|| kv_storage::ConditionalWriteError::Conflict(key.to_string()))
+            }
+        }
+
+        // removes every value and leftover temporary file, the lock, format and generation
+        // files stay
+        impl kv_storage::KvStoragePurge for FileBasedKvStorage {
+            fn purge(&self) -> Result<(), Self::WriteErrorType> {
+                self.pending().clear();
+                self.locked(true, || {
+                    for dir in self.value_dirs()? {
+                        self.erase_files_in(&dir)?;
+                    }
+                    self.erase_files_in(&self.temp_dir())
+                })
             }
         }
     }
@@ -0,0 +1,92 @@
+// uniffi interface to the backends for kotlin and swift apps. generate the bindings from the
+// built library with the bundled bindgen, e.g.
+//
+//     cargo build --release --features uniffi
+//     cargo run --features uniffi --bin uniffi-bindgen -- generate \
+//         --library target/release/libeasy_storage.so --language kotlin --out-dir out
+//
+// the library has to be built as a cdylib for android and a staticlib for ios, e.g. with
+// `cargo rustc --lib --release --features uniffi --crate-type staticlib`
+
+use crate::kv_storage::boxed_kv_storage::BoxedKvStorage;
+use crate::kv_storage::memory_kv_storage::MemoryKvStorage;
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use std::fmt::Display;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug, uniffi::Error)]
+pub enum StoreError {
+    #[error("The file backend is not available on this platform")]
+    Unavailable,
+
+    #[error("{message}")]
+    Failed { message: String },
+}
+
+impl StoreError {
+    fn failed(e: impl Display) -> Self {
+        StoreError::Failed {
+            message: e.to_string(),
+        }
+    }
+}
+
+#[derive(uniffi::Object)]
+pub struct Store(BoxedKvStorage);
+
+#[uniffi::export]
+impl Store {
+    // keeps everything in memory, e.g. for tests
+    #[uniffi::constructor]
+    pub fn memory() -> Arc<Self> {
+        Arc::new(Store(BoxedKvStorage::new(MemoryKvStorage::new())))
+    }
+
+    // the file backend in `path`, e.g. `context.filesDir` on android
+    #[uniffi::constructor]
+    pub fn file(path: String) -> Result<Arc<Self>, StoreError> {
+        #[cfg(all(
+            feature = "file",
+            any(target_family = "unix", target_family = "windows")
+        ))]
+        {
+            use crate::kv_storage::file_based_kv_storage::FileBasedKvStorage;
+
+            let store = FileBasedKvStorage::at(path);
+            Ok(Arc::new(Store(BoxedKvStorage::new(store))))
+        }
+
+        #[cfg(not(all(
+            feature = "file",
+            any(target_family = "unix", target_family = "windows")
+        )))]
+        {
+            let _ = path;
+            Err(StoreError::Unavailable)
+        }
+    }
+
+    // null or nil for a missing key
+    pub fn read(&self, key: String) -> Result<Option<String>, StoreError> {
+        self.0.read_opt(&key).map_err(StoreError::failed)
+    }
+
+    pub fn write(&self, key: String, value: String) -> Result<(), StoreError> {
+        self.0.write(&key, &value).map_err(StoreError::failed)
+    }
+
+    pub fn delete(&self, key: String) -> Result<(), StoreError> {
+        self.0.delete(&key).map_err(StoreError::failed)
+    }
+
+    pub fn delete_prefix(&self, prefix: String) -> Result<(), StoreError> {
+        self.0.delete_prefix(&prefix).map_err(StoreError::failed)
+    }
+
+    // every key in order
+    pub fn keys(&self) -> Result<Vec<String>, StoreError> {
+        let entries = self.0.scan_all().map_err(StoreError::failed)?;
+        Ok(entries.into_iter().map(|(key, _)| key).collect())
+    }
+}
@@ -0,0 +1,99 @@
+// node module exposing the backends, so e.g. an electron shell shares the store (locking,
+// atomic writes and all) with the rust side. build it with the napi cli, or
+// `cargo rustc --lib --release --features node --crate-type cdylib` and rename the library to
+// `easy_storage.node`:
+//
+//     const { Store } = require("./easy_storage.node");
+//     const store = Store.file("/path/to/store");
+//     store.write("greeting", "hello");
+//     store.read("greeting"); // "hello", null for a missing key
+
+use crate::kv_storage::boxed_kv_storage::BoxedKvStorage;
+use crate::kv_storage::memory_kv_storage::MemoryKvStorage;
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use napi::{Error, Result};
+use napi_derive::napi;
+use std::fmt::Display;
+
+fn storage_error(e: impl Display) -> Error {
+    Error::from_reason(e.to_string())
+}
+
+#[napi]
+pub struct Store(BoxedKvStorage);
+
+#[napi]
+impl Store {
+    // keeps everything in memory, e.g. for tests
+    #[napi(factory)]
+    pub fn memory() -> Self {
+        Store(BoxedKvStorage::new(MemoryKvStorage::new()))
+    }
+
+    // the file backend, in the application's default directory without a path
+    #[napi(factory)]
+    pub fn file(path: Option<String>) -> Result<Self> {
+        #[cfg(all(
+            feature = "file",
+            any(target_family = "unix", target_family = "windows")
+        ))]
+        {
+            use crate::kv_storage::file_based_kv_storage::FileBasedKvStorage;
+
+            let store = match path {
+                Some(path) => FileBasedKvStorage::at(path),
+                None => FileBasedKvStorage::default(),
+            };
+            Ok(Store(BoxedKvStorage::new(store)))
+        }
+
+        #[cfg(not(all(
+            feature = "file",
+            any(target_family = "unix", target_family = "windows")
+        )))]
+        {
+            let _ = path;
+            Err(Error::from_reason(
+                "the file backend is not available on this platform",
+            ))
+        }
+    }
+
+    // null for a missing key
+    #[napi]
+    pub fn read(&self, key: String) -> Result<Option<String>> {
+        self.0.read_opt(&key).map_err(storage_error)
+    }
+
+    #[napi]
+    pub fn write(&self, key: String, value: String) -> Result<()> {
+        self.0.write(&key, &value).map_err(storage_error)
+    }
+
+    #[napi]
+    pub fn delete(&self, key: String) -> Result<()> {
+        self.0.delete(&key).map_err(storage_error)
+    }
+
+    #[napi]
+    pub fn delete_prefix(&self, prefix: String) -> Result<()> {
+        self.0.delete_prefix(&prefix).map_err(storage_error)
+    }
+
+    // every key in order
+    #[napi]
+    pub fn keys(&self) -> Result<Vec<String>> {
+        let entries = self.0.scan_all().map_err(storage_error)?;
+        Ok(entries.into_iter().map(|(key, _)| key).collect())
+    }
+
+    // every entry as [key, value] pairs in key order, e.g. for `new Map(store.entries())`
+    #[napi]
+    pub fn entries(&self) -> Result<Vec<Vec<String>>> {
+        let entries = self.0.scan_all().map_err(storage_error)?;
+        Ok(entries
+            .into_iter()
+            .map(|(key, value)| vec![key, value])
+            .collect())
+    }
+}
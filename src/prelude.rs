@@ -0,0 +1,51 @@
+// `use easy_storage::prelude::*;` brings in the traits, so their methods can be called, and the
+// stores most programs start with
+
+pub use crate::kv_storage::memory_kv_storage::MemoryKvStorage;
+pub use crate::kv_storage::utf8_kv_storage::{Utf8KvStorage, Utf8ReadError};
+#[cfg(feature = "std")]
+pub use crate::kv_storage::KvStorageTtl;
+pub use crate::kv_storage::{
+    ConditionalWriteError, Health, IsNotFound, KvStorage, KvStorageBytes, KvStorageCompact,
+    KvStorageExt, KvStorageGeneration, KvStoragePurge, KvStorageRevision, Page, RawKvStorage,
+    RawPage, ReadError, Revision, WriteError,
+};
+
+#[cfg(feature = "serde")]
+pub use crate::kv_storage::codec::KvStorageCodecExt;
+#[cfg(feature = "derive")]
+pub use crate::kv_storage::kv_model::KvModel;
+#[cfg(feature = "json")]
+pub use crate::kv_storage::kv_value::KvValue;
+#[cfg(all(feature = "std", not(target_family = "wasm")))]
+pub use crate::kv_storage::parallel::KvStorageParallelExt;
+#[cfg(any(feature = "encryption", feature = "age"))]
+pub use crate::kv_storage::secret::SecretString;
+#[cfg(feature = "json")]
+pub use crate::kv_storage::struct_storage::KvStorageStructExt;
+
+#[cfg(feature = "std")]
+pub use crate::kv_storage::{
+    boxed_kv_storage::{BoxedError, BoxedKvStorage},
+    cached_kv_storage::{CachedKvStorage, CachedReadError},
+    defaulting_kv_storage::DefaultingKvStorage,
+    expiring_kv_storage::ExpiringKvStorage,
+    mirrored_kv_storage::{MirroredKvStorage, MirroredReadError, MirroredWriteError, ReadPolicy},
+    read_only_kv_storage::{ReadOnly, ReadOnlyKvStorage},
+    retrying_kv_storage::{RetryPolicy, RetryingKvStorage},
+    tiered_kv_storage::TieredKvStorage,
+};
+
+#[cfg(all(feature = "file", target_os = "ios"))]
+pub use crate::kv_storage::file_based_kv_storage::IosDirectory;
+#[cfg(all(
+    feature = "file",
+    any(target_family = "unix", target_family = "windows", target_os = "wasi")
+))]
+pub use crate::kv_storage::file_based_kv_storage::{AppId, FileBasedKvStorage, Scope};
+#[cfg(all(feature = "packed", not(target_family = "wasm")))]
+pub use crate::kv_storage::packed_file_kv_storage::PackedFileKvStorage;
+#[cfg(all(feature = "cookies", target_family = "wasm"))]
+pub use crate::kv_storage::wasm_cookies_kv_storage::{
+    WasmCookieReadError, WasmCookieWriteError, WasmCookiesKvStorage,
+};
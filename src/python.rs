@@ -0,0 +1,194 @@
+// python module exposing the backends, built with maturin (see pyproject.toml):
+//
+//     import easy_storage
+//     store = easy_storage.Store.file("/path/to/store")
+//     store.write_typed("settings", {"volume": 3})
+//     store.read_typed("settings")  # {'volume': 3}
+//
+// typed values go through the same codecs as `KvStorageCodecExt`, so either side can read
+// what the other wrote
+
+use crate::kv_storage::boxed_kv_storage::BoxedKvStorage;
+use crate::kv_storage::codec::{self, Codec};
+use crate::kv_storage::memory_kv_storage::MemoryKvStorage;
+use crate::kv_storage::{KvStorage, KvStorageExt};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use std::fmt::Display;
+
+create_exception!(easy_storage, StorageError, PyException);
+
+fn storage_error(e: impl Display) -> PyErr {
+    StorageError::new_err(e.to_string())
+}
+
+#[pyclass(frozen, module = "easy_storage")]
+struct Store(BoxedKvStorage);
+
+#[pymethods]
+impl Store {
+    // keeps everything in memory, e.g. for tests
+    #[staticmethod]
+    fn memory() -> Self {
+        Store(BoxedKvStorage::new(MemoryKvStorage::new()))
+    }
+
+    // the file backend, in the application's default directory without a path
+    #[staticmethod]
+    #[pyo3(signature = (path = None))]
+    fn file(path: Option<std::path::PathBuf>) -> PyResult<Self> {
+        #[cfg(all(
+            feature = "file",
+            any(target_family = "unix", target_family = "windows")
+        ))]
+        {
+            use crate::kv_storage::file_based_kv_storage::FileBasedKvStorage;
+
+            let store = match path {
+                Some(path) => FileBasedKvStorage::at(path),
+                None => FileBasedKvStorage::default(),
+            };
+            Ok(Store(BoxedKvStorage::new(store)))
+        }
+
+        #[cfg(not(all(
+            feature = "file",
+            any(target_family = "unix", target_family = "windows")
+        )))]
+        {
+            let _ = path;
+            Err(StorageError::new_err(
+                "the file backend is not available on this platform",
+            ))
+        }
+    }
+
+    // None for a missing key
+    fn read(&self, py: Python<'_>, key: &str) -> PyResult<Option<String>> {
+        py.detach(|| self.0.read_opt(key)).map_err(storage_error)
+    }
+
+    fn write(&self, py: Python<'_>, key: &str, value: &str) -> PyResult<()> {
+        py.detach(|| self.0.write(key, value))
+            .map_err(storage_error)
+    }
+
+    fn delete(&self, py: Python<'_>, key: &str) -> PyResult<()> {
+        py.detach(|| self.0.delete(key)).map_err(storage_error)
+    }
+
+    fn delete_prefix(&self, py: Python<'_>, prefix: &str) -> PyResult<()> {
+        py.detach(|| self.0.delete_prefix(prefix))
+            .map_err(storage_error)
+    }
+
+    // every entry as (key, value) pairs in key order
+    fn items(&self, py: Python<'_>) -> PyResult<Vec<(String, String)>> {
+        py.detach(|| self.0.scan_all()).map_err(storage_error)
+    }
+
+    fn keys(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        let items = self.items(py)?;
+        Ok(items.into_iter().map(|(key, _)| key).collect())
+    }
+
+    // decodes the value with one of the self describing codecs, "json", "msgpack" or "cbor"
+    // depending on the features the module was built with. None for a missing key
+    #[pyo3(signature = (key, codec = "json"))]
+    fn read_typed<'py>(
+        &self,
+        py: Python<'py>,
+        key: &str,
+        codec: &str,
+    ) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let codec = TypedCodec::parse(codec)?;
+        let Some(encoded) = self.read(py, key)? else {
+            return Ok(None);
+        };
+        let value = codec.decode(&encoded)?;
+        Ok(Some(pythonize::pythonize(py, &value)?))
+    }
+
+    #[pyo3(signature = (key, value, codec = "json"))]
+    fn write_typed(
+        &self,
+        py: Python<'_>,
+        key: &str,
+        value: &Bound<'_, PyAny>,
+        codec: &str,
+    ) -> PyResult<()> {
+        let codec = TypedCodec::parse(codec)?;
+        let value: serde_json::Value = pythonize::depythonize(value)?;
+        let encoded = codec.encode(&value)?;
+        self.write(py, key, &encoded)
+    }
+
+    fn __contains__(&self, py: Python<'_>, key: &str) -> PyResult<bool> {
+        Ok(self.read(py, key)?.is_some())
+    }
+}
+
+// bincode isn't self describing, its values can't be decoded without knowing their type
+#[derive(Clone, Copy)]
+enum TypedCodec {
+    Json,
+    #[cfg(feature = "msgpack")]
+    MsgPack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl TypedCodec {
+    fn parse(name: &str) -> PyResult<Self> {
+        match name {
+            "json" => Ok(TypedCodec::Json),
+            #[cfg(feature = "msgpack")]
+            "msgpack" => Ok(TypedCodec::MsgPack),
+            #[cfg(feature = "cbor")]
+            "cbor" => Ok(TypedCodec::Cbor),
+            _ => Err(PyValueError::new_err(format!("unknown codec '{name}'"))),
+        }
+    }
+
+    fn encode(self, value: &serde_json::Value) -> PyResult<String> {
+        match self {
+            TypedCodec::Json => encode(&codec::JsonCodec::default(), value),
+            #[cfg(feature = "msgpack")]
+            TypedCodec::MsgPack => encode(&codec::MsgPackCodec, value),
+            #[cfg(feature = "cbor")]
+            TypedCodec::Cbor => encode(&codec::CborCodec, value),
+        }
+    }
+
+    fn decode(self, encoded: &str) -> PyResult<serde_json::Value> {
+        match self {
+            TypedCodec::Json => decode(&codec::JsonCodec::default(), encoded),
+            #[cfg(feature = "msgpack")]
+            TypedCodec::MsgPack => decode(&codec::MsgPackCodec, encoded),
+            #[cfg(feature = "cbor")]
+            TypedCodec::Cbor => decode(&codec::CborCodec, encoded),
+        }
+    }
+}
+
+fn encode<C: Codec>(codec: &C, value: &serde_json::Value) -> PyResult<String>
+where
+    C::Error: Display,
+{
+    codec.encode(value).map_err(storage_error)
+}
+
+fn decode<C: Codec>(codec: &C, encoded: &str) -> PyResult<serde_json::Value>
+where
+    C::Error: Display,
+{
+    codec.decode(encoded).map_err(storage_error)
+}
+
+#[pymodule]
+fn easy_storage(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Store>()?;
+    module.add("StorageError", module.py().get_type::<StorageError>())?;
+    Ok(())
+}
@@ -0,0 +1,33 @@
+#![cfg(all(feature = "file", any(unix, windows)))]
+
+use easy_storage::prelude::*;
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("easy_storage-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// another handle's write has to clear the cache even when one of the cache's own writes lands
+// before it checks the generation again
+#[test]
+fn write_from_other_handle_is_seen_after_own_write() {
+    let dir = temp_dir("cached-generation");
+    let cached = CachedKvStorage::new(FileBasedKvStorage::at(&dir), 16)
+        .with_generation_check(Duration::ZERO);
+    let other = FileBasedKvStorage::at(&dir);
+
+    cached.write("a", "1").unwrap();
+    assert_eq!(cached.read("a").unwrap(), "1");
+    other.write("a", "2").unwrap();
+    cached.write("b", "x").unwrap();
+    assert_eq!(cached.read("a").unwrap(), "2");
+
+    other.delete("a").unwrap();
+    cached.delete("b").unwrap();
+    assert!(cached.read_opt("a").unwrap().is_none());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,14 @@
+#![cfg(feature = "test-util")]
+
+use easy_storage::prelude::*;
+
+easy_storage::kv_storage_conformance_tests!(memory, MemoryKvStorage::new());
+
+// the checks run in parallel in one directory, each under its own keys
+#[cfg(all(feature = "file", any(unix, windows)))]
+easy_storage::kv_storage_conformance_tests!(
+    file,
+    FileBasedKvStorage::at(
+        std::env::temp_dir().join(format!("easy_storage-conformance-{}", std::process::id()))
+    )
+);
@@ -0,0 +1,19 @@
+#![cfg(feature = "test-util")]
+
+use easy_storage::kv_storage::model::{self, Config};
+use easy_storage::prelude::*;
+
+#[test]
+fn memory_matches_model() {
+    model::run_model_test(Config::default(), 32, MemoryKvStorage::new);
+}
+
+// every case clears the keys it uses first, so they can share a directory
+#[cfg(all(feature = "file", any(unix, windows)))]
+#[test]
+fn file_matches_model() {
+    let dir = std::env::temp_dir().join(format!("easy_storage-model-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    model::run_model_test(Config::default(), 32, || FileBasedKvStorage::at(&dir));
+    let _ = std::fs::remove_dir_all(&dir);
+}